@@ -0,0 +1,71 @@
+//! Compares the slice conversions between `f32` and `i16` with converting each sample on its own.
+//!
+//! Run with `cargo bench --bench conversion`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use cpal::Sample;
+
+// Roughly one second of 64 channels at 48 kHz.
+const SAMPLES: usize = 64 * 48_000;
+const ROUNDS: u32 = 50;
+
+fn main() {
+    let floats: Vec<f32> = (0..SAMPLES)
+        .map(|i| (i as f32 * 0.001).sin() * 0.9)
+        .collect();
+    let ints: Vec<i16> = floats.iter().map(|&s| s.to_sample()).collect();
+    compare(
+        "f32 -> i16",
+        &floats,
+        |src, dst| {
+            for (d, &s) in dst.iter_mut().zip(src) {
+                *d = i16::from_sample(s);
+            }
+        },
+        cpal::f32_to_i16,
+    );
+    compare(
+        "i16 -> f32",
+        &ints,
+        |src, dst| {
+            for (d, &s) in dst.iter_mut().zip(src) {
+                *d = f32::from_sample(s);
+            }
+        },
+        cpal::i16_to_f32,
+    );
+}
+
+fn compare<S, D: Copy + Default>(
+    name: &str,
+    src: &[S],
+    per_sample: fn(&[S], &mut [D]),
+    slice: fn(&[S], &mut [D]),
+) {
+    let mut dst = vec![D::default(); src.len()];
+    let mut time = |convert: fn(&[S], &mut [D])| fastest(|| convert(black_box(src), &mut dst));
+    let slice = time(slice);
+    let per_sample = time(per_sample);
+    println!(
+        "{}: per sample {:?}, slice {:?} ({:.1}x)",
+        name,
+        per_sample,
+        slice,
+        per_sample.as_secs_f64() / slice.as_secs_f64()
+    );
+}
+
+// The fastest of a number of rounds, after one to warm up.
+fn fastest(mut f: impl FnMut()) -> Duration {
+    f();
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
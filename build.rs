@@ -1,6 +1,7 @@
 use std::env;
 
 const CPAL_ASIO_DIR: &str = "CPAL_ASIO_DIR";
+const ALSA_ERROR_HANDLER: &str = "src/host/alsa/error_handler.c";
 
 fn main() {
     println!("cargo:rerun-if-env-changed={}", CPAL_ASIO_DIR);
@@ -11,4 +12,16 @@ fn main() {
         Err(_) => {}
         Ok(_) => println!("cargo:rustc-cfg=asio"),
     };
+
+    // ALSA's error handler is variadic, which Rust can't define, so it is written in C.
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    if matches!(
+        target_os.as_str(),
+        "linux" | "dragonfly" | "freebsd" | "netbsd"
+    ) {
+        println!("cargo:rerun-if-changed={}", ALSA_ERROR_HANDLER);
+        cc::Build::new()
+            .file(ALSA_ERROR_HANDLER)
+            .compile("cpal_alsa_error_handler");
+    }
 }
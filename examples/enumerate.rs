@@ -21,6 +21,9 @@ fn main() -> Result<(), anyhow::Error> {
         println!("  Devices: ");
         for (device_index, device) in devices.enumerate() {
             println!("  {}. \"{}\"", device_index + 1, device.name()?);
+            if let Some(info) = device.backend_info() {
+                println!("    {}", info);
+            }
 
             // Input configs
             if let Ok(conf) = device.default_input_config() {
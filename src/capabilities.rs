@@ -0,0 +1,153 @@
+//! A summary of what a device supports.
+
+use crate::{
+    ChannelCount, DeviceDirection, FrameCount, SampleFormat, SampleRate, SupportedBufferSize,
+    SupportedStreamConfigRange,
+};
+
+/// Whether a device supports a feature, as far as its host can tell.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Capability {
+    Supported,
+    Unsupported,
+    /// The host has no way of telling, or doesn't implement the query.
+    #[default]
+    Unknown,
+}
+
+/// What a device supports, as returned by
+/// [`DeviceTrait::capabilities`](crate::traits::DeviceTrait::capabilities).
+///
+/// The ranges span the supported configurations of both directions. Fields the host can't
+/// determine are `None` or [`Capability::Unknown`] rather than a guess.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// The directions streams can be built in, or `None` if the device supports neither.
+    pub direction: Option<DeviceDirection>,
+    pub min_channels: Option<ChannelCount>,
+    pub max_channels: Option<ChannelCount>,
+    pub min_sample_rate: Option<SampleRate>,
+    pub max_sample_rate: Option<SampleRate>,
+    /// The sample formats the device supports natively, in ascending order.
+    pub sample_formats: Vec<SampleFormat>,
+    /// The smallest buffer size in frames the device supports.
+    pub min_buffer_size: Option<FrameCount>,
+    /// The buffer size in frames of streams built with [`BufferSize::Default`].
+    ///
+    /// [`BufferSize::Default`]: crate::BufferSize::Default
+    pub default_buffer_size: Option<FrameCount>,
+    /// The largest buffer size in frames the device supports.
+    pub max_buffer_size: Option<FrameCount>,
+    /// Whether streams can have exclusive use of the device, bypassing the system mixer.
+    pub exclusive_mode: Capability,
+    /// Whether the output of the device can be recorded by an input stream.
+    pub loopback: Capability,
+    /// Whether output streams can be played through a hardware offload engine.
+    pub offload: Capability,
+}
+
+impl Capabilities {
+    // Summarize the supported configurations of a device, leaving everything they don't tell
+    // unknown.
+    pub(crate) fn from_configs(
+        input: &[SupportedStreamConfigRange],
+        output: &[SupportedStreamConfigRange],
+    ) -> Self {
+        let direction = match (input.is_empty(), output.is_empty()) {
+            (false, false) => Some(DeviceDirection::Duplex),
+            (false, true) => Some(DeviceDirection::Input),
+            (true, false) => Some(DeviceDirection::Output),
+            (true, true) => None,
+        };
+        let configs = || input.iter().chain(output);
+        let mut sample_formats: Vec<_> = configs().map(|config| config.sample_format).collect();
+        sample_formats.sort();
+        sample_formats.dedup();
+        let buffer_sizes = || {
+            configs().filter_map(|config| match config.buffer_size {
+                SupportedBufferSize::Range { min, max } => Some((min, max)),
+                SupportedBufferSize::Unknown => None,
+            })
+        };
+        Capabilities {
+            direction,
+            min_channels: configs().map(|config| config.channels).min(),
+            max_channels: configs().map(|config| config.channels).max(),
+            min_sample_rate: configs().map(|config| config.min_sample_rate).min(),
+            max_sample_rate: configs().map(|config| config.max_sample_rate).max(),
+            sample_formats,
+            min_buffer_size: buffer_sizes().map(|(min, _)| min).min(),
+            default_buffer_size: None,
+            max_buffer_size: buffer_sizes().map(|(_, max)| max).max(),
+            exclusive_mode: Capability::Unknown,
+            loopback: Capability::Unknown,
+            offload: Capability::Unknown,
+        }
+    }
+}
+
+#[test]
+fn test_capabilities_from_configs() {
+    let range = |channels, min_rate, max_rate, buffer_size, sample_format| {
+        SupportedStreamConfigRange::new(
+            channels,
+            SampleRate(min_rate),
+            SampleRate(max_rate),
+            buffer_size,
+            sample_format,
+        )
+    };
+    let input = [range(
+        1,
+        8_000,
+        48_000,
+        SupportedBufferSize::Unknown,
+        SampleFormat::I16,
+    )];
+    let output = [
+        range(
+            2,
+            44_100,
+            96_000,
+            SupportedBufferSize::Range { min: 64, max: 4096 },
+            SampleFormat::F32,
+        ),
+        range(
+            6,
+            48_000,
+            48_000,
+            SupportedBufferSize::Range { min: 32, max: 1024 },
+            SampleFormat::I16,
+        ),
+    ];
+    let capabilities = Capabilities::from_configs(&input, &output);
+    assert_eq!(capabilities.direction, Some(DeviceDirection::Duplex));
+    assert_eq!(
+        (capabilities.min_channels, capabilities.max_channels),
+        (Some(1), Some(6))
+    );
+    assert_eq!(
+        (capabilities.min_sample_rate, capabilities.max_sample_rate),
+        (Some(SampleRate(8_000)), Some(SampleRate(96_000)))
+    );
+    assert_eq!(
+        capabilities.sample_formats,
+        [SampleFormat::I16, SampleFormat::F32]
+    );
+    assert_eq!(
+        (capabilities.min_buffer_size, capabilities.max_buffer_size),
+        (Some(32), Some(4096))
+    );
+    assert_eq!(capabilities.offload, Capability::Unknown);
+
+    let none = Capabilities::from_configs(&[], &[]);
+    assert_eq!(none.direction, None);
+    assert_eq!(none.min_channels, None);
+    assert_eq!(none.min_buffer_size, None);
+}
@@ -0,0 +1,91 @@
+/// The speaker a channel is meant to be played through.
+///
+/// The variants correspond to the speaker positions of `WAVEFORMATEXTENSIBLE` channel masks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ChannelPosition {
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    LowFrequency,
+    BackLeft,
+    BackRight,
+    FrontLeftOfCenter,
+    FrontRightOfCenter,
+    BackCenter,
+    SideLeft,
+    SideRight,
+    TopCenter,
+    TopFrontLeft,
+    TopFrontCenter,
+    TopFrontRight,
+    TopBackLeft,
+    TopBackCenter,
+    TopBackRight,
+}
+
+impl ChannelPosition {
+    /// The position of this channel in the canonical channel order.
+    ///
+    /// In a `WAVEFORMATEXTENSIBLE` stream, the channels present in the channel mask are
+    /// interleaved in this order, so a layout in any other order plays channels through the wrong
+    /// speakers. The index is also the bit of the position in the channel mask.
+    pub fn canonical_order_index(self) -> u32 {
+        match self {
+            ChannelPosition::FrontLeft => 0,
+            ChannelPosition::FrontRight => 1,
+            ChannelPosition::FrontCenter => 2,
+            ChannelPosition::LowFrequency => 3,
+            ChannelPosition::BackLeft => 4,
+            ChannelPosition::BackRight => 5,
+            ChannelPosition::FrontLeftOfCenter => 6,
+            ChannelPosition::FrontRightOfCenter => 7,
+            ChannelPosition::BackCenter => 8,
+            ChannelPosition::SideLeft => 9,
+            ChannelPosition::SideRight => 10,
+            ChannelPosition::TopCenter => 11,
+            ChannelPosition::TopFrontLeft => 12,
+            ChannelPosition::TopFrontCenter => 13,
+            ChannelPosition::TopFrontRight => 14,
+            ChannelPosition::TopBackLeft => 15,
+            ChannelPosition::TopBackCenter => 16,
+            ChannelPosition::TopBackRight => 17,
+        }
+    }
+}
+
+/// Sort `channels` into the canonical channel order (see
+/// [`ChannelPosition::canonical_order_index`]).
+pub fn sort_channels_canonical(channels: &mut [ChannelPosition]) {
+    channels.sort_by_key(|position| position.canonical_order_index());
+}
+
+#[test]
+fn test_sort_channels_canonical() {
+    use ChannelPosition::*;
+
+    let mut channels = vec![
+        SideRight,
+        LowFrequency,
+        FrontRight,
+        SideLeft,
+        FrontCenter,
+        FrontLeft,
+    ];
+    sort_channels_canonical(&mut channels);
+    assert_eq!(
+        channels,
+        [
+            FrontLeft,
+            FrontRight,
+            FrontCenter,
+            LowFrequency,
+            SideLeft,
+            SideRight
+        ]
+    );
+}
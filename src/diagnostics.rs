@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// A condition detected by a backend while running a stream.
+///
+/// Diagnostics describe problems the stream survived (or, for
+/// [`DeviceLost`](DiagnosticKind::DeviceLost), the reason it stopped) and are meant for
+/// telemetry. Errors that require action are still reported through the stream's error callback.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The name of the host running the stream, e.g. `"ALSA"`.
+    pub host: &'static str,
+    /// The name of the stream's device, as used by the host.
+    pub device: String,
+    /// When the condition was detected.
+    pub time: Instant,
+    /// What happened.
+    pub kind: DiagnosticKind,
+}
+
+/// The conditions reported through [`set_diagnostics_handler`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DiagnosticKind {
+    /// The device ran out of samples to play and output was interrupted.
+    Underrun,
+    /// The device had nowhere to put captured samples and some were lost.
+    Overrun,
+    /// The data callback took longer than the duration of the buffer it was handed, so the
+    /// stream is at risk of xruns.
+    CallbackOverrun {
+        /// How long the callback took.
+        elapsed: Duration,
+        /// The duration of the audio in the buffer.
+        budget: Duration,
+    },
+    /// The device was disconnected and the stream stopped.
+    DeviceLost,
+}
+
+type DiagnosticsHandler = Arc<dyn Fn(Diagnostic) + Send + Sync + 'static>;
+
+static HANDLER: RwLock<Option<DiagnosticsHandler>> = RwLock::new(None);
+
+// Checked before doing any work to build a diagnostic, so that streams pay nothing beyond an
+// atomic load when no handler is set.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Receive [`Diagnostic`]s from all streams.
+///
+/// Setting a new handler replaces the previous one. The handler is called from the streams' audio
+/// threads, so it should return quickly, e.g. by sending the diagnostic to another thread.
+pub fn set_diagnostics_handler<F>(handler: F)
+where
+    F: Fn(Diagnostic) + Send + Sync + 'static,
+{
+    *HANDLER.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(handler));
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Whether a diagnostics handler has been set.
+#[allow(dead_code)]
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Pass a diagnostic to the user's handler, if one has been set.
+///
+/// `device` is only called if there is a handler.
+#[allow(dead_code)]
+pub(crate) fn report(host: &'static str, device: impl FnOnce() -> String, kind: DiagnosticKind) {
+    if !enabled() {
+        return;
+    }
+    let handler = match HANDLER.read() {
+        Ok(guard) => guard.clone(),
+        Err(_) => return,
+    };
+    if let Some(handler) = handler {
+        handler(Diagnostic {
+            host,
+            device: device(),
+            time: Instant::now(),
+            kind,
+        });
+    }
+}
+
+#[test]
+fn test_diagnostics_handler() {
+    use std::sync::Mutex;
+
+    // Without a handler, the device name isn't even computed.
+    report("Test", || unreachable!(), DiagnosticKind::Underrun);
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    set_diagnostics_handler(move |diagnostic| sink.lock().unwrap().push(diagnostic));
+    report("Test", || "device".to_string(), DiagnosticKind::Overrun);
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].host, "Test");
+    assert_eq!(received[0].device, "device");
+    assert_eq!(received[0].kind, DiagnosticKind::Overrun);
+}
@@ -0,0 +1,293 @@
+use super::ChannelOutOfRange;
+use crate::{
+    ChannelCount, ChannelPosition, Data, OutputCallbackInfo, SampleFormat, StreamConfig,
+    SupportedStreamConfigRange,
+};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// Mixes interleaved audio with several channels down to a single channel, e.g. to play stereo
+/// content on a mono device such as a Bluetooth hands-free headset.
+///
+/// Each output sample is the sum of the samples of its frame, each multiplied by the weight of
+/// its channel. Unlike keeping only the first channel, this keeps content that is panned to any
+/// side. The weights aren't normalized, so a signal present at full scale on every channel can
+/// exceed full scale after the downmix.
+///
+/// [`DeviceTrait::build_output_stream`](crate::traits::DeviceTrait::build_output_stream) applies
+/// the downmix of [`for_channels`](Downmix::for_channels) on its own when the device only plays
+/// mono. Pass another one to
+/// [`DeviceTrait::build_output_stream_downmixed`](crate::traits::DeviceTrait::build_output_stream_downmixed)
+/// to choose the weights.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Downmix {
+    weights: Vec<f32>,
+}
+
+impl Downmix {
+    /// Create a downmix with the given weight for each input channel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weights` is empty.
+    pub fn new(weights: Vec<f32>) -> Self {
+        assert!(!weights.is_empty(), "downmix needs at least one channel");
+        Downmix { weights }
+    }
+
+    /// The default downmix for `channels` input channels.
+    ///
+    /// Six channels are taken to be 5.1 in the usual order (front left, front right, center,
+    /// LFE, back left, back right) and mixed as by [`for_layout`](Downmix::for_layout). Any other
+    /// number of channels is mixed with equal power, i.e. a weight of `1 / sqrt(channels)` each,
+    /// which is `-3 dB` for stereo.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` is zero.
+    pub fn for_channels(channels: ChannelCount) -> Self {
+        use ChannelPosition::*;
+
+        assert!(channels > 0, "downmix needs at least one channel");
+        if channels == 6 {
+            return Self::for_layout(&[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+            ]);
+        }
+        let weight = 1.0 / (channels as f32).sqrt();
+        Downmix::new(vec![weight; channels as usize])
+    }
+
+    /// The downmix for channels at the given speaker positions, following the ITU-R BS.775
+    /// downmix to stereo followed by an equal power downmix to mono.
+    ///
+    /// Front left and right get `-3 dB`, the center `0 dB`, the LFE channel is dropped and all
+    /// other channels get `-6 dB`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `positions` is empty.
+    pub fn for_layout(positions: &[ChannelPosition]) -> Self {
+        let weights = positions
+            .iter()
+            .map(|position| match position {
+                ChannelPosition::FrontLeft | ChannelPosition::FrontRight => FRAC_1_SQRT_2,
+                ChannelPosition::FrontCenter => 1.0,
+                ChannelPosition::LowFrequency => 0.0,
+                _ => 0.5,
+            })
+            .collect();
+        Downmix::new(weights)
+    }
+
+    /// The number of input channels.
+    pub fn channels(&self) -> ChannelCount {
+        self.weights.len() as ChannelCount
+    }
+
+    /// The weight of each input channel.
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Change the weight of the input channel at `index`.
+    pub fn set_weight(&mut self, index: usize, weight: f32) -> Result<(), ChannelOutOfRange> {
+        match self.weights.get_mut(index) {
+            Some(w) => {
+                *w = weight;
+                Ok(())
+            }
+            None => Err(ChannelOutOfRange {
+                index,
+                channels: self.channels(),
+            }),
+        }
+    }
+
+    /// Mix the interleaved frames of `input` into `output`, one sample per frame.
+    ///
+    /// Stops at whichever of the two runs out first and returns the number of frames mixed. A
+    /// trailing partial frame in `input` is ignored.
+    pub fn process(&self, input: &[f32], output: &mut [f32]) -> usize {
+        let mut frames = 0;
+        for (frame, out) in input
+            .chunks_exact(self.weights.len())
+            .zip(output.iter_mut())
+        {
+            *out = frame.iter().zip(&self.weights).map(|(s, w)| s * w).sum();
+            frames += 1;
+        }
+        frames
+    }
+}
+
+// Whether `build_output_stream` should mix `config`'s channels down for a mono device: when none
+// of the device's `configs` has `config`'s channel count at its sample rate, but a mono one does.
+pub(crate) fn needs_downmix(
+    configs: impl Iterator<Item = SupportedStreamConfigRange>,
+    config: &StreamConfig,
+) -> bool {
+    if config.channels < 2 {
+        return false;
+    }
+    let mut mono = false;
+    for range in configs {
+        if range.min_sample_rate() > config.sample_rate
+            || range.max_sample_rate() < config.sample_rate
+        {
+            continue;
+        }
+        if range.channels() == config.channels {
+            return false;
+        }
+        mono |= range.channels() == 1;
+    }
+    mono
+}
+
+// A data callback for a mono device, which hands `data_callback` buffers with
+// `downmix.channels()` channels in `sample_format` and mixes them down into the device's.
+pub(crate) fn downmixing_callback<D>(
+    sample_format: SampleFormat,
+    downmix: Downmix,
+    mut data_callback: D,
+) -> impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+{
+    let channels = downmix.weights.len();
+    // Backed by `u64`s so that the samples are aligned for every sample format.
+    let mut buffer: Vec<u64> = Vec::new();
+    let mut input = Vec::new();
+    let mut output = Vec::new();
+    move |data, info| {
+        let frames = data.len();
+        let len = frames * channels;
+        buffer.resize((len * sample_format.sample_size() + 7) / 8, 0);
+        let mut frames_data =
+            unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), len, sample_format) };
+        data_callback(&mut frames_data, info);
+        input.clear();
+        frames_data.for_each_f32(|sample| input.push(sample));
+        output.resize(frames, 0.0);
+        downmix.process(&input, &mut output);
+        let mut mixed = output.iter();
+        data.fill_from_f32(|| mixed.next().copied().unwrap_or(0.0));
+    }
+}
+
+#[test]
+fn test_downmix() {
+    // A stereo signal panned hard right is still audible, at -3 dB.
+    let downmix = Downmix::for_channels(2);
+    let input = [0.0, 0.5, 0.0, -0.5, 0.0, 0.25];
+    let mut output = [0.0; 4];
+    assert_eq!(downmix.process(&input, &mut output), 3);
+    for (out, expected) in output.iter().zip([0.5, -0.5, 0.25, 0.0]) {
+        assert!((out - expected * FRAC_1_SQRT_2).abs() < 1e-6);
+    }
+
+    // Identical channels add up to +3 dB.
+    let mut output = [0.0; 1];
+    downmix.process(&[0.5, 0.5], &mut output);
+    assert!((output[0] - 0.5 * 2f32.sqrt()).abs() < 1e-6);
+
+    // 5.1 drops the LFE channel.
+    let downmix = Downmix::for_channels(6);
+    assert_eq!(
+        downmix.weights(),
+        [FRAC_1_SQRT_2, FRAC_1_SQRT_2, 1.0, 0.0, 0.5, 0.5]
+    );
+    let mut output = [1.0; 1];
+    downmix.process(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0], &mut output);
+    assert_eq!(output, [0.0]);
+
+    let mut downmix = Downmix::for_channels(2);
+    downmix.set_weight(0, 0.0).unwrap();
+    assert_eq!(
+        downmix.set_weight(2, 0.0),
+        Err(ChannelOutOfRange {
+            index: 2,
+            channels: 2
+        })
+    );
+    let mut output = [0.0; 1];
+    downmix.process(&[1.0, 0.0], &mut output);
+    assert_eq!(output, [0.0]);
+}
+
+#[test]
+fn test_needs_downmix() {
+    use crate::{SampleRate, SupportedBufferSize};
+
+    let range = |channels, min, max| {
+        SupportedStreamConfigRange::new(
+            channels,
+            SampleRate(min),
+            SampleRate(max),
+            SupportedBufferSize::Unknown,
+            SampleFormat::F32,
+        )
+    };
+    let stereo = StreamConfig {
+        channels: 2,
+        sample_rate: SampleRate(48000),
+        buffer_size: crate::BufferSize::Default,
+    };
+    let mono = [range(1, 8000, 48000)];
+    assert!(needs_downmix(mono.into_iter(), &stereo));
+    // A device that plays the stream's channels needs no downmix, nor one that can't play mono
+    // at its rate either.
+    assert!(!needs_downmix(
+        [range(1, 8000, 48000), range(2, 48000, 48000)].into_iter(),
+        &stereo
+    ));
+    assert!(!needs_downmix([range(1, 8000, 16000)].into_iter(), &stereo));
+    assert!(!needs_downmix(std::iter::empty(), &stereo));
+    let config = StreamConfig {
+        channels: 1,
+        ..stereo
+    };
+    assert!(!needs_downmix(mono.into_iter(), &config));
+}
+
+#[test]
+fn test_downmixing_callback() {
+    use crate::{OutputStreamTimestamp, StreamInstant};
+
+    // A stereo `i16` sine panned hard right, played on a mono `f32` device.
+    let mut phase = 0.0f32;
+    let mut callback = downmixing_callback(
+        SampleFormat::I16,
+        Downmix::for_channels(2),
+        move |data: &mut Data, _: &OutputCallbackInfo| {
+            let samples = data.as_slice_mut::<i16>().unwrap();
+            for frame in samples.chunks_exact_mut(2) {
+                frame[0] = 0;
+                frame[1] = (phase.sin() * 16384.0) as i16;
+                phase += 2.0 * std::f32::consts::PI * 1000.0 / 48000.0;
+            }
+        },
+    );
+    let timestamp = OutputStreamTimestamp {
+        callback: StreamInstant::new(0, 0),
+        playback: StreamInstant::new(0, 0),
+    };
+    let info = OutputCallbackInfo::new(timestamp);
+    let mut device = vec![0.0f32; 480];
+    let mut peak = 0.0f32;
+    // Buffers of varying sizes, as some hosts hand out.
+    for len in [480, 100, 333] {
+        let samples = &mut device[..len];
+        let mut data =
+            unsafe { Data::from_parts(samples.as_mut_ptr() as *mut (), len, SampleFormat::F32) };
+        callback(&mut data, &info);
+        peak = samples.iter().fold(peak, |peak, s| peak.max(s.abs()));
+    }
+    // Still there, at -3 dB.
+    assert!((peak - 0.5 * FRAC_1_SQRT_2).abs() < 1e-2, "{}", peak);
+}
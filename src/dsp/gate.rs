@@ -0,0 +1,87 @@
+use crate::{ChannelCount, SampleRate};
+use std::time::Duration;
+
+/// A simple noise gate for captured audio.
+///
+/// Each buffer passed to [`process`](NoiseGate::process) is measured as a whole. If its peak level
+/// is below the threshold and the gate has been closed for longer than the hold time, the buffer
+/// is replaced with silence. The hold time keeps the gate open across short pauses (e.g. between
+/// words) so that speech isn't chopped up.
+#[derive(Clone, Debug)]
+pub struct NoiseGate {
+    threshold: f32,
+    hold_frames: u64,
+    channels: ChannelCount,
+    // Frames since the level last exceeded the threshold.
+    frames_below: u64,
+}
+
+impl NoiseGate {
+    /// Create a gate that silences buffers quieter than `threshold_db` dBFS once they have been
+    /// quiet for at least `hold`.
+    ///
+    /// `channels` and `sample_rate` should match the configuration of the input stream.
+    pub fn new(
+        threshold_db: f32,
+        hold: Duration,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+    ) -> Self {
+        let hold_frames = (hold.as_secs_f64() * sample_rate.0 as f64) as u64;
+        NoiseGate {
+            threshold: 10f32.powf(threshold_db / 20.0),
+            hold_frames,
+            channels,
+            // Start closed.
+            frames_below: hold_frames,
+        }
+    }
+
+    /// Apply the gate to an interleaved buffer of samples, zeroing it if the gate is closed.
+    ///
+    /// Returns whether the gate was open for this buffer.
+    pub fn process(&mut self, samples: &mut [f32]) -> bool {
+        let peak = samples.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+        if peak >= self.threshold {
+            self.frames_below = 0;
+            return true;
+        }
+        let frames = (samples.len() / self.channels.max(1) as usize) as u64;
+        let open = self.frames_below < self.hold_frames;
+        self.frames_below = self.frames_below.saturating_add(frames);
+        if !open {
+            samples.iter_mut().for_each(|s| *s = 0.0);
+        }
+        open
+    }
+
+    /// Whether the gate is currently letting audio through.
+    pub fn is_open(&self) -> bool {
+        self.frames_below < self.hold_frames
+    }
+}
+
+#[test]
+fn test_noise_gate() {
+    // -20 dBFS threshold, 10 frames of hold at 100 Hz mono.
+    let mut gate = NoiseGate::new(-20.0, Duration::from_millis(100), 1, SampleRate(100));
+    assert!(!gate.is_open());
+
+    let mut quiet = [0.01f32; 5];
+    assert!(!gate.process(&mut quiet));
+    assert_eq!(quiet, [0.0; 5]);
+
+    let mut loud = [0.5f32; 5];
+    assert!(gate.process(&mut loud));
+    assert_eq!(loud, [0.5; 5]);
+
+    // Held open for 10 frames after the level drops.
+    let mut quiet = [0.01f32; 5];
+    assert!(gate.process(&mut quiet));
+    assert_eq!(quiet, [0.01; 5]);
+    let mut quiet = [0.01f32; 5];
+    assert!(gate.process(&mut quiet));
+    let mut quiet = [0.01f32; 5];
+    assert!(!gate.process(&mut quiet));
+    assert_eq!(quiet, [0.0; 5]);
+}
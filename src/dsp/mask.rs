@@ -0,0 +1,224 @@
+use crate::{ChannelCount, SampleRate};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Stored in `Controls::solo` when no channel is soloed.
+const NO_SOLO: usize = usize::MAX;
+
+/// A channel index that is out of range for a [`ChannelMask`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelOutOfRange {
+    /// The index that was passed.
+    pub index: usize,
+    /// The number of channels of the mask.
+    pub channels: ChannelCount,
+}
+
+impl Display for ChannelOutOfRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "channel {} is out of range for {} channels",
+            self.index, self.channels
+        )
+    }
+}
+
+impl Error for ChannelOutOfRange {}
+
+struct Controls {
+    enabled: Box<[AtomicBool]>,
+    solo: AtomicUsize,
+}
+
+impl Controls {
+    fn is_enabled(&self, index: usize) -> bool {
+        self.enabled
+            .get(index)
+            .is_some_and(|enabled| enabled.load(Ordering::Relaxed))
+    }
+
+    fn solo(&self) -> Option<usize> {
+        match self.solo.load(Ordering::Relaxed) {
+            NO_SOLO => None,
+            index => Some(index),
+        }
+    }
+
+    fn is_audible(&self, index: usize) -> bool {
+        match self.solo() {
+            Some(solo) => solo == index,
+            None => self.is_enabled(index),
+        }
+    }
+}
+
+/// Silences individual channels of interleaved audio, e.g. to play a test tone on a single
+/// speaker.
+///
+/// The mask is applied by [`process`](ChannelMask::process) in the data callback, after any
+/// channel conversion, and changed from anywhere through a [`ChannelMaskHandle`]. Changes take
+/// effect at the next buffer, fading the affected channels in or out to avoid clicks.
+pub struct ChannelMask {
+    controls: Arc<Controls>,
+    // The current gain of each channel.
+    gains: Vec<f32>,
+    // The change in gain per frame while fading.
+    step: f32,
+}
+
+/// Changes the channels a [`ChannelMask`] lets through. Created by [`ChannelMask::handle`].
+#[derive(Clone)]
+pub struct ChannelMaskHandle {
+    controls: Arc<Controls>,
+}
+
+impl ChannelMask {
+    /// Create a mask with all of `channels` enabled, fading channels in or out over `fade` at
+    /// `sample_rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` is zero.
+    pub fn new(channels: ChannelCount, fade: Duration, sample_rate: SampleRate) -> Self {
+        assert!(channels > 0, "channel mask channel count must be non-zero");
+        let fade_frames = fade.as_secs_f32() * sample_rate.0 as f32;
+        ChannelMask {
+            controls: Arc::new(Controls {
+                enabled: (0..channels).map(|_| AtomicBool::new(true)).collect(),
+                solo: AtomicUsize::new(NO_SOLO),
+            }),
+            gains: vec![1.0; channels as usize],
+            step: if fade_frames >= 1.0 {
+                1.0 / fade_frames
+            } else {
+                1.0
+            },
+        }
+    }
+
+    /// A handle for changing this mask, e.g. from the thread that owns the stream.
+    pub fn handle(&self) -> ChannelMaskHandle {
+        ChannelMaskHandle {
+            controls: self.controls.clone(),
+        }
+    }
+
+    /// Apply the mask to an interleaved buffer of whole frames.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let channels = self.gains.len();
+        let step = self.step;
+        for (channel, gain) in self.gains.iter_mut().enumerate() {
+            let target = if self.controls.is_audible(channel) {
+                1.0
+            } else {
+                0.0
+            };
+            if *gain == target {
+                if target == 0.0 {
+                    samples
+                        .iter_mut()
+                        .skip(channel)
+                        .step_by(channels)
+                        .for_each(|s| *s = 0.0);
+                }
+                continue;
+            }
+            for sample in samples.iter_mut().skip(channel).step_by(channels) {
+                *gain = if target > *gain {
+                    (*gain + step).min(target)
+                } else {
+                    (*gain - step).max(target)
+                };
+                *sample *= *gain;
+            }
+        }
+    }
+}
+
+impl ChannelMaskHandle {
+    /// Enable or disable the channel at `index`.
+    pub fn set_channel_enabled(
+        &self,
+        index: usize,
+        enabled: bool,
+    ) -> Result<(), ChannelOutOfRange> {
+        self.check(index)?;
+        self.controls.enabled[index].store(enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Whether the channel at `index` is enabled, regardless of any solo. Returns `false` for
+    /// channels that don't exist.
+    pub fn is_channel_enabled(&self, index: usize) -> bool {
+        self.controls.is_enabled(index)
+    }
+
+    /// Let only the channel at `index` through, or lift the solo with `None`. The enabled state
+    /// of each channel is kept and applies again once the solo is lifted.
+    pub fn set_solo(&self, index: Option<usize>) -> Result<(), ChannelOutOfRange> {
+        if let Some(index) = index {
+            self.check(index)?;
+        }
+        self.controls
+            .solo
+            .store(index.unwrap_or(NO_SOLO), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// The soloed channel, if any.
+    pub fn solo(&self) -> Option<usize> {
+        self.controls.solo()
+    }
+
+    /// Whether the channel at `index` is let through, taking the solo into account.
+    pub fn is_channel_audible(&self, index: usize) -> bool {
+        self.controls.is_audible(index)
+    }
+
+    fn check(&self, index: usize) -> Result<(), ChannelOutOfRange> {
+        let channels = self.controls.enabled.len();
+        if index < channels {
+            Ok(())
+        } else {
+            Err(ChannelOutOfRange {
+                index,
+                channels: channels as ChannelCount,
+            })
+        }
+    }
+}
+
+#[test]
+fn test_channel_mask() {
+    // Two frames of fade at 2 Hz.
+    let mut mask = ChannelMask::new(3, Duration::from_secs(1), SampleRate(2));
+    let handle = mask.handle();
+    assert_eq!(
+        handle.set_channel_enabled(3, false),
+        Err(ChannelOutOfRange {
+            index: 3,
+            channels: 3
+        })
+    );
+
+    handle.set_channel_enabled(1, false).unwrap();
+    assert!(!handle.is_channel_enabled(1));
+    let mut samples = [1.0; 9];
+    mask.process(&mut samples);
+    assert_eq!(samples, [1.0, 0.5, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0]);
+
+    // The solo overrides the enabled state.
+    handle.set_solo(Some(1)).unwrap();
+    assert!(handle.is_channel_audible(1) && !handle.is_channel_audible(0));
+    let mut samples = [1.0; 9];
+    mask.process(&mut samples);
+    assert_eq!(samples, [0.5, 0.5, 0.5, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0]);
+
+    handle.set_solo(None).unwrap();
+    assert_eq!(handle.solo(), None);
+    assert!(!handle.is_channel_audible(1));
+}
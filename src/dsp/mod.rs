@@ -0,0 +1,18 @@
+//! Small helpers for processing audio inside stream callbacks.
+//!
+//! Apart from the [`Downmix`] that output streams apply for mono devices, none of these are
+//! applied automatically; call them from your data callback on the samples you have received from
+//! or are about to hand to a stream.
+
+pub use self::downmix::Downmix;
+pub(crate) use self::downmix::{downmixing_callback, needs_downmix};
+pub use self::fade::{Crossfader, FadeLaw};
+pub use self::gate::NoiseGate;
+pub use self::mask::{ChannelMask, ChannelMaskHandle, ChannelOutOfRange};
+pub use self::resample::{ResampleQuality, Resampler};
+
+mod downmix;
+mod fade;
+mod gate;
+mod mask;
+mod resample;
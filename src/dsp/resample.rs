@@ -0,0 +1,151 @@
+use crate::{ChannelCount, SampleRate};
+
+/// The interpolation used by a [`Resampler`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResampleQuality {
+    /// Linear interpolation between neighbouring frames. Cheap, but attenuates high frequencies
+    /// and lets some aliasing through.
+    Linear,
+    /// Cubic (Catmull-Rom) interpolation over four neighbouring frames.
+    #[default]
+    Cubic,
+}
+
+impl ResampleQuality {
+    // The number of frames after the interpolation point that must be available.
+    fn lookahead(self) -> usize {
+        match self {
+            ResampleQuality::Linear => 1,
+            ResampleQuality::Cubic => 2,
+        }
+    }
+}
+
+/// Converts interleaved `f32` audio from one sample rate to another.
+///
+/// The resampler is streaming: buffers of any length can be passed to
+/// [`process`](Resampler::process) one after another, and the output is continuous across them.
+/// A few frames are held back until the frames following them arrive, so the output lags the
+/// input by up to three frames.
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    channels: usize,
+    // Input frames advanced per output frame.
+    step: f64,
+    quality: ResampleQuality,
+    // Interleaved input that hasn't been fully consumed yet.
+    pending: Vec<f32>,
+    // Position of the next output frame, in frames relative to the start of `pending`.
+    position: f64,
+}
+
+impl Resampler {
+    /// Create a resampler converting `channels`-channel audio from rate `from` to rate `to`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels`, `from` or `to` is zero.
+    pub fn new(
+        channels: ChannelCount,
+        from: SampleRate,
+        to: SampleRate,
+        quality: ResampleQuality,
+    ) -> Self {
+        assert!(channels > 0, "resampler channel count must be non-zero");
+        assert!(
+            from.0 > 0 && to.0 > 0,
+            "resampler sample rates must be non-zero"
+        );
+        Resampler {
+            channels: channels as usize,
+            step: from.0 as f64 / to.0 as f64,
+            quality,
+            pending: Vec::new(),
+            position: 0.0,
+        }
+    }
+
+    /// Resample an interleaved buffer, appending the result to `output`.
+    ///
+    /// `input` must contain whole frames. `output` only allocates if it doesn't have enough spare
+    /// capacity, so reserving room for a few callbacks' worth of frames up front keeps this
+    /// allocation-free inside a stream callback.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        debug_assert_eq!(
+            input.len() % self.channels,
+            0,
+            "input must contain whole frames"
+        );
+        self.pending.extend_from_slice(input);
+        let frames = self.pending.len() / self.channels;
+        let lookahead = self.quality.lookahead();
+
+        while (self.position as usize) + lookahead < frames {
+            let index = self.position as usize;
+            let t = (self.position - index as f64) as f32;
+            for channel in 0..self.channels {
+                let sample = |frame: usize| self.pending[frame * self.channels + channel];
+                let value = match self.quality {
+                    ResampleQuality::Linear => {
+                        let (a, b) = (sample(index), sample(index + 1));
+                        a + (b - a) * t
+                    }
+                    ResampleQuality::Cubic => {
+                        let p0 = sample(index.saturating_sub(1));
+                        let (p1, p2, p3) = (sample(index), sample(index + 1), sample(index + 2));
+                        p1 + 0.5
+                            * t
+                            * (p2 - p0
+                                + t * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3
+                                    + t * (3.0 * (p1 - p2) + p3 - p0)))
+                    }
+                };
+                output.push(value);
+            }
+            self.position += self.step;
+        }
+
+        // Keep one frame before the current position for cubic interpolation.
+        let consumed = (self.position as usize).saturating_sub(1).min(frames);
+        self.pending.drain(..consumed * self.channels);
+        self.position -= consumed as f64;
+    }
+
+    /// Discard any buffered input, e.g. after the stream has been paused.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.position = 0.0;
+    }
+}
+
+#[test]
+fn test_resampler_tone() {
+    // A 1 kHz tone captured at 44.1 kHz, delivered in 10 ms buffers and resampled to 16 kHz.
+    let (from, to) = (44_100, 16_000);
+    for quality in [ResampleQuality::Linear, ResampleQuality::Cubic] {
+        let mut resampler = Resampler::new(1, SampleRate(from), SampleRate(to), quality);
+        let mut output = Vec::new();
+        let tone: Vec<f32> = (0..from)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / from as f32).sin())
+            .collect();
+        for buffer in tone.chunks(441) {
+            resampler.process(buffer, &mut output);
+        }
+
+        // One second in, (almost) one second out.
+        assert!((output.len() as i64 - to as i64).abs() <= 3);
+
+        // Each period of the tone has two zero crossings.
+        let crossings = output
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count();
+        let frequency = crossings as f32 / 2.0 * to as f32 / output.len() as f32;
+        assert!(
+            (frequency - 1000.0).abs() < 5.0,
+            "{:?}: {}",
+            quality,
+            frequency
+        );
+    }
+}
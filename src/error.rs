@@ -65,6 +65,31 @@ impl From<BackendSpecificError> for DevicesError {
     }
 }
 
+/// An error that might occur while looking up the default device of a host.
+///
+/// The absence of a default device is not an error.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DefaultDeviceError {
+    /// See the [`BackendSpecificError`] docs for more information about this error variant.
+    BackendSpecific { err: BackendSpecificError },
+}
+
+impl Display for DefaultDeviceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BackendSpecific { err } => err.fmt(f),
+        }
+    }
+}
+
+impl Error for DefaultDeviceError {}
+
+impl From<BackendSpecificError> for DefaultDeviceError {
+    fn from(err: BackendSpecificError) -> Self {
+        Self::BackendSpecific { err }
+    }
+}
+
 /// An error that may occur while attempting to retrieve a device name.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum DeviceNameError {
@@ -152,7 +177,10 @@ impl From<BackendSpecificError> for DefaultStreamConfigError {
     }
 }
 /// Error that can happen when creating a [`Stream`](crate::Stream).
+///
+/// New variants may be added in minor releases, so matches need a wildcard arm.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum BuildStreamError {
     /// The device no longer exists. This can happen if the device is disconnected while the
     /// program is running.
@@ -166,6 +194,11 @@ pub enum BuildStreamError {
     InvalidArgument,
     /// Occurs if adding a new Stream ID would cause an integer overflow.
     StreamIdOverflow,
+    /// The requested device period can't be used, e.g. because it is shorter than the device's
+    /// minimum period.
+    PeriodNotSupported,
+    /// The device period is fixed by another stream and can't be changed.
+    PeriodLocked,
     /// See the [`BackendSpecificError`] docs for more information about this error variant.
     BackendSpecific { err: BackendSpecificError },
 }
@@ -184,6 +217,12 @@ impl Display for BuildStreamError {
                 "The requested device does not support this capability (invalid argument)",
             ),
             Self::StreamIdOverflow => f.write_str("Adding a new stream ID would cause an overflow"),
+            Self::PeriodNotSupported => {
+                f.write_str("The requested device period is not supported by the device.")
+            }
+            Self::PeriodLocked => {
+                f.write_str("The device period is locked by another stream and can't be changed.")
+            }
         }
     }
 }
@@ -260,12 +299,106 @@ impl From<BackendSpecificError> for PauseStreamError {
     }
 }
 
+/// Errors that might occur when calling [`Stream::close()`](crate::traits::StreamTrait::close).
+///
+/// The stream is released whether or not an error is returned.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CloseStreamError {
+    /// The device associated with the stream is no longer available, e.g. because the stream
+    /// had already stopped after the device was disconnected.
+    DeviceNotAvailable,
+    /// See the [`BackendSpecificError`] docs for more information about this error variant.
+    BackendSpecific { err: BackendSpecificError },
+}
+
+impl Display for CloseStreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BackendSpecific { err } => err.fmt(f),
+            Self::DeviceNotAvailable => {
+                f.write_str("the device associated with the stream is no longer available")
+            }
+        }
+    }
+}
+
+impl Error for CloseStreamError {}
+
+impl From<BackendSpecificError> for CloseStreamError {
+    fn from(err: BackendSpecificError) -> Self {
+        Self::BackendSpecific { err }
+    }
+}
+
+/// Errors that might occur when calling
+/// [`Stream::drain_timeout()`](crate::traits::StreamTrait::drain_timeout).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DrainError {
+    /// The audio queued on the device didn't finish playing within the timeout.
+    TimedOut,
+    /// [`play`](crate::traits::StreamTrait::play) was called before the queued audio finished
+    /// playing, which resumes the stream and cancels the drain.
+    Cancelled,
+    /// The device associated with the stream is no longer available.
+    DeviceNotAvailable,
+    /// See the [`BackendSpecificError`] docs for more information about this error variant.
+    BackendSpecific { err: BackendSpecificError },
+}
+
+impl Display for DrainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BackendSpecific { err } => err.fmt(f),
+            Self::TimedOut => f.write_str("the stream did not finish draining before the timeout"),
+            Self::Cancelled => f.write_str("the stream was resumed before it finished draining"),
+            Self::DeviceNotAvailable => {
+                f.write_str("the device associated with the stream is no longer available")
+            }
+        }
+    }
+}
+
+impl Error for DrainError {}
+
+impl From<BackendSpecificError> for DrainError {
+    fn from(err: BackendSpecificError) -> Self {
+        Self::BackendSpecific { err }
+    }
+}
+
 /// Errors that might occur while a stream is running.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum StreamError {
     /// The device no longer exists. This can happen if the device is disconnected while the
     /// program is running.
     DeviceNotAvailable,
+    /// Another application took the device over, e.g. by opening it for its exclusive use.
+    ///
+    /// If `resumable` is true, the stream is paused until the device is released again, which is
+    /// reported as [`Resumed`](Self::Resumed). Otherwise the stream can't be used anymore and
+    /// has to be built anew once the device is available again.
+    ///
+    /// Reported by WASAPI when a shared-mode stream is disconnected for an exclusive-mode one
+    /// (never resumable), and by CoreAudio on macOS when another process takes the device in hog
+    /// mode (resumable).
+    Interrupted { resumable: bool },
+    /// The interruption of the stream reported by [`Interrupted`](Self::Interrupted) ended. If
+    /// the stream was playing when it was interrupted, it plays again.
+    ///
+    /// This isn't an error, but the error callback is the stream's only way of reaching the
+    /// application outside of its data callback.
+    Resumed,
+    /// The format of the device was changed by the system or the user while the stream was
+    /// running, e.g. its sample rate in the system's sound settings, and the stream can't
+    /// continue with the config it was built with.
+    ///
+    /// The stream is stopped and has to be built anew with a config queried again from the
+    /// device, e.g. with [`rebuild_output_stream`](crate::recovery::rebuild_output_stream).
+    ///
+    /// Reported by WASAPI when a shared-mode stream is disconnected because of a format change,
+    /// and by CoreAudio on macOS when the sample rate of a device an input stream is capturing
+    /// from changes.
+    DeviceInvalidated,
     /// See the [`BackendSpecificError`] docs for more information about this error variant.
     BackendSpecific { err: BackendSpecificError },
 }
@@ -277,14 +410,126 @@ impl Display for StreamError {
             Self::DeviceNotAvailable => f.write_str(
                 "The requested device is no longer available. For example, it has been unplugged.",
             ),
+            Self::Interrupted { resumable: true } => f.write_str(
+                "The stream was interrupted by another application using the device. It resumes once the device is released.",
+            ),
+            Self::Interrupted { resumable: false } => f.write_str(
+                "The stream was interrupted by another application using the device and can't be resumed.",
+            ),
+            Self::Resumed => f.write_str("The interruption of the stream ended."),
+            Self::DeviceInvalidated => f.write_str(
+                "The format of the device changed while the stream was running. The stream has to be built again.",
+            ),
         }
     }
 }
 
 impl Error for StreamError {}
 
+impl StreamError {
+    // Whether the stream can't play or capture anymore after this error, as opposed to errors it
+    // goes on after.
+    pub(crate) fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::DeviceNotAvailable
+                | Self::Interrupted { resumable: false }
+                | Self::DeviceInvalidated
+        )
+    }
+}
+
 impl From<BackendSpecificError> for StreamError {
     fn from(err: BackendSpecificError) -> Self {
         Self::BackendSpecific { err }
     }
 }
+
+/// Error that can happen in [`DeviceTrait::play_buffer`](crate::traits::DeviceTrait::play_buffer).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PlayBufferError {
+    /// The stream couldn't be built.
+    Build(BuildStreamError),
+    /// The stream couldn't be started.
+    Play(PlayStreamError),
+    /// The stream stopped before the buffer had been played.
+    Stream(StreamError),
+}
+
+impl Display for PlayBufferError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Build(err) => err.fmt(f),
+            Self::Play(err) => err.fmt(f),
+            Self::Stream(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for PlayBufferError {}
+
+impl From<BuildStreamError> for PlayBufferError {
+    fn from(err: BuildStreamError) -> Self {
+        Self::Build(err)
+    }
+}
+
+impl From<PlayStreamError> for PlayBufferError {
+    fn from(err: PlayStreamError) -> Self {
+        Self::Play(err)
+    }
+}
+
+impl From<StreamError> for PlayBufferError {
+    fn from(err: StreamError) -> Self {
+        Self::Stream(err)
+    }
+}
+
+/// Error that can happen in [`DeviceTrait::refresh`](crate::traits::DeviceTrait::refresh).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RefreshDeviceError {
+    /// The device is no longer available.
+    DeviceNotAvailable,
+    /// See the [`BackendSpecificError`] docs for more information about this error variant.
+    BackendSpecific { err: BackendSpecificError },
+}
+
+impl Display for RefreshDeviceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BackendSpecific { err } => err.fmt(f),
+            Self::DeviceNotAvailable => f.write_str("the device is no longer available"),
+        }
+    }
+}
+
+impl Error for RefreshDeviceError {}
+
+impl From<BackendSpecificError> for RefreshDeviceError {
+    fn from(err: BackendSpecificError) -> Self {
+        Self::BackendSpecific { err }
+    }
+}
+
+/// Error that can happen in [`negotiate_config_with_formats`](crate::negotiate_config_with_formats).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NegotiateConfigError {
+    /// There were no supported configurations to choose from.
+    NoConfigs,
+    /// None of the supported configurations has one of the acceptable sample formats.
+    NoAcceptableFormat,
+}
+
+impl Display for NegotiateConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoConfigs => f.write_str("the device has no supported configurations"),
+            Self::NoAcceptableFormat => {
+                f.write_str("the device supports none of the acceptable sample formats")
+            }
+        }
+    }
+}
+
+impl Error for NegotiateConfigError {}
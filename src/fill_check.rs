@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{Data, SampleFormat};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// Written over output buffers before the data callback. Float samples get a NaN that audio never
+// contains, integer samples a byte pattern a callback is unlikely to produce.
+const FILL_BYTE: u8 = 0xa5;
+const FILL_F32: u32 = 0x7fa5_a5a5;
+const FILL_F64: u64 = 0x7ff4_a5a5_a5a5_a5a5;
+
+/// In debug builds, panic when the data callback of an output stream leaves part of its buffer
+/// unwritten. Disabled by default.
+///
+/// Hosts hand the callback buffers that still hold earlier audio or garbage, so a callback that
+/// fills less than the whole buffer plays noise rather than failing. With the check enabled,
+/// buffers are filled with a marker before the callback runs and checked after it returns. An
+/// integer sample the callback writes with exactly the marker's bytes is mistaken for an
+/// unwritten one, which is unlikely but possible for 8 and 16 bit formats.
+///
+/// Only streams built through a [`Device`](crate::Device) are checked. In release builds this has
+/// no effect and the streams don't pay for the check.
+pub fn set_output_fill_check(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn enabled() -> bool {
+    cfg!(debug_assertions) && ENABLED.load(Ordering::Relaxed)
+}
+
+// Mark every sample of `data` as unwritten.
+pub(crate) fn mark(data: &mut Data) {
+    let sample_format = data.sample_format();
+    let bytes = data.bytes_mut();
+    match sample_format {
+        SampleFormat::F32 => {
+            for sample in bytes.chunks_exact_mut(4) {
+                sample.copy_from_slice(&FILL_F32.to_ne_bytes());
+            }
+        }
+        SampleFormat::F64 => {
+            for sample in bytes.chunks_exact_mut(8) {
+                sample.copy_from_slice(&FILL_F64.to_ne_bytes());
+            }
+        }
+        _ => bytes.fill(FILL_BYTE),
+    }
+}
+
+// Panic if a sample of `data` is still marked as unwritten.
+pub(crate) fn assert_filled(data: &Data) {
+    let sample_format = data.sample_format();
+    let size = sample_format.sample_size();
+    let unwritten = data
+        .bytes()
+        .chunks_exact(size)
+        .position(|sample| match sample_format {
+            SampleFormat::F32 => sample == FILL_F32.to_ne_bytes(),
+            SampleFormat::F64 => sample == FILL_F64.to_ne_bytes(),
+            _ => sample.iter().all(|&byte| byte == FILL_BYTE),
+        });
+    if let Some(index) = unwritten {
+        panic!(
+            "the output data callback left sample {} of {} unwritten",
+            index,
+            data.len()
+        );
+    }
+}
+
+#[test]
+fn test_fill_check() {
+    let mut samples = [0.0f32; 4];
+    let mut data =
+        unsafe { Data::from_parts(samples.as_mut_ptr() as *mut (), 4, SampleFormat::F32) };
+    mark(&mut data);
+    data.as_slice_mut::<f32>().unwrap()[..3].fill(0.5);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| assert_filled(&data)));
+    assert!(result.is_err());
+    data.as_slice_mut::<f32>().unwrap()[3] = f32::NAN;
+    assert_filled(&data);
+
+    let mut samples = [0i16; 2];
+    let mut data =
+        unsafe { Data::from_parts(samples.as_mut_ptr() as *mut (), 2, SampleFormat::I16) };
+    mark(&mut data);
+    data.as_slice_mut::<i16>().unwrap().fill(0);
+    assert_filled(&data);
+}
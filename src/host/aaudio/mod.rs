@@ -39,6 +39,15 @@ const SAMPLE_RATES: [i32; 13] = [
 pub struct Host;
 #[derive(Clone)]
 pub struct Device(Option<AudioDeviceInfo>);
+
+// Devices are identified by their ID alone; `None` is the default device.
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_ref().map(|info| info.id) == other.0.as_ref().map(|info| info.id)
+    }
+}
+
+impl Eq for Device {}
 pub enum Stream {
     Input(AudioStream),
     Output(AudioStream),
@@ -238,11 +247,15 @@ where
     let channel_count = config.channels as i32;
     let stream = builder
         .data_callback(Box::new(move |stream, data, num_frames| {
+            if num_frames == 0 {
+                return ndk::audio::AudioCallbackResult::Continue;
+            }
             let cb_info = InputCallbackInfo {
                 timestamp: InputStreamTimestamp {
                     callback: to_stream_instant(created.elapsed()),
                     capture: stream_instant(stream),
                 },
+                discontinuity: false,
             };
             (data_callback)(
                 &unsafe {
@@ -280,6 +293,9 @@ where
     let channel_count = config.channels as i32;
     let stream = builder
         .data_callback(Box::new(move |stream, data, num_frames| {
+            if num_frames == 0 {
+                return ndk::audio::AudioCallbackResult::Continue;
+            }
             let cb_info = OutputCallbackInfo {
                 timestamp: OutputStreamTimestamp {
                     callback: to_stream_instant(created.elapsed()),
@@ -0,0 +1,270 @@
+use super::alsa;
+use super::{configs_error, DeviceHandles, FORMATS};
+use crate::{
+    ChannelCount, SampleRate, SupportedBufferSize, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
+};
+use std::cmp;
+use std::sync::{Arc, Mutex};
+
+// The rates tested when a device only supports some of the rates between its minimum and
+// maximum.
+const RATES: [libc::c_uint; 13] = [
+    5512, 8000, 11025, 16000, 22050, 32000, 44100, 48000, 64000, 88200, 96000, 176400, 192000,
+];
+
+// The highest channel count tested, as some plugins claim to support any number of channels.
+const MAX_CHANNELS: libc::c_uint = 32;
+
+/// The configurations supported by a device, probed lazily.
+///
+/// Each call to `next` only tests the parts of the next combination of sample format, channel
+/// count and sample rate that haven't been tested yet, so taking the first suitable
+/// configuration and dropping the iterator skips the rest of the probing. Unlike the platform's
+/// [`SupportedOutputConfigs`](crate::SupportedOutputConfigs), the configurations are yielded
+/// by sample format, then channel count, then rate, rather than sorted. The platform's
+/// [`Device::supported_output_configs_lazy`](crate::Device::supported_output_configs_lazy)
+/// passes them on as they are probed.
+///
+/// Probing locks the device's handles for the duration of each test, so it briefly contends
+/// with building a stream on the same device. If a stream has taken the device's handle in the
+/// meantime, the device is reopened to continue probing. If the device can't be reopened (e.g.
+/// because it has been unplugged or a stream holds it exclusively), iteration ends early and the
+/// error is kept for [`take_error`](Self::take_error).
+pub struct SupportedConfigs {
+    handles: Arc<Mutex<DeviceHandles>>,
+    pcm_id: String,
+    direction: alsa::Direction,
+    buffer_size: SupportedBufferSize,
+    rate_range: (libc::c_uint, libc::c_uint),
+    channels: Probed<libc::c_uint>,
+    rates: Probed<(libc::c_uint, libc::c_uint)>,
+    // Whether `rates` holds the rates of `RATES`, which fall back to `rate_range` if none of
+    // them is supported.
+    discrete_rates: bool,
+    // The position of the next combination.
+    format: usize,
+    format_supported: Option<bool>,
+    channel: usize,
+    rate: usize,
+    // The failure that ended the probing early.
+    error: Option<SupportedStreamConfigsError>,
+}
+
+// The candidates of one dimension of the configurations, each tested once on first use.
+struct Probed<T> {
+    candidates: Vec<T>,
+    supported: Vec<Option<bool>>,
+}
+
+impl<T: Copy> Probed<T> {
+    fn new(candidates: Vec<T>) -> Self {
+        let supported = vec![None; candidates.len()];
+        Probed {
+            candidates,
+            supported,
+        }
+    }
+
+    fn is_supported<E>(
+        &mut self,
+        index: usize,
+        test: impl FnOnce(T) -> Result<bool, E>,
+    ) -> Result<bool, E> {
+        if let Some(supported) = self.supported[index] {
+            return Ok(supported);
+        }
+        let supported = test(self.candidates[index])?;
+        self.supported[index] = Some(supported);
+        Ok(supported)
+    }
+}
+
+impl SupportedConfigs {
+    // Read the ranges of the device's parameters, without testing any of them yet.
+    pub(super) fn new(
+        handles: Arc<Mutex<DeviceHandles>>,
+        pcm_id: String,
+        direction: alsa::Direction,
+        handle: &alsa::PCM,
+    ) -> Result<Self, alsa::Error> {
+        let hw_params = alsa::pcm::HwParams::any(handle)?;
+        let min_rate = hw_params.get_rate_min()?;
+        let max_rate = hw_params.get_rate_max()?;
+        let continuous = min_rate == max_rate || hw_params.test_rate(min_rate + 1).is_ok();
+        let rates = if continuous {
+            vec![(min_rate, max_rate)]
+        } else {
+            RATES.iter().map(|&rate| (rate, rate)).collect()
+        };
+        let min_channels = hw_params.get_channels_min()?;
+        let max_channels = cmp::min(hw_params.get_channels_max()?, MAX_CHANNELS);
+        let buffer_size = SupportedBufferSize::Range {
+            min: hw_params.get_buffer_size_min()? as u32,
+            max: hw_params.get_buffer_size_max()? as u32,
+        };
+        let mut rates = Probed::new(rates);
+        if continuous {
+            rates.supported[0] = Some(true);
+        }
+        Ok(SupportedConfigs {
+            handles,
+            pcm_id,
+            direction,
+            buffer_size,
+            rate_range: (min_rate, max_rate),
+            channels: Probed::new((min_channels..=max_channels).collect()),
+            rates,
+            discrete_rates: !continuous,
+            format: 0,
+            format_supported: None,
+            channel: 0,
+            rate: 0,
+            error: None,
+        })
+    }
+
+    /// The error that ended the iteration early, if the device failed while it was probed.
+    ///
+    /// Returns `None` once the error has been taken, and if all combinations were probed.
+    pub fn take_error(&mut self) -> Option<SupportedStreamConfigsError> {
+        self.error.take()
+    }
+
+    // Run `test` on the parameters of the device, reopening it if a stream took the handle.
+    fn probe(
+        handles: &Mutex<DeviceHandles>,
+        pcm_id: &str,
+        direction: alsa::Direction,
+        test: impl FnOnce(&alsa::pcm::HwParams) -> bool,
+    ) -> Result<bool, alsa::Error> {
+        let mut guard = handles.lock().unwrap();
+        let handle = guard.get_mut(pcm_id, direction)?;
+        let hw_params = alsa::pcm::HwParams::any(handle)?;
+        Ok(test(&hw_params))
+    }
+
+    // Probe up to the next supported combination.
+    fn advance(&mut self) -> Result<Option<SupportedStreamConfigRange>, alsa::Error> {
+        let SupportedConfigs {
+            ref handles,
+            ref pcm_id,
+            direction,
+            ..
+        } = *self;
+        let probe = |test: &dyn Fn(&alsa::pcm::HwParams) -> bool| {
+            Self::probe(handles, pcm_id, direction, test)
+        };
+        loop {
+            let &(sample_format, alsa_format) = match FORMATS.get(self.format) {
+                Some(format) => format,
+                None => return Ok(None),
+            };
+            let format_supported = match self.format_supported {
+                Some(supported) => supported,
+                None => probe(&|hw| hw.test_format(alsa_format).is_ok())?,
+            };
+            self.format_supported = Some(format_supported);
+            if !format_supported || self.channel == self.channels.candidates.len() {
+                self.format += 1;
+                self.format_supported = None;
+                self.channel = 0;
+                continue;
+            }
+            let channels_supported = self.channels.is_supported(self.channel, |channels| {
+                probe(&|hw| hw.test_channels(channels).is_ok())
+            })?;
+            if !channels_supported {
+                self.channel += 1;
+                continue;
+            }
+            if self.rate == self.rates.candidates.len() {
+                // None of the common rates is accepted, so report the whole range instead.
+                if self.discrete_rates && self.rates.supported.iter().all(|s| *s == Some(false)) {
+                    self.rates = Probed::new(vec![self.rate_range]);
+                    self.rates.supported[0] = Some(true);
+                    self.discrete_rates = false;
+                    self.rate = 0;
+                    continue;
+                }
+                self.channel += 1;
+                self.rate = 0;
+                continue;
+            }
+            let rate_supported = self.rates.is_supported(self.rate, |(rate, _)| {
+                probe(&|hw| hw.test_rate(rate).is_ok())
+            })?;
+            let (min_rate, max_rate) = self.rates.candidates[self.rate];
+            self.rate += 1;
+            if !rate_supported {
+                continue;
+            }
+            return Ok(Some(SupportedStreamConfigRange {
+                channels: self.channels.candidates[self.channel] as ChannelCount,
+                min_sample_rate: SampleRate(min_rate),
+                max_sample_rate: SampleRate(max_rate),
+                buffer_size: self.buffer_size,
+                sample_format,
+            }));
+        }
+    }
+}
+
+impl Iterator for SupportedConfigs {
+    type Item = SupportedStreamConfigRange;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.advance() {
+            Ok(config) => config,
+            Err(err) => {
+                // Nothing more is probed after a failure.
+                self.format = FORMATS.len();
+                self.error = Some(configs_error(err));
+                None
+            }
+        }
+    }
+}
+
+impl crate::host::ProbedConfigs for SupportedConfigs {
+    fn take_error(&mut self) -> Option<SupportedStreamConfigsError> {
+        SupportedConfigs::take_error(self)
+    }
+}
+
+#[test]
+fn test_probing_reopens_the_device() {
+    // The device's handle was taken by a stream, so probing reopens it.
+    let handle = alsa::PCM::new("null", alsa::Direction::Playback, true).unwrap();
+    let handles = Arc::new(Mutex::new(DeviceHandles::default()));
+    let mut configs = SupportedConfigs::new(
+        handles,
+        "null".to_string(),
+        alsa::Direction::Playback,
+        &handle,
+    )
+    .unwrap();
+    assert!(configs.next().is_some());
+    assert!(configs.take_error().is_none());
+}
+
+#[test]
+fn test_probing_reports_reopen_failures() {
+    let handle = alsa::PCM::new("null", alsa::Direction::Playback, true).unwrap();
+    let handles = Arc::new(Mutex::new(DeviceHandles::default()));
+    let mut configs = SupportedConfigs::new(
+        handles,
+        "cpal_test_missing".to_string(),
+        alsa::Direction::Playback,
+        &handle,
+    )
+    .unwrap();
+    assert!(configs.next().is_none());
+    assert_eq!(
+        configs.take_error(),
+        Some(SupportedStreamConfigsError::DeviceNotAvailable)
+    );
+    // Iteration stays ended.
+    assert!(configs.next().is_none());
+    assert!(configs.take_error().is_none());
+}
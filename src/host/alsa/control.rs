@@ -0,0 +1,158 @@
+// The control plane between a stream's methods and its worker thread.
+//
+// `play`, `pause` and `drain_timeout` don't touch the device themselves. They push a
+// `Command` onto a single-producer, single-consumer queue and wake the worker up, which pops
+// the commands at the top of each iteration and issues the device calls itself. The senders
+// take turns through a lock of their own, so the worker never waits on a control thread. The
+// worker publishes the state it applied in an atomic state word, which control methods read
+// without involving it.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+
+// The states of the state word, as last applied by the worker.
+pub(super) const RUNNING: u8 = 0;
+// Paused, in hardware or by stopping a device that can't pause with its unplayed frames kept.
+pub(super) const PAUSED: u8 = 1;
+// Waiting for the queued audio to play out, after which the device is stopped.
+pub(super) const DRAINING: u8 = 2;
+// Stopped once the queued audio played out, waiting for `play`.
+pub(super) const DRAINED: u8 = 3;
+
+// How many commands can be queued before the senders wait for the worker.
+pub(super) const COMMAND_CAPACITY: usize = 64;
+
+pub(super) enum Command {
+    Play,
+    Pause,
+    // Notify the sender once the queued audio has played out, or drop it if `play` cancels the
+    // drain first.
+    Drain(mpsc::Sender<()>),
+}
+
+// A bounded queue of items passed from one thread to another without locking.
+struct Queue<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // The number of items popped and pushed so far. Only the receiver stores `head` and only the
+    // sender stores `tail`, so each can read its own without synchronizing.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// The sender and receiver never access the same slot at the same time.
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        let tail = *self.tail.get_mut();
+        for index in *self.head.get_mut()..tail {
+            let slot = self.slots[index % self.slots.len()].get_mut();
+            unsafe { slot.assume_init_drop() };
+        }
+    }
+}
+
+// The sending half of a command queue. There can only be one at a time, so senders on several
+// threads share it behind a lock.
+pub(super) struct Sender<T>(Arc<Queue<T>>);
+
+// The receiving half of a command queue, owned by the worker.
+pub(super) struct Receiver<T>(Arc<Queue<T>>);
+
+pub(super) fn queue<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "queue capacity must be non-zero");
+    let queue = Arc::new(Queue {
+        slots: (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (Sender(queue.clone()), Receiver(queue))
+}
+
+impl<T> Sender<T> {
+    // Queue `item`, or hand it back if the queue is full.
+    pub(super) fn push(&mut self, item: T) -> Result<(), T> {
+        let queue = &*self.0;
+        let tail = queue.tail.load(Ordering::Relaxed);
+        if tail - queue.head.load(Ordering::Acquire) == queue.slots.len() {
+            return Err(item);
+        }
+        unsafe { (*queue.slots[tail % queue.slots.len()].get()).write(item) };
+        queue.tail.store(tail + 1, Ordering::Release);
+        Ok(())
+    }
+
+    // Whether the receiver has popped every item pushed so far.
+    pub(super) fn is_empty(&self) -> bool {
+        let queue = &*self.0;
+        queue.head.load(Ordering::Acquire) == queue.tail.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Receiver<T> {
+    // The oldest item in the queue, if any.
+    pub(super) fn pop(&mut self) -> Option<T> {
+        let queue = &*self.0;
+        let head = queue.head.load(Ordering::Relaxed);
+        if head == queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let item = unsafe { (*queue.slots[head % queue.slots.len()].get()).assume_init_read() };
+        queue.head.store(head + 1, Ordering::Release);
+        Some(item)
+    }
+}
+
+#[test]
+fn test_queue() {
+    let (mut tx, mut rx) = queue(4);
+    assert!(tx.is_empty());
+    assert_eq!(rx.pop(), None);
+    for i in 0..4 {
+        tx.push(i).unwrap();
+    }
+    assert_eq!(tx.push(4), Err(4));
+    assert_eq!(rx.pop(), Some(0));
+    assert!(!tx.is_empty());
+    tx.push(4).unwrap();
+    for i in 1..5 {
+        assert_eq!(rx.pop(), Some(i));
+    }
+    assert!(tx.is_empty());
+
+    // Items left in the queue are dropped with it.
+    let item = Arc::new(());
+    let (mut tx, rx) = queue(2);
+    tx.push(item.clone()).unwrap();
+    drop((tx, rx));
+    assert_eq!(Arc::strong_count(&item), 1);
+}
+
+#[test]
+fn test_queue_across_threads() {
+    let (mut tx, mut rx) = queue(8);
+    let sender = std::thread::spawn(move || {
+        for mut i in 0..100_000u32 {
+            while let Err(rejected) = tx.push(i) {
+                i = rejected;
+                std::thread::yield_now();
+            }
+        }
+    });
+    let mut expected = 0;
+    while expected < 100_000 {
+        match rx.pop() {
+            Some(i) => {
+                assert_eq!(i, expected);
+                expected += 1;
+            }
+            None => std::thread::yield_now(),
+        }
+    }
+    sender.join().unwrap();
+    assert_eq!(rx.pop(), None);
+}
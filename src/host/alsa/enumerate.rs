@@ -37,6 +37,7 @@ impl Iterator for Devices {
                     name: name.to_string(),
                     pcm_id: name.to_string(),
                     handles: Arc::new(Mutex::new(handles)),
+                    wakeup_granularity: None,
                 });
             }
         }
@@ -66,6 +67,7 @@ impl Iterator for Devices {
             // ...
             // For now, many hardware only support 24bit / 3 bytes, which isn't yet supported by
             // cpal. So we have to enable plughw (unfortunately) for maximum compatibility.
+            // `Device::with_plug` opts out of it.
             const USE_PLUGHW: bool = true;
             let pcm_id = if USE_PLUGHW {
                 format!("plughw:{}", card.get_index())
@@ -77,6 +79,7 @@ impl Iterator for Devices {
                     name: card_name.to_string(),
                     pcm_id: pcm_id.to_string(),
                     handles: Arc::new(Mutex::new(handles)),
+                    wakeup_granularity: None,
                 });
             }
         }
@@ -89,6 +92,7 @@ pub fn default_input_device() -> Option<Device> {
         name: "default".to_owned(),
         pcm_id: "default".to_owned(),
         handles: Arc::new(Mutex::new(Default::default())),
+        wakeup_granularity: None,
     })
 }
 
@@ -98,6 +102,7 @@ pub fn default_output_device() -> Option<Device> {
         name: "default".to_owned(),
         pcm_id: "default".to_owned(),
         handles: Arc::new(Mutex::new(Default::default())),
+        wakeup_granularity: None,
     })
 }
 
@@ -0,0 +1,51 @@
+/*
+ * ALSA reports errors through a printf-style handler, which can't be written in Rust as Rust
+ * can't read C variadic arguments. This handler expands the message and passes it on to the
+ * function set with `cpal_alsa_set_log_fn`.
+ */
+
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef void (*cpal_alsa_log_fn)(const char *file, int line, const char *function, int err,
+                                 const char *message);
+
+static cpal_alsa_log_fn log_fn;
+
+void cpal_alsa_set_log_fn(cpal_alsa_log_fn fn) { log_fn = fn; }
+
+void cpal_alsa_error_handler(const char *file, int line, const char *function, int err,
+                             const char *fmt, ...) {
+    char buffer[512];
+    char *message = buffer;
+    va_list args;
+    int len;
+
+    if (!log_fn) {
+        return;
+    }
+
+    va_start(args, fmt);
+    len = vsnprintf(buffer, sizeof(buffer), fmt, args);
+    va_end(args);
+    if (len < 0) {
+        buffer[0] = '\0';
+    } else if ((size_t)len >= sizeof(buffer)) {
+        /* Too long for the buffer, so expand it again into one that fits, or keep it cut short
+         * if that can't be allocated. */
+        char *allocated = malloc((size_t)len + 1);
+        if (allocated) {
+            va_start(args, fmt);
+            vsnprintf(allocated, (size_t)len + 1, fmt, args);
+            va_end(args);
+            message = allocated;
+        }
+    }
+
+    log_fn(file, line, function, err, message);
+
+    if (message != buffer) {
+        free(message);
+    }
+}
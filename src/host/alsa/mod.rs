@@ -2,28 +2,36 @@ extern crate alsa;
 extern crate libc;
 
 use self::alsa::poll::Descriptors;
+use self::control::Command;
+use crate::diagnostics::{self, DiagnosticKind};
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
-    BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, Data,
-    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo,
-    PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, CloseStreamError, Data,
+    DefaultStreamConfigError, DeviceNameError, DevicesError, DrainError, FrameCount,
+    InputCallbackInfo, OsProcessing, OutputCallbackInfo, PauseStreamError, PlayStreamError,
+    PowerPreference, RefreshDeviceError, SampleFormat, SampleRate, StreamConfig, StreamError,
+    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigsError,
 };
-use std::cell::Cell;
-use std::cmp;
+use std::collections::VecDeque;
 use std::convert::TryInto;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
-use std::vec::IntoIter as VecIntoIter;
+use std::time::{Duration, Instant};
 
+pub use self::configs::SupportedConfigs;
 pub use self::enumerate::{default_input_device, default_output_device, Devices};
+pub use self::volume::{MixerVolume, VolumeListener};
 
-pub type SupportedInputConfigs = VecIntoIter<SupportedStreamConfigRange>;
-pub type SupportedOutputConfigs = VecIntoIter<SupportedStreamConfigRange>;
+pub type SupportedInputConfigs = SupportedConfigs;
+pub type SupportedOutputConfigs = SupportedConfigs;
 
+mod configs;
+mod control;
 mod enumerate;
+mod volume;
 
 /// The default linux, dragonfly, freebsd and netbsd host type.
 #[derive(Debug)]
@@ -66,6 +74,14 @@ impl DeviceTrait for Device {
         Device::name(self)
     }
 
+    fn adapter_id(&self) -> Option<String> {
+        self.card().map(str::to_owned)
+    }
+
+    fn refresh(&mut self) -> Result<(), RefreshDeviceError> {
+        Device::refresh(self)
+    }
+
     fn supported_input_configs(
         &self,
     ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
@@ -86,6 +102,30 @@ impl DeviceTrait for Device {
         Device::default_output_config(self)
     }
 
+    fn current_input_config(&self) -> Option<SupportedStreamConfig> {
+        self.current_config(alsa::Direction::Capture)
+    }
+
+    fn current_output_config(&self) -> Option<SupportedStreamConfig> {
+        self.current_config(alsa::Direction::Playback)
+    }
+
+    fn backend_info(&self) -> Option<String> {
+        match self.card_info() {
+            Some(card) => Some(format!("PCM {} on {}", self.pcm_id, card)),
+            None => Some(format!("PCM {}", self.pcm_id)),
+        }
+    }
+
+    fn physical_location(&self) -> Option<String> {
+        Device::physical_location(self)
+    }
+
+    fn has_hardware_volume(&self) -> bool {
+        self.mixer_elements()
+            .is_ok_and(|elements| !elements.is_empty())
+    }
+
     fn build_input_stream_raw<D, E>(
         &self,
         conf: &StreamConfig,
@@ -133,6 +173,85 @@ impl DeviceTrait for Device {
     }
 }
 
+// The signature ALSA calls its error handler with: the place the error was raised, and a
+// printf-style message.
+type ErrorHandler = unsafe extern "C" fn(
+    file: *const libc::c_char,
+    line: libc::c_int,
+    function: *const libc::c_char,
+    err: libc::c_int,
+    fmt: *const libc::c_char,
+    ...
+);
+
+// The handler after it has expanded the message.
+type LogFn = unsafe extern "C" fn(
+    file: *const libc::c_char,
+    line: libc::c_int,
+    function: *const libc::c_char,
+    err: libc::c_int,
+    message: *const libc::c_char,
+);
+
+extern "C" {
+    fn snd_lib_error_set_handler(handler: Option<ErrorHandler>) -> libc::c_int;
+
+    // Defined in `error_handler.c`, as Rust can't read the variadic arguments of the message.
+    fn cpal_alsa_set_log_fn(log_fn: Option<LogFn>);
+    pub(crate) fn cpal_alsa_error_handler(
+        file: *const libc::c_char,
+        line: libc::c_int,
+        function: *const libc::c_char,
+        err: libc::c_int,
+        fmt: *const libc::c_char,
+        ...
+    );
+}
+
+unsafe extern "C" fn log_error(
+    file: *const libc::c_char,
+    line: libc::c_int,
+    function: *const libc::c_char,
+    err: libc::c_int,
+    message: *const libc::c_char,
+) {
+    let file = match file.is_null() {
+        true => "alsa-lib".into(),
+        false => std::ffi::CStr::from_ptr(file).to_string_lossy(),
+    };
+    let mut text = format!("{}:{}", file, line);
+    if !function.is_null() {
+        let function = std::ffi::CStr::from_ptr(function).to_string_lossy();
+        text = format!("{}: {}", text, function);
+    }
+    if !message.is_null() {
+        let message = std::ffi::CStr::from_ptr(message).to_string_lossy();
+        if !message.is_empty() {
+            text = format!("{}: {}", text, message);
+        }
+    }
+    if err != 0 {
+        let description = std::ffi::CStr::from_ptr(libc::strerror(err)).to_string_lossy();
+        text = format!("{}: {}", text, description);
+    }
+    let level = match err {
+        0 => crate::LogLevel::Warning,
+        _ => crate::LogLevel::Error,
+    };
+    // A panic can't unwind into alsa-lib.
+    let _ = std::panic::catch_unwind(|| crate::log::log(level, &text));
+}
+
+// Install an ALSA error handler that forwards messages to the user's log callback instead of
+// printing them on stderr.
+pub(crate) fn route_log_messages() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| unsafe {
+        cpal_alsa_set_log_fn(Some(log_error));
+        snd_lib_error_set_handler(Some(cpal_alsa_error_handler));
+    });
+}
+
 struct TriggerSender(libc::c_int);
 
 struct TriggerReceiver(libc::c_int);
@@ -140,8 +259,9 @@ struct TriggerReceiver(libc::c_int);
 impl TriggerSender {
     fn wakeup(&self) {
         let buf = 1u64;
-        let ret = unsafe { libc::write(self.0, &buf as *const u64 as *const _, 8) };
-        assert_eq!(ret, 8);
+        // The worker may already have exited (e.g. after the device was unplugged) and closed
+        // the read end of the pipe, in which case there is no one left to wake up.
+        unsafe { libc::write(self.0, &buf as *const u64 as *const _, 8) };
     }
 }
 
@@ -235,14 +355,178 @@ impl DeviceHandles {
     }
 }
 
+// The sample formats supported by cpal and their ALSA equivalents.
+// TODO: check endianness
+const FORMATS: [(SampleFormat, alsa::pcm::Format); 8] = [
+    (SampleFormat::I8, alsa::pcm::Format::S8),
+    (SampleFormat::U8, alsa::pcm::Format::U8),
+    (SampleFormat::I16, alsa::pcm::Format::S16LE),
+    //SND_PCM_FORMAT_S16_BE,
+    (SampleFormat::U16, alsa::pcm::Format::U16LE),
+    //SND_PCM_FORMAT_U16_BE,
+    //SND_PCM_FORMAT_S24_LE,
+    //SND_PCM_FORMAT_S24_BE,
+    //SND_PCM_FORMAT_U24_LE,
+    //SND_PCM_FORMAT_U24_BE,
+    (SampleFormat::I32, alsa::pcm::Format::S32LE),
+    //SND_PCM_FORMAT_S32_BE,
+    (SampleFormat::U32, alsa::pcm::Format::U32LE),
+    //SND_PCM_FORMAT_U32_BE,
+    (SampleFormat::F32, alsa::pcm::Format::FloatLE),
+    //SND_PCM_FORMAT_FLOAT_BE,
+    (SampleFormat::F64, alsa::pcm::Format::Float64LE),
+    //SND_PCM_FORMAT_FLOAT64_BE,
+    //SND_PCM_FORMAT_IEC958_SUBFRAME_LE,
+    //SND_PCM_FORMAT_IEC958_SUBFRAME_BE,
+    //SND_PCM_FORMAT_MU_LAW,
+    //SND_PCM_FORMAT_A_LAW,
+    //SND_PCM_FORMAT_IMA_ADPCM,
+    //SND_PCM_FORMAT_MPEG,
+    //SND_PCM_FORMAT_GSM,
+    //SND_PCM_FORMAT_SPECIAL,
+    //SND_PCM_FORMAT_S24_3LE,
+    //SND_PCM_FORMAT_S24_3BE,
+    //SND_PCM_FORMAT_U24_3LE,
+    //SND_PCM_FORMAT_U24_3BE,
+    //SND_PCM_FORMAT_S20_3LE,
+    //SND_PCM_FORMAT_S20_3BE,
+    //SND_PCM_FORMAT_U20_3LE,
+    //SND_PCM_FORMAT_U20_3BE,
+    //SND_PCM_FORMAT_S18_3LE,
+    //SND_PCM_FORMAT_S18_3BE,
+    //SND_PCM_FORMAT_U18_3LE,
+    //SND_PCM_FORMAT_U18_3BE,
+];
+
 #[derive(Clone)]
 pub struct Device {
     name: String,
     pcm_id: String,
     handles: Arc<Mutex<DeviceHandles>>,
+    // The fewest frames streams built from this device should be woken up for.
+    wakeup_granularity: Option<FrameCount>,
+}
+
+// Devices are identified by their PCM name alone; the card name, the open handles and the
+// stream settings are only cached or carried along.
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.pcm_id == other.pcm_id
+    }
+}
+
+impl Eq for Device {}
+
+impl Hash for Device {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pcm_id.hash(state);
+    }
 }
 
 impl Device {
+    // The name or index of the sound card the device belongs to, e.g. `PCH` for
+    // `front:CARD=PCH,DEV=0` or `0` for `plughw:0`. Plugin devices such as `default` don't name a
+    // card.
+    pub(crate) fn card(&self) -> Option<&str> {
+        let rest = match self.pcm_id.split_once("CARD=") {
+            Some((_, rest)) => rest,
+            None => self
+                .pcm_id
+                .strip_prefix("plughw:")
+                .or_else(|| self.pcm_id.strip_prefix("hw:"))?,
+        };
+        rest.split(',').next()
+    }
+
+    // The driver and long name of the sound card behind the device, e.g.
+    // `HDA-Intel (HDA Intel PCH at 0xf7f10000 irq 33)`.
+    fn card_info(&self) -> Option<String> {
+        let ctl = alsa::Ctl::new(&format!("hw:{}", self.card()?), false).ok()?;
+        let info = ctl.card_info().ok()?;
+        Some(format!(
+            "{} ({})",
+            info.get_driver().ok()?,
+            info.get_longname().ok()?
+        ))
+    }
+
+    // Reopen the device and look up the name of its sound card again.
+    fn refresh(&mut self) -> Result<(), RefreshDeviceError> {
+        let handles = DeviceHandles::open(&self.pcm_id)?;
+        if let Some(card) = self.card() {
+            let ctl = alsa::Ctl::new(&format!("hw:{}", card), false)?;
+            self.name = ctl.card_info()?.get_name()?.to_string();
+        }
+        self.handles = Arc::new(Mutex::new(handles));
+        Ok(())
+    }
+
+    // The udev link to the control device of the sound card in `/dev/snd/by-path`, or the path of
+    // the card in sysfs.
+    fn physical_location(&self) -> Option<String> {
+        let ctl = alsa::Ctl::new(&format!("hw:{}", self.card()?), false).ok()?;
+        let index = ctl.card_info().ok()?.get_card().get_index();
+        let control = format!("controlC{}", index);
+        if let Ok(links) = std::fs::read_dir("/dev/snd/by-path") {
+            for link in links.flatten() {
+                let target = std::fs::read_link(link.path());
+                if target.is_ok_and(|target| target.file_name() == Some(control.as_ref())) {
+                    return link.file_name().into_string().ok();
+                }
+            }
+        }
+        let device = std::fs::canonicalize(format!("/sys/class/sound/card{}/device", index));
+        device.ok()?.into_os_string().into_string().ok()
+    }
+
+    /// Open this sound card through ALSA's `plug` plugin (`plughw:N`), or access the hardware
+    /// directly (`hw:N`).
+    ///
+    /// Sound cards are opened through `plug` by default. It converts the sample format, sample
+    /// rate and channel count to ones the hardware supports, so that any configuration can be
+    /// used, but the audio is no longer bit-exact and the supported configurations no longer
+    /// reflect the hardware. Direct access is bit-perfect, but only the configurations the
+    /// hardware supports natively can be used; many cards only take 24-bit samples packed in 3
+    /// bytes, which cpal has no sample format for.
+    ///
+    /// This has no effect on plugin devices such as `default` or `pulse`, which always convert.
+    pub fn with_plug(mut self, plug: bool) -> Self {
+        let pcm_id = match (plug, self.pcm_id.strip_prefix("plughw:")) {
+            (false, Some(card)) => format!("hw:{}", card),
+            (true, None) => match self.pcm_id.strip_prefix("hw:") {
+                Some(card) => format!("plughw:{}", card),
+                None => return self,
+            },
+            _ => return self,
+        };
+        // Handles opened for the old PCM can't be reused.
+        self.pcm_id = pcm_id;
+        self.handles = Arc::new(Mutex::new(Default::default()));
+        self
+    }
+
+    /// Whether the device is opened through ALSA's `plug` plugin. See
+    /// [`with_plug`](Self::with_plug).
+    pub fn uses_plug(&self) -> bool {
+        self.pcm_id.starts_with("plughw:")
+    }
+
+    /// Ask streams built from this device to call the data callback with at least `frames`
+    /// frames at a time, trading latency for fewer wakeups, e.g. on battery.
+    ///
+    /// This picks a period of about `frames`, and a buffer of four periods with
+    /// [`BufferSize::Default`]. With [`BufferSize::Fixed`] the buffer size is kept and the
+    /// period is limited to half of it. The device may round the period to a size it supports.
+    pub fn with_wakeup_granularity(mut self, frames: FrameCount) -> Self {
+        self.wakeup_granularity = Some(frames);
+        self
+    }
+
+    /// The granularity set with [`with_wakeup_granularity`](Self::with_wakeup_granularity).
+    pub fn wakeup_granularity(&self) -> Option<FrameCount> {
+        self.wakeup_granularity
+    }
+
     fn build_stream_inner(
         &self,
         conf: &StreamConfig,
@@ -262,7 +546,8 @@ impl Device {
             Err((e, _)) => return Err(e.into()),
             Ok(handle) => handle,
         };
-        let can_pause = set_hw_params_from_format(&handle, conf, sample_format)?;
+        let can_pause =
+            set_hw_params_from_format(&handle, conf, sample_format, self.wakeup_granularity)?;
         let period_len = set_sw_params_from_format(&handle, conf, stream_type)?;
 
         handle.prepare()?;
@@ -287,14 +572,16 @@ impl Device {
         }
 
         let stream_inner = StreamInner {
-            dropping: Cell::new(false),
+            dropping: AtomicBool::new(false),
+            pcm_id: self.pcm_id.clone(),
             channel: handle,
             sample_format,
             num_descriptors,
             conf: conf.clone(),
-            period_len,
+            period_len: AtomicUsize::new(period_len),
             can_pause,
             creation_instant,
+            state: AtomicU8::new(control::RUNNING),
         };
 
         Ok(stream_inner)
@@ -308,137 +595,18 @@ impl Device {
     fn supported_configs(
         &self,
         stream_t: alsa::Direction,
-    ) -> Result<VecIntoIter<SupportedStreamConfigRange>, SupportedStreamConfigsError> {
+    ) -> Result<SupportedConfigs, SupportedStreamConfigsError> {
         let mut guard = self.handles.lock().unwrap();
-        let handle_result = guard
+        let handle = guard
             .get_mut(&self.pcm_id, stream_t)
-            .map_err(|e| (e, e.errno()));
-
-        let handle = match handle_result {
-            Err((_, libc::ENOENT)) | Err((_, libc::EBUSY)) => {
-                return Err(SupportedStreamConfigsError::DeviceNotAvailable)
-            }
-            Err((_, libc::EINVAL)) => return Err(SupportedStreamConfigsError::InvalidArgument),
-            Err((e, _)) => return Err(e.into()),
-            Ok(handle) => handle,
-        };
-
-        let hw_params = alsa::pcm::HwParams::any(handle)?;
-
-        // TODO: check endianness
-        const FORMATS: [(SampleFormat, alsa::pcm::Format); 8] = [
-            (SampleFormat::I8, alsa::pcm::Format::S8),
-            (SampleFormat::U8, alsa::pcm::Format::U8),
-            (SampleFormat::I16, alsa::pcm::Format::S16LE),
-            //SND_PCM_FORMAT_S16_BE,
-            (SampleFormat::U16, alsa::pcm::Format::U16LE),
-            //SND_PCM_FORMAT_U16_BE,
-            //SND_PCM_FORMAT_S24_LE,
-            //SND_PCM_FORMAT_S24_BE,
-            //SND_PCM_FORMAT_U24_LE,
-            //SND_PCM_FORMAT_U24_BE,
-            (SampleFormat::I32, alsa::pcm::Format::S32LE),
-            //SND_PCM_FORMAT_S32_BE,
-            (SampleFormat::U32, alsa::pcm::Format::U32LE),
-            //SND_PCM_FORMAT_U32_BE,
-            (SampleFormat::F32, alsa::pcm::Format::FloatLE),
-            //SND_PCM_FORMAT_FLOAT_BE,
-            (SampleFormat::F64, alsa::pcm::Format::Float64LE),
-            //SND_PCM_FORMAT_FLOAT64_BE,
-            //SND_PCM_FORMAT_IEC958_SUBFRAME_LE,
-            //SND_PCM_FORMAT_IEC958_SUBFRAME_BE,
-            //SND_PCM_FORMAT_MU_LAW,
-            //SND_PCM_FORMAT_A_LAW,
-            //SND_PCM_FORMAT_IMA_ADPCM,
-            //SND_PCM_FORMAT_MPEG,
-            //SND_PCM_FORMAT_GSM,
-            //SND_PCM_FORMAT_SPECIAL,
-            //SND_PCM_FORMAT_S24_3LE,
-            //SND_PCM_FORMAT_S24_3BE,
-            //SND_PCM_FORMAT_U24_3LE,
-            //SND_PCM_FORMAT_U24_3BE,
-            //SND_PCM_FORMAT_S20_3LE,
-            //SND_PCM_FORMAT_S20_3BE,
-            //SND_PCM_FORMAT_U20_3LE,
-            //SND_PCM_FORMAT_U20_3BE,
-            //SND_PCM_FORMAT_S18_3LE,
-            //SND_PCM_FORMAT_S18_3BE,
-            //SND_PCM_FORMAT_U18_3LE,
-            //SND_PCM_FORMAT_U18_3BE,
-        ];
-
-        let mut supported_formats = Vec::new();
-        for &(sample_format, alsa_format) in FORMATS.iter() {
-            if hw_params.test_format(alsa_format).is_ok() {
-                supported_formats.push(sample_format);
-            }
-        }
-
-        let min_rate = hw_params.get_rate_min()?;
-        let max_rate = hw_params.get_rate_max()?;
-
-        let sample_rates = if min_rate == max_rate || hw_params.test_rate(min_rate + 1).is_ok() {
-            vec![(min_rate, max_rate)]
-        } else {
-            const RATES: [libc::c_uint; 13] = [
-                5512, 8000, 11025, 16000, 22050, 32000, 44100, 48000, 64000, 88200, 96000, 176400,
-                192000,
-            ];
-
-            let mut rates = Vec::new();
-            for &rate in RATES.iter() {
-                if hw_params.test_rate(rate).is_ok() {
-                    rates.push((rate, rate));
-                }
-            }
-
-            if rates.is_empty() {
-                vec![(min_rate, max_rate)]
-            } else {
-                rates
-            }
-        };
-
-        let min_channels = hw_params.get_channels_min()?;
-        let max_channels = hw_params.get_channels_max()?;
-
-        let max_channels = cmp::min(max_channels, 32); // TODO: limiting to 32 channels or too much stuff is returned
-        let supported_channels = (min_channels..max_channels + 1)
-            .filter_map(|num| {
-                if hw_params.test_channels(num).is_ok() {
-                    Some(num as ChannelCount)
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
-
-        let min_buffer_size = hw_params.get_buffer_size_min()?;
-        let max_buffer_size = hw_params.get_buffer_size_max()?;
-
-        let buffer_size_range = SupportedBufferSize::Range {
-            min: min_buffer_size as u32,
-            max: max_buffer_size as u32,
-        };
-
-        let mut output = Vec::with_capacity(
-            supported_formats.len() * supported_channels.len() * sample_rates.len(),
-        );
-        for &sample_format in supported_formats.iter() {
-            for &channels in supported_channels.iter() {
-                for &(min_rate, max_rate) in sample_rates.iter() {
-                    output.push(SupportedStreamConfigRange {
-                        channels,
-                        min_sample_rate: SampleRate(min_rate as u32),
-                        max_sample_rate: SampleRate(max_rate as u32),
-                        buffer_size: buffer_size_range.clone(),
-                        sample_format,
-                    });
-                }
-            }
-        }
-
-        Ok(output.into_iter())
+            .map_err(configs_error)?;
+
+        Ok(SupportedConfigs::new(
+            self.handles.clone(),
+            self.pcm_id.clone(),
+            stream_t,
+            handle,
+        )?)
     }
 
     fn supported_input_configs(
@@ -493,6 +661,21 @@ impl Device {
         }
     }
 
+    // The configuration the device runs at whatever a stream asks for. Plugins such as `dmix`,
+    // behind `default`, pin the format, rate and channel count to those of the mixer, which
+    // shows in the device's configuration space without installing anything on the handle.
+    // Devices that don't pin them, such as hardware devices, report the default configuration,
+    // as do those pinned to a format cpal has no sample format for.
+    fn current_config(&self, stream_t: alsa::Direction) -> Option<SupportedStreamConfig> {
+        let pinned = {
+            let mut guard = self.handles.lock().unwrap();
+            let handle = guard.get_mut(&self.pcm_id, stream_t).ok()?;
+            let hw_params = alsa::pcm::HwParams::any(handle).ok()?;
+            pinned_config(&hw_params)
+        };
+        pinned.or_else(|| self.default_config(stream_t).ok())
+    }
+
     fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
         self.default_config(alsa::Direction::Capture)
     }
@@ -502,10 +685,32 @@ impl Device {
     }
 }
 
+// The configuration `hw_params` allows, if it allows only one. Getting the format, rate or channel
+// count fails unless the configuration space holds a single value.
+fn pinned_config(hw_params: &alsa::pcm::HwParams) -> Option<SupportedStreamConfig> {
+    let format = hw_params.get_format().ok()?;
+    let sample_format = FORMATS
+        .iter()
+        .find(|&&(_, alsa_format)| alsa_format == format)
+        .map(|&(sample_format, _)| sample_format)?;
+    Some(SupportedStreamConfig {
+        channels: hw_params.get_channels().ok()? as ChannelCount,
+        sample_rate: SampleRate(hw_params.get_rate().ok()?),
+        buffer_size: SupportedBufferSize::Range {
+            min: hw_params.get_buffer_size_min().ok()? as u32,
+            max: hw_params.get_buffer_size_max().ok()? as u32,
+        },
+        sample_format,
+    })
+}
+
 struct StreamInner {
     // Flag used to check when to stop polling, regardless of the state of the stream
     // (e.g. broken due to a disconnected device).
-    dropping: Cell<bool>,
+    dropping: AtomicBool,
+
+    // The name of the device the stream was opened on, used to identify it in diagnostics.
+    pcm_id: String,
 
     // The ALSA channel.
     channel: alsa::pcm::PCM,
@@ -520,12 +725,12 @@ struct StreamInner {
     // The configuration used to open this stream.
     conf: StreamConfig,
 
-    // Minimum number of samples to put in the buffer.
-    period_len: usize,
+    // Minimum number of samples to put in the buffer, raised from one period by a preference for
+    // low power.
+    period_len: AtomicUsize,
 
-    #[allow(dead_code)]
-    // Whether or not the hardware supports pausing the stream.
-    // TODO: We need an API to expose this. See #197, #284.
+    // Whether or not the hardware supports pausing the stream. If not, the worker stops the device
+    // on `pause` and keeps the frames it hadn't played yet, writing them again on `play`.
     can_pause: bool,
 
     // In the case that the device does not return valid timestamps via `get_htstamp`, this field
@@ -537,11 +742,187 @@ struct StreamInner {
     // If this field is `None` then the elapsed duration between `get_trigger_htstamp` and
     // `get_htstamp` is used.
     creation_instant: Option<std::time::Instant>,
+
+    // The state the worker last applied, one of the states in `control`. Only the worker stores
+    // it.
+    state: AtomicU8,
+}
+
+// The worker's end of the control plane: the commands sent by `play`, `pause` and
+// `drain_timeout`, and the state they led to.
+struct WorkerControl {
+    commands: control::Receiver<Command>,
+    state: u8,
+    // The senders waiting for the queued audio to play out.
+    waiting: Vec<mpsc::Sender<()>>,
+    // The last device buffer of audio written to an output device that can't pause, so that
+    // pausing can take back the part the device hasn't played yet.
+    history: History,
+    // The frames taken back by the last pause, written again before new audio once the stream
+    // plays.
+    replay: Vec<u8>,
+}
+
+impl WorkerControl {
+    fn new(commands: control::Receiver<Command>, stream: &StreamInner) -> Self {
+        let playback = stream
+            .channel
+            .info()
+            .is_ok_and(|info| info.get_stream() == alsa::Direction::Playback);
+        let history_capacity = match stream.channel.get_params() {
+            Ok((buffer, _period)) if playback && !stream.can_pause => {
+                buffer as usize * stream.frame_bytes()
+            }
+            _ => 0,
+        };
+        Self {
+            commands,
+            state: control::RUNNING,
+            waiting: Vec::new(),
+            history: History {
+                bytes: VecDeque::with_capacity(history_capacity),
+                capacity: history_capacity,
+            },
+            replay: Vec::with_capacity(history_capacity),
+        }
+    }
+
+    fn set_state(&mut self, stream: &StreamInner, state: u8) {
+        self.state = state;
+        stream.state.store(state, Ordering::Release);
+    }
+
+    // Apply the commands sent since the last iteration, issuing the device calls they need.
+    fn apply_commands(&mut self, stream: &StreamInner) -> Result<(), StreamError> {
+        while let Some(command) = self.commands.pop() {
+            match command {
+                Command::Play => {
+                    let previous = self.state;
+                    // Dropping the senders tells `drain_timeout` the drain was cancelled.
+                    self.waiting.clear();
+                    self.set_state(stream, control::RUNNING);
+                    match previous {
+                        control::PAUSED if stream.can_pause => {
+                            stream.channel.pause(false).ok();
+                        }
+                        // Playback restarts once the worker has written enough, but capture has
+                        // to be started.
+                        control::PAUSED | control::DRAINED
+                            if stream.channel.info()?.get_stream() == alsa::Direction::Capture =>
+                        {
+                            stream.channel.start()?;
+                        }
+                        _ => (),
+                    }
+                }
+                Command::Pause => match self.state {
+                    control::RUNNING | control::DRAINING if stream.can_pause => {
+                        stream.channel.pause(true).ok();
+                        self.set_state(stream, control::PAUSED);
+                    }
+                    control::RUNNING => {
+                        self.take_back_queued(stream)?;
+                        self.set_state(stream, control::PAUSED);
+                    }
+                    _ => (),
+                },
+                Command::Drain(done) => match self.state {
+                    control::DRAINED => {
+                        let _ = done.send(());
+                    }
+                    control::RUNNING => {
+                        self.waiting.push(done);
+                        self.set_state(stream, control::DRAINING);
+                    }
+                    // A paused stream drains once it is resumed, which cancels the drain instead.
+                    _ => self.waiting.push(done),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // Stop a device that can't pause, keeping the frames it hasn't played yet to write again on
+    // `play`. `snd_pcm_drop` discards the queue at the hardware pointer, so the frames it leaves
+    // unplayed are the last `delay` frames written.
+    fn take_back_queued(&mut self, stream: &StreamInner) -> Result<(), StreamError> {
+        // An underrun leaves nothing queued.
+        let delay = stream.channel.delay().unwrap_or(0).max(0) as usize;
+        stream.channel.drop()?;
+        self.take_back(delay * stream.frame_bytes());
+        stream.channel.prepare()?;
+        Ok(())
+    }
+
+    // Move the last `queued` bytes written, as far as they are remembered, to the frames to write
+    // again. Frames taken back by an earlier pause and not written again yet come after them.
+    fn take_back(&mut self, queued: usize) {
+        let history = &mut self.history.bytes;
+        let start = history.len() - queued.min(history.len());
+        self.replay.splice(0..0, history.range(start..).copied());
+        history.clear();
+    }
+
+    // Write as much of the frames taken back by the last pause as the device has room for.
+    fn write_replay(
+        &mut self,
+        stream: &StreamInner,
+        available_frames: usize,
+    ) -> Result<(), StreamError> {
+        let frame_bytes = stream.frame_bytes();
+        let len = self.replay.len().min(available_frames * frame_bytes);
+        match stream.channel.io_bytes().writei(&self.replay[..len]) {
+            Ok(frames) => {
+                let written = frames * frame_bytes;
+                self.history.record(&self.replay[..written]);
+                self.replay.drain(..written);
+                Ok(())
+            }
+            Err(err) if err.errno() == libc::EPIPE => {
+                stream.report(DiagnosticKind::Underrun);
+                let _ = stream.channel.try_recover(err, false);
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+struct History {
+    bytes: VecDeque<u8>,
+    // Zero for devices that can pause, which keep no history.
+    capacity: usize,
+}
+
+impl History {
+    // Remember audio written to the device, keeping the last `capacity` bytes of it.
+    fn record(&mut self, data: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let data = &data[data.len().saturating_sub(self.capacity)..];
+        let excess = (self.bytes.len() + data.len()).saturating_sub(self.capacity);
+        self.bytes.drain(..excess);
+        self.bytes.extend(data);
+    }
 }
 
+// How often a draining output worker checks whether the queued audio has been played.
+const DRAIN_POLL_INTERVAL_MS: i32 = 5;
+
 // Assume that the ALSA library is built with thread safe option.
 unsafe impl Sync for StreamInner {}
 
+impl StreamInner {
+    fn report(&self, kind: DiagnosticKind) {
+        diagnostics::report("ALSA", || self.pcm_id.clone(), kind);
+    }
+
+    fn frame_bytes(&self) -> usize {
+        self.conf.channels as usize * self.sample_format.sample_size()
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 enum StreamType {
     Input,
@@ -558,6 +939,9 @@ pub struct Stream {
 
     /// Used to signal to stop processing.
     trigger: TriggerSender,
+
+    /// Commands for the worker. Only control threads take the lock, never the worker.
+    commands: Mutex<control::Sender<Command>>,
 }
 
 struct StreamWorkerContext {
@@ -584,24 +968,45 @@ impl StreamWorkerContext {
 
 fn input_stream_worker(
     rx: TriggerReceiver,
+    commands: control::Receiver<Command>,
     stream: &StreamInner,
     data_callback: &mut (dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static),
     error_callback: &mut (dyn FnMut(StreamError) + Send + 'static),
     timeout: Option<Duration>,
 ) {
     let mut ctxt = StreamWorkerContext::new(&timeout);
+    let mut control = WorkerControl::new(commands, stream);
+    // Set after an overrun until the next buffer is delivered.
+    let mut discontinuity = false;
     loop {
-        let flow =
-            poll_descriptors_and_prepare_buffer(&rx, stream, &mut ctxt).unwrap_or_else(|err| {
-                error_callback(err.into());
+        let flow = match control
+            .apply_commands(stream)
+            .and_then(|()| poll_drain(&rx, stream, &mut control))
+        {
+            Ok(None) => poll_descriptors_and_prepare_buffer(&rx, stream, &mut ctxt),
+            Ok(Some(flow)) => Ok(flow),
+            Err(err) => Err(err),
+        };
+        let flow = match flow {
+            Ok(flow) => flow,
+            Err(StreamError::DeviceNotAvailable) => {
+                stream.report(DiagnosticKind::DeviceLost);
+                error_callback(StreamError::DeviceNotAvailable);
+                return;
+            }
+            Err(err) => {
+                error_callback(err);
                 PollDescriptorsFlow::Continue
-            });
+            }
+        };
 
         match flow {
             PollDescriptorsFlow::Continue => {
                 continue;
             }
             PollDescriptorsFlow::XRun => {
+                discontinuity = true;
+                stream.report(DiagnosticKind::Overrun);
                 if let Err(err) = stream.channel.prepare() {
                     error_callback(err.into());
                 }
@@ -619,14 +1024,21 @@ fn input_stream_worker(
                     StreamType::Input,
                     "expected input stream, but polling descriptors indicated output",
                 );
-                if let Err(err) = process_input(
+                match process_input(
                     stream,
                     &mut ctxt.buffer,
                     status,
                     delay_frames,
+                    discontinuity,
                     data_callback,
                 ) {
-                    error_callback(err.into());
+                    Ok(()) => discontinuity = false,
+                    Err(StreamError::DeviceNotAvailable) => {
+                        stream.report(DiagnosticKind::DeviceLost);
+                        error_callback(StreamError::DeviceNotAvailable);
+                        return;
+                    }
+                    Err(err) => error_callback(err),
                 }
             }
         }
@@ -635,22 +1047,40 @@ fn input_stream_worker(
 
 fn output_stream_worker(
     rx: TriggerReceiver,
+    commands: control::Receiver<Command>,
     stream: &StreamInner,
     data_callback: &mut (dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static),
     error_callback: &mut (dyn FnMut(StreamError) + Send + 'static),
     timeout: Option<Duration>,
 ) {
     let mut ctxt = StreamWorkerContext::new(&timeout);
+    let mut control = WorkerControl::new(commands, stream);
     loop {
-        let flow =
-            poll_descriptors_and_prepare_buffer(&rx, stream, &mut ctxt).unwrap_or_else(|err| {
-                error_callback(err.into());
+        let flow = match control
+            .apply_commands(stream)
+            .and_then(|()| poll_drain(&rx, stream, &mut control))
+        {
+            Ok(None) => poll_descriptors_and_prepare_buffer(&rx, stream, &mut ctxt),
+            Ok(Some(flow)) => Ok(flow),
+            Err(err) => Err(err),
+        };
+        let flow = match flow {
+            Ok(flow) => flow,
+            Err(StreamError::DeviceNotAvailable) => {
+                stream.report(DiagnosticKind::DeviceLost);
+                error_callback(StreamError::DeviceNotAvailable);
+                return;
+            }
+            Err(err) => {
+                error_callback(err);
                 PollDescriptorsFlow::Continue
-            });
+            }
+        };
 
         match flow {
             PollDescriptorsFlow::Continue => continue,
             PollDescriptorsFlow::XRun => {
+                stream.report(DiagnosticKind::Underrun);
                 if let Err(err) = stream.channel.prepare() {
                     error_callback(err.into());
                 }
@@ -668,7 +1098,19 @@ fn output_stream_worker(
                     StreamType::Output,
                     "expected output stream, but polling descriptors indicated input",
                 );
-                if let Err(err) = process_output(
+                if !control.replay.is_empty() {
+                    match control.write_replay(stream, avail_frames) {
+                        Ok(()) => (),
+                        Err(StreamError::DeviceNotAvailable) => {
+                            stream.report(DiagnosticKind::DeviceLost);
+                            error_callback(StreamError::DeviceNotAvailable);
+                            return;
+                        }
+                        Err(err) => error_callback(err),
+                    }
+                    continue;
+                }
+                match process_output(
                     stream,
                     &mut ctxt.buffer,
                     status,
@@ -677,13 +1119,78 @@ fn output_stream_worker(
                     data_callback,
                     error_callback,
                 ) {
-                    error_callback(err.into());
+                    Ok(()) => control.history.record(&ctxt.buffer),
+                    Err(StreamError::DeviceNotAvailable) => {
+                        stream.report(DiagnosticKind::DeviceLost);
+                        error_callback(StreamError::DeviceNotAvailable);
+                        return;
+                    }
+                    Err(err) => error_callback(err),
                 }
             }
         }
     }
 }
 
+// While the stream is paused, draining or drained, leave the device alone and only wake up to
+// check on the queued audio, for the next command or to be dropped. Returns `None` if the stream
+// is running normally.
+fn poll_drain(
+    rx: &TriggerReceiver,
+    stream: &StreamInner,
+    control: &mut WorkerControl,
+) -> Result<Option<PollDescriptorsFlow>, StreamError> {
+    let timeout = match control.state {
+        control::RUNNING => return Ok(None),
+        control::DRAINING => DRAIN_POLL_INTERVAL_MS,
+        // Sending a command wakes the worker up.
+        _ => -1,
+    };
+    if stream.dropping.load(Ordering::Acquire) {
+        rx.clear_pipe();
+        return Ok(Some(PollDescriptorsFlow::Return));
+    }
+
+    let mut descriptors = [libc::pollfd {
+        fd: rx.0,
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+    if alsa::poll::poll(&mut descriptors, timeout)? > 0 {
+        // Either the stream is being dropped or a command was sent; both are picked up on the
+        // next iteration.
+        rx.clear_pipe();
+        return Ok(Some(PollDescriptorsFlow::Continue));
+    }
+
+    if control.state == control::DRAINING {
+        let capture = stream.channel.info()?.get_stream() == alsa::Direction::Capture;
+        let finished = match stream.channel.state() {
+            // A capture stream has nothing to play out.
+            _ if capture => true,
+            alsa::pcm::State::Running => stream.channel.delay()? <= 0,
+            alsa::pcm::State::Paused => false,
+            // Queued audio that didn't reach the start threshold wouldn't play otherwise.
+            alsa::pcm::State::Prepared if stream.channel.delay()? > 0 => {
+                stream.channel.start()?;
+                false
+            }
+            alsa::pcm::State::Disconnected => return Err(StreamError::DeviceNotAvailable),
+            _ => true,
+        };
+        if finished {
+            // Stop the device without reporting the underrun that would follow.
+            stream.channel.drop()?;
+            stream.channel.prepare()?;
+            control.set_state(stream, control::DRAINED);
+            for done in control.waiting.drain(..) {
+                let _ = done.send(());
+            }
+        }
+    }
+    Ok(Some(PollDescriptorsFlow::Continue))
+}
+
 enum PollDescriptorsFlow {
     Continue,
     Return,
@@ -701,8 +1208,8 @@ fn poll_descriptors_and_prepare_buffer(
     rx: &TriggerReceiver,
     stream: &StreamInner,
     ctxt: &mut StreamWorkerContext,
-) -> Result<PollDescriptorsFlow, BackendSpecificError> {
-    if stream.dropping.get() {
+) -> Result<PollDescriptorsFlow, StreamError> {
+    if stream.dropping.load(Ordering::Acquire) {
         // The stream has been requested to be destroyed.
         rx.clear_pipe();
         return Ok(PollDescriptorsFlow::Return);
@@ -740,19 +1247,26 @@ fn poll_descriptors_and_prepare_buffer(
     let res = alsa::poll::poll(descriptors, *poll_timeout)?;
     if res == 0 {
         let description = String::from("`alsa::poll()` spuriously returned");
-        return Err(BackendSpecificError { description });
+        return Err(BackendSpecificError { description }.into());
     }
 
     if descriptors[0].revents != 0 {
-        // The stream has been requested to be destroyed.
+        // The stream has been requested to be destroyed, or `play` woke up a drained stream just
+        // as it resumed.
         rx.clear_pipe();
-        return Ok(PollDescriptorsFlow::Return);
+        if stream.dropping.load(Ordering::Acquire) {
+            return Ok(PollDescriptorsFlow::Return);
+        }
+        return Ok(PollDescriptorsFlow::Continue);
     }
 
     let revents = stream.channel.revents(&descriptors[1..])?;
     if revents.contains(alsa::poll::Flags::ERR) {
+        if stream.channel.state() == alsa::pcm::State::Disconnected {
+            return Err(StreamError::DeviceNotAvailable);
+        }
         let description = String::from("`alsa::poll()` returned POLLERR");
-        return Err(BackendSpecificError { description });
+        return Err(BackendSpecificError { description }.into());
     }
     let stream_type = match revents {
         alsa::poll::Flags::OUT => StreamType::Output,
@@ -775,8 +1289,8 @@ fn poll_descriptors_and_prepare_buffer(
     };
     let available_samples = avail_frames * stream.conf.channels as usize;
 
-    // Only go on if there is at least `stream.period_len` samples.
-    if available_samples < stream.period_len {
+    // Only go on if there is at least one frame and `stream.period_len` samples.
+    if avail_frames == 0 || available_samples < stream.period_len.load(Ordering::Relaxed) {
         return Ok(PollDescriptorsFlow::Continue);
     }
 
@@ -798,8 +1312,9 @@ fn process_input(
     buffer: &mut [u8],
     status: alsa::pcm::Status,
     delay_frames: usize,
+    discontinuity: bool,
     data_callback: &mut (dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static),
-) -> Result<(), BackendSpecificError> {
+) -> Result<(), StreamError> {
     stream.channel.io_bytes().readi(buffer)?;
     let sample_format = stream.sample_format;
     let data = buffer.as_mut_ptr() as *mut ();
@@ -811,12 +1326,30 @@ fn process_input(
         .sub(delay_duration)
         .expect("`capture` is earlier than representation supported by `StreamInstant`");
     let timestamp = crate::InputStreamTimestamp { callback, capture };
-    let info = crate::InputCallbackInfo { timestamp };
+    let info = crate::InputCallbackInfo {
+        timestamp,
+        discontinuity,
+    };
+    let start = diagnostics::enabled().then(std::time::Instant::now);
     data_callback(&data, &info);
+    if let Some(start) = start {
+        check_callback_duration(stream, start, len);
+    }
 
     Ok(())
 }
 
+// Report a `CallbackOverrun` if the data callback, started at `start` and handed `len` samples,
+// took longer than the audio it was handed lasts.
+fn check_callback_duration(stream: &StreamInner, start: std::time::Instant, len: usize) {
+    let elapsed = start.elapsed();
+    let frames = len / stream.conf.channels as usize;
+    let budget = frames_to_duration(frames, stream.conf.sample_rate);
+    if elapsed > budget {
+        stream.report(DiagnosticKind::CallbackOverrun { elapsed, budget });
+    }
+}
+
 // Request data from the user's function and write it via ALSA.
 //
 // Returns `true`
@@ -828,7 +1361,7 @@ fn process_output(
     delay_frames: usize,
     data_callback: &mut (dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static),
     error_callback: &mut dyn FnMut(StreamError),
-) -> Result<(), BackendSpecificError> {
+) -> Result<(), StreamError> {
     {
         // We're now sure that we're ready to write data.
         let sample_format = stream.sample_format;
@@ -842,19 +1375,27 @@ fn process_output(
             .expect("`playback` occurs beyond representation supported by `StreamInstant`");
         let timestamp = crate::OutputStreamTimestamp { callback, playback };
         let info = crate::OutputCallbackInfo { timestamp };
+        let start = diagnostics::enabled().then(std::time::Instant::now);
         data_callback(&mut data, &info);
+        if let Some(start) = start {
+            check_callback_duration(stream, start, len);
+        }
     }
     loop {
         match stream.channel.io_bytes().writei(buffer) {
             Err(err) if err.errno() == libc::EPIPE => {
                 // buffer underrun
-                // TODO: Notify the user of this.
+                stream.report(DiagnosticKind::Underrun);
                 let _ = stream.channel.try_recover(err, false);
             }
-            Err(err) => {
-                error_callback(err.into());
-                continue;
-            }
+            Err(err) => match StreamError::from(err) {
+                // Retrying a write to a device that has gone away would spin forever.
+                StreamError::DeviceNotAvailable => return Err(StreamError::DeviceNotAvailable),
+                err => {
+                    error_callback(err);
+                    continue;
+                }
+            },
             Ok(result) if result != available_frames => {
                 let description = format!(
                     "unexpected number of frames written: expected {}, \
@@ -938,6 +1479,7 @@ impl Stream {
         E: FnMut(StreamError) + Send + 'static,
     {
         let (tx, rx) = trigger();
+        let (commands, commands_rx) = control::queue(control::COMMAND_CAPACITY);
         // Clone the handle for passing into worker thread.
         let stream = inner.clone();
         let thread = thread::Builder::new()
@@ -945,6 +1487,7 @@ impl Stream {
             .spawn(move || {
                 input_stream_worker(
                     rx,
+                    commands_rx,
                     &stream,
                     &mut data_callback,
                     &mut error_callback,
@@ -956,6 +1499,7 @@ impl Stream {
             thread: Some(thread),
             inner,
             trigger: tx,
+            commands: Mutex::new(commands),
         }
     }
 
@@ -970,6 +1514,7 @@ impl Stream {
         E: FnMut(StreamError) + Send + 'static,
     {
         let (tx, rx) = trigger();
+        let (commands, commands_rx) = control::queue(control::COMMAND_CAPACITY);
         // Clone the handle for passing into worker thread.
         let stream = inner.clone();
         let thread = thread::Builder::new()
@@ -977,6 +1522,7 @@ impl Stream {
             .spawn(move || {
                 output_stream_worker(
                     rx,
+                    commands_rx,
                     &stream,
                     &mut data_callback,
                     &mut error_callback,
@@ -988,33 +1534,167 @@ impl Stream {
             thread: Some(thread),
             inner,
             trigger: tx,
+            commands: Mutex::new(commands),
+        }
+    }
+
+    // Queue `command` for the worker and wake it up. Returns `false` if the worker has exited.
+    fn send(&self, command: Command) -> bool {
+        let mut commands = self.commands.lock().unwrap();
+        self.push(&mut commands, command)
+    }
+
+    fn push(&self, commands: &mut control::Sender<Command>, mut command: Command) -> bool {
+        while let Err(rejected) = commands.push(command) {
+            // The worker empties the queue at the top of each iteration, unless it has exited.
+            if self.is_terminated() {
+                return false;
+            }
+            command = rejected;
+            self.trigger.wakeup();
+            thread::yield_now();
         }
+        self.trigger.wakeup();
+        true
     }
 }
 
 impl Drop for Stream {
     fn drop(&mut self) {
-        self.inner.dropping.set(true);
+        self.inner.dropping.store(true, Ordering::Release);
         self.trigger.wakeup();
-        self.thread.take().unwrap().join().unwrap();
+        // A panic on the worker thread has already been reported there; don't turn it into a
+        // second panic while dropping.
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
     }
 }
 
 impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
-        self.inner.channel.pause(false).ok();
+        if self.is_terminated() {
+            return Err(PlayStreamError::DeviceNotAvailable);
+        }
+        if !self.send(Command::Play) {
+            return Err(PlayStreamError::DeviceNotAvailable);
+        }
         Ok(())
     }
     fn pause(&self) -> Result<(), PauseStreamError> {
-        self.inner.channel.pause(true).ok();
+        if self.is_terminated() {
+            return Err(PauseStreamError::DeviceNotAvailable);
+        }
+        if !self.send(Command::Pause) {
+            return Err(PauseStreamError::DeviceNotAvailable);
+        }
+        Ok(())
+    }
+    fn is_terminated(&self) -> bool {
+        // The worker only returns early after reporting a fatal error.
+        self.thread
+            .as_ref()
+            .map_or(true, |thread| thread.is_finished())
+    }
+    fn close(mut self) -> Result<(), CloseStreamError> {
+        let terminated = self.is_terminated();
+        self.inner.dropping.store(true, Ordering::Release);
+        self.trigger.wakeup();
+        if let Some(thread) = self.thread.take() {
+            thread.join().map_err(|_| BackendSpecificError {
+                description: "the stream's worker thread panicked".to_owned(),
+            })?;
+        }
+        if terminated {
+            return Err(CloseStreamError::DeviceNotAvailable);
+        }
+        // Stop the device right away instead of letting the queued audio play out on close.
+        self.inner.channel.drop()?;
         Ok(())
     }
+    fn drain_timeout(&self, timeout: Duration) -> Result<(), DrainError> {
+        if self.is_terminated() {
+            return Err(DrainError::DeviceNotAvailable);
+        }
+        if self.inner.channel.info()?.get_stream() == alsa::Direction::Capture {
+            return Ok(());
+        }
+        let (tx, rx) = mpsc::channel();
+        {
+            let mut commands = self.commands.lock().unwrap();
+            // With no command left for the worker to apply, a drained stream stays drained.
+            if commands.is_empty() && self.inner.state.load(Ordering::Acquire) == control::DRAINED {
+                return Ok(());
+            }
+            if !self.push(&mut commands, Command::Drain(tx)) {
+                return Err(DrainError::DeviceNotAvailable);
+            }
+        }
+
+        // Wait in steps so that a worker that exits after a fatal error isn't waited on for the
+        // whole timeout.
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(DrainError::TimedOut);
+            }
+            match rx.recv_timeout(remaining.min(Duration::from_millis(50))) {
+                Ok(()) => return Ok(()),
+                // `play` was called, which cancels the drain.
+                Err(RecvTimeoutError::Disconnected) => return Err(DrainError::Cancelled),
+                Err(RecvTimeoutError::Timeout) if self.is_terminated() => {
+                    return Err(DrainError::DeviceNotAvailable)
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+            }
+        }
+    }
+    fn actual_buffer_size(&self) -> Option<FrameCount> {
+        let (buffer, _period) = self.inner.channel.get_params().ok()?;
+        buffer.try_into().ok()
+    }
+    fn set_power_preference(&self, preference: PowerPreference) -> Result<(), StreamError> {
+        if self.is_terminated() {
+            return Err(StreamError::DeviceNotAvailable);
+        }
+        let (buffer, period) = self.inner.channel.get_params()?;
+        // Keep a period of headroom, and wake up for whole periods.
+        let avail_min = match preference {
+            PowerPreference::LowPower => period.max(buffer - buffer % period - period),
+            PowerPreference::Default => period,
+        };
+        let sw_params = self.inner.channel.sw_params_current()?;
+        sw_params.set_avail_min(avail_min as alsa::pcm::Frames)?;
+        self.inner.channel.sw_params(&sw_params)?;
+        let samples = avail_min as usize * self.inner.conf.channels as usize;
+        self.inner.period_len.store(samples, Ordering::Relaxed);
+        // Apply it from the next poll rather than after the one in progress.
+        self.trigger.wakeup();
+        Ok(())
+    }
+    fn wakeup_interval(&self) -> Option<Duration> {
+        let frames =
+            self.inner.period_len.load(Ordering::Relaxed) / self.inner.conf.channels as usize;
+        let rate = self.inner.conf.sample_rate.0;
+        (rate != 0).then(|| Duration::from_secs_f64(frames as f64 / rate as f64))
+    }
+    fn os_processing(&self) -> OsProcessing {
+        // Only `hw` devices are opened without any plugin in between. Others, such as `plughw`,
+        // `dmix` or `pulse`, may convert, but don't tell.
+        if self.inner.pcm_id.starts_with("hw:") {
+            OsProcessing::BitExact
+        } else {
+            OsProcessing::Unknown
+        }
+    }
 }
 
 fn set_hw_params_from_format(
     pcm_handle: &alsa::pcm::PCM,
     config: &StreamConfig,
     sample_format: SampleFormat,
+    wakeup_granularity: Option<FrameCount>,
 ) -> Result<bool, BackendSpecificError> {
     let hw_params = alsa::pcm::HwParams::any(pcm_handle)?;
     hw_params.set_access(alsa::pcm::Access::RWInterleaved)?;
@@ -1075,12 +1755,18 @@ fn set_hw_params_from_format(
     hw_params.set_rate(config.sample_rate.0, alsa::ValueOr::Nearest)?;
     hw_params.set_channels(config.channels as u32)?;
 
-    match config.buffer_size {
-        BufferSize::Fixed(v) => {
-            hw_params.set_period_size_near((v / 4) as alsa::pcm::Frames, alsa::ValueOr::Nearest)?;
+    match (config.buffer_size, wakeup_granularity) {
+        (BufferSize::Fixed(v), granularity) => {
+            let period = granularity.map_or(v / 4, |frames| frames.clamp(v / 4, v / 2));
+            hw_params.set_period_size_near(period as alsa::pcm::Frames, alsa::ValueOr::Nearest)?;
             hw_params.set_buffer_size(v as alsa::pcm::Frames)?;
         }
-        BufferSize::Default => {
+        (BufferSize::Default, Some(frames)) => {
+            let period = hw_params
+                .set_period_size_near(frames as alsa::pcm::Frames, alsa::ValueOr::Nearest)?;
+            hw_params.set_buffer_size_near(4 * period)?;
+        }
+        (BufferSize::Default, None) => {
             // These values together represent a moderate latency and wakeup interval.
             // Without them, we are at the mercy of the device
             hw_params.set_period_time_near(25_000, alsa::ValueOr::Nearest)?;
@@ -1147,15 +1833,36 @@ impl From<alsa::Error> for BackendSpecificError {
 
 impl From<alsa::Error> for BuildStreamError {
     fn from(err: alsa::Error) -> Self {
-        let err: BackendSpecificError = err.into();
-        err.into()
+        match err.errno() {
+            libc::ENODEV => BuildStreamError::DeviceNotAvailable,
+            _ => {
+                let err: BackendSpecificError = err.into();
+                err.into()
+            }
+        }
     }
 }
 
 impl From<alsa::Error> for SupportedStreamConfigsError {
     fn from(err: alsa::Error) -> Self {
-        let err: BackendSpecificError = err.into();
-        err.into()
+        match err.errno() {
+            libc::ENODEV => SupportedStreamConfigsError::DeviceNotAvailable,
+            _ => {
+                let err: BackendSpecificError = err.into();
+                err.into()
+            }
+        }
+    }
+}
+
+// The error querying the configurations of a device that couldn't be opened reports.
+fn configs_error(err: alsa::Error) -> SupportedStreamConfigsError {
+    match err.errno() {
+        libc::ENOENT | libc::EBUSY | libc::ENODEV => {
+            SupportedStreamConfigsError::DeviceNotAvailable
+        }
+        libc::EINVAL => SupportedStreamConfigsError::InvalidArgument,
+        _ => err.into(),
     }
 }
 
@@ -1173,9 +1880,331 @@ impl From<alsa::Error> for PauseStreamError {
     }
 }
 
-impl From<alsa::Error> for StreamError {
+impl From<alsa::Error> for CloseStreamError {
+    fn from(err: alsa::Error) -> Self {
+        match err.errno() {
+            errno if device_gone(errno) => CloseStreamError::DeviceNotAvailable,
+            _ => {
+                let err: BackendSpecificError = err.into();
+                err.into()
+            }
+        }
+    }
+}
+
+impl From<alsa::Error> for DrainError {
     fn from(err: alsa::Error) -> Self {
         let err: BackendSpecificError = err.into();
         err.into()
     }
 }
+
+impl From<alsa::Error> for RefreshDeviceError {
+    fn from(err: alsa::Error) -> Self {
+        match err.errno() {
+            libc::ENOENT | libc::ENODEV => RefreshDeviceError::DeviceNotAvailable,
+            _ => {
+                let err: BackendSpecificError = err.into();
+                err.into()
+            }
+        }
+    }
+}
+
+impl From<alsa::Error> for StreamError {
+    fn from(err: alsa::Error) -> Self {
+        match err.errno() {
+            errno if device_gone(errno) => StreamError::DeviceNotAvailable,
+            _ => {
+                let err: BackendSpecificError = err.into();
+                err.into()
+            }
+        }
+    }
+}
+
+/// Whether an error from a running stream's PCM means the device is gone: `ENODEV` once it has
+/// been unplugged, `EBADFD` when the PCM was left in a state it can't leave, e.g. after the card
+/// was removed while the stream was being recovered.
+fn device_gone(errno: libc::c_int) -> bool {
+    matches!(errno, libc::ENODEV | libc::EBADFD)
+}
+
+#[test]
+fn test_commands_never_block_the_worker() {
+    // ALSA's `null` device accepts any configuration and plays nothing, consuming the audio as
+    // fast as it is written.
+    let device = Device {
+        name: "null".to_string(),
+        pcm_id: "null".to_string(),
+        handles: Arc::new(Mutex::new(DeviceHandles::open("null").unwrap())),
+        wakeup_granularity: None,
+    };
+    let config = StreamConfig {
+        channels: 2,
+        sample_rate: crate::SampleRate(48000),
+        buffer_size: BufferSize::Default,
+    };
+    let callbacks = Arc::new(AtomicUsize::new(0));
+    let counter = callbacks.clone();
+    let stream = device
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], _: &OutputCallbackInfo| {
+                data.fill(0.0);
+                counter.fetch_add(1, Ordering::Relaxed);
+            },
+            |err| panic!("{}", err),
+            None,
+        )
+        .unwrap();
+    let wait_for_callbacks = || {
+        let start = callbacks.load(Ordering::Relaxed);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while callbacks.load(Ordering::Relaxed) < start + 10 {
+            assert!(Instant::now() < deadline, "the worker stopped calling back");
+            thread::yield_now();
+        }
+    };
+
+    // Control threads contending for the stream while it renders.
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..200 {
+                    stream.play().unwrap();
+                    stream.pause().unwrap();
+                    let _ = stream.drain_timeout(Duration::from_millis(1));
+                }
+            });
+        }
+    });
+    stream.play().unwrap();
+    wait_for_callbacks();
+    assert_eq!(stream.inner.state.load(Ordering::Acquire), control::RUNNING);
+
+    // The worker keeps going while a control thread holds the lock.
+    let commands = stream.commands.lock().unwrap();
+    wait_for_callbacks();
+    drop(commands);
+}
+
+#[test]
+fn test_device_gone() {
+    for errno in [libc::ENODEV, libc::EBADFD] {
+        assert!(device_gone(errno));
+        let err = alsa::Error::new("snd_pcm_writei", errno);
+        assert_eq!(StreamError::from(err), StreamError::DeviceNotAvailable);
+    }
+    // Underruns, a busy device and the like are recovered from or reported as they are.
+    for errno in [libc::EPIPE, libc::EAGAIN, libc::EINVAL, libc::EIO] {
+        assert!(!device_gone(errno));
+        let err = alsa::Error::new("snd_pcm_writei", errno);
+        assert!(matches!(
+            StreamError::from(err),
+            StreamError::BackendSpecific { .. }
+        ));
+    }
+}
+
+#[test]
+fn test_drop_after_the_worker_exited() {
+    let channel = alsa::PCM::new("null", alsa::Direction::Playback, true).unwrap();
+    let inner = Arc::new(StreamInner {
+        dropping: AtomicBool::new(false),
+        pcm_id: "null".to_string(),
+        channel,
+        sample_format: SampleFormat::F32,
+        num_descriptors: 1,
+        conf: StreamConfig {
+            channels: 2,
+            sample_rate: crate::SampleRate(48000),
+            buffer_size: BufferSize::Default,
+        },
+        period_len: AtomicUsize::new(0),
+        can_pause: false,
+        creation_instant: None,
+        state: AtomicU8::new(control::RUNNING),
+    });
+    // Workers that returned after a fatal error, and that panicked, both closing their end of
+    // the trigger pipe on the way out.
+    for panics in [false, true] {
+        let (tx, rx) = trigger();
+        let thread = thread::spawn(move || {
+            drop(rx);
+            if panics {
+                panic!("worker panicked");
+            }
+        });
+        while !thread.is_finished() {
+            thread::yield_now();
+        }
+        let stream = Stream {
+            thread: Some(thread),
+            inner: inner.clone(),
+            trigger: tx,
+            commands: Mutex::new(control::queue(1).0),
+        };
+        assert!(stream.is_terminated());
+        assert!(matches!(
+            stream.play(),
+            Err(PlayStreamError::DeviceNotAvailable)
+        ));
+        // Waking the worker writes to a pipe no one reads anymore, and joining it returns its
+        // panic, neither of which may panic here.
+        drop(stream);
+    }
+}
+
+#[test]
+fn test_pinned_config() {
+    let pcm = alsa::PCM::new("null", alsa::Direction::Playback, true).unwrap();
+    // `null` accepts any configuration, so none is pinned and nothing is installed.
+    let hw_params = alsa::pcm::HwParams::any(&pcm).unwrap();
+    assert_eq!(pinned_config(&hw_params), None);
+    assert_eq!(pcm.state(), alsa::pcm::State::Open);
+
+    hw_params.set_format(alsa::pcm::Format::s16()).unwrap();
+    hw_params.set_channels(2).unwrap();
+    hw_params.set_rate(48000, alsa::ValueOr::Nearest).unwrap();
+    let config = pinned_config(&hw_params).unwrap();
+    assert_eq!(config.sample_format, SampleFormat::I16);
+    assert_eq!(config.channels, 2);
+    assert_eq!(config.sample_rate, SampleRate(48000));
+}
+
+#[test]
+fn test_pause_takes_back_the_unplayed_frames() {
+    use crate::testsignal::{Signal, SignalGenerator, SignalVerifier};
+
+    // A stereo `f32` device with a 4800 frame buffer, which has played all but the frames still
+    // queued on it when it is paused.
+    const FRAME_BYTES: usize = 8;
+    let mut control = WorkerControl {
+        commands: control::queue(1).1,
+        state: control::RUNNING,
+        waiting: Vec::new(),
+        history: History {
+            bytes: VecDeque::new(),
+            capacity: 4800 * FRAME_BYTES,
+        },
+        replay: Vec::new(),
+    };
+    fn write(control: &mut WorkerControl, device: &mut Vec<u8>, bytes: &[u8]) {
+        control.history.record(bytes);
+        device.extend_from_slice(bytes);
+    }
+    fn pause(control: &mut WorkerControl, device: &mut Vec<u8>, queued_frames: usize) {
+        device.truncate(device.len() - queued_frames * FRAME_BYTES);
+        control.take_back(queued_frames * FRAME_BYTES);
+    }
+    fn write_replay(control: &mut WorkerControl, device: &mut Vec<u8>, frames: usize) {
+        let len = control.replay.len().min(frames * FRAME_BYTES);
+        let bytes: Vec<u8> = control.replay[..len].to_vec();
+        write(control, device, &bytes);
+        control.replay.drain(..len);
+    }
+    let mut generator = SignalGenerator::new(Signal::Sine, 2, SampleRate(48000));
+    let mut generate = |control: &mut WorkerControl, device: &mut Vec<u8>, blocks: usize| {
+        let mut block = [0.0f32; 2 * 480];
+        for _ in 0..blocks {
+            generator.fill_f32(&mut block);
+            let bytes: Vec<u8> = block.iter().flat_map(|s| s.to_ne_bytes()).collect();
+            write(control, device, &bytes);
+        }
+    };
+
+    // The audio the device has played, or will play once it is no longer queued.
+    let mut device = Vec::new();
+    generate(&mut control, &mut device, 20);
+    pause(&mut control, &mut device, 1000);
+    assert_eq!(control.replay.len(), 1000 * FRAME_BYTES);
+    // Paused again before the frames taken back have all been written again.
+    write_replay(&mut control, &mut device, 300);
+    pause(&mut control, &mut device, 100);
+    assert_eq!(control.replay.len(), 800 * FRAME_BYTES);
+    write_replay(&mut control, &mut device, 800);
+    assert!(control.replay.is_empty());
+    generate(&mut control, &mut device, 10);
+
+    let samples: Vec<f32> = device
+        .chunks_exact(4)
+        .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect();
+    assert_eq!(samples.len(), 30 * 2 * 480);
+    let mut verifier = SignalVerifier::new(Signal::Sine, 2, SampleRate(48000));
+    verifier.push_f32(&samples);
+    for report in verifier.report() {
+        assert_eq!(report.discontinuities, 0, "{:?}", report);
+    }
+}
+
+#[test]
+fn test_pause_without_hardware_support() {
+    use crate::testsignal::{Signal, SignalGenerator, SignalVerifier};
+    use std::sync::atomic::AtomicU64;
+
+    // The `file` plugin writes what is played to a file, through a `null` device that plays it
+    // right away.
+    let path = std::env::temp_dir().join(format!("cpal_alsa_pause_{}.raw", std::process::id()));
+    let pcm_id = format!("file:'{}',raw", path.display());
+    let device = Device {
+        name: pcm_id.clone(),
+        handles: Arc::new(Mutex::new(DeviceHandles::open(&pcm_id).unwrap())),
+        pcm_id,
+        wakeup_granularity: None,
+    };
+    let config = StreamConfig {
+        channels: 2,
+        sample_rate: SampleRate(48000),
+        buffer_size: BufferSize::Default,
+    };
+    let mut inner = device
+        .build_stream_inner(&config, SampleFormat::F32, alsa::Direction::Playback)
+        .unwrap();
+    inner.can_pause = false;
+    let frames = Arc::new(AtomicU64::new(0));
+    let mut generator = SignalGenerator::new(Signal::Sine, 2, SampleRate(48000));
+    let position = frames.clone();
+    let stream = Stream::new_output(
+        Arc::new(inner),
+        move |data: &mut Data, _: &OutputCallbackInfo| {
+            generator.fill(data);
+            position.store(generator.position(), Ordering::Relaxed);
+        },
+        |err| panic!("{}", err),
+        None,
+    );
+    let wait_for_frames = |count: u64| {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while frames.load(Ordering::Relaxed) < count {
+            assert!(Instant::now() < deadline, "the worker stopped calling back");
+            thread::yield_now();
+        }
+    };
+
+    wait_for_frames(48000);
+    stream.pause().unwrap();
+    while stream.inner.state.load(Ordering::Acquire) != control::PAUSED {
+        thread::yield_now();
+    }
+    let paused_at = frames.load(Ordering::Relaxed);
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(frames.load(Ordering::Relaxed), paused_at);
+    stream.play().unwrap();
+    wait_for_frames(paused_at + 48000);
+    drop(stream);
+
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let samples: Vec<f32> = bytes
+        .chunks_exact(4)
+        .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect();
+    assert_eq!(samples.len() as u64, frames.load(Ordering::Relaxed) * 2);
+    let mut verifier = SignalVerifier::new(Signal::Sine, 2, SampleRate(48000));
+    verifier.push_f32(&samples);
+    for report in verifier.report() {
+        assert_eq!(report.discontinuities, 0, "{:?}", report);
+    }
+}
@@ -0,0 +1,246 @@
+use super::alsa;
+use super::Device;
+use crate::BackendSpecificError;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+// How long the watcher waits for mixer events before checking whether it should stop.
+const POLL_TIMEOUT_MS: u32 = 100;
+
+// The simple mixer elements tried, in order, as the device's volume control.
+const ELEMENT_NAMES: [&str; 2] = ["Master", "PCM"];
+
+/// Watches a device's mixer for volume changes. Created by [`Device::on_volume_changed`].
+///
+/// The callback stops being called when this is dropped.
+pub struct VolumeListener {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// A volume control bound to one of a device's simple mixer elements. Created by
+/// [`Device::attach_mixer`].
+///
+/// When bound, [`set_volume`](Self::set_volume) changes the element through the mixer, which
+/// is either the card's hardware volume or a `softvol` plugin control, and [`gain`](Self::gain)
+/// stays at `1.0`. When the element is missing, the volume falls back to software: it is only
+/// stored, and the data callback applies it by multiplying the samples by [`gain`](Self::gain).
+pub struct MixerVolume {
+    // The mixer and the bound element, or `None` when the volume is applied in software.
+    mixer: Option<(Mutex<alsa::mixer::Mixer>, String)>,
+    // The software volume, as `f32` bits.
+    software: AtomicU32,
+}
+
+impl MixerVolume {
+    /// Whether the volume goes through a mixer element rather than being applied in software.
+    pub fn is_hardware(&self) -> bool {
+        self.mixer.is_some()
+    }
+
+    /// The name of the bound mixer element, or `None` if the volume is applied in software.
+    pub fn element(&self) -> Option<&str> {
+        self.mixer.as_ref().map(|(_, element)| element.as_str())
+    }
+
+    /// The current volume, between `0.0` and `1.0`.
+    ///
+    /// A bound element is read back from the mixer, so this reflects changes made elsewhere,
+    /// e.g. from the system mixer.
+    pub fn volume(&self) -> f32 {
+        match &self.mixer {
+            Some((mixer, element)) => {
+                let mixer = mixer.lock().unwrap();
+                let _ = mixer.handle_events();
+                element_volume(&mixer, element).unwrap_or(1.0)
+            }
+            None => f32::from_bits(self.software.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Change the volume. `volume` is clamped to between `0.0` and `1.0`.
+    pub fn set_volume(&self, volume: f32) -> Result<(), BackendSpecificError> {
+        let volume = volume.clamp(0.0, 1.0);
+        match &self.mixer {
+            Some((mixer, element)) => {
+                let mixer = mixer.lock().unwrap();
+                let selem = mixer
+                    .find_selem(&alsa::mixer::SelemId::new(element, 0))
+                    .ok_or_else(|| BackendSpecificError {
+                        description: format!("the mixer element `{}` went away", element),
+                    })?;
+                let (min, max) = selem.get_playback_volume_range();
+                let value = min + ((max - min) as f32 * volume).round() as i64;
+                selem.set_playback_volume_all(value)?;
+            }
+            None => self.software.store(volume.to_bits(), Ordering::Relaxed),
+        }
+        Ok(())
+    }
+
+    /// The factor the data callback should multiply the samples by: the volume when it is
+    /// applied in software, `1.0` when it goes through a mixer element.
+    pub fn gain(&self) -> f32 {
+        match self.mixer {
+            Some(_) => 1.0,
+            None => f32::from_bits(self.software.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Device {
+    /// The names of the simple mixer elements with a playback volume on this device's sound
+    /// card, e.g. `Master`, `PCM` or the control of a `softvol` plugin, for
+    /// [`attach_mixer`](Self::attach_mixer).
+    ///
+    /// Devices that aren't tied to a card (such as `default`) list the default mixer.
+    pub fn mixer_elements(&self) -> Result<Vec<String>, BackendSpecificError> {
+        let mixer = alsa::mixer::Mixer::new(&self.mixer_name(), false)?;
+        let elements = mixer
+            .iter()
+            .filter_map(alsa::mixer::Selem::new)
+            .filter(|selem| selem.has_playback_volume())
+            .filter_map(|selem| {
+                let id = selem.get_id();
+                // Further elements of the same name aren't reachable by name alone.
+                if id.get_index() != 0 {
+                    return None;
+                }
+                id.get_name().ok().map(str::to_owned)
+            })
+            .collect();
+        Ok(elements)
+    }
+
+    /// Bind a volume control to the mixer element named `element` on this device's sound card,
+    /// one of those listed by [`mixer_elements`](Self::mixer_elements).
+    ///
+    /// If the mixer can't be opened or has no such element with a playback volume, the returned
+    /// control applies the volume in software instead. See [`MixerVolume`].
+    pub fn attach_mixer(&self, element: &str) -> MixerVolume {
+        let mixer = alsa::mixer::Mixer::new(&self.mixer_name(), false)
+            .ok()
+            .filter(|mixer| element_volume(mixer, element).is_some())
+            .map(|mixer| (Mutex::new(mixer), element.to_owned()));
+        MixerVolume {
+            mixer,
+            software: AtomicU32::new(1.0f32.to_bits()),
+        }
+    }
+
+    // The mixer controlling the card, e.g. `hw:PCH` for `front:CARD=PCH,DEV=0`.
+    fn mixer_name(&self) -> String {
+        match self.card() {
+            Some(card) => format!("hw:{}", card),
+            None => "default".to_owned(),
+        }
+    }
+
+    /// Call `callback` with the new volume, between `0.0` and `1.0`, whenever the playback volume
+    /// of this device's sound card is changed, e.g. from the system mixer.
+    ///
+    /// The volume is read from the card's `Master` control, or from `PCM` if it has none.
+    /// Devices that aren't tied to a card (such as `default`) use the default mixer.
+    pub fn on_volume_changed<F>(&self, callback: F) -> Result<VolumeListener, BackendSpecificError>
+    where
+        F: FnMut(f32) + Send + 'static,
+    {
+        let mixer_name = self.mixer_name();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("cpal_alsa_volume".to_owned())
+                .spawn(move || watch_volume(&mixer_name, &stop, ready_tx, callback))
+                .map_err(|e| BackendSpecificError {
+                    description: format!("failed to spawn the volume watcher thread: {}", e),
+                })?
+        };
+        // Wait for the mixer to be opened so that errors are reported here.
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(VolumeListener {
+                stop,
+                thread: Some(thread),
+            }),
+            Ok(Err(err)) => {
+                let _ = thread.join();
+                Err(err)
+            }
+            Err(_) => Err(BackendSpecificError {
+                description: "the volume watcher thread exited unexpectedly".to_owned(),
+            }),
+        }
+    }
+}
+
+impl Drop for VolumeListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn watch_volume<F>(
+    mixer_name: &str,
+    stop: &AtomicBool,
+    ready: mpsc::Sender<Result<(), BackendSpecificError>>,
+    mut callback: F,
+) where
+    F: FnMut(f32),
+{
+    let mixer = match alsa::mixer::Mixer::new(mixer_name, true) {
+        Ok(mixer) => mixer,
+        Err(err) => {
+            let _ = ready.send(Err(err.into()));
+            return;
+        }
+    };
+    let volume = || {
+        ELEMENT_NAMES
+            .iter()
+            .find_map(|name| element_volume(&mixer, name))
+    };
+    let mut last = match volume() {
+        Some(volume) => volume,
+        None => {
+            let description = format!("`{}` has no playback volume control", mixer_name);
+            let _ = ready.send(Err(BackendSpecificError { description }));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(()));
+
+    while !stop.load(Ordering::Relaxed) {
+        if mixer.wait(Some(POLL_TIMEOUT_MS)).is_err() || mixer.handle_events().is_err() {
+            return;
+        }
+        if let Some(volume) = volume() {
+            if volume != last {
+                last = volume;
+                callback(volume);
+            }
+        }
+    }
+}
+
+// The playback volume of the simple element `name`, between `0.0` and `1.0`, or `None` if the
+// mixer has no such element with a playback volume.
+fn element_volume(mixer: &alsa::mixer::Mixer, name: &str) -> Option<f32> {
+    let selem = mixer.find_selem(&alsa::mixer::SelemId::new(name, 0))?;
+    if !selem.has_playback_volume() {
+        return None;
+    }
+    let (min, max) = selem.get_playback_volume_range();
+    let value = selem
+        .get_playback_volume(alsa::mixer::SelemChannelId::mono())
+        .ok()?;
+    if max <= min {
+        return Some(1.0);
+    }
+    Some((value - min) as f32 / (max - min) as f32)
+}
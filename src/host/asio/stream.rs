@@ -945,6 +945,6 @@ unsafe fn apply_input_callback_to_data<A, D>(
         .sub(delay)
         .expect("`capture` occurs before origin of alsa `StreamInstant`");
     let timestamp = crate::InputStreamTimestamp { callback, capture };
-    let info = InputCallbackInfo { timestamp };
+    let info = InputCallbackInfo::new(timestamp);
     data_callback(&data, &info);
 }
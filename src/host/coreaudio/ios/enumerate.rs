@@ -19,7 +19,7 @@ impl Devices {
 
 impl Default for Devices {
     fn default() -> Devices {
-        Devices(vec![Device].into_iter())
+        Devices(vec![Device::default()].into_iter())
     }
 }
 
@@ -34,10 +34,10 @@ impl Iterator for Devices {
 
 #[inline]
 pub fn default_input_device() -> Option<Device> {
-    Some(Device)
+    Some(Device::default())
 }
 
 #[inline]
 pub fn default_output_device() -> Option<Device> {
-    Some(Device)
+    Some(Device::default())
 }
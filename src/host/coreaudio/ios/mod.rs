@@ -19,7 +19,10 @@ use self::coreaudio::sys::{
     AudioStreamBasicDescription,
 };
 
-use super::{asbd_from_config, frames_to_duration, host_time_to_stream_instant};
+use super::{
+    asbd_from_config, configure_voice_processing, frames_to_duration, host_time_to_stream_instant,
+    VoiceProcessing,
+};
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 
 use crate::{
@@ -41,8 +44,10 @@ pub mod enumerate;
 // These days the default of iOS is now F32 and no longer I16
 const SUPPORTED_SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Device;
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Device {
+    voice_processing: Option<VoiceProcessing>,
+}
 
 pub struct Host;
 
@@ -74,6 +79,22 @@ impl HostTrait for Host {
 }
 
 impl Device {
+    /// Build streams for this device on Apple's voice-processing I/O unit instead of the RemoteIO
+    /// unit.
+    ///
+    /// Echo cancellation applies to audio captured while a voice-processing output stream is
+    /// playing, so for VoIP both the input and the output stream should be built from devices with
+    /// voice processing enabled. Pass `None` to go back to the RemoteIO unit.
+    pub fn with_voice_processing(mut self, settings: Option<VoiceProcessing>) -> Self {
+        self.voice_processing = settings;
+        self
+    }
+
+    /// The voice-processing settings streams for this device are built with, if any.
+    pub fn voice_processing(&self) -> Option<VoiceProcessing> {
+        self.voice_processing
+    }
+
     #[inline]
     fn name(&self) -> Result<String, DeviceNameError> {
         Ok("Default Device".to_owned())
@@ -185,7 +206,7 @@ impl DeviceTrait for Device {
         let scope = Scope::Output;
         let element = Element::Input;
 
-        let mut audio_unit = create_audio_unit()?;
+        let mut audio_unit = create_audio_unit(self.voice_processing)?;
         audio_unit.uninitialize()?;
         configure_for_recording(&mut audio_unit)?;
         audio_unit.initialize()?;
@@ -221,6 +242,9 @@ impl DeviceTrait for Device {
 
             let data = data as *mut ();
             let len = (data_byte_size as usize / bytes_per_channel) as usize;
+            if len == 0 {
+                return Ok(());
+            }
             let data = Data::from_parts(data, len, sample_format);
 
             // TODO: Need a better way to get delay, for now we assume a double-buffer offset.
@@ -238,7 +262,7 @@ impl DeviceTrait for Device {
                 .expect("`capture` occurs before origin of alsa `StreamInstant`");
             let timestamp = crate::InputStreamTimestamp { callback, capture };
 
-            let info = InputCallbackInfo { timestamp };
+            let info = InputCallbackInfo::new(timestamp);
             data_callback(&data, &info);
             Ok(())
         })?;
@@ -271,7 +295,7 @@ impl DeviceTrait for Device {
             BufferSize::Default => (),
         };
 
-        let mut audio_unit = create_audio_unit()?;
+        let mut audio_unit = create_audio_unit(self.voice_processing)?;
 
         // The scope and element for working with a device's output stream.
         let scope = Scope::Input;
@@ -298,6 +322,9 @@ impl DeviceTrait for Device {
 
             let data = data as *mut ();
             let len = (data_byte_size as usize / bytes_per_channel) as usize;
+            if len == 0 {
+                return Ok(());
+            }
             let mut data = Data::from_parts(data, len, sample_format);
 
             let callback = match host_time_to_stream_instant(args.time_stamp.mHostTime) {
@@ -377,8 +404,17 @@ struct StreamInner {
     audio_unit: AudioUnit,
 }
 
-fn create_audio_unit() -> Result<AudioUnit, coreaudio::Error> {
-    AudioUnit::new(coreaudio::audio_unit::IOType::RemoteIO)
+fn create_audio_unit(
+    voice_processing: Option<VoiceProcessing>,
+) -> Result<AudioUnit, coreaudio::Error> {
+    match voice_processing {
+        Some(settings) => {
+            let mut audio_unit = AudioUnit::new(coreaudio::audio_unit::IOType::VoiceProcessingIO)?;
+            configure_voice_processing(&mut audio_unit, settings)?;
+            Ok(audio_unit)
+        }
+        None => AudioUnit::new(coreaudio::audio_unit::IOType::RemoteIO),
+    }
 }
 
 fn configure_for_recording(audio_unit: &mut AudioUnit) -> Result<(), coreaudio::Error> {
@@ -404,7 +440,7 @@ fn configure_for_recording(audio_unit: &mut AudioUnit) -> Result<(), coreaudio::
 }
 
 fn default_output_asbd() -> Result<AudioStreamBasicDescription, coreaudio::Error> {
-    let audio_unit = create_audio_unit()?;
+    let audio_unit = create_audio_unit(None)?;
     let id = kAudioUnitProperty_StreamFormat;
     let asbd: AudioStreamBasicDescription =
         audio_unit.get_property(id, Scope::Output, Element::Output)?;
@@ -412,7 +448,7 @@ fn default_output_asbd() -> Result<AudioStreamBasicDescription, coreaudio::Error
 }
 
 fn default_input_asbd() -> Result<AudioStreamBasicDescription, coreaudio::Error> {
-    let mut audio_unit = create_audio_unit()?;
+    let mut audio_unit = create_audio_unit(None)?;
     audio_unit.uninitialize()?;
     configure_for_recording(&mut audio_unit)?;
     audio_unit.initialize()?;
@@ -0,0 +1,315 @@
+//! Aggregate devices combine several physical devices into one, so that separate input and output
+//! interfaces can be driven from a single clock.
+
+extern crate core_foundation_sys;
+extern crate coreaudio;
+
+use self::core_foundation_sys::array::{
+    kCFTypeArrayCallBacks, CFArrayAppendValue, CFArrayCreateMutable,
+};
+use self::core_foundation_sys::base::{kCFAllocatorDefault, CFRelease, CFRetain, CFTypeRef};
+use self::core_foundation_sys::dictionary::{
+    kCFTypeDictionaryKeyCallBacks, kCFTypeDictionaryValueCallBacks, CFDictionaryCreateMutable,
+    CFDictionarySetValue, CFMutableDictionaryRef,
+};
+use self::core_foundation_sys::number::{kCFNumberSInt32Type, CFNumberCreate};
+use self::core_foundation_sys::string::{
+    kCFStringEncodingUTF8, CFStringCreateWithBytes, CFStringRef,
+};
+use self::coreaudio::sys::{
+    kAudioAggregateDevicePropertyActiveSubDeviceList, kAudioDevicePropertyDeviceUID,
+    kAudioDevicePropertyTransportType, kAudioDeviceTransportTypeAggregate,
+    kAudioObjectPropertyElementMaster, kAudioObjectPropertyScopeGlobal,
+    kAudioSubDevicePropertyDriftCompensation, AudioDeviceID, AudioHardwareCreateAggregateDevice,
+    AudioHardwareDestroyAggregateDevice, AudioObjectGetPropertyData,
+    AudioObjectGetPropertyDataSize, AudioObjectID, AudioObjectPropertyAddress,
+};
+use super::{check_os_status, Device};
+use crate::BackendSpecificError;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr::null;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Keys of the aggregate device description, from `AudioHardware.h`.
+const AGGREGATE_DEVICE_UID_KEY: &str = "uid";
+const AGGREGATE_DEVICE_NAME_KEY: &str = "name";
+const AGGREGATE_DEVICE_SUB_DEVICE_LIST_KEY: &str = "subdevices";
+const AGGREGATE_DEVICE_MAIN_SUB_DEVICE_KEY: &str = "master";
+const AGGREGATE_DEVICE_IS_PRIVATE_KEY: &str = "private";
+const SUB_DEVICE_UID_KEY: &str = "uid";
+const SUB_DEVICE_DRIFT_COMPENSATION_KEY: &str = "drift";
+
+/// An aggregate device created by [`AggregateDevice::new`].
+///
+/// The aggregate device is private to this process and is destroyed when this handle is dropped.
+/// Streams built from it should be dropped first.
+#[derive(Debug)]
+pub struct AggregateDevice {
+    device: Device,
+}
+
+impl AggregateDevice {
+    /// Combine `devices` into a new aggregate device called `name`.
+    ///
+    /// The first device provides the clock. If `drift_compensation` is set, the other devices are
+    /// resampled to follow it, which is necessary unless all of them share a clock source.
+    pub fn new(
+        name: &str,
+        devices: &[Device],
+        drift_compensation: bool,
+    ) -> Result<Self, BackendSpecificError> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        if devices.is_empty() {
+            return Err(BackendSpecificError {
+                description: "an aggregate device needs at least one sub-device".to_string(),
+            });
+        }
+        let uids = devices
+            .iter()
+            .map(Device::uid)
+            .collect::<Result<Vec<_>, _>>()?;
+        let uid = format!(
+            "cpal.aggregate.{}.{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+
+        unsafe {
+            let description = Dictionary::new();
+            description.set(AGGREGATE_DEVICE_UID_KEY, cf_string(&uid));
+            description.set(AGGREGATE_DEVICE_NAME_KEY, cf_string(name));
+            description.set(AGGREGATE_DEVICE_IS_PRIVATE_KEY, cf_number(1));
+            description.set(
+                AGGREGATE_DEVICE_MAIN_SUB_DEVICE_KEY,
+                CFRetain(uids[0].0 as CFTypeRef),
+            );
+
+            let sub_devices = CFArrayCreateMutable(kCFAllocatorDefault, 0, &kCFTypeArrayCallBacks);
+            for (i, uid) in uids.into_iter().enumerate() {
+                let sub_device = Dictionary::new();
+                sub_device.set(SUB_DEVICE_UID_KEY, uid.into_raw());
+                let drift = drift_compensation && i > 0;
+                sub_device.set(SUB_DEVICE_DRIFT_COMPENSATION_KEY, cf_number(drift as i32));
+                CFArrayAppendValue(sub_devices, sub_device.0 as *const c_void);
+            }
+            description.set(
+                AGGREGATE_DEVICE_SUB_DEVICE_LIST_KEY,
+                sub_devices as CFTypeRef,
+            );
+
+            let mut audio_device_id: AudioDeviceID = 0;
+            let status =
+                AudioHardwareCreateAggregateDevice(description.0 as _, &mut audio_device_id);
+            check_os_status(status)?;
+
+            Ok(AggregateDevice {
+                device: Device {
+                    audio_device_id,
+                    is_default: false,
+                    voice_processing: None,
+                },
+            })
+        }
+    }
+
+    /// The aggregate device, for building streams.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+}
+
+impl Drop for AggregateDevice {
+    fn drop(&mut self) {
+        unsafe {
+            AudioHardwareDestroyAggregateDevice(self.device.audio_device_id);
+        }
+    }
+}
+
+impl Device {
+    /// Whether this is an aggregate device combining several other devices.
+    ///
+    /// The channels of an aggregate device are those of its sub-devices combined.
+    pub fn is_aggregate(&self) -> bool {
+        let property_address = AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyTransportType,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        };
+        let transport_type = 0u32;
+        let data_size = mem::size_of::<u32>() as u32;
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                self.audio_device_id,
+                &property_address as *const _,
+                0,
+                null(),
+                &data_size as *const _ as *mut _,
+                &transport_type as *const _ as *mut _,
+            )
+        };
+        // Devices that can't be queried (e.g. because they were just unplugged) aren't reported as
+        // aggregates.
+        check_os_status(status).is_ok()
+            && transport_type == kAudioDeviceTransportTypeAggregate as u32
+    }
+
+    /// Whether the sub-devices of this aggregate device are resampled to follow the clock of its
+    /// main sub-device, as set with the `drift_compensation` argument of
+    /// [`AggregateDevice::new`] or in Audio MIDI Setup.
+    ///
+    /// This is `true` if any sub-device compensates for drift, and `None` if this isn't an
+    /// aggregate device or its sub-devices can't be queried.
+    pub fn drift_compensation(&self) -> Option<bool> {
+        if !self.is_aggregate() {
+            return None;
+        }
+        let mut compensated = false;
+        for sub_device in active_sub_devices(self.audio_device_id)? {
+            let property_address = AudioObjectPropertyAddress {
+                mSelector: kAudioSubDevicePropertyDriftCompensation,
+                mScope: kAudioObjectPropertyScopeGlobal,
+                mElement: kAudioObjectPropertyElementMaster,
+            };
+            let drift = 0u32;
+            let data_size = mem::size_of::<u32>() as u32;
+            let status = unsafe {
+                AudioObjectGetPropertyData(
+                    sub_device,
+                    &property_address as *const _,
+                    0,
+                    null(),
+                    &data_size as *const _ as *mut _,
+                    &drift as *const _ as *mut _,
+                )
+            };
+            check_os_status(status).ok()?;
+            compensated |= drift != 0;
+        }
+        Some(compensated)
+    }
+
+    // The persistent UID of the device.
+    fn uid(&self) -> Result<CfString, BackendSpecificError> {
+        let property_address = AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyDeviceUID,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        };
+        let uid: CFStringRef = null();
+        let data_size = mem::size_of::<CFStringRef>() as u32;
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                self.audio_device_id,
+                &property_address as *const _,
+                0,
+                null(),
+                &data_size as *const _ as *mut _,
+                &uid as *const _ as *mut _,
+            )
+        };
+        check_os_status(status)?;
+        Ok(CfString(uid))
+    }
+}
+
+// The IDs of the sub-devices of the aggregate device `device_id` that are currently present.
+fn active_sub_devices(device_id: AudioDeviceID) -> Option<Vec<AudioObjectID>> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioAggregateDevicePropertyActiveSubDeviceList,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    unsafe {
+        let data_size = 0u32;
+        let status = AudioObjectGetPropertyDataSize(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+        );
+        check_os_status(status).ok()?;
+        let mut sub_devices: Vec<AudioObjectID> =
+            vec![0; data_size as usize / mem::size_of::<AudioObjectID>()];
+        let status = AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            sub_devices.as_mut_ptr() as *mut _,
+        );
+        check_os_status(status).ok()?;
+        sub_devices.truncate(data_size as usize / mem::size_of::<AudioObjectID>());
+        Some(sub_devices)
+    }
+}
+
+// A `CFString` owned by us, released on drop unless handed over with `into_raw`.
+struct CfString(CFStringRef);
+
+impl CfString {
+    // Give up ownership of the string, e.g. to a `Dictionary`.
+    fn into_raw(self) -> CFTypeRef {
+        let string = self.0 as CFTypeRef;
+        mem::forget(self);
+        string
+    }
+}
+
+impl Drop for CfString {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { CFRelease(self.0 as CFTypeRef) }
+        }
+    }
+}
+
+// A mutable `CFDictionary` with `CFString` keys, released on drop.
+struct Dictionary(CFMutableDictionaryRef);
+
+impl Dictionary {
+    unsafe fn new() -> Self {
+        Dictionary(CFDictionaryCreateMutable(
+            kCFAllocatorDefault,
+            0,
+            &kCFTypeDictionaryKeyCallBacks,
+            &kCFTypeDictionaryValueCallBacks,
+        ))
+    }
+
+    // Insert `value`, taking ownership of it.
+    unsafe fn set(&self, key: &str, value: CFTypeRef) {
+        let key = cf_string(key);
+        CFDictionarySetValue(self.0, key, value);
+        CFRelease(key);
+        CFRelease(value);
+    }
+}
+
+impl Drop for Dictionary {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.0 as CFTypeRef) }
+    }
+}
+
+unsafe fn cf_string(s: &str) -> CFTypeRef {
+    CFStringCreateWithBytes(
+        kCFAllocatorDefault,
+        s.as_ptr(),
+        s.len() as _,
+        kCFStringEncodingUTF8,
+        false as _,
+    ) as CFTypeRef
+}
+
+unsafe fn cf_number(n: i32) -> CFTypeRef {
+    CFNumberCreate(
+        kCFAllocatorDefault,
+        kCFNumberSInt32Type,
+        &n as *const i32 as *const c_void,
+    ) as CFTypeRef
+}
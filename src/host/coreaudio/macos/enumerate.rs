@@ -84,6 +84,7 @@ impl Iterator for Devices {
         self.0.next().map(|id| Device {
             audio_device_id: id,
             is_default: false,
+            voice_processing: None,
         })
     }
 }
@@ -114,6 +115,7 @@ pub fn default_input_device() -> Option<Device> {
     let device = Device {
         audio_device_id,
         is_default: true,
+        voice_processing: None,
     };
     Some(device)
 }
@@ -144,6 +146,7 @@ pub fn default_output_device() -> Option<Device> {
     let device = Device {
         audio_device_id,
         is_default: true,
+        voice_processing: None,
     };
     Some(device)
 }
@@ -0,0 +1,284 @@
+// Output buffers filled outside the render callback and handed to CoreAudio without copying.
+//
+// Three buffers rotate between the producer and the render callback. The producer fills its back
+// buffer and publishes it as the middle one, and the render callback takes the published buffer
+// as its front one and points CoreAudio at its memory instead of copying it. The producer only
+// gets a buffer to fill once the one it published last has been taken, so at most one buffer is
+// ever queued, which is all the latency the handoff adds.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::frames_to_duration;
+use crate::{ChannelCount, Data, FrameCount, SampleFormat, SampleRate};
+
+// Set in `Shared::middle` while the middle buffer has been published and not taken yet.
+const PUBLISHED: u8 = 0b100;
+
+struct Shared {
+    // Backed by `u128`s, which aligns them to 16 bytes as CoreAudio expects of render buffers.
+    buffers: [UnsafeCell<Box<[u128]>>; 3],
+    // The index of the middle buffer, with `PUBLISHED` set once it is ready to be played.
+    middle: AtomicU8,
+    sample_format: SampleFormat,
+    // The number of samples in each buffer.
+    len: usize,
+}
+
+// The producer only touches its back buffer and the render callback its front buffer, and the
+// two only change hands through `middle`.
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn buffer(&self, index: u8) -> *mut u8 {
+        unsafe { (*self.buffers[index as usize].get()).as_mut_ptr() as *mut u8 }
+    }
+
+    fn len_bytes(&self) -> usize {
+        self.len * self.sample_format.sample_size()
+    }
+}
+
+/// The producer side of an output stream built with
+/// [`Device::build_output_stream_buffered`](super::Device::build_output_stream_buffered).
+///
+/// Each buffer holds exactly one device buffer of interleaved frames. A buffer is played once the
+/// device has played the one before it, so the handoff adds exactly one buffer of latency (see
+/// [`latency`](Self::latency)). The render callback plays silence when no buffer is ready in time.
+pub struct OutputBuffers {
+    shared: Arc<Shared>,
+    back: u8,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+}
+
+/// A buffer to fill, from [`OutputBuffers::next_buffer`].
+pub struct OutputBuffer<'a> {
+    buffers: &'a mut OutputBuffers,
+    data: Data,
+}
+
+// The render callback's side of the handoff.
+pub(super) struct Renderer {
+    shared: Arc<Shared>,
+    front: u8,
+    // The bytes of the front buffer already played, all of them once it has been played out.
+    position: usize,
+}
+
+// Create the two ends of a handoff of buffers of `frames` frames.
+pub(super) fn handoff(
+    frames: FrameCount,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    sample_format: SampleFormat,
+) -> (OutputBuffers, Renderer) {
+    let len = frames as usize * channels as usize;
+    let words = (len * sample_format.sample_size() + 15) / 16;
+    let buffer = || UnsafeCell::new(vec![0u128; words].into_boxed_slice());
+    let shared = Arc::new(Shared {
+        buffers: [buffer(), buffer(), buffer()],
+        middle: AtomicU8::new(1),
+        sample_format,
+        len,
+    });
+    let renderer = Renderer {
+        shared: shared.clone(),
+        front: 0,
+        position: shared.len_bytes(),
+    };
+    let buffers = OutputBuffers {
+        shared,
+        back: 2,
+        channels,
+        sample_rate,
+    };
+    (buffers, renderer)
+}
+
+impl OutputBuffers {
+    /// The buffer to fill next, or `None` while the device hasn't taken the last one finished.
+    ///
+    /// The buffer holds whatever was played from it last; all of it is played once it is
+    /// finished.
+    pub fn next_buffer(&mut self) -> Option<OutputBuffer<'_>> {
+        if self.shared.middle.load(Ordering::Acquire) & PUBLISHED != 0 {
+            return None;
+        }
+        let data = self.shared.buffer(self.back) as *mut ();
+        let data = unsafe { Data::from_parts(data, self.shared.len, self.shared.sample_format) };
+        Some(OutputBuffer {
+            buffers: self,
+            data,
+        })
+    }
+
+    /// The number of frames in each buffer.
+    pub fn buffer_frames(&self) -> FrameCount {
+        (self.shared.len / self.channels as usize) as FrameCount
+    }
+
+    /// The latency the handoff adds to the device's own, exactly one buffer.
+    pub fn latency(&self) -> Duration {
+        frames_to_duration(self.buffer_frames() as usize, self.sample_rate)
+    }
+}
+
+impl OutputBuffer<'_> {
+    /// The samples of the buffer, interleaved.
+    pub fn data(&mut self) -> &mut Data {
+        &mut self.data
+    }
+
+    /// Queue the buffer to be played after the one the device is playing.
+    pub fn finish(self) {
+        let shared = &self.buffers.shared;
+        let previous = shared
+            .middle
+            .swap(self.buffers.back | PUBLISHED, Ordering::AcqRel);
+        self.buffers.back = previous & !PUBLISHED;
+    }
+}
+
+impl Renderer {
+    // Make the published buffer the front one, if there is one.
+    fn take(&mut self) -> bool {
+        if self.shared.middle.load(Ordering::Acquire) & PUBLISHED == 0 {
+            return false;
+        }
+        let previous = self.shared.middle.swap(self.front, Ordering::AcqRel);
+        self.front = previous & !PUBLISHED;
+        self.position = 0;
+        true
+    }
+
+    // Fill the `len` bytes CoreAudio asks for at `data`.
+    //
+    // When they are exactly one whole buffer, returns the published buffer for CoreAudio to play
+    // in place of `data`. It stays the front buffer, and so untouched by the producer, until the
+    // next render. Otherwise, e.g. while the audio unit converts the sample rate and asks for
+    // varying amounts, copies from the buffers into `data`. Plays silence for whatever isn't
+    // ready in time.
+    pub(super) unsafe fn render(&mut self, data: *mut u8, len: usize) -> Option<*mut u8> {
+        let buffer_len = self.shared.len_bytes();
+        if len == buffer_len && self.position == buffer_len && self.take() {
+            self.position = buffer_len;
+            return Some(self.shared.buffer(self.front));
+        }
+        let mut written = 0;
+        while written < len {
+            if self.position == buffer_len && !self.take() {
+                let sample_size = self.shared.sample_format.sample_size();
+                let rest = data.add(written) as *mut ();
+                let mut silence = Data::from_parts(
+                    rest,
+                    (len - written) / sample_size,
+                    self.shared.sample_format,
+                );
+                silence.fill_from_f32(|| 0.0);
+                break;
+            }
+            let count = (len - written).min(buffer_len - self.position);
+            let front = self.shared.buffer(self.front);
+            std::ptr::copy_nonoverlapping(front.add(self.position), data.add(written), count);
+            self.position += count;
+            written += count;
+        }
+        None
+    }
+}
+
+#[test]
+fn test_handoff() {
+    // Two stereo frames per buffer.
+    let (mut buffers, mut renderer) = handoff(2, 2, SampleRate(48000), SampleFormat::F32);
+    assert_eq!(buffers.buffer_frames(), 2);
+    assert_eq!(buffers.latency(), frames_to_duration(2, SampleRate(48000)));
+    let fill = |buffers: &mut OutputBuffers, first: f32| match buffers.next_buffer() {
+        Some(mut buffer) => {
+            let samples = buffer.data().as_slice_mut::<f32>().unwrap();
+            for (i, sample) in samples.iter_mut().enumerate() {
+                *sample = first + i as f32;
+            }
+            buffer.finish();
+            true
+        }
+        None => false,
+    };
+    let mut device = [0.0f32; 4];
+    let render = |renderer: &mut Renderer, device: &mut [f32]| unsafe {
+        let len = device.len() * 4;
+        match renderer.render(device.as_mut_ptr() as *mut u8, len) {
+            Some(buffer) => std::slice::from_raw_parts(buffer as *const f32, device.len()).to_vec(),
+            None => device.to_vec(),
+        }
+    };
+
+    // Nothing published yet plays silence.
+    device.fill(9.0);
+    assert_eq!(render(&mut renderer, &mut device), [0.0; 4]);
+
+    // Whole buffers are handed over in place, one at a time.
+    assert!(fill(&mut buffers, 1.0));
+    assert!(!fill(&mut buffers, 100.0));
+    assert_eq!(render(&mut renderer, &mut device), [1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(device, [0.0; 4]);
+    assert!(fill(&mut buffers, 5.0));
+    assert_eq!(render(&mut renderer, &mut device), [5.0, 6.0, 7.0, 8.0]);
+
+    // Requests of other sizes are copied across buffers, and run into silence when the producer
+    // falls behind.
+    assert!(fill(&mut buffers, 9.0));
+    let mut short = [0.0f32; 3];
+    assert_eq!(render(&mut renderer, &mut short), [9.0, 10.0, 11.0]);
+    assert!(fill(&mut buffers, 13.0));
+    let mut long = [0.0f32; 6];
+    long.fill(9.0);
+    assert_eq!(
+        render(&mut renderer, &mut long),
+        [12.0, 13.0, 14.0, 15.0, 16.0, 0.0]
+    );
+    assert!(fill(&mut buffers, 17.0));
+    assert_eq!(render(&mut renderer, &mut device), [17.0, 18.0, 19.0, 20.0]);
+}
+
+#[test]
+fn test_handoff_across_threads() {
+    let (mut buffers, mut renderer) = handoff(64, 1, SampleRate(48000), SampleFormat::I32);
+    let producer = std::thread::spawn(move || {
+        // Counting from one, so that samples can be told from silence.
+        let mut next = 1;
+        while next <= 64 * 1000 {
+            match buffers.next_buffer() {
+                Some(mut buffer) => {
+                    for sample in buffer.data().as_slice_mut::<i32>().unwrap() {
+                        *sample = next;
+                        next += 1;
+                    }
+                    buffer.finish();
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+    });
+    // Every sample arrives once and in order, with only silence in between.
+    let mut expected = 1;
+    let mut device = vec![0i32; 64];
+    while expected <= 64 * 1000 {
+        let played = match unsafe { renderer.render(device.as_mut_ptr() as *mut u8, 64 * 4) } {
+            Some(buffer) => unsafe { std::slice::from_raw_parts(buffer as *const i32, 64) },
+            None => &device[..],
+        };
+        if played.iter().all(|&sample| sample == 0) {
+            std::thread::yield_now();
+            continue;
+        }
+        for &sample in played {
+            assert_eq!(sample, expected);
+            expected += 1;
+        }
+    }
+    producer.join().unwrap();
+}
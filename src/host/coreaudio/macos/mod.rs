@@ -1,17 +1,23 @@
 extern crate core_foundation_sys;
 extern crate coreaudio;
 
-use super::{asbd_from_config, check_os_status, frames_to_duration, host_time_to_stream_instant};
+use super::{
+    asbd_from_config, check_os_status, configure_voice_processing, frames_to_duration,
+    host_time_to_stream_instant, VoiceProcessing,
+};
 
+use self::core_foundation_sys::base::CFRelease;
 use self::core_foundation_sys::string::{CFStringGetCString, CFStringGetCStringPtr, CFStringRef};
 use self::coreaudio::audio_unit::render_callback::{self, data};
 use self::coreaudio::audio_unit::{AudioUnit, Element, Scope};
 use self::coreaudio::sys::{
     kAudioDevicePropertyAvailableNominalSampleRates, kAudioDevicePropertyBufferFrameSize,
     kAudioDevicePropertyBufferFrameSizeRange, kAudioDevicePropertyDeviceIsAlive,
-    kAudioDevicePropertyDeviceNameCFString, kAudioDevicePropertyNominalSampleRate,
-    kAudioDevicePropertyScopeOutput, kAudioDevicePropertyStreamConfiguration,
-    kAudioDevicePropertyStreamFormat, kAudioObjectPropertyElementMaster,
+    kAudioDevicePropertyDeviceNameCFString, kAudioDevicePropertyHogMode,
+    kAudioDevicePropertyLatency, kAudioDevicePropertyNominalSampleRate,
+    kAudioDevicePropertySafetyOffset, kAudioDevicePropertyScopeOutput,
+    kAudioDevicePropertyStreamConfiguration, kAudioDevicePropertyStreamFormat,
+    kAudioObjectPropertyElementMaster, kAudioObjectPropertyManufacturer,
     kAudioObjectPropertyScopeGlobal, kAudioObjectPropertyScopeInput,
     kAudioObjectPropertyScopeOutput, kAudioOutputUnitProperty_CurrentDevice,
     kAudioOutputUnitProperty_EnableIO, kAudioUnitProperty_StreamFormat, kCFStringEncodingUTF8,
@@ -23,13 +29,14 @@ use self::coreaudio::sys::{
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
     BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, Data,
-    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo,
-    PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    DefaultStreamConfigError, DeviceNameError, DevicesError, FrameCount, InputCallbackInfo,
+    OsProcessing, OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat, SampleRate,
+    StreamConfig, StreamError, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 use std::ffi::CStr;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::mem;
 use std::os::raw::c_char;
 use std::ptr::null;
@@ -38,14 +45,18 @@ use std::sync::mpsc::{channel, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+pub use self::aggregate::AggregateDevice;
 pub use self::enumerate::{
     default_input_device, default_output_device, Devices, SupportedInputConfigs,
     SupportedOutputConfigs,
 };
+pub use self::handoff::{OutputBuffer, OutputBuffers};
 
 use property_listener::AudioObjectPropertyListener;
 
+mod aggregate;
 pub mod enumerate;
+mod handoff;
 mod property_listener;
 
 /// Coreaudio host, the default host on macOS.
@@ -109,6 +120,11 @@ impl DeviceTrait for Device {
         Device::default_output_config(self)
     }
 
+    fn backend_info(&self) -> Option<String> {
+        self.manufacturer()
+            .map(|manufacturer| format!("made by {}", manufacturer))
+    }
+
     fn build_input_stream_raw<D, E>(
         &self,
         config: &StreamConfig,
@@ -154,13 +170,82 @@ impl DeviceTrait for Device {
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone)]
 pub struct Device {
     pub(crate) audio_device_id: AudioDeviceID,
     is_default: bool,
+    voice_processing: Option<VoiceProcessing>,
+}
+
+// Devices are identified by their ID alone, so that the default device compares equal to the same
+// device from the list of all devices.
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.audio_device_id == other.audio_device_id
+    }
+}
+
+impl Eq for Device {}
+
+impl Hash for Device {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.audio_device_id.hash(state);
+    }
 }
 
 impl Device {
+    /// Build streams for this device on Apple's voice-processing I/O unit instead of the HAL unit.
+    ///
+    /// Echo cancellation applies to audio captured while a voice-processing output stream is
+    /// playing, so for VoIP both the input and the output stream should be built from devices with
+    /// voice processing enabled. Pass `None` to go back to the HAL unit.
+    pub fn with_voice_processing(mut self, settings: Option<VoiceProcessing>) -> Self {
+        self.voice_processing = settings;
+        self
+    }
+
+    /// The voice-processing settings streams for this device are built with, if any.
+    pub fn voice_processing(&self) -> Option<VoiceProcessing> {
+        self.voice_processing
+    }
+
+    // The manufacturer the device reports, e.g. `Apple Inc.`.
+    fn manufacturer(&self) -> Option<String> {
+        let property_address = AudioObjectPropertyAddress {
+            mSelector: kAudioObjectPropertyManufacturer,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        };
+        let manufacturer: CFStringRef = null();
+        let data_size = mem::size_of::<CFStringRef>();
+        unsafe {
+            let status = AudioObjectGetPropertyData(
+                self.audio_device_id,
+                &property_address as *const _,
+                0,
+                null(),
+                &data_size as *const _ as *mut _,
+                &manufacturer as *const _ as *mut _,
+            );
+            check_os_status(status).ok()?;
+            if manufacturer.is_null() {
+                return None;
+            }
+            let mut buf: [c_char; 255] = [0; 255];
+            let result = CFStringGetCString(
+                manufacturer,
+                buf.as_mut_ptr(),
+                buf.len() as _,
+                kCFStringEncodingUTF8,
+            );
+            CFRelease(manufacturer as *const _);
+            if result == 0 {
+                return None;
+            }
+            Some(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+
     fn name(&self) -> Result<String, DeviceNameError> {
         let property_address = AudioObjectPropertyAddress {
             mSelector: kAudioDevicePropertyDeviceNameCFString,
@@ -456,12 +541,21 @@ struct StreamInner {
     audio_unit: AudioUnit,
     /// Manage the lifetime of the closure that handles device disconnection.
     _disconnect_listener: Option<AudioObjectPropertyListener>,
+    /// Manage the lifetime of the closure that handles other processes taking the device.
+    _hog_mode_listener: Option<AudioObjectPropertyListener>,
+    /// Manage the lifetime of the closure that handles the device's sample rate being changed.
+    _sample_rate_listener: Option<AudioObjectPropertyListener>,
+    // While another process holds the device in hog mode, whether the stream is to play once it
+    // is released.
+    interrupted: Option<bool>,
     // Track the device with which the audio unit was spawned.
     //
     // We must do this so that we can avoid changing the device sample rate if there is already
     // a stream associated with the device.
     #[allow(dead_code)]
     device_id: AudioDeviceID,
+    // The sample rate the stream was built with.
+    sample_rate: SampleRate,
 }
 
 impl StreamInner {
@@ -520,14 +614,184 @@ where
     Ok(())
 }
 
+/// Register the callback pausing the stream while another process holds the device in hog mode.
+/// The error callback is called with `Interrupted` when the device is taken and `Resumed` when it
+/// is released, after the stream plays again if it was playing.
+/// This function should only be called once per stream.
+fn add_hog_mode_listener<E>(
+    stream: &Stream,
+    error_callback: Arc<Mutex<E>>,
+) -> Result<(), BuildStreamError>
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let stream_inner_weak = Arc::downgrade(&stream.inner);
+    let mut stream_inner = stream.inner.lock().unwrap();
+    let device_id = stream_inner.device_id;
+    stream_inner._hog_mode_listener = Some(AudioObjectPropertyListener::new(
+        device_id,
+        AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyHogMode,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        },
+        move || {
+            let Some(stream_inner_strong) = stream_inner_weak.upgrade() else {
+                return;
+            };
+            let mut stream_inner = stream_inner_strong.lock().unwrap();
+            let taken = hog_mode_owner(device_id).is_some_and(|pid| pid != std::process::id());
+            let mut error_callback = error_callback.lock().unwrap();
+            match (taken, stream_inner.interrupted) {
+                (true, None) => {
+                    let playing = stream_inner.playing;
+                    let _ = stream_inner.pause();
+                    stream_inner.interrupted = Some(playing);
+                    error_callback(StreamError::Interrupted { resumable: true });
+                }
+                (false, Some(play)) => {
+                    stream_inner.interrupted = None;
+                    if play {
+                        if let Err(err) = stream_inner.play() {
+                            error_callback(match err {
+                                PlayStreamError::DeviceNotAvailable => {
+                                    StreamError::DeviceNotAvailable
+                                }
+                                PlayStreamError::BackendSpecific { err } => err.into(),
+                            });
+                        }
+                    }
+                    error_callback(StreamError::Resumed);
+                }
+                _ => (),
+            }
+        },
+    )?);
+    Ok(())
+}
+
+/// Register the callback stopping the stream when the device's sample rate is changed away from
+/// `sample_rate`, e.g. in Audio MIDI Setup. This will call the error callback with
+/// `DeviceInvalidated`.
+/// This function should only be called once per stream.
+fn add_sample_rate_listener<E>(
+    stream: &Stream,
+    sample_rate: SampleRate,
+    error_callback: Arc<Mutex<E>>,
+) -> Result<(), BuildStreamError>
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let stream_inner_weak = Arc::downgrade(&stream.inner);
+    let mut stream_inner = stream.inner.lock().unwrap();
+    let device_id = stream_inner.device_id;
+    stream_inner._sample_rate_listener = Some(AudioObjectPropertyListener::new(
+        device_id,
+        AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyNominalSampleRate,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        },
+        move || {
+            let Some(stream_inner_strong) = stream_inner_weak.upgrade() else {
+                return;
+            };
+            match nominal_sample_rate(device_id) {
+                Some(rate) if rate as u32 != sample_rate.0 => (),
+                _ => return,
+            }
+            let mut stream_inner = stream_inner_strong.lock().unwrap();
+            let _ = stream_inner.pause();
+            (error_callback.lock().unwrap())(StreamError::DeviceInvalidated);
+        },
+    )?);
+    Ok(())
+}
+
+// The current sample rate of `device_id`.
+fn nominal_sample_rate(device_id: AudioDeviceID) -> Option<f64> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyNominalSampleRate,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let rate: f64 = 0.0;
+    let data_size = mem::size_of::<f64>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &rate as *const _ as *mut _,
+        )
+    };
+    check_os_status(status).ok()?;
+    Some(rate)
+}
+
+// The size of the IO buffer of `device_id`, in frames.
+fn buffer_frame_size(device_id: AudioDeviceID) -> Option<FrameCount> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyBufferFrameSize,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let frames = 0u32;
+    let data_size = mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &frames as *const _ as *mut _,
+        )
+    };
+    check_os_status(status).ok()?;
+    Some(frames)
+}
+
+// The ID of the process holding `device_id` in hog mode, if any.
+fn hog_mode_owner(device_id: AudioDeviceID) -> Option<u32> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyHogMode,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    // A `pid_t`, which is -1 while no process holds the device.
+    let pid = -1i32;
+    let data_size = mem::size_of::<i32>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &pid as *const _ as *mut _,
+        )
+    };
+    check_os_status(status).ok()?;
+    u32::try_from(pid).ok()
+}
+
 fn audio_unit_from_device(device: &Device, input: bool) -> Result<AudioUnit, coreaudio::Error> {
-    let output_type = if device.is_default && !input {
+    let output_type = if device.voice_processing.is_some() {
+        coreaudio::audio_unit::IOType::VoiceProcessingIO
+    } else if device.is_default && !input {
         coreaudio::audio_unit::IOType::DefaultOutput
     } else {
         coreaudio::audio_unit::IOType::HalOutput
     };
     let mut audio_unit = AudioUnit::new(output_type)?;
 
+    if let Some(settings) = device.voice_processing {
+        configure_voice_processing(&mut audio_unit, settings)?;
+    }
+
     if input {
         // Enable input processing.
         let enable_input = 1u32;
@@ -617,6 +881,8 @@ impl Device {
         // fed to the audio buffer.
         let bytes_per_channel = sample_format.sample_size();
         let sample_rate = config.sample_rate;
+        // The sample time the next buffer starts at if the device didn't skip any.
+        let mut next_sample_time = None;
         type Args = render_callback::Args<data::Raw>;
         audio_unit.set_input_callback(move |args: Args| unsafe {
             let ptr = (*args.data.data).mBuffers.as_ptr();
@@ -632,6 +898,9 @@ impl Device {
 
             let data = data as *mut ();
             let len = data_byte_size as usize / bytes_per_channel;
+            if len == 0 {
+                return Ok(());
+            }
             let data = Data::from_parts(data, len, sample_format);
 
             // TODO: Need a better way to get delay, for now we assume a double-buffer offset.
@@ -649,7 +918,14 @@ impl Device {
                 .expect("`capture` occurs before origin of alsa `StreamInstant`");
             let timestamp = crate::InputStreamTimestamp { callback, capture };
 
-            let info = InputCallbackInfo { timestamp };
+            let sample_time = args.time_stamp.mSampleTime;
+            let discontinuity = next_sample_time.is_some_and(|next: f64| sample_time > next + 0.5);
+            next_sample_time = Some(sample_time + buffer_frames as f64);
+
+            let info = InputCallbackInfo {
+                timestamp,
+                discontinuity,
+            };
             data_callback(&data, &info);
             Ok(())
         })?;
@@ -657,15 +933,23 @@ impl Device {
         let stream = Stream::new(StreamInner {
             playing: true,
             _disconnect_listener: None,
+            _hog_mode_listener: None,
+            _sample_rate_listener: None,
+            interrupted: None,
             audio_unit,
             device_id: self.audio_device_id,
+            sample_rate: config.sample_rate,
         });
 
         // If we didn't request the default device, stop the stream if the
         // device disconnects.
         if !self.is_default {
-            add_disconnect_listener(&stream, error_callback_disconnect)?;
+            add_disconnect_listener(&stream, error_callback_disconnect.clone())?;
         }
+        add_hog_mode_listener(&stream, error_callback_disconnect.clone())?;
+        // The input unit doesn't convert the sample rate, so the stream can't continue if the
+        // device's rate is changed under it.
+        add_sample_rate_listener(&stream, config.sample_rate, error_callback_disconnect)?;
 
         stream.inner.lock().unwrap().audio_unit.start()?;
 
@@ -684,51 +968,19 @@ impl Device {
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        let mut audio_unit = audio_unit_from_device(self, false)?;
-
-        // The scope and element for working with a device's output stream.
-        let scope = Scope::Input;
-        let element = Element::Output;
-
-        // Set the stream in interleaved mode.
-        let asbd = asbd_from_config(config, sample_format);
-        audio_unit.set_property(kAudioUnitProperty_StreamFormat, scope, element, Some(&asbd))?;
-
-        // Set the buffersize
-        match config.buffer_size {
-            BufferSize::Fixed(v) => {
-                let buffer_size_range = get_io_buffer_frame_size_range(&audio_unit)?;
-                match buffer_size_range {
-                    SupportedBufferSize::Range { min, max } => {
-                        if v >= min && v <= max {
-                            audio_unit.set_property(
-                                kAudioDevicePropertyBufferFrameSize,
-                                scope,
-                                element,
-                                Some(&v),
-                            )?
-                        } else {
-                            return Err(BuildStreamError::StreamConfigNotSupported);
-                        }
-                    }
-                    SupportedBufferSize::Unknown => (),
-                }
-            }
-            BufferSize::Default => (),
-        }
-
+        let mut audio_unit = self.output_audio_unit(config, sample_format)?;
         let error_callback = Arc::new(Mutex::new(error_callback));
-        let error_callback_disconnect = error_callback.clone();
+        let error_callback_render = error_callback.clone();
 
         // Register the callback that is being called by coreaudio whenever it needs data to be
         // fed to the audio buffer.
         let bytes_per_channel = sample_format.sample_size();
         let sample_rate = config.sample_rate;
+        let device_latency = output_latency_frames(self.audio_device_id);
         type Args = render_callback::Args<data::Raw>;
         audio_unit.set_render_callback(move |args: Args| unsafe {
-            // If `run()` is currently running, then a callback will be available from this list.
-            // Otherwise, we just fill the buffer with zeroes and return.
-
+            // The stream is interleaved, so the user writes straight into the single buffer
+            // CoreAudio hands us; there is no intermediate copy.
             let AudioBuffer {
                 mNumberChannels: channels,
                 mDataByteSize: data_byte_size,
@@ -737,18 +989,21 @@ impl Device {
 
             let data = data as *mut ();
             let len = data_byte_size as usize / bytes_per_channel;
+            if len == 0 {
+                return Ok(());
+            }
             let mut data = Data::from_parts(data, len, sample_format);
 
             let callback = match host_time_to_stream_instant(args.time_stamp.mHostTime) {
                 Err(err) => {
-                    (error_callback.lock().unwrap())(err.into());
+                    (error_callback_render.lock().unwrap())(err.into());
                     return Err(());
                 }
                 Ok(cb) => cb,
             };
-            // TODO: Need a better way to get delay, for now we assume a double-buffer offset.
+            // The buffer is played once the device has consumed it, after its own latency.
             let buffer_frames = len / channels as usize;
-            let delay = frames_to_duration(buffer_frames, sample_rate);
+            let delay = frames_to_duration(buffer_frames + device_latency, sample_rate);
             let playback = callback
                 .add(delay)
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
@@ -759,18 +1014,126 @@ impl Device {
             Ok(())
         })?;
 
+        self.start_output_stream(audio_unit, config, error_callback)
+    }
+
+    /// Create an output stream that plays buffers filled outside the render callback, e.g. by a
+    /// thread rendering ahead of the device.
+    ///
+    /// Fill the buffers from [`OutputBuffers::next_buffer`] and
+    /// [`finish`](OutputBuffer::finish) them. A finished buffer is handed to CoreAudio as it is,
+    /// without copying, when it is exactly the size of the device's buffer, and only copied while
+    /// the audio unit converts the sample rate and asks for other amounts. Either way the handoff
+    /// adds exactly one buffer of latency, see [`OutputBuffers::latency`].
+    ///
+    /// The buffers hold `config.buffer_size` frames if it is fixed, otherwise the device's current
+    /// buffer size.
+    pub fn build_output_stream_buffered<E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        error_callback: E,
+    ) -> Result<(Stream, OutputBuffers), BuildStreamError>
+    where
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let mut audio_unit = self.output_audio_unit(config, sample_format)?;
+        let frames = match config.buffer_size {
+            BufferSize::Fixed(frames) => frames,
+            BufferSize::Default => {
+                buffer_frame_size(self.audio_device_id).ok_or_else(|| BackendSpecificError {
+                    description: "failed to read the device's buffer size".to_owned(),
+                })?
+            }
+        };
+        let (buffers, mut renderer) =
+            handoff::handoff(frames, config.channels, config.sample_rate, sample_format);
+
+        type Args = render_callback::Args<data::Raw>;
+        audio_unit.set_render_callback(move |args: Args| unsafe {
+            let buffer = &mut (*args.data.data).mBuffers[0];
+            let len = buffer.mDataByteSize as usize;
+            if let Some(front) = renderer.render(buffer.mData as *mut u8, len) {
+                // The audio unit plays whatever `mData` points to once the callback returns.
+                buffer.mData = front as *mut _;
+            }
+            Ok(())
+        })?;
+
+        let stream =
+            self.start_output_stream(audio_unit, config, Arc::new(Mutex::new(error_callback)))?;
+        Ok((stream, buffers))
+    }
+
+    // Open an audio unit playing `config` on the device, without a render callback yet.
+    fn output_audio_unit(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<AudioUnit, BuildStreamError> {
+        let mut audio_unit = audio_unit_from_device(self, false)?;
+
+        // The scope and element for working with a device's output stream.
+        let scope = Scope::Input;
+        let element = Element::Output;
+
+        // Set the stream in interleaved mode.
+        let asbd = asbd_from_config(config, sample_format);
+        audio_unit.set_property(kAudioUnitProperty_StreamFormat, scope, element, Some(&asbd))?;
+
+        // Set the buffersize
+        match config.buffer_size {
+            BufferSize::Fixed(v) => {
+                let buffer_size_range = get_io_buffer_frame_size_range(&audio_unit)?;
+                match buffer_size_range {
+                    SupportedBufferSize::Range { min, max } => {
+                        if v >= min && v <= max {
+                            audio_unit.set_property(
+                                kAudioDevicePropertyBufferFrameSize,
+                                scope,
+                                element,
+                                Some(&v),
+                            )?
+                        } else {
+                            return Err(BuildStreamError::StreamConfigNotSupported);
+                        }
+                    }
+                    SupportedBufferSize::Unknown => (),
+                }
+            }
+            BufferSize::Default => (),
+        }
+
+        Ok(audio_unit)
+    }
+
+    // Wrap an output audio unit, with its render callback set, in a stream and start it.
+    fn start_output_stream<E>(
+        &self,
+        audio_unit: AudioUnit,
+        config: &StreamConfig,
+        error_callback_disconnect: Arc<Mutex<E>>,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        E: FnMut(StreamError) + Send + 'static,
+    {
         let stream = Stream::new(StreamInner {
             playing: true,
             _disconnect_listener: None,
+            _hog_mode_listener: None,
+            _sample_rate_listener: None,
+            interrupted: None,
             audio_unit,
             device_id: self.audio_device_id,
+            sample_rate: config.sample_rate,
         });
 
         // If we didn't request the default device, stop the stream if the
         // device disconnects.
         if !self.is_default {
-            add_disconnect_listener(&stream, error_callback_disconnect)?;
+            add_disconnect_listener(&stream, error_callback_disconnect.clone())?;
         }
+        add_hog_mode_listener(&stream, error_callback_disconnect)?;
 
         stream.inner.lock().unwrap().audio_unit.start()?;
 
@@ -948,14 +1311,73 @@ impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
         let mut stream = self.inner.lock().unwrap();
 
+        // While interrupted, the stream starts playing once the device is released.
+        if stream.interrupted.is_some() {
+            stream.interrupted = Some(true);
+            return Ok(());
+        }
         stream.play()
     }
 
     fn pause(&self) -> Result<(), PauseStreamError> {
         let mut stream = self.inner.lock().unwrap();
 
+        if stream.interrupted.is_some() {
+            stream.interrupted = Some(false);
+            return Ok(());
+        }
         stream.pause()
     }
+
+    fn actual_buffer_size(&self) -> Option<FrameCount> {
+        let device_id = self.inner.lock().unwrap().device_id;
+        buffer_frame_size(device_id)
+    }
+
+    fn os_processing(&self) -> OsProcessing {
+        let (device_id, sample_rate) = {
+            let stream = self.inner.lock().unwrap();
+            (stream.device_id, stream.sample_rate)
+        };
+        // The HAL mixes the streams of all processes, and the audio unit converts to the
+        // device's rate where it differs.
+        match nominal_sample_rate(device_id) {
+            Some(rate) if rate as u32 != sample_rate.0 => OsProcessing::Resampled {
+                device_rate: SampleRate(rate as u32),
+            },
+            Some(_) => OsProcessing::Mixed,
+            None => OsProcessing::Unknown,
+        }
+    }
+}
+
+// The frames between the output buffer being consumed and it being heard: the device's reported
+// latency plus its safety offset. Properties that can't be read count as zero.
+fn output_latency_frames(audio_device_id: AudioDeviceID) -> usize {
+    let get = |selector| {
+        let property_address = AudioObjectPropertyAddress {
+            mSelector: selector,
+            mScope: kAudioObjectPropertyScopeOutput,
+            mElement: kAudioObjectPropertyElementMaster,
+        };
+        let frames = 0u32;
+        let data_size = mem::size_of::<u32>() as u32;
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                audio_device_id,
+                &property_address as *const _,
+                0,
+                null(),
+                &data_size as *const _ as *mut _,
+                &frames as *const _ as *mut _,
+            )
+        };
+        match check_os_status(status) {
+            Ok(()) => frames as usize,
+            Err(_) => 0,
+        }
+    };
+    get(kAudioDevicePropertyLatency) + get(kAudioDevicePropertySafetyOffset)
 }
 
 fn get_io_buffer_frame_size_range(
@@ -1,5 +1,6 @@
 extern crate coreaudio;
 
+use self::coreaudio::audio_unit::{AudioUnit, Element, Scope};
 use self::coreaudio::sys::{
     kAudioFormatFlagIsFloat, kAudioFormatFlagIsPacked, kAudioFormatLinearPCM,
     AudioStreamBasicDescription, OSStatus,
@@ -24,9 +25,38 @@ pub use self::ios::{
 #[cfg(target_os = "macos")]
 pub use self::macos::{
     enumerate::{Devices, SupportedInputConfigs, SupportedOutputConfigs},
-    Device, Host, Stream,
+    AggregateDevice, Device, Host, OutputBuffer, OutputBuffers, Stream,
 };
 
+/// Settings for Apple's voice-processing I/O unit.
+///
+/// The voice-processing unit cancels the echo of audio played through voice-processing output
+/// streams from the captured signal, making it suitable for VoIP. It is enabled per device with
+/// `Device::with_voice_processing`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VoiceProcessing {
+    /// Apply automatic gain control to the captured signal.
+    pub agc: bool,
+    /// Route audio through the voice-processing unit without processing it.
+    pub bypass: bool,
+}
+
+impl Default for VoiceProcessing {
+    /// Echo cancellation with automatic gain control, matching the unit's own defaults.
+    fn default() -> Self {
+        VoiceProcessing {
+            agc: true,
+            bypass: false,
+        }
+    }
+}
+
+// From `AudioUnitProperties.h`.
+#[allow(non_upper_case_globals)]
+const kAUVoiceIOProperty_BypassVoiceProcessing: u32 = 2100;
+#[allow(non_upper_case_globals)]
+const kAUVoiceIOProperty_VoiceProcessingEnableAGC: u32 = 2101;
+
 /// Common helper methods used by both macOS and iOS
 
 fn check_os_status(os_status: OSStatus) -> Result<(), BackendSpecificError> {
@@ -39,6 +69,28 @@ fn check_os_status(os_status: OSStatus) -> Result<(), BackendSpecificError> {
     }
 }
 
+// Apply the AGC and bypass toggles to a voice-processing I/O unit.
+fn configure_voice_processing(
+    audio_unit: &mut AudioUnit,
+    settings: VoiceProcessing,
+) -> Result<(), coreaudio::Error> {
+    let bypass = settings.bypass as u32;
+    audio_unit.set_property(
+        kAUVoiceIOProperty_BypassVoiceProcessing,
+        Scope::Global,
+        Element::Input,
+        Some(&bypass),
+    )?;
+    let agc = settings.agc as u32;
+    audio_unit.set_property(
+        kAUVoiceIOProperty_VoiceProcessingEnableAGC,
+        Scope::Global,
+        Element::Input,
+        Some(&agc),
+    )?;
+    Ok(())
+}
+
 // Create a coreaudio AudioStreamBasicDescription from a CPAL Format.
 fn asbd_from_config(
     config: &StreamConfig,
@@ -1,7 +1,9 @@
 extern crate jack;
 
 use crate::traits::HostTrait;
-use crate::{DevicesError, SampleFormat, SupportedStreamConfigRange};
+use crate::{DevicesError, LogLevel, SampleFormat, SupportedStreamConfigRange};
+use std::cell::Cell;
+use std::ffi::CStr;
 
 mod device;
 pub use self::device::Device;
@@ -175,3 +177,55 @@ fn get_client(name: &str, client_options: jack::ClientOptions) -> Result<jack::C
         Err(e) => Err(format!("Failed to open client because of error: {:?}", e)),
     }
 }
+
+thread_local! {
+    // Non-zero while this thread probes for a running server. JACK reports the failure to connect
+    // on the thread opening the client, so its complaints about not finding a server are expected
+    // there and dropped, while messages from other threads and clients still get through.
+    static PROBING: Cell<usize> = const { Cell::new(0) };
+}
+
+fn forward_log_message(level: LogLevel, message: *const libc::c_char) {
+    if PROBING.with(Cell::get) > 0 {
+        return;
+    }
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    if !crate::log::log(level, &message) {
+        match level {
+            LogLevel::Error => eprintln!("{}", message),
+            _ => println!("{}", message),
+        }
+    }
+}
+
+unsafe extern "C" fn error_handler(message: *const libc::c_char) {
+    forward_log_message(LogLevel::Error, message);
+}
+
+unsafe extern "C" fn info_handler(message: *const libc::c_char) {
+    forward_log_message(LogLevel::Info, message);
+}
+
+// Route JACK's messages to the user's log callback, falling back to printing them like JACK does
+// by default.
+pub(crate) fn route_log_messages() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        jack::set_logger(jack::LoggerType::Custom {
+            info: info_handler,
+            error: error_handler,
+        });
+    });
+}
+
+/// Whether a JACK server is running, without starting one or printing anything.
+///
+/// Connecting to the server's socket fails immediately if no server is listening, so this
+/// doesn't block.
+pub(crate) fn server_running() -> bool {
+    route_log_messages();
+    PROBING.with(|probing| probing.set(probing.get() + 1));
+    let running = get_client("cpal_probe", get_client_options(false)).is_ok();
+    PROBING.with(|probing| probing.set(probing.get() - 1));
+    running
+}
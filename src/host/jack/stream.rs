@@ -336,7 +336,7 @@ impl jack::ProcessHandler for LocalProcessHandler {
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
             let capture = start_callback_instant;
             let timestamp = crate::InputStreamTimestamp { callback, capture };
-            let info = crate::InputCallbackInfo { timestamp };
+            let info = crate::InputCallbackInfo::new(timestamp);
             input_callback(&data, &info);
         }
 
@@ -24,7 +24,35 @@ pub(crate) mod emscripten;
 ))]
 pub(crate) mod jack;
 pub(crate) mod null;
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "android",
+        target_os = "emscripten"
+    ))
+))]
+pub(crate) mod oss;
 #[cfg(windows)]
 pub(crate) mod wasapi;
 #[cfg(all(target_arch = "wasm32", feature = "wasm-bindgen"))]
 pub(crate) mod webaudio;
+
+/// The supported configurations of a host's device, as yielded while they are probed.
+///
+/// Iterators that probe the device as they are advanced end early if the device fails, e.g.
+/// because it was unplugged, and keep the error for [`take_error`](Self::take_error).
+pub(crate) trait ProbedConfigs: Iterator<Item = crate::SupportedStreamConfigRange> {
+    /// The error that ended the iteration early, if any.
+    fn take_error(&mut self) -> Option<crate::SupportedStreamConfigsError> {
+        None
+    }
+}
+
+// Configurations collected up front, which can't fail anymore.
+impl ProbedConfigs for std::vec::IntoIter<crate::SupportedStreamConfigRange> {}
@@ -141,6 +141,10 @@ impl Iterator for Devices {
     }
 }
 
+impl crate::host::ProbedConfigs for SupportedInputConfigs {}
+
+impl crate::host::ProbedConfigs for SupportedOutputConfigs {}
+
 impl Iterator for SupportedInputConfigs {
     type Item = SupportedStreamConfigRange;
 
@@ -0,0 +1,596 @@
+//! The OSS-compatible audio interface (`/dev/dsp`, `/dev/audio`) of Unix systems without a
+//! dedicated host, such as illumos and Solaris.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, Data,
+    DefaultStreamConfigError, DeviceNameError, DevicesError, FrameCount, InputCallbackInfo,
+    InputStreamTimestamp, OutputCallbackInfo, OutputStreamTimestamp, PauseStreamError,
+    PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError, StreamInstant,
+    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError, COMMON_SAMPLE_RATES,
+};
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+// The device nodes tried as the default device, in order.
+const DEFAULT_NODES: [&str; 2] = ["/dev/dsp", "/dev/audio"];
+
+// The numbered device nodes, e.g. `/dev/dsp0`, and how many of each are looked for.
+const NUMBERED_NODES: [&str; 2] = ["/dev/dsp", "/dev/audio"];
+const MAX_NODE_NUMBER: usize = 16;
+
+// The directory in which illumos and Solaris list their audio devices.
+const SOUND_DIR: &str = "/dev/sound";
+
+// The number of frames handed to the data callback at a time with `BufferSize::Default`.
+const DEFAULT_PERIOD_FRAMES: usize = 1024;
+
+// The number of periods the device buffers.
+const PERIODS: u32 = 4;
+
+// The channel counts probed when listing a device's configurations.
+const MAX_CHANNELS: ChannelCount = 8;
+
+// The OSS ioctl requests: the direction in the top bits, then the size of the `int` argument, the
+// group `'P'` and the command number. The direction bits are those of OSS's own `soundcard.h` on
+// illumos and Solaris, and of `sys/ioccom.h` elsewhere.
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+const IOC_OUT: u32 = 0x2000_0000;
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+const IOC_INOUT: u32 = 0x6000_0000;
+#[cfg(not(any(target_os = "illumos", target_os = "solaris")))]
+const IOC_OUT: u32 = 0x4000_0000;
+#[cfg(not(any(target_os = "illumos", target_os = "solaris")))]
+const IOC_INOUT: u32 = 0xc000_0000;
+
+const fn ioc(direction: u32, number: u32) -> u32 {
+    direction
+        | ((mem::size_of::<libc::c_int>() as u32 & 0x1fff) << 16)
+        | ((b'P' as u32) << 8)
+        | number
+}
+
+const SNDCTL_DSP_SPEED: u32 = ioc(IOC_INOUT, 2);
+const SNDCTL_DSP_SETFMT: u32 = ioc(IOC_INOUT, 5);
+const SNDCTL_DSP_CHANNELS: u32 = ioc(IOC_INOUT, 6);
+const SNDCTL_DSP_SETFRAGMENT: u32 = ioc(IOC_INOUT, 10);
+const SNDCTL_DSP_GETFMTS: u32 = ioc(IOC_OUT, 11);
+const SNDCTL_DSP_GETODELAY: u32 = ioc(IOC_OUT, 23);
+
+const AFMT_U8: libc::c_int = 0x0000_0008;
+#[cfg(target_endian = "little")]
+const AFMT_S16_NE: libc::c_int = 0x0000_0010;
+#[cfg(target_endian = "big")]
+const AFMT_S16_NE: libc::c_int = 0x0000_0020;
+#[cfg(target_endian = "little")]
+const AFMT_S32_NE: libc::c_int = 0x0000_1000;
+#[cfg(target_endian = "big")]
+const AFMT_S32_NE: libc::c_int = 0x0000_2000;
+
+// The sample formats supported by the host, in order of preference, with their OSS formats.
+const FORMATS: [(SampleFormat, libc::c_int); 3] = [
+    (SampleFormat::I16, AFMT_S16_NE),
+    (SampleFormat::I32, AFMT_S32_NE),
+    (SampleFormat::U8, AFMT_U8),
+];
+
+/// The OSS host, the default host on Unix systems without a dedicated host.
+///
+/// Devices are the OSS device nodes, e.g. `/dev/dsp` or `/dev/sound/0`. Devices that don't
+/// answer the OSS format queries are assumed to support 16-bit mono and stereo audio at common
+/// sample rates.
+#[derive(Debug)]
+pub struct Host;
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        Ok(Host)
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        DEFAULT_NODES.iter().any(|node| Path::new(node).exists())
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        Devices::new()
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        default_device()
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        default_device()
+    }
+}
+
+fn default_device() -> Option<Device> {
+    DEFAULT_NODES
+        .iter()
+        .map(Path::new)
+        .find(|path| path.exists())
+        .map(|path| Device {
+            path: path.to_owned(),
+        })
+}
+
+/// An iterator over the OSS device nodes.
+pub struct Devices(std::vec::IntoIter<Device>);
+
+impl Devices {
+    fn new() -> Result<Self, DevicesError> {
+        let mut paths: Vec<PathBuf> = DEFAULT_NODES.iter().map(PathBuf::from).collect();
+        for node in NUMBERED_NODES {
+            paths.extend((0..MAX_NODE_NUMBER).map(|n| PathBuf::from(format!("{}{}", node, n))));
+        }
+        if let Ok(entries) = fs::read_dir(SOUND_DIR) {
+            let mut entries: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                // The `ctl` nodes are for the mixer.
+                .filter(|path| !path.to_string_lossy().ends_with("ctl"))
+                .collect();
+            entries.sort();
+            paths.extend(entries);
+        }
+
+        // Nodes are often links to one another, e.g. `/dev/dsp` to `/dev/dsp0`.
+        let mut seen = Vec::new();
+        let mut devices = Vec::new();
+        for path in paths {
+            if let Ok(target) = fs::canonicalize(&path) {
+                if !seen.contains(&target) {
+                    seen.push(target);
+                    devices.push(Device { path });
+                }
+            }
+        }
+        Ok(Devices(devices.into_iter()))
+    }
+}
+
+impl Iterator for Devices {
+    type Item = Device;
+
+    fn next(&mut self) -> Option<Device> {
+        self.0.next()
+    }
+}
+
+/// An OSS device node.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Device {
+    path: PathBuf,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Direction {
+    Input,
+    Output,
+}
+
+impl Device {
+    /// The path of the device node.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn open(&self, direction: Direction) -> io::Result<File> {
+        let mut options = OpenOptions::new();
+        match direction {
+            Direction::Input => options.read(true),
+            Direction::Output => options.write(true),
+        };
+        // Opening a busy device blocks on some systems.
+        let file = options.custom_flags(libc::O_NONBLOCK).open(&self.path)?;
+        let fd = file.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags == -1 || libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(file)
+    }
+
+    fn supported_configs(
+        &self,
+        direction: Direction,
+    ) -> Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError> {
+        let file = match self.open(direction) {
+            Ok(file) => file,
+            // The node doesn't support this direction.
+            Err(ref err) if matches!(err.raw_os_error(), Some(libc::EINVAL | libc::ENXIO)) => {
+                return Ok(Vec::new())
+            }
+            Err(err) if is_device_gone(&err) => {
+                return Err(SupportedStreamConfigsError::DeviceNotAvailable)
+            }
+            Err(err) => return Err(backend_specific(err, "failed to open the device").into()),
+        };
+
+        let formats = match ioctl(&file, SNDCTL_DSP_GETFMTS, 0) {
+            Ok(mask) => FORMATS
+                .iter()
+                .filter(|(_, format)| mask & format != 0)
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let mut configs = Vec::new();
+        for &(sample_format, format) in formats {
+            if ioctl(&file, SNDCTL_DSP_SETFMT, format).ok() != Some(format) {
+                continue;
+            }
+            for channels in 1..=MAX_CHANNELS {
+                let requested = channels as libc::c_int;
+                if ioctl(&file, SNDCTL_DSP_CHANNELS, requested).ok() != Some(requested) {
+                    continue;
+                }
+                for &rate in COMMON_SAMPLE_RATES {
+                    let requested = rate.0 as libc::c_int;
+                    if ioctl(&file, SNDCTL_DSP_SPEED, requested).ok() == Some(requested) {
+                        configs.push(SupportedStreamConfigRange::new(
+                            channels,
+                            rate,
+                            rate,
+                            SupportedBufferSize::Unknown,
+                            sample_format,
+                        ));
+                    }
+                }
+            }
+        }
+
+        // The device doesn't answer the queries, so assume the usual formats.
+        if configs.is_empty() {
+            for channels in 1..=2 {
+                for rate in [SampleRate(44_100), SampleRate(48_000)] {
+                    configs.push(SupportedStreamConfigRange::new(
+                        channels,
+                        rate,
+                        rate,
+                        SupportedBufferSize::Unknown,
+                        SampleFormat::I16,
+                    ));
+                }
+            }
+        }
+        Ok(configs)
+    }
+
+    fn default_config(
+        &self,
+        direction: Direction,
+    ) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        let configs = self.supported_configs(direction).map_err(|err| match err {
+            SupportedStreamConfigsError::DeviceNotAvailable => {
+                DefaultStreamConfigError::DeviceNotAvailable
+            }
+            SupportedStreamConfigsError::InvalidArgument => {
+                DefaultStreamConfigError::StreamTypeNotSupported
+            }
+            SupportedStreamConfigsError::BackendSpecific { err } => err.into(),
+        })?;
+        configs
+            .into_iter()
+            .max_by(|a, b| a.cmp_default_heuristics(b))
+            .map(SupportedStreamConfigRange::with_max_sample_rate)
+            .ok_or(DefaultStreamConfigError::StreamTypeNotSupported)
+    }
+
+    fn build_stream(
+        &self,
+        direction: Direction,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<(File, usize), BuildStreamError> {
+        let format = FORMATS
+            .iter()
+            .find(|(format, _)| *format == sample_format)
+            .map(|&(_, format)| format)
+            .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        let file = match self.open(direction) {
+            Ok(file) => file,
+            Err(err) if is_device_gone(&err) => return Err(BuildStreamError::DeviceNotAvailable),
+            Err(err) => return Err(backend_specific(err, "failed to open the device").into()),
+        };
+
+        let period_frames = match config.buffer_size {
+            BufferSize::Fixed(frames) => frames as usize,
+            BufferSize::Default => DEFAULT_PERIOD_FRAMES,
+        };
+        let frame_size = config.channels as usize * sample_format.sample_size();
+        // The fragment size is a power of two, requested as its logarithm. Devices that don't
+        // support fragments keep their own buffering.
+        let fragment_shift = (period_frames * frame_size)
+            .next_power_of_two()
+            .trailing_zeros();
+        let _ = ioctl(
+            &file,
+            SNDCTL_DSP_SETFRAGMENT,
+            ((PERIODS << 16) | fragment_shift) as libc::c_int,
+        );
+
+        // OSS requires the format, the channel count and the rate to be set in this order.
+        let settings = [
+            (SNDCTL_DSP_SETFMT, format),
+            (SNDCTL_DSP_CHANNELS, config.channels as libc::c_int),
+            (SNDCTL_DSP_SPEED, config.sample_rate.0 as libc::c_int),
+        ];
+        for (request, value) in settings {
+            match ioctl(&file, request, value) {
+                Ok(set) if set == value => (),
+                Ok(_) => return Err(BuildStreamError::StreamConfigNotSupported),
+                Err(err) => {
+                    return Err(backend_specific(err, "failed to configure the device").into())
+                }
+            }
+        }
+        Ok((file, period_frames))
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.path.to_string_lossy().into_owned())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
+        self.supported_configs(Direction::Input).map(Vec::into_iter)
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
+        self.supported_configs(Direction::Output)
+            .map(Vec::into_iter)
+    }
+
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config(Direction::Input)
+    }
+
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config(Direction::Output)
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        mut error_callback: E,
+        _timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (mut file, period_frames) =
+            self.build_stream(Direction::Input, config, sample_format)?;
+        let config = config.clone();
+        Stream::new("cpal_oss_in", period_frames, move |shared| {
+            let frame_size = config.channels as usize * sample_format.sample_size();
+            let mut buffer = vec![0u8; period_frames * frame_size];
+            let creation = Instant::now();
+            while shared.wait_until_playing() {
+                if let Err(err) = file.read_exact(&mut buffer) {
+                    if report(err, &mut error_callback) {
+                        return;
+                    }
+                    continue;
+                }
+                let callback = stream_instant(creation);
+                let capture = callback
+                    .sub(frames_to_duration(period_frames, config.sample_rate))
+                    .unwrap_or(callback);
+                let len = buffer.len() / sample_format.sample_size();
+                let data =
+                    unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), len, sample_format) };
+                let info = InputCallbackInfo {
+                    timestamp: InputStreamTimestamp { callback, capture },
+                    discontinuity: false,
+                };
+                data_callback(&data, &info);
+            }
+        })
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        mut error_callback: E,
+        _timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (mut file, period_frames) =
+            self.build_stream(Direction::Output, config, sample_format)?;
+        let config = config.clone();
+        Stream::new("cpal_oss_out", period_frames, move |shared| {
+            let frame_size = config.channels as usize * sample_format.sample_size();
+            let mut buffer = vec![0u8; period_frames * frame_size];
+            let creation = Instant::now();
+            while shared.wait_until_playing() {
+                // The delay is unknown on devices without `SNDCTL_DSP_GETODELAY`.
+                let delay_frames = ioctl(&file, SNDCTL_DSP_GETODELAY, 0)
+                    .map_or(0, |bytes| bytes.max(0) as usize / frame_size);
+                let callback = stream_instant(creation);
+                let playback = callback
+                    .add(frames_to_duration(delay_frames, config.sample_rate))
+                    .unwrap_or(callback);
+                let len = buffer.len() / sample_format.sample_size();
+                let mut data =
+                    unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), len, sample_format) };
+                let info = OutputCallbackInfo {
+                    timestamp: OutputStreamTimestamp { callback, playback },
+                };
+                data_callback(&mut data, &info);
+                if let Err(err) = file.write_all(&buffer) {
+                    if report(err, &mut error_callback) {
+                        return;
+                    }
+                }
+            }
+        })
+    }
+}
+
+// The state shared between a `Stream` and its thread.
+struct Shared {
+    playing: AtomicBool,
+    stop: AtomicBool,
+}
+
+impl Shared {
+    // Block while the stream is paused. Returns `false` once the stream is dropped.
+    fn wait_until_playing(&self) -> bool {
+        loop {
+            if self.stop.load(Ordering::Acquire) {
+                return false;
+            }
+            if self.playing.load(Ordering::Acquire) {
+                return true;
+            }
+            thread::park();
+        }
+    }
+}
+
+/// A stream on an OSS device, read or written with blocking I/O on its own thread.
+pub struct Stream {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+    // Every read or write transfers a whole period.
+    period_frames: usize,
+}
+
+impl Stream {
+    fn new<F>(name: &str, period_frames: usize, run: F) -> Result<Self, BuildStreamError>
+    where
+        F: FnOnce(&Shared) + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            playing: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name(name.to_owned())
+                .spawn(move || run(&shared))
+                .map_err(|err| BackendSpecificError {
+                    description: format!("failed to spawn the stream thread: {}", err),
+                })?
+        };
+        Ok(Stream {
+            shared,
+            thread: Some(thread),
+            period_frames,
+        })
+    }
+
+    fn set_playing(&self, playing: bool) {
+        self.shared.playing.store(playing, Ordering::Release);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.set_playing(true);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.set_playing(false);
+        Ok(())
+    }
+
+    fn is_terminated(&self) -> bool {
+        self.thread.as_ref().map_or(true, JoinHandle::is_finished)
+    }
+
+    fn fixed_buffer_size(&self) -> Option<FrameCount> {
+        Some(self.period_frames as FrameCount)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+// Pass an I/O error of a stream to the error callback. Returns `true` if the device is gone and
+// the stream should stop.
+fn report(err: io::Error, error_callback: &mut dyn FnMut(StreamError)) -> bool {
+    if is_device_gone(&err) {
+        error_callback(StreamError::DeviceNotAvailable);
+        true
+    } else {
+        error_callback(backend_specific(err, "I/O error").into());
+        false
+    }
+}
+
+fn ioctl(file: &File, request: u32, value: libc::c_int) -> io::Result<libc::c_int> {
+    let mut value = value;
+    if unsafe { libc::ioctl(file.as_raw_fd(), request as _, &mut value) } == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(value)
+    }
+}
+
+fn is_device_gone(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::NotFound || err.raw_os_error() == Some(libc::ENODEV)
+}
+
+fn backend_specific(err: io::Error, context: &str) -> BackendSpecificError {
+    BackendSpecificError {
+        description: format!("{}: {}", context, err),
+    }
+}
+
+fn stream_instant(creation: Instant) -> StreamInstant {
+    StreamInstant::from_nanos_i128(creation.elapsed().as_nanos() as i128)
+        .expect("stream duration has exceeded `StreamInstant` representation")
+}
+
+fn frames_to_duration(frames: usize, rate: SampleRate) -> Duration {
+    Duration::from_secs_f64(frames as f64 / rate.0 as f64)
+}
@@ -0,0 +1,139 @@
+use super::device::{default_device, device_from_id, enumerator};
+use super::{com, Device, Host};
+use crate::BackendSpecificError;
+use std::sync::Mutex;
+use windows::core::{implement, PCWSTR};
+use windows::Win32::Media::Audio;
+use windows::Win32::UI::Shell::PropertiesSystem::PROPERTYKEY;
+
+/// A change of the default input or output device, reported to the callback of
+/// [`Host::on_default_device_changed`].
+///
+/// When the user switches the default from one device to another, the old default is reported as
+/// [`NoLongerDefault`](Self::NoLongerDefault) before the new one is reported as
+/// [`BecameDefault`](Self::BecameDefault). Compare the device with the one of a stream to decide
+/// whether to move the stream, e.g. to follow the default device.
+#[derive(Clone, Debug)]
+pub enum DefaultDeviceChange {
+    /// The device became the default device of its direction.
+    BecameDefault(Device),
+    /// The device was the default device of its direction and isn't anymore.
+    NoLongerDefault(Device),
+}
+
+/// Receives default device changes. Created by [`Host::on_default_device_changed`].
+///
+/// The callback stops being called when this is dropped.
+pub struct DefaultDeviceListener {
+    client: Audio::IMMNotificationClient,
+}
+
+#[implement(Audio::IMMNotificationClient)]
+struct DefaultDeviceClient {
+    callback: Mutex<Box<dyn FnMut(DefaultDeviceChange) + Send>>,
+    // The current default output and input device, in that order.
+    defaults: Mutex<[Option<Device>; 2]>,
+}
+
+impl Audio::IMMNotificationClient_Impl for DefaultDeviceClient {
+    fn OnDeviceStateChanged(
+        &self,
+        _: &PCWSTR,
+        _: Audio::DEVICE_STATE,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, _: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, _: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDefaultDeviceChanged(
+        &self,
+        flow: Audio::EDataFlow,
+        role: Audio::ERole,
+        id: &PCWSTR,
+    ) -> windows::core::Result<()> {
+        // The default devices of cpal are those of the console role; the other roles are
+        // notified separately.
+        let index = match flow {
+            Audio::eRender => 0,
+            Audio::eCapture => 1,
+            _ => return Ok(()),
+        };
+        if role != Audio::eConsole {
+            return Ok(());
+        }
+        let new = if id.is_null() {
+            None
+        } else {
+            unsafe { device_from_id(id) }
+        };
+        let old = std::mem::replace(&mut self.defaults.lock().unwrap()[index], new.clone());
+        if old == new {
+            return Ok(());
+        }
+        if let Ok(mut callback) = self.callback.lock() {
+            if let Some(old) = old {
+                callback(DefaultDeviceChange::NoLongerDefault(old));
+            }
+            if let Some(new) = new {
+                callback(DefaultDeviceChange::BecameDefault(new));
+            }
+        }
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(&self, _: &PCWSTR, _: &PROPERTYKEY) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
+
+impl Host {
+    /// Call `callback` whenever a device becomes or stops being the default input or output
+    /// device, e.g. because the user picked another default in the sound settings.
+    ///
+    /// The callback is invoked on a thread owned by the system.
+    pub fn on_default_device_changed<F>(
+        &self,
+        callback: F,
+    ) -> Result<DefaultDeviceListener, BackendSpecificError>
+    where
+        F: FnMut(DefaultDeviceChange) + Send + 'static,
+    {
+        com::com_initialized();
+        let defaults = [
+            default_device(Audio::eRender).ok().flatten(),
+            default_device(Audio::eCapture).ok().flatten(),
+        ];
+        let client: Audio::IMMNotificationClient = DefaultDeviceClient {
+            callback: Mutex::new(Box::new(callback)),
+            defaults: Mutex::new(defaults),
+        }
+        .into();
+        unsafe { enumerator().RegisterEndpointNotificationCallback(&client)? };
+        Ok(DefaultDeviceListener { client })
+    }
+}
+
+impl Device {
+    /// Whether this is currently the default device of its direction.
+    pub fn is_default(&self) -> bool {
+        default_device(self.data_flow())
+            .ok()
+            .flatten()
+            .is_some_and(|default| default == *self)
+    }
+}
+
+impl Drop for DefaultDeviceListener {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = enumerator().UnregisterEndpointNotificationCallback(&self.client);
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use crate::FrameCount;
 use crate::{
-    BackendSpecificError, BufferSize, Data, DefaultStreamConfigError, DeviceNameError,
-    DevicesError, InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig,
+    BackendSpecificError, BufferSize, Capabilities, Capability, Data, DefaultDeviceError,
+    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OsProcessing,
+    OutputCallbackInfo, PowerPreference, SampleFormat, SampleRate, StreamConfig,
     SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
     SupportedStreamConfigsError, COMMON_SAMPLE_RATES,
 };
@@ -11,11 +12,14 @@ use std::mem;
 use std::os::windows::ffi::OsStringExt;
 use std::ptr;
 use std::slice;
+use std::sync::atomic::AtomicU32;
 use std::sync::OnceLock;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
 use super::com;
+use super::session::SessionWatch;
+use super::ShareMode;
 use super::{windows_err_to_cpal_err, windows_err_to_cpal_err_message};
 use windows::core::Interface;
 use windows::core::GUID;
@@ -26,7 +30,7 @@ use windows::Win32::Media::{Audio, KernelStreaming, Multimedia};
 use windows::Win32::System::Com;
 use windows::Win32::System::Com::{StructuredStorage, STGM_READ};
 use windows::Win32::System::Threading;
-use windows::Win32::System::Variant::VT_LPWSTR;
+use windows::Win32::System::Variant::{VT_CLSID, VT_LPWSTR};
 
 use super::stream::{AudioClientFlow, Stream, StreamInner};
 use crate::{traits::DeviceTrait, BuildStreamError, StreamError};
@@ -47,6 +51,14 @@ pub struct Device {
     /// We cache an uninitialized `IAudioClient` so that we can call functions from it without
     /// having to create/destroy audio clients all the time.
     future_audio_client: Arc<Mutex<Option<IAudioClientWrapper>>>, // TODO: add NonZero around the ptr
+    /// How output streams built from this device trade latency for power consumption.
+    power_preference: PowerPreference,
+    /// Whether streams built from this device share it with other applications.
+    share_mode: ShareMode,
+    /// The device period requested for exclusive-mode streams.
+    exclusive_period: Option<Duration>,
+    /// The fewest frames output streams built from this device should be woken up for.
+    wakeup_granularity: Option<FrameCount>,
 }
 
 impl DeviceTrait for Device {
@@ -66,6 +78,10 @@ impl DeviceTrait for Device {
         self.data_flow() == Audio::eRender
     }
 
+    fn adapter_id(&self) -> Option<String> {
+        Device::adapter_id(self)
+    }
+
     fn supported_input_configs(
         &self,
     ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
@@ -86,6 +102,30 @@ impl DeviceTrait for Device {
         Device::default_output_config(self)
     }
 
+    fn current_input_config(&self) -> Option<SupportedStreamConfig> {
+        Device::current_config(self, Audio::eCapture)
+    }
+
+    fn current_output_config(&self) -> Option<SupportedStreamConfig> {
+        Device::current_config(self, Audio::eRender)
+    }
+
+    fn backend_info(&self) -> Option<String> {
+        Device::backend_info(self)
+    }
+
+    fn physical_location(&self) -> Option<String> {
+        Device::physical_location(self)
+    }
+
+    fn has_hardware_volume(&self) -> bool {
+        Device::has_hardware_volume(self)
+    }
+
+    fn capabilities(&self) -> Result<Capabilities, SupportedStreamConfigsError> {
+        Device::capabilities(self)
+    }
+
     fn build_input_stream_raw<D, E>(
         &self,
         config: &StreamConfig,
@@ -142,31 +182,51 @@ impl Drop for WaveFormatExPtr {
     }
 }
 
+// What the audio engine does to a stream at `sample_rate` on `audio_client`.
+unsafe fn os_processing(
+    audio_client: &Audio::IAudioClient,
+    share_mode: ShareMode,
+    sample_rate: SampleRate,
+) -> OsProcessing {
+    if share_mode == ShareMode::Exclusive {
+        return OsProcessing::BitExact;
+    }
+    match audio_client.GetMixFormat().map(WaveFormatExPtr) {
+        Ok(mix) if (*mix.0).nSamplesPerSec != sample_rate.0 => OsProcessing::Resampled {
+            device_rate: SampleRate((*mix.0).nSamplesPerSec),
+        },
+        Ok(_) => OsProcessing::Mixed,
+        Err(_) => OsProcessing::Unknown,
+    }
+}
+
 unsafe fn immendpoint_from_immdevice(device: Audio::IMMDevice) -> Audio::IMMEndpoint {
     device
         .cast::<Audio::IMMEndpoint>()
         .expect("could not query IMMDevice interface for IMMEndpoint")
 }
 
-unsafe fn data_flow_from_immendpoint(endpoint: &Audio::IMMEndpoint) -> Audio::EDataFlow {
-    endpoint
-        .GetDataFlow()
-        .expect("could not get endpoint data_flow")
+unsafe fn data_flow_from_immendpoint(
+    endpoint: &Audio::IMMEndpoint,
+) -> Result<Audio::EDataFlow, windows::core::Error> {
+    endpoint.GetDataFlow()
 }
 
 // Given the audio client and format, returns whether or not the format is supported.
 pub unsafe fn is_format_supported(
     client: &Audio::IAudioClient,
     waveformatex_ptr: *const Audio::WAVEFORMATEX,
+    share_mode: ShareMode,
 ) -> Result<bool, SupportedStreamConfigsError> {
     // Check if the given format is supported.
     let mut closest_waveformatex_ptr: *mut Audio::WAVEFORMATEX = ptr::null_mut();
 
-    let result = client.IsFormatSupported(
-        Audio::AUDCLNT_SHAREMODE_SHARED,
-        waveformatex_ptr,
-        Some(&mut closest_waveformatex_ptr as *mut _),
-    );
+    // In exclusive mode there is no closest match, and passing a pointer for one is an error.
+    let closest = match share_mode {
+        ShareMode::Shared => Some(&mut closest_waveformatex_ptr as *mut _),
+        ShareMode::Exclusive => None,
+    };
+    let result = client.IsFormatSupported(share_mode.to_audclnt(), waveformatex_ptr, closest);
 
     if !closest_waveformatex_ptr.is_null() {
         Com::CoTaskMemFree(Some(closest_waveformatex_ptr as *mut std::ffi::c_void));
@@ -182,11 +242,15 @@ pub unsafe fn is_format_supported(
     }
 }
 
-// Get a cpal Format from a WAVEFORMATEX.
-unsafe fn format_from_waveformatex_ptr(
+// The sample format of a WAVEFORMATEX, or `None` if cpal doesn't support it.
+//
+// Extensible PCM formats can hold fewer valid bits than their container, e.g. 24 bits in the
+// upper bytes of 32. Those with at most 24 valid bits in 32 are `I24`, which the stream shifts
+// into place; others whose valid bits don't fill the container aren't supported. The valid bits
+// of float formats are ignored, as some drivers report nonsense there.
+unsafe fn sample_format_from_waveformatex_ptr(
     waveformatex_ptr: *const Audio::WAVEFORMATEX,
-    audio_client: &Audio::IAudioClient,
-) -> Option<SupportedStreamConfig> {
+) -> Option<SampleFormat> {
     fn cmp_guid(a: &GUID, b: &GUID) -> bool {
         (a.data1, a.data2, a.data3, a.data4) == (b.data1, b.data2, b.data3, b.data4)
     }
@@ -200,13 +264,19 @@ unsafe fn format_from_waveformatex_ptr(
         (n_bits, KernelStreaming::WAVE_FORMAT_EXTENSIBLE) => {
             let waveformatextensible_ptr = waveformatex_ptr as *const Audio::WAVEFORMATEXTENSIBLE;
             let sub = (*waveformatextensible_ptr).SubFormat;
+            // Zero means that all bits are valid.
+            let valid_bits = match (*waveformatextensible_ptr).Samples.wValidBitsPerSample {
+                0 => n_bits,
+                valid_bits => valid_bits,
+            };
 
             if cmp_guid(&sub, &KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM) {
-                match n_bits {
-                    8 => SampleFormat::U8,
-                    16 => SampleFormat::I16,
-                    32 => SampleFormat::I32,
-                    64 => SampleFormat::I64,
+                match (n_bits, valid_bits) {
+                    (8, 8) => SampleFormat::U8,
+                    (16, 16) => SampleFormat::I16,
+                    (32, 17..=24) => SampleFormat::I24,
+                    (32, 32) => SampleFormat::I32,
+                    (64, 64) => SampleFormat::I64,
                     _ => return None,
                 }
             } else if n_bits == 32 && cmp_guid(&sub, &Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT) {
@@ -218,7 +288,15 @@ unsafe fn format_from_waveformatex_ptr(
         // Unknown data format returned by GetMixFormat.
         _ => return None,
     };
+    Some(sample_format)
+}
 
+// Get a cpal Format from a WAVEFORMATEX.
+unsafe fn format_from_waveformatex_ptr(
+    waveformatex_ptr: *const Audio::WAVEFORMATEX,
+    audio_client: &Audio::IAudioClient,
+) -> Option<SupportedStreamConfig> {
+    let sample_format = sample_format_from_waveformatex_ptr(waveformatex_ptr)?;
     let sample_rate = SampleRate((*waveformatex_ptr).nSamplesPerSec);
 
     // GetBufferSizeLimits is only used for Hardware-Offloaded Audio
@@ -271,54 +349,143 @@ unsafe impl Sync for Device {}
 impl Device {
     pub fn name(&self) -> Result<String, DeviceNameError> {
         unsafe {
-            // Open the device's property store.
-            let property_store = self
-                .device
-                .OpenPropertyStore(STGM_READ)
-                .expect("could not open property store");
-
-            // Get the endpoint's friendly-name property.
-            let mut property_value = property_store
-                .GetValue(&Properties::DEVPKEY_Device_FriendlyName as *const _ as *const _)
+            self.string_property(&Properties::DEVPKEY_Device_FriendlyName)
                 .map_err(|err| {
-                    let description =
-                        format!("failed to retrieve name from property store: {}", err);
-                    let err = BackendSpecificError { description };
-                    DeviceNameError::from(err)
-                })?;
+                    let description = format!("failed to retrieve name: {}", err);
+                    DeviceNameError::from(BackendSpecificError { description })
+                })
+        }
+    }
 
-            let prop_variant = &property_value.as_raw().Anonymous.Anonymous;
+    // The device instance path of the adapter the endpoint belongs to, taken from the interface
+    // path of the kernel streaming filter its connector is wired to, e.g.
+    // `{2}.\\?\usb#vid_046d&pid_0a44&mi_00#7&2a6a1b4b&0&0000#{class}\global`.
+    fn adapter_id(&self) -> Option<String> {
+        let id = unsafe {
+            let topology: Audio::IDeviceTopology =
+                self.device.Activate(Com::CLSCTX_ALL, None).ok()?;
+            let id = topology
+                .GetConnector(0)
+                .ok()?
+                .GetDeviceIdConnectedTo()
+                .ok()?;
+            let string = id.to_string();
+            Com::CoTaskMemFree(Some(id.0 as *const std::ffi::c_void));
+            string.ok()?
+        };
+        let path = match id.split_once("\\\\?\\") {
+            Some((_, path)) => path,
+            None => &id,
+        };
+        // The interface class and reference string follow the instance path.
+        let path = path.split("#{").next().unwrap_or(path);
+        Some(path.replace('#', "\\"))
+    }
 
-            // Read the friendly-name from the union data field, expecting a *const u16.
-            if prop_variant.vt != VT_LPWSTR.0 {
-                let description = format!(
-                    "property store produced invalid data: {:?}",
-                    prop_variant.vt
-                );
-                let err = BackendSpecificError { description };
-                return Err(err.into());
+    // The adapter of the endpoint, its driver version where the endpoint lists it, and the share
+    // mode streams are opened in.
+    fn backend_info(&self) -> Option<String> {
+        let mut info = Vec::new();
+        unsafe {
+            if let Ok(adapter) =
+                self.string_property(&Properties::DEVPKEY_DeviceInterface_FriendlyName)
+            {
+                info.push(adapter);
             }
-            let ptr_utf16 = *(&prop_variant.Anonymous as *const _ as *const *const u16);
-
-            // Find the length of the friendly name.
-            let mut len = 0;
-            while *ptr_utf16.offset(len) != 0 {
-                len += 1;
+            if let Ok(version) = self.string_property(&Properties::DEVPKEY_Device_DriverVersion) {
+                info.push(format!("driver {}", version));
             }
+        }
+        info.push(match self.share_mode {
+            ShareMode::Shared => "shared mode".to_owned(),
+            ShareMode::Exclusive => "exclusive mode".to_owned(),
+        });
+        Some(info.join(", "))
+    }
 
-            // Create the utf16 slice and convert it into a string.
-            let name_slice = slice::from_raw_parts(ptr_utf16, len as usize);
-            let name_os_string: OsString = OsStringExt::from_wide(name_slice);
-            let name_string = match name_os_string.into_string() {
-                Ok(string) => string,
-                Err(os_string) => os_string.to_string_lossy().into(),
-            };
+    // Read a property of the endpoint from its property store.
+    unsafe fn property(
+        &self,
+        key: &Properties::DEVPROPKEY,
+    ) -> Result<windows::core::PROPVARIANT, BackendSpecificError> {
+        // Open the device's property store.
+        let property_store = self.device.OpenPropertyStore(STGM_READ).map_err(|err| {
+            let description = format!("failed to open the property store: {}", err);
+            BackendSpecificError { description }
+        })?;
+
+        property_store
+            .GetValue(key as *const _ as *const _)
+            .map_err(|err| {
+                let description =
+                    format!("failed to retrieve property from property store: {}", err);
+                BackendSpecificError { description }
+            })
+    }
 
-            // Clean up the property.
+    // Read a GUID property of the endpoint from its property store.
+    unsafe fn guid_property(
+        &self,
+        key: &Properties::DEVPROPKEY,
+    ) -> Result<GUID, BackendSpecificError> {
+        let mut property_value = self.property(key)?;
+        let prop_variant = &property_value.as_raw().Anonymous.Anonymous;
+
+        // Read the GUID the union data field points to.
+        let result = if prop_variant.vt == VT_CLSID.0 && !prop_variant.Anonymous.puuid.is_null() {
+            Ok(*(prop_variant.Anonymous.puuid as *const GUID))
+        } else {
+            let description = format!(
+                "property store produced invalid data: {:?}",
+                prop_variant.vt
+            );
+            Err(BackendSpecificError { description })
+        };
+
+        // Clean up the property.
+        StructuredStorage::PropVariantClear(&mut property_value).ok();
+
+        result
+    }
+
+    // Read a string property of the endpoint from its property store.
+    unsafe fn string_property(
+        &self,
+        key: &Properties::DEVPROPKEY,
+    ) -> Result<String, BackendSpecificError> {
+        let mut property_value = self.property(key)?;
+
+        let prop_variant = &property_value.as_raw().Anonymous.Anonymous;
+
+        // Read the string from the union data field, expecting a *const u16.
+        if prop_variant.vt != VT_LPWSTR.0 {
+            let description = format!(
+                "property store produced invalid data: {:?}",
+                prop_variant.vt
+            );
             StructuredStorage::PropVariantClear(&mut property_value).ok();
+            return Err(BackendSpecificError { description });
+        }
+        let ptr_utf16 = *(&prop_variant.Anonymous as *const _ as *const *const u16);
 
-            Ok(name_string)
+        // Find the length of the string.
+        let mut len = 0;
+        while *ptr_utf16.offset(len) != 0 {
+            len += 1;
         }
+
+        // Create the utf16 slice and convert it into a string.
+        let slice = slice::from_raw_parts(ptr_utf16, len as usize);
+        let os_string: OsString = OsStringExt::from_wide(slice);
+        let string = match os_string.into_string() {
+            Ok(string) => string,
+            Err(os_string) => os_string.to_string_lossy().into(),
+        };
+
+        // Clean up the property.
+        StructuredStorage::PropVariantClear(&mut property_value).ok();
+
+        Ok(string)
     }
 
     #[inline]
@@ -326,9 +493,88 @@ impl Device {
         Device {
             device,
             future_audio_client: Arc::new(Mutex::new(None)),
+            power_preference: PowerPreference::Default,
+            share_mode: ShareMode::Shared,
+            exclusive_period: None,
+            wakeup_granularity: None,
         }
     }
 
+    /// Build streams from this device with the given share mode.
+    ///
+    /// Exclusive-mode streams run at the device period set with
+    /// [`with_exclusive_period`](Self::with_exclusive_period), at the period matching a
+    /// [`BufferSize::Fixed`] buffer size, or at the device's default period otherwise. Use
+    /// [`supported_configs_for_share_mode`](Self::supported_configs_for_share_mode) to find the
+    /// configurations available in exclusive mode.
+    pub fn with_share_mode(mut self, share_mode: ShareMode) -> Self {
+        self.share_mode = share_mode;
+        self
+    }
+
+    /// The share mode set with [`with_share_mode`](Self::with_share_mode).
+    pub fn share_mode(&self) -> ShareMode {
+        self.share_mode
+    }
+
+    /// Request a device period for exclusive-mode streams built from this device. The period is
+    /// also the size of the stream's buffer.
+    ///
+    /// If the driver needs the buffer aligned to its own granularity, the period is rounded to
+    /// the nearest aligned size. Building a stream fails with
+    /// [`BuildStreamError::PeriodNotSupported`] if the device can't run at the period and with
+    /// [`BuildStreamError::PeriodLocked`] if another stream has fixed it. This has no effect in
+    /// shared mode.
+    pub fn with_exclusive_period(mut self, period: Duration) -> Self {
+        self.exclusive_period = Some(period);
+        self
+    }
+
+    /// The period set with [`with_exclusive_period`](Self::with_exclusive_period).
+    pub fn exclusive_period(&self) -> Option<Duration> {
+        self.exclusive_period
+    }
+
+    /// Build output streams from this device with the given power preference.
+    ///
+    /// With [`PowerPreference::LowPower`], output streams are handed to the endpoint's audio
+    /// offload engine if it has one, so that the hardware plays them with a large buffer while
+    /// the CPU sleeps. Otherwise, streams opened with [`BufferSize::Default`] use a large
+    /// shared-mode buffer instead. See [`Stream::is_offloaded`] for which one was used.
+    pub fn with_power_preference(mut self, power_preference: PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// The power preference set with [`with_power_preference`](Self::with_power_preference).
+    pub fn power_preference(&self) -> PowerPreference {
+        self.power_preference
+    }
+
+    /// Ask output streams built from this device to call the data callback with at least
+    /// `frames` frames at a time, trading latency for fewer wakeups, e.g. on battery.
+    ///
+    /// Shared-mode streams get a buffer of at least twice `frames`, and the data callback is
+    /// skipped until that much can be written. The audio engine still signals the stream's
+    /// thread once per engine period, but the callback and the work behind it run less often.
+    /// Exclusive-mode streams without [`with_exclusive_period`](Self::with_exclusive_period) or
+    /// a [`BufferSize::Fixed`] buffer size run at a device period of `frames` instead, so the
+    /// thread itself wakes up less often.
+    pub fn with_wakeup_granularity(mut self, frames: FrameCount) -> Self {
+        self.wakeup_granularity = Some(frames);
+        self
+    }
+
+    /// The granularity set with [`with_wakeup_granularity`](Self::with_wakeup_granularity).
+    pub fn wakeup_granularity(&self) -> Option<FrameCount> {
+        self.wakeup_granularity
+    }
+
+    #[inline]
+    pub(crate) fn immdevice(&self) -> &Audio::IMMDevice {
+        &self.device
+    }
+
     /// Ensures that `future_audio_client` contains a `Some` and returns a locked mutex to it.
     fn ensure_future_audio_client(
         &self,
@@ -365,8 +611,11 @@ impl Device {
     // When calling `IsFormatSupported` with the shared-mode audio engine, only the default
     // number of channels seems to be supported. Any, more or less returns an invalid
     // parameter error. Thus, we just assume that the default number of channels is the only
-    // number supported.
-    fn supported_formats(&self) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
+    // number supported. In exclusive mode, mono and stereo are tried as well.
+    fn supported_formats(
+        &self,
+        share_mode: ShareMode,
+    ) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
         // initializing COM because we call `CoTaskMemFree` to release the format.
         com::com_initialized();
 
@@ -392,7 +641,7 @@ impl Device {
                 .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
 
             // If the default format can't succeed we have no hope of finding other formats.
-            if !is_format_supported(client, default_waveformatex_ptr.0)? {
+            if !is_format_supported(client, default_waveformatex_ptr.0, ShareMode::Shared)? {
                 let description =
                     "Could not determine support for default `WAVEFORMATEX`".to_string();
                 let err = BackendSpecificError { description };
@@ -416,35 +665,44 @@ impl Device {
                 sample_rates.push(format.sample_rate)
             }
 
+            let mut channel_counts = vec![format.channels];
+            if share_mode == ShareMode::Exclusive {
+                channel_counts.extend([1, 2].iter().filter(|&&c| c != format.channels));
+            }
+
             let mut supported_formats = Vec::new();
 
-            for sample_rate in sample_rates {
-                for sample_format in [
-                    SampleFormat::U8,
-                    SampleFormat::I16,
-                    SampleFormat::I32,
-                    SampleFormat::I64,
-                    SampleFormat::F32,
-                ] {
-                    if let Some(waveformat) = config_to_waveformatextensible(
-                        &StreamConfig {
-                            channels: format.channels,
-                            sample_rate,
-                            buffer_size: BufferSize::Default,
-                        },
-                        sample_format,
-                    ) {
-                        if is_format_supported(
-                            client,
-                            &waveformat.Format as *const Audio::WAVEFORMATEX,
-                        )? {
-                            supported_formats.push(SupportedStreamConfigRange {
-                                channels: format.channels,
-                                min_sample_rate: sample_rate,
-                                max_sample_rate: sample_rate,
-                                buffer_size: format.buffer_size,
-                                sample_format,
-                            })
+            for &channels in &channel_counts {
+                for &sample_rate in &sample_rates {
+                    for sample_format in [
+                        SampleFormat::U8,
+                        SampleFormat::I16,
+                        SampleFormat::I24,
+                        SampleFormat::I32,
+                        SampleFormat::I64,
+                        SampleFormat::F32,
+                    ] {
+                        if let Some(waveformat) = config_to_waveformatextensible(
+                            &StreamConfig {
+                                channels,
+                                sample_rate,
+                                buffer_size: BufferSize::Default,
+                            },
+                            sample_format,
+                        ) {
+                            if is_format_supported(
+                                client,
+                                &waveformat.Format as *const Audio::WAVEFORMATEX,
+                                share_mode,
+                            )? {
+                                supported_formats.push(SupportedStreamConfigRange {
+                                    channels,
+                                    min_sample_rate: sample_rate,
+                                    max_sample_rate: sample_rate,
+                                    buffer_size: format.buffer_size,
+                                    sample_format,
+                                })
+                            }
                         }
                     }
                 }
@@ -456,8 +714,11 @@ impl Device {
     pub fn supported_input_configs(
         &self,
     ) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
-        if self.data_flow() == Audio::eCapture {
-            self.supported_formats()
+        let data_flow = self
+            .try_data_flow()
+            .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
+        if data_flow == Audio::eCapture {
+            self.supported_formats(ShareMode::Shared)
         // If it's an output device, assume no input formats.
         } else {
             Ok(vec![].into_iter())
@@ -467,14 +728,96 @@ impl Device {
     pub fn supported_output_configs(
         &self,
     ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
-        if self.data_flow() == Audio::eRender {
-            self.supported_formats()
+        let data_flow = self
+            .try_data_flow()
+            .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
+        if data_flow == Audio::eRender {
+            self.supported_formats(ShareMode::Shared)
         // If it's an input device, assume no output formats.
         } else {
             Ok(vec![].into_iter())
         }
     }
 
+    // The container ID of the physical device the endpoint belongs to, or `None` for endpoints
+    // without one, such as virtual devices.
+    fn physical_location(&self) -> Option<String> {
+        let id = unsafe { self.guid_property(&Properties::DEVPKEY_Device_ContainerId) }.ok()?;
+        if id == GUID::zeroed() {
+            return None;
+        }
+        Some(format!("{{{:?}}}", id))
+    }
+
+    /// The configurations supported by the device when opened with the given share mode.
+    ///
+    /// These are input configurations for capture devices and output configurations for render
+    /// devices. With [`ShareMode::Shared`] this is the same as `supported_input_configs` or
+    /// `supported_output_configs`.
+    pub fn supported_configs_for_share_mode(
+        &self,
+        share_mode: ShareMode,
+    ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
+        self.supported_formats(share_mode)
+    }
+
+    // The shared-mode configurations of the endpoint, along with what it supports beyond them.
+    fn capabilities(&self) -> Result<Capabilities, SupportedStreamConfigsError> {
+        let data_flow = self
+            .try_data_flow()
+            .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
+        let configs: Vec<_> = self.supported_formats(ShareMode::Shared)?.collect();
+        let mut capabilities = if data_flow == Audio::eCapture {
+            Capabilities::from_configs(&configs, &[])
+        } else {
+            Capabilities::from_configs(&[], &configs)
+        };
+        capabilities.exclusive_mode = match self.supported_formats(ShareMode::Exclusive) {
+            Ok(configs) if configs.len() > 0 => Capability::Supported,
+            Ok(_) => Capability::Unsupported,
+            Err(SupportedStreamConfigsError::DeviceNotAvailable) => {
+                return Err(SupportedStreamConfigsError::DeviceNotAvailable)
+            }
+            Err(_) => Capability::Unknown,
+        };
+        // Any render endpoint can be recorded by opening it as a loopback input stream.
+        capabilities.loopback = if data_flow == Audio::eRender {
+            Capability::Supported
+        } else {
+            Capability::Unsupported
+        };
+        let mix_sample_rate = self.default_format().ok().map(|config| config.sample_rate);
+        if let Ok(audio_client) = self.build_audioclient() {
+            unsafe {
+                capabilities.offload = if data_flow == Audio::eCapture {
+                    Capability::Unsupported
+                } else {
+                    match audio_client.cast::<Audio::IAudioClient2>() {
+                        Ok(audio_client) => {
+                            match audio_client.IsOffloadCapable(Audio::AudioCategory_Media) {
+                                Ok(capable) if capable.as_bool() => Capability::Supported,
+                                Ok(_) => Capability::Unsupported,
+                                Err(_) => Capability::Unknown,
+                            }
+                        }
+                        // Only Windows 8 and later, which `IAudioClient2` came with, offload.
+                        Err(_) => Capability::Unsupported,
+                    }
+                };
+                // Shared-mode streams are processed once every default period at the mix rate.
+                let mut default_period = 0;
+                if let (Ok(()), Some(sample_rate)) = (
+                    audio_client.GetDevicePeriod(Some(&mut default_period), None),
+                    mix_sample_rate,
+                ) {
+                    let frames = default_period as u64 * sample_rate.0 as u64 / 10_000_000;
+                    capabilities.default_buffer_size = Some(frames as FrameCount);
+                }
+            }
+        }
+        Ok(capabilities)
+    }
+
     // We always create voices in shared mode, therefore all samples go through an audio
     // processor to mix them together.
     //
@@ -508,6 +851,12 @@ impl Device {
     }
 
     pub(crate) fn data_flow(&self) -> Audio::EDataFlow {
+        self.try_data_flow()
+            .expect("could not get endpoint data_flow")
+    }
+
+    // Like `data_flow`, but doesn't panic if the device has been removed.
+    fn try_data_flow(&self) -> Result<Audio::EDataFlow, windows::core::Error> {
         let endpoint = Endpoint::from(self.device.clone());
         endpoint.data_flow()
     }
@@ -529,6 +878,14 @@ impl Device {
         }
     }
 
+    // The shared-mode mix format, which is what the audio engine runs the endpoint at.
+    fn current_config(&self, data_flow: Audio::EDataFlow) -> Option<SupportedStreamConfig> {
+        if self.try_data_flow().ok()? != data_flow {
+            return None;
+        }
+        self.default_format().ok()
+    }
+
     pub(crate) fn build_input_stream_raw_inner(
         &self,
         config: &StreamConfig,
@@ -562,40 +919,30 @@ impl Device {
             }
 
             // Computing the format and initializing the device.
-            let waveformatex = {
+            let (audio_client, waveformatex) = {
                 let format_attempt = config_to_waveformatextensible(config, sample_format)
                     .ok_or(BuildStreamError::StreamConfigNotSupported)?;
-                let share_mode = Audio::AUDCLNT_SHAREMODE_SHARED;
 
                 // Ensure the format is supported.
-                match super::device::is_format_supported(&audio_client, &format_attempt.Format) {
+                match super::device::is_format_supported(
+                    &audio_client,
+                    &format_attempt.Format,
+                    self.share_mode,
+                ) {
                     Ok(false) => return Err(BuildStreamError::StreamConfigNotSupported),
                     Err(_) => return Err(BuildStreamError::DeviceNotAvailable),
                     _ => (),
                 }
 
                 // Finally, initializing the audio client
-                let hresult = audio_client.Initialize(
-                    share_mode,
+                let audio_client = self.initialize_audio_client(
+                    audio_client,
                     stream_flags,
                     buffer_duration,
-                    0,
                     &format_attempt.Format,
-                    None,
-                );
-                match hresult {
-                    Err(ref e) if e.code() == Audio::AUDCLNT_E_DEVICE_INVALIDATED => {
-                        return Err(BuildStreamError::DeviceNotAvailable);
-                    }
-                    Err(e) => {
-                        let description = format!("{}", e);
-                        let err = BackendSpecificError { description };
-                        return Err(err.into());
-                    }
-                    Ok(()) => (),
-                };
+                )?;
 
-                format_attempt.Format
+                (audio_client, format_attempt.Format)
             };
 
             // obtaining the size of the samples buffer in number of frames
@@ -603,25 +950,6 @@ impl Device {
                 .GetBufferSize()
                 .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
 
-            // Creating the event that will be signalled whenever we need to submit some samples.
-            let event = {
-                let event =
-                    Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
-                        .map_err(|e| {
-                            let description = format!("failed to create event: {}", e);
-                            let err = BackendSpecificError { description };
-                            BuildStreamError::from(err)
-                        })?;
-
-                if let Err(e) = audio_client.SetEventHandle(event) {
-                    let description = format!("failed to call SetEventHandle: {}", e);
-                    let err = BackendSpecificError { description };
-                    return Err(err.into());
-                }
-
-                event
-            };
-
             // Building a `IAudioCaptureClient` that will be used to read captured samples.
             let capture_client = audio_client
                 .GetService::<Audio::IAudioCaptureClient>()
@@ -638,6 +966,18 @@ impl Device {
 
             let audio_clock = get_audio_clock(&audio_client)?;
 
+            // Creating the event that will be signalled whenever we need to submit some samples.
+            // This comes last so that no error path leaks it.
+            let event = create_stream_event(&audio_client)?;
+            let session = SessionWatch::new(&audio_client);
+
+            let os_processing = os_processing(&audio_client, self.share_mode, config.sample_rate);
+            let period_frames = match self.share_mode {
+                ShareMode::Shared => engine_period_frames(&audio_client, config.sample_rate),
+                // Every event hands over the whole buffer.
+                ShareMode::Exclusive => max_frames_in_buffer,
+            };
+
             Ok(StreamInner {
                 audio_client,
                 audio_clock,
@@ -648,10 +988,84 @@ impl Device {
                 bytes_per_frame: waveformatex.nBlockAlign,
                 config: config.clone(),
                 sample_format,
+                device_name: self.name().unwrap_or_default(),
+                draining: None,
+                offloaded: false,
+                exclusive: self.share_mode == ShareMode::Exclusive,
+                os_processing,
+                min_callback_frames: Arc::new(AtomicU32::new(0)),
+                period_frames,
+                session,
             })
         }
     }
 
+    // Initialize `audio_client` in the share mode of this device, returning the client to use.
+    //
+    // In exclusive mode the buffer duration is also the device period. If the driver needs the
+    // buffer aligned to its own granularity, `Initialize` fails with
+    // `AUDCLNT_E_BUFFER_SIZE_NOT_ALIGNED`; as documented for `IAudioClient::Initialize`, the
+    // aligned size is then read back and a fresh client is initialized with it.
+    unsafe fn initialize_audio_client(
+        &self,
+        audio_client: Audio::IAudioClient,
+        stream_flags: u32,
+        buffer_duration: i64,
+        format: &Audio::WAVEFORMATEX,
+    ) -> Result<Audio::IAudioClient, BuildStreamError> {
+        if self.share_mode == ShareMode::Shared {
+            audio_client
+                .Initialize(
+                    Audio::AUDCLNT_SHAREMODE_SHARED,
+                    stream_flags,
+                    buffer_duration,
+                    0,
+                    format,
+                    None,
+                )
+                .map_err(initialize_err)?;
+            return Ok(audio_client);
+        }
+
+        let period = match self.exclusive_period {
+            Some(period) => (period.as_nanos() / 100) as i64,
+            None if buffer_duration > 0 => buffer_duration,
+            None => {
+                let mut default_period = 0;
+                audio_client
+                    .GetDevicePeriod(Some(&mut default_period), None)
+                    .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+                default_period
+            }
+        };
+        let initialize = |audio_client: &Audio::IAudioClient, period| {
+            audio_client.Initialize(
+                Audio::AUDCLNT_SHAREMODE_EXCLUSIVE,
+                stream_flags,
+                period,
+                period,
+                format,
+                None,
+            )
+        };
+        match initialize(&audio_client, period) {
+            Ok(()) => Ok(audio_client),
+            Err(ref e) if e.code() == Audio::AUDCLNT_E_BUFFER_SIZE_NOT_ALIGNED => {
+                let frames = audio_client
+                    .GetBufferSize()
+                    .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+                drop(audio_client);
+                let period = aligned_period(frames, format.nSamplesPerSec);
+                let audio_client = self
+                    .build_audioclient()
+                    .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+                initialize(&audio_client, period).map_err(initialize_err)?;
+                Ok(audio_client)
+            }
+            Err(e) => Err(initialize_err(e)),
+        }
+    }
+
     pub(crate) fn build_output_stream_raw_inner(
         &self,
         config: &StreamConfig,
@@ -667,54 +1081,65 @@ impl Device {
                 .build_audioclient()
                 .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
 
-            let buffer_duration =
+            let mut buffer_duration =
                 buffer_size_to_duration(&config.buffer_size, config.sample_rate.0);
+            let mut offloaded = false;
 
             // Computing the format and initializing the device.
-            let waveformatex = {
+            let (audio_client, waveformatex) = {
                 let format_attempt = config_to_waveformatextensible(config, sample_format)
                     .ok_or(BuildStreamError::StreamConfigNotSupported)?;
-                let share_mode = Audio::AUDCLNT_SHAREMODE_SHARED;
 
                 // Ensure the format is supported.
-                match super::device::is_format_supported(&audio_client, &format_attempt.Format) {
+                match super::device::is_format_supported(
+                    &audio_client,
+                    &format_attempt.Format,
+                    self.share_mode,
+                ) {
                     Ok(false) => return Err(BuildStreamError::StreamConfigNotSupported),
                     Err(_) => return Err(BuildStreamError::DeviceNotAvailable),
                     _ => (),
                 }
 
-                // Finally, initializing the audio client
-                audio_client
-                    .Initialize(
-                        share_mode,
-                        Audio::AUDCLNT_STREAMFLAGS_EVENTCALLBACK,
-                        buffer_duration,
-                        0,
-                        &format_attempt.Format,
-                        None,
-                    )
-                    .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
-
-                format_attempt.Format
-            };
+                // Offloading is only available to shared-mode streams.
+                if self.power_preference == PowerPreference::LowPower
+                    && self.share_mode == ShareMode::Shared
+                {
+                    match offload_buffer_duration(&audio_client, &format_attempt.Format) {
+                        Some(duration) => {
+                            buffer_duration = duration;
+                            offloaded = true;
+                        }
+                        None if config.buffer_size == BufferSize::Default => {
+                            buffer_duration =
+                                (PowerPreference::LOW_POWER_BUFFER_SECS * 10_000_000.0) as i64;
+                        }
+                        None => (),
+                    }
+                }
 
-            // Creating the event that will be signalled whenever we need to submit some samples.
-            let event = {
-                let event =
-                    Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
-                        .map_err(|e| {
-                            let description = format!("failed to create event: {}", e);
-                            let err = BackendSpecificError { description };
-                            BuildStreamError::from(err)
-                        })?;
-
-                if let Err(e) = audio_client.SetEventHandle(event) {
-                    let description = format!("failed to call SetEventHandle: {}", e);
-                    let err = BackendSpecificError { description };
-                    return Err(err.into());
+                if let Some(frames) = self.wakeup_granularity.filter(|_| !offloaded) {
+                    match self.share_mode {
+                        ShareMode::Shared => {
+                            let duration = aligned_period(2 * frames, config.sample_rate.0);
+                            buffer_duration = buffer_duration.max(duration);
+                        }
+                        ShareMode::Exclusive if buffer_duration == 0 => {
+                            buffer_duration = aligned_period(frames, config.sample_rate.0);
+                        }
+                        ShareMode::Exclusive => (),
+                    }
                 }
 
-                event
+                // Finally, initializing the audio client
+                let audio_client = self.initialize_audio_client(
+                    audio_client,
+                    Audio::AUDCLNT_STREAMFLAGS_EVENTCALLBACK,
+                    buffer_duration,
+                    &format_attempt.Format,
+                )?;
+
+                (audio_client, format_attempt.Format)
             };
 
             // obtaining the size of the samples buffer in number of frames
@@ -741,6 +1166,26 @@ impl Device {
 
             let audio_clock = get_audio_clock(&audio_client)?;
 
+            // Creating the event that will be signalled whenever we need to submit some samples.
+            // This comes last so that no error path leaks it.
+            let event = create_stream_event(&audio_client)?;
+            let session = SessionWatch::new(&audio_client);
+
+            let os_processing = os_processing(&audio_client, self.share_mode, config.sample_rate);
+            // Every event hands over the whole buffer in exclusive mode.
+            let min_callback_frames = match (self.wakeup_granularity, self.share_mode) {
+                (Some(frames), ShareMode::Shared) if !offloaded => {
+                    frames.min(max_frames_in_buffer / 2)
+                }
+                _ => 0,
+            };
+            let period_frames = match self.share_mode {
+                ShareMode::Shared if !offloaded => {
+                    engine_period_frames(&audio_client, config.sample_rate)
+                }
+                _ => max_frames_in_buffer,
+            };
+
             Ok(StreamInner {
                 audio_client,
                 audio_clock,
@@ -751,11 +1196,97 @@ impl Device {
                 bytes_per_frame: waveformatex.nBlockAlign,
                 config: config.clone(),
                 sample_format,
+                device_name: self.name().unwrap_or_default(),
+                draining: None,
+                offloaded,
+                exclusive: self.share_mode == ShareMode::Exclusive,
+                os_processing,
+                min_callback_frames: Arc::new(AtomicU32::new(min_callback_frames)),
+                period_frames,
+                session,
             })
         }
     }
 }
 
+// Create the event signalled whenever the audio client has a buffer ready, closing it again if it
+// can't be registered with the client.
+unsafe fn create_stream_event(
+    audio_client: &Audio::IAudioClient,
+) -> Result<Foundation::HANDLE, BuildStreamError> {
+    let event = Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
+        .map_err(|e| {
+            let description = format!("failed to create event: {}", e);
+            BuildStreamError::from(BackendSpecificError { description })
+        })?;
+    if let Err(e) = audio_client.SetEventHandle(event) {
+        let _ = Foundation::CloseHandle(event);
+        let description = format!("failed to call SetEventHandle: {}", e);
+        return Err(BackendSpecificError { description }.into());
+    }
+    Ok(event)
+}
+
+// Map an error from `IAudioClient::Initialize` to a `BuildStreamError`.
+fn initialize_err(err: windows::core::Error) -> BuildStreamError {
+    match err.code() {
+        Audio::AUDCLNT_E_UNSUPPORTED_FORMAT => BuildStreamError::StreamConfigNotSupported,
+        Audio::AUDCLNT_E_INVALID_DEVICE_PERIOD | Audio::AUDCLNT_E_BUFFER_SIZE_ERROR => {
+            BuildStreamError::PeriodNotSupported
+        }
+        Audio::AUDCLNT_E_ENGINE_PERIODICITY_LOCKED => BuildStreamError::PeriodLocked,
+        _ => windows_err_to_cpal_err(err),
+    }
+}
+
+// The number of frames the audio engine processes per period for shared-mode streams, or zero if
+// it doesn't tell.
+unsafe fn engine_period_frames(audio_client: &Audio::IAudioClient, sample_rate: SampleRate) -> u32 {
+    let mut default_period = 0;
+    match audio_client.GetDevicePeriod(Some(&mut default_period), None) {
+        Ok(()) => (default_period as u64 * sample_rate.0 as u64 / 10_000_000) as u32,
+        Err(_) => 0,
+    }
+}
+
+// The period, in 100-nanosecond units, of a buffer of `frames` at `sample_rate`, rounded to the
+// nearest unit.
+fn aligned_period(frames: u32, sample_rate: u32) -> i64 {
+    (10_000_000.0 * frames as f64 / sample_rate as f64).round() as i64
+}
+
+// Request offloaded playback from `audio_client` for the given format, returning the buffer
+// duration to initialize it with, or `None` if the endpoint can't offload the stream.
+//
+// This must be called before the client is initialized.
+unsafe fn offload_buffer_duration(
+    audio_client: &Audio::IAudioClient,
+    format: &Audio::WAVEFORMATEX,
+) -> Option<i64> {
+    let audio_client = audio_client.cast::<Audio::IAudioClient2>().ok()?;
+    if !audio_client
+        .IsOffloadCapable(Audio::AudioCategory_Media)
+        .ok()?
+        .as_bool()
+    {
+        return None;
+    }
+    let properties = Audio::AudioClientProperties {
+        cbSize: mem::size_of::<Audio::AudioClientProperties>() as u32,
+        bIsOffload: Foundation::TRUE,
+        eCategory: Audio::AudioCategory_Media,
+        Options: Audio::AUDCLNT_STREAMOPTIONS_NONE,
+    };
+    audio_client.SetClientProperties(&properties).ok()?;
+    let (mut min, mut max) = (0, 0);
+    audio_client
+        .GetBufferSizeLimits(format, true, &mut min, &mut max)
+        .ok()?;
+    // Offload engines support buffers of up to a few seconds; one second keeps pausing and
+    // seeking responsive.
+    Some(max.min(10_000_000).max(min))
+}
+
 impl PartialEq for Device {
     #[inline]
     fn eq(&self, other: &Device) -> bool {
@@ -818,7 +1349,7 @@ impl From<Audio::IMMDevice> for Endpoint {
 }
 
 impl Endpoint {
-    fn data_flow(&self) -> Audio::EDataFlow {
+    fn data_flow(&self) -> Result<Audio::EDataFlow, windows::core::Error> {
         unsafe { data_flow_from_immendpoint(&self.endpoint) }
     }
 }
@@ -904,22 +1435,60 @@ impl Iterator for Devices {
     }
 }
 
-fn default_device(data_flow: Audio::EDataFlow) -> Option<Device> {
-    unsafe {
-        let device = get_enumerator()
+// The system's device enumerator, which also delivers device notifications.
+pub(crate) fn enumerator() -> &'static Audio::IMMDeviceEnumerator {
+    &get_enumerator().0
+}
+
+// The device with the endpoint ID `id`, or `None` if there is no such device.
+pub(crate) unsafe fn device_from_id(id: &windows::core::PCWSTR) -> Option<Device> {
+    enumerator().GetDevice(*id).ok().map(Device::from_immdevice)
+}
+
+pub(crate) fn default_device(
+    data_flow: Audio::EDataFlow,
+) -> Result<Option<Device>, DefaultDeviceError> {
+    let device = unsafe {
+        get_enumerator()
             .0
             .GetDefaultAudioEndpoint(data_flow, Audio::eConsole)
-            .ok()?;
-        // TODO: check specifically for `E_NOTFOUND`, and panic otherwise
-        Some(Device::from_immdevice(device))
+    };
+    match device {
+        Ok(device) => Ok(Some(Device::from_immdevice(device))),
+        Err(err) => match default_device_error(err) {
+            Some(err) => Err(err),
+            None => Ok(None),
+        },
     }
 }
 
+// Map a failure of `GetDefaultAudioEndpoint`, returning `None` if it just means that there is no
+// default device.
+fn default_device_error(err: windows::core::Error) -> Option<DefaultDeviceError> {
+    let description = match err.code() {
+        // There is no active endpoint in that direction.
+        code if code == Foundation::ERROR_NOT_FOUND.to_hresult() => return None,
+        Foundation::E_ACCESSDENIED => "access to the audio endpoints was denied".to_owned(),
+        Foundation::CO_E_NOTINITIALIZED => "COM is not initialized on this thread".to_owned(),
+        Foundation::E_OUTOFMEMORY => "out of memory".to_owned(),
+        _ => format!("failed to get the default audio endpoint: {}", err),
+    };
+    Some(BackendSpecificError { description }.into())
+}
+
 pub fn default_input_device() -> Option<Device> {
-    default_device(Audio::eCapture)
+    default_device(Audio::eCapture).ok().flatten()
 }
 
 pub fn default_output_device() -> Option<Device> {
+    default_device(Audio::eRender).ok().flatten()
+}
+
+pub fn try_default_input_device() -> Result<Option<Device>, DefaultDeviceError> {
+    default_device(Audio::eCapture)
+}
+
+pub fn try_default_output_device() -> Result<Option<Device>, DefaultDeviceError> {
     default_device(Audio::eRender)
 }
 
@@ -944,7 +1513,7 @@ fn config_to_waveformatextensible(
     let format_tag = match sample_format {
         SampleFormat::U8 | SampleFormat::I16 => Audio::WAVE_FORMAT_PCM,
 
-        SampleFormat::I32 | SampleFormat::I64 | SampleFormat::F32 => {
+        SampleFormat::I24 | SampleFormat::I32 | SampleFormat::I64 | SampleFormat::F32 => {
             KernelStreaming::WAVE_FORMAT_EXTENSIBLE
         }
 
@@ -956,6 +1525,11 @@ fn config_to_waveformatextensible(
     let avg_bytes_per_sec = u32::from(channels) * sample_rate * u32::from(sample_bytes);
     let block_align = channels * sample_bytes;
     let bits_per_sample = 8 * sample_bytes;
+    // `I24` is sent as 24 valid bits in a 32-bit container.
+    let valid_bits_per_sample = match sample_format {
+        SampleFormat::I24 => 24,
+        _ => bits_per_sample,
+    };
 
     let cb_size = if format_tag == Audio::WAVE_FORMAT_PCM {
         0
@@ -979,9 +1553,11 @@ fn config_to_waveformatextensible(
     let channel_mask = KernelStreaming::KSAUDIO_SPEAKER_DIRECTOUT;
 
     let sub_format = match sample_format {
-        SampleFormat::U8 | SampleFormat::I16 | SampleFormat::I32 | SampleFormat::I64 => {
-            KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM
-        }
+        SampleFormat::U8
+        | SampleFormat::I16
+        | SampleFormat::I24
+        | SampleFormat::I32
+        | SampleFormat::I64 => KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM,
 
         SampleFormat::F32 => Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
         _ => return None,
@@ -990,7 +1566,7 @@ fn config_to_waveformatextensible(
     let waveformatextensible = Audio::WAVEFORMATEXTENSIBLE {
         Format: waveformatex,
         Samples: Audio::WAVEFORMATEXTENSIBLE_0 {
-            wSamplesPerBlock: bits_per_sample,
+            wValidBitsPerSample: valid_bits_per_sample,
         },
         dwChannelMask: channel_mask,
         SubFormat: sub_format,
@@ -1009,3 +1585,94 @@ fn buffer_size_to_duration(buffer_size: &BufferSize, sample_rate: u32) -> i64 {
 fn buffer_duration_to_frames(buffer_duration: i64, sample_rate: u32) -> FrameCount {
     (buffer_duration * sample_rate as i64 * 100 / 1_000_000_000) as FrameCount
 }
+
+#[test]
+fn test_default_device_error() {
+    use windows::core::Error;
+
+    let not_found = Error::from(Foundation::ERROR_NOT_FOUND.to_hresult());
+    assert_eq!(default_device_error(not_found), None);
+
+    let description = |code| match default_device_error(Error::from(code)) {
+        Some(DefaultDeviceError::BackendSpecific { err }) => err.description,
+        None => panic!("{:?} was mapped to no device", code),
+    };
+    assert_eq!(
+        description(Foundation::E_ACCESSDENIED),
+        "access to the audio endpoints was denied"
+    );
+    assert_eq!(
+        description(Foundation::CO_E_NOTINITIALIZED),
+        "COM is not initialized on this thread"
+    );
+    assert!(description(Foundation::E_FAIL).starts_with("failed to get the default audio endpoint"));
+}
+
+#[test]
+fn test_initialize_err() {
+    let err = |code: windows::core::HRESULT| initialize_err(code.into());
+    assert_eq!(
+        err(Audio::AUDCLNT_E_ENGINE_PERIODICITY_LOCKED),
+        BuildStreamError::PeriodLocked
+    );
+    assert_eq!(
+        err(Audio::AUDCLNT_E_INVALID_DEVICE_PERIOD),
+        BuildStreamError::PeriodNotSupported
+    );
+    assert_eq!(
+        err(Audio::AUDCLNT_E_DEVICE_INVALIDATED),
+        BuildStreamError::DeviceNotAvailable
+    );
+    assert_eq!(aligned_period(480, 48_000), 100_000);
+}
+
+#[test]
+fn test_waveformatextensible_valid_bits() {
+    // Mix formats as reported by real drivers: a Realtek HDA codec, a USB DAC in exclusive mode
+    // and a driver that leaves the valid bits of its float format at zero.
+    let descriptor = |bits, valid_bits, sub_format| Audio::WAVEFORMATEXTENSIBLE {
+        Format: Audio::WAVEFORMATEX {
+            wFormatTag: KernelStreaming::WAVE_FORMAT_EXTENSIBLE as u16,
+            nChannels: 2,
+            nSamplesPerSec: 48_000,
+            nAvgBytesPerSec: 48_000 * 2 * u32::from(bits / 8),
+            nBlockAlign: 2 * bits / 8,
+            wBitsPerSample: bits,
+            cbSize: 22,
+        },
+        Samples: Audio::WAVEFORMATEXTENSIBLE_0 {
+            wValidBitsPerSample: valid_bits,
+        },
+        dwChannelMask: 3,
+        SubFormat: sub_format,
+    };
+    let decode = |format: &Audio::WAVEFORMATEXTENSIBLE| unsafe {
+        sample_format_from_waveformatex_ptr(&format.Format)
+    };
+    let pcm = KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM;
+    let float = Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT;
+
+    assert_eq!(decode(&descriptor(32, 32, float)), Some(SampleFormat::F32));
+    assert_eq!(decode(&descriptor(32, 0, float)), Some(SampleFormat::F32));
+    assert_eq!(decode(&descriptor(32, 24, pcm)), Some(SampleFormat::I24));
+    assert_eq!(decode(&descriptor(32, 20, pcm)), Some(SampleFormat::I24));
+    assert_eq!(decode(&descriptor(32, 32, pcm)), Some(SampleFormat::I32));
+    assert_eq!(decode(&descriptor(16, 0, pcm)), Some(SampleFormat::I16));
+    // Packed 24-bit samples have no cpal equivalent.
+    assert_eq!(decode(&descriptor(24, 24, pcm)), None);
+
+    // `I24` is requested as 24 valid bits in 32, and reads back as such.
+    let config = StreamConfig {
+        channels: 2,
+        sample_rate: SampleRate(48_000),
+        buffer_size: BufferSize::Default,
+    };
+    let encoded = config_to_waveformatextensible(&config, SampleFormat::I24).unwrap();
+    assert_eq!({ encoded.Format.wBitsPerSample }, 32);
+    assert_eq!(unsafe { encoded.Samples.wValidBitsPerSample }, 24);
+    assert_eq!(decode(&encoded), Some(SampleFormat::I24));
+    for sample_format in [SampleFormat::I16, SampleFormat::I32, SampleFormat::F32] {
+        let encoded = config_to_waveformatextensible(&config, sample_format).unwrap();
+        assert_eq!(decode(&encoded), Some(sample_format));
+    }
+}
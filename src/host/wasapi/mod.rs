@@ -1,17 +1,25 @@
+pub use self::default_device::{DefaultDeviceChange, DefaultDeviceListener};
 pub use self::device::{
-    default_input_device, default_output_device, Device, Devices, SupportedInputConfigs,
-    SupportedOutputConfigs,
+    default_input_device, default_output_device, try_default_input_device,
+    try_default_output_device, Device, Devices, SupportedInputConfigs, SupportedOutputConfigs,
 };
+pub use self::spatial::{ObjectHandle, SpatialAudio, SpatialAudioError, SpatialStream};
 pub use self::stream::Stream;
+pub use self::volume::VolumeListener;
 use crate::traits::HostTrait;
 use crate::BackendSpecificError;
+use crate::DefaultDeviceError;
 use crate::DevicesError;
 use std::io::Error as IoError;
 use windows::Win32::Media::Audio;
 
 mod com;
+mod default_device;
 mod device;
+mod session;
+mod spatial;
 mod stream;
+mod volume;
 
 /// The WASAPI host, the default windows host type.
 ///
@@ -21,6 +29,27 @@ mod stream;
 #[derive(Debug)]
 pub struct Host;
 
+/// Whether a stream shares the device with other applications through the audio engine, or has
+/// exclusive access to it.
+///
+/// Exclusive mode bypasses the system mixer, so formats that the mixer doesn't run at (e.g. 24-bit
+/// at 96 kHz for bit-perfect playback) may only be available in this mode.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ShareMode {
+    #[default]
+    Shared,
+    Exclusive,
+}
+
+impl ShareMode {
+    fn to_audclnt(self) -> Audio::AUDCLNT_SHAREMODE {
+        match self {
+            ShareMode::Shared => Audio::AUDCLNT_SHAREMODE_SHARED,
+            ShareMode::Exclusive => Audio::AUDCLNT_SHAREMODE_EXCLUSIVE,
+        }
+    }
+}
+
 impl Host {
     pub fn new() -> Result<Self, crate::HostUnavailable> {
         Ok(Host)
@@ -47,6 +76,14 @@ impl HostTrait for Host {
     fn default_output_device(&self) -> Option<Self::Device> {
         default_output_device()
     }
+
+    fn try_default_input_device(&self) -> Result<Option<Self::Device>, DefaultDeviceError> {
+        try_default_input_device()
+    }
+
+    fn try_default_output_device(&self) -> Result<Option<Self::Device>, DefaultDeviceError> {
+        try_default_output_device()
+    }
 }
 
 impl From<windows::core::Error> for BackendSpecificError {
@@ -79,6 +116,12 @@ impl ErrDeviceNotAvailable for crate::DefaultStreamConfigError {
     }
 }
 
+impl ErrDeviceNotAvailable for crate::CloseStreamError {
+    fn device_not_available() -> Self {
+        Self::DeviceNotAvailable
+    }
+}
+
 impl ErrDeviceNotAvailable for crate::StreamError {
     fn device_not_available() -> Self {
         Self::DeviceNotAvailable
@@ -0,0 +1,131 @@
+use crate::StreamError;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use windows::core::{implement, GUID, PCSTR, PCWSTR};
+use windows::Win32::Foundation;
+use windows::Win32::Media::Audio;
+use windows::Win32::System::Threading;
+
+// The reason the audio session of a stream was disconnected, if it was.
+pub struct Disconnect(AtomicI32);
+
+// Stored while the session is connected; the disconnect reasons are all non-negative.
+const CONNECTED: i32 = -1;
+
+impl Disconnect {
+    // The error to report for the disconnection, or `None` while the session is connected.
+    pub fn error(&self) -> Option<StreamError> {
+        match self.0.load(Ordering::SeqCst) {
+            CONNECTED => None,
+            reason if reason == Audio::DisconnectReasonExclusiveModeOverride.0 => {
+                Some(StreamError::Interrupted { resumable: false })
+            }
+            reason if reason == Audio::DisconnectReasonFormatChanged.0 => {
+                Some(StreamError::DeviceInvalidated)
+            }
+            _ => Some(StreamError::DeviceNotAvailable),
+        }
+    }
+}
+
+// Watches the audio session of a stream for being disconnected, which happens when the device is
+// removed or taken over by a stream in exclusive mode, among others. The audio client of a
+// disconnected session can't be used anymore.
+pub struct SessionWatch {
+    control: Audio::IAudioSessionControl,
+    events: Audio::IAudioSessionEvents,
+    // Signalled once the session is disconnected. Owned by `events`.
+    pub event: Foundation::HANDLE,
+    pub disconnect: Arc<Disconnect>,
+}
+
+#[implement(Audio::IAudioSessionEvents)]
+struct SessionEvents {
+    event: Foundation::HANDLE,
+    disconnect: Arc<Disconnect>,
+}
+
+impl Audio::IAudioSessionEvents_Impl for SessionEvents {
+    fn OnDisplayNameChanged(&self, _: &PCWSTR, _: *const GUID) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnIconPathChanged(&self, _: &PCWSTR, _: *const GUID) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnSimpleVolumeChanged(
+        &self,
+        _: f32,
+        _: Foundation::BOOL,
+        _: *const GUID,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnChannelVolumeChanged(
+        &self,
+        _: u32,
+        _: *const f32,
+        _: u32,
+        _: *const GUID,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnGroupingParamChanged(&self, _: *const GUID, _: *const GUID) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnStateChanged(&self, _: Audio::AudioSessionState) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnSessionDisconnected(
+        &self,
+        reason: Audio::AudioSessionDisconnectReason,
+    ) -> windows::core::Result<()> {
+        self.disconnect.0.store(reason.0, Ordering::SeqCst);
+        unsafe { Threading::SetEvent(self.event) }
+    }
+}
+
+impl Drop for SessionEvents {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = Foundation::CloseHandle(self.event);
+        }
+    }
+}
+
+impl SessionWatch {
+    // Start watching the session of `audio_client`. A stream works without, so this yields
+    // `None` rather than an error if the session can't be watched.
+    pub unsafe fn new(audio_client: &Audio::IAudioClient) -> Option<Self> {
+        let control: Audio::IAudioSessionControl = audio_client.GetService().ok()?;
+        let event = Threading::CreateEventA(None, false, false, PCSTR::null()).ok()?;
+        let disconnect = Arc::new(Disconnect(AtomicI32::new(CONNECTED)));
+        let events: Audio::IAudioSessionEvents = SessionEvents {
+            event,
+            disconnect: disconnect.clone(),
+        }
+        .into();
+        control.RegisterAudioSessionNotification(&events).ok()?;
+        Some(SessionWatch {
+            control,
+            events,
+            event,
+            disconnect,
+        })
+    }
+}
+
+impl Drop for SessionWatch {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self
+                .control
+                .UnregisterAudioSessionNotification(&self.events);
+        }
+    }
+}
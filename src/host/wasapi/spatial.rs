@@ -0,0 +1,617 @@
+use super::com;
+use super::stream::wait_for_handle_signal;
+use super::Device;
+use crate::ring_buffer::{ring_buffer, RingConsumer, RingProducer};
+use crate::{
+    BackendSpecificError, ChannelCount, SampleFormat, SampleRate, StreamError, SupportedBufferSize,
+    SupportedStreamConfig,
+};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::mem::{self, ManuallyDrop};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use windows::core::Interface;
+use windows::Win32::Foundation;
+use windows::Win32::Media::Audio;
+use windows::Win32::System::Com;
+use windows::Win32::System::Threading;
+use windows::Win32::System::Variant::VT_BLOB;
+
+/// An error related to spatial audio.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SpatialAudioError {
+    /// The endpoint or the OS doesn't support spatial audio, or no spatial sound format (e.g.
+    /// Windows Sonic or Dolby Atmos) is enabled for the endpoint.
+    Unsupported,
+    /// The device no longer exists.
+    DeviceNotAvailable,
+    /// The stream renders as many dynamic objects as it was built for.
+    NoMoreObjects,
+    /// The object handle doesn't belong to the stream or the object was removed.
+    InvalidObject,
+    /// See the [`BackendSpecificError`] docs for more information about this error variant.
+    BackendSpecific { err: BackendSpecificError },
+}
+
+impl Display for SpatialAudioError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BackendSpecific { err } => err.fmt(f),
+            Self::Unsupported => f.write_str("Spatial audio is not available on the device."),
+            Self::DeviceNotAvailable => f.write_str(
+                "The requested device is no longer available. For example, it has been unplugged.",
+            ),
+            Self::NoMoreObjects => {
+                f.write_str("The stream renders as many audio objects as it supports.")
+            }
+            Self::InvalidObject => f.write_str("The audio object does not exist."),
+        }
+    }
+}
+
+impl Error for SpatialAudioError {}
+
+impl From<BackendSpecificError> for SpatialAudioError {
+    fn from(err: BackendSpecificError) -> Self {
+        Self::BackendSpecific { err }
+    }
+}
+
+impl From<windows::core::Error> for SpatialAudioError {
+    fn from(err: windows::core::Error) -> Self {
+        match err.code() {
+            Audio::AUDCLNT_E_DEVICE_INVALIDATED => Self::DeviceNotAvailable,
+            Foundation::E_NOINTERFACE
+            | Audio::SPTLAUDCLNT_E_STREAM_NOT_AVAILABLE
+            | Audio::SPTLAUDCLNT_E_STATIC_OBJECT_NOT_AVAILABLE => Self::Unsupported,
+            Audio::SPTLAUDCLNT_E_NO_MORE_OBJECTS => Self::NoMoreObjects,
+            _ => BackendSpecificError::from(err).into(),
+        }
+    }
+}
+
+/// The spatial audio capabilities of an endpoint. See [`Device::spatial_audio`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SpatialAudio {
+    /// The number of static bed channels the endpoint renders natively, e.g. 12 for 7.1.4.
+    pub bed_channels: ChannelCount,
+    /// The most dynamic objects the endpoint can render at once.
+    pub max_dynamic_objects: u32,
+    /// The sample rate of the bed and of every object.
+    pub sample_rate: SampleRate,
+}
+
+impl SpatialAudio {
+    /// The configuration of the static bed written with [`SpatialStream::write_bed`]: the native
+    /// bed channels of the endpoint as interleaved `f32` samples.
+    ///
+    /// The channels are in the usual WAVE order: front left, front right, front center, low
+    /// frequency, side left, side right, back left, back right, then the top and bottom
+    /// channels, leaving out those the endpoint doesn't have.
+    pub fn bed_config(&self) -> SupportedStreamConfig {
+        SupportedStreamConfig::new(
+            self.bed_channels,
+            self.sample_rate,
+            SupportedBufferSize::Unknown,
+            SampleFormat::F32,
+        )
+    }
+}
+
+/// Identifies a dynamic audio object of a [`SpatialStream`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectHandle(usize);
+
+// The position of an object, shared with the render thread.
+struct ObjectShared {
+    // The x, y and z coordinates as `f32` bits.
+    position: [AtomicU32; 3],
+    removed: AtomicBool,
+}
+
+struct Object {
+    samples: RingProducer,
+    shared: Arc<ObjectShared>,
+}
+
+enum Command {
+    Play,
+    Pause,
+    AddObject(RingConsumer, Arc<ObjectShared>),
+    Terminate,
+}
+
+// The event signalled when a command is sent to the render thread.
+struct CommandEvent(Foundation::HANDLE);
+
+unsafe impl Send for CommandEvent {}
+
+/// Renders a static bed and dynamic audio objects with 3D positions through the spatial audio
+/// engine of an endpoint. Built with [`Device::build_spatial_stream`].
+///
+/// Samples are queued with [`write_bed`](Self::write_bed) and
+/// [`write_object`](Self::write_object) and rendered from a thread owned by the stream, which
+/// renders silence where the queues run out. The stream is paused after it is built.
+pub struct SpatialStream {
+    spatial_audio: SpatialAudio,
+    bed: RingProducer,
+    objects: Vec<Option<Object>>,
+    // Bounds the number of live objects to what the stream was built for.
+    max_objects: usize,
+    capacity: usize,
+    commands: Sender<Command>,
+    event: CommandEvent,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Device {
+    /// The spatial audio capabilities of this endpoint.
+    ///
+    /// Fails with [`SpatialAudioError::Unsupported`] if spatial audio isn't available, e.g. on
+    /// Windows versions before the Creators Update or when no spatial sound format is enabled for
+    /// the endpoint.
+    pub fn spatial_audio(&self) -> Result<SpatialAudio, SpatialAudioError> {
+        com::com_initialized();
+        unsafe {
+            let client = self.spatial_audio_client()?;
+            let (spatial_audio, _, _) = query_spatial_audio(&client)?;
+            Ok(spatial_audio)
+        }
+    }
+
+    /// Build a stream rendering a static bed and up to `max_objects` dynamic objects.
+    ///
+    /// `capacity` is the number of samples queued per object, and per bed channel, before the
+    /// oldest ones are dropped. `error_callback` is called from the render thread.
+    pub fn build_spatial_stream<E>(
+        &self,
+        max_objects: u32,
+        capacity: usize,
+        error_callback: E,
+    ) -> Result<SpatialStream, SpatialAudioError>
+    where
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let device = self.clone();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (commands, commands_rx) = mpsc::channel();
+        let event = unsafe {
+            Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
+        }
+        .map_err(|err| SpatialAudioError::from(BackendSpecificError::from(err)))?;
+        let event = CommandEvent(event);
+        let thread_event = CommandEvent(event.0);
+        let thread = thread::Builder::new()
+            .name("cpal_wasapi_spatial".to_owned())
+            .spawn(move || {
+                let thread_event = thread_event;
+                run(
+                    &device,
+                    max_objects,
+                    capacity,
+                    thread_event.0,
+                    commands_rx,
+                    ready_tx,
+                    error_callback,
+                )
+            })
+            .map_err(|err| BackendSpecificError {
+                description: format!("failed to spawn the spatial audio thread: {}", err),
+            })?;
+
+        // The stream is activated on its thread, which reports back here.
+        let (spatial_audio, bed) = match ready_rx.recv() {
+            Ok(Ok(ready)) => ready,
+            Ok(Err(err)) => {
+                let _ = thread.join();
+                unsafe {
+                    let _ = Foundation::CloseHandle(event.0);
+                }
+                return Err(err);
+            }
+            Err(_) => {
+                unsafe {
+                    let _ = Foundation::CloseHandle(event.0);
+                }
+                return Err(BackendSpecificError {
+                    description: "the spatial audio thread exited unexpectedly".to_owned(),
+                }
+                .into());
+            }
+        };
+        Ok(SpatialStream {
+            spatial_audio,
+            bed,
+            objects: Vec::new(),
+            max_objects: max_objects as usize,
+            capacity,
+            commands,
+            event,
+            thread: Some(thread),
+        })
+    }
+
+    unsafe fn spatial_audio_client(&self) -> Result<Audio::ISpatialAudioClient, SpatialAudioError> {
+        Ok(self
+            .immdevice()
+            .Activate::<Audio::ISpatialAudioClient>(Com::CLSCTX_INPROC_SERVER, None)?)
+    }
+}
+
+impl SpatialStream {
+    /// The capabilities of the endpoint the stream renders to.
+    pub fn spatial_audio(&self) -> &SpatialAudio {
+        &self.spatial_audio
+    }
+
+    /// Start rendering.
+    pub fn play(&self) -> Result<(), SpatialAudioError> {
+        self.send(Command::Play)
+    }
+
+    /// Stop rendering, keeping the queued samples.
+    pub fn pause(&self) -> Result<(), SpatialAudioError> {
+        self.send(Command::Pause)
+    }
+
+    /// Queue interleaved samples for the static bed, in the layout of
+    /// [`SpatialAudio::bed_config`].
+    pub fn write_bed(&mut self, samples: &[f32]) {
+        self.bed.push_slice(samples);
+    }
+
+    /// Add a dynamic audio object, positioned at the listener until moved with
+    /// [`write_object`](Self::write_object).
+    pub fn add_object(&mut self) -> Result<ObjectHandle, SpatialAudioError> {
+        let live = self.objects.iter().flatten().count();
+        if live >= self.max_objects {
+            return Err(SpatialAudioError::NoMoreObjects);
+        }
+        let (producer, consumer) = ring_buffer(self.capacity);
+        let shared = Arc::new(ObjectShared {
+            position: Default::default(),
+            removed: AtomicBool::new(false),
+        });
+        self.send(Command::AddObject(consumer, shared.clone()))?;
+        let object = Object {
+            samples: producer,
+            shared,
+        };
+        let index = match self.objects.iter().position(Option::is_none) {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                index
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.objects.len() - 1
+            }
+        };
+        Ok(ObjectHandle(index))
+    }
+
+    /// Queue mono samples for an object and move it to `position`.
+    ///
+    /// The position is in meters relative to the listener, with x to the right, y up and z
+    /// behind the listener. It applies from the next buffer the object renders.
+    pub fn write_object(
+        &mut self,
+        handle: ObjectHandle,
+        samples: &[f32],
+        position: [f32; 3],
+    ) -> Result<(), SpatialAudioError> {
+        let object = self
+            .objects
+            .get_mut(handle.0)
+            .and_then(Option::as_mut)
+            .ok_or(SpatialAudioError::InvalidObject)?;
+        for (coordinate, value) in object.shared.position.iter().zip(position) {
+            coordinate.store(value.to_bits(), Ordering::Relaxed);
+        }
+        object.samples.push_slice(samples);
+        Ok(())
+    }
+
+    /// Remove an object once its queued samples have been rendered.
+    pub fn remove_object(&mut self, handle: ObjectHandle) -> Result<(), SpatialAudioError> {
+        let object = self
+            .objects
+            .get_mut(handle.0)
+            .and_then(Option::take)
+            .ok_or(SpatialAudioError::InvalidObject)?;
+        object.shared.removed.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn send(&self, command: Command) -> Result<(), SpatialAudioError> {
+        self.commands
+            .send(command)
+            .map_err(|_| SpatialAudioError::DeviceNotAvailable)?;
+        unsafe {
+            Threading::SetEvent(self.event.0)
+                .map_err(|err| SpatialAudioError::from(BackendSpecificError::from(err)))
+        }
+    }
+}
+
+impl Drop for SpatialStream {
+    fn drop(&mut self) {
+        let _ = self.send(Command::Terminate);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe {
+            let _ = Foundation::CloseHandle(self.event.0);
+        }
+    }
+}
+
+// The capabilities of the endpoint, with the static object mask of its bed and the format of
+// objects.
+unsafe fn query_spatial_audio(
+    client: &Audio::ISpatialAudioClient,
+) -> Result<(SpatialAudio, Audio::AudioObjectType, Audio::WAVEFORMATEX), SpatialAudioError> {
+    client.IsSpatialAudioStreamAvailable(&Audio::ISpatialAudioObjectRenderStream::IID, None)?;
+    let mask = client.GetNativeStaticObjectTypeMask()?.0 & !Audio::AudioObjectType_Dynamic.0;
+    let max_dynamic_objects = client.GetMaxDynamicObjectCount()?;
+    let formats = client.GetSupportedAudioObjectFormatEnumerator()?;
+    let format = *formats.GetFormat(0)?;
+    let spatial_audio = SpatialAudio {
+        bed_channels: mask.count_ones() as ChannelCount,
+        max_dynamic_objects,
+        sample_rate: SampleRate(format.nSamplesPerSec),
+    };
+    Ok((spatial_audio, Audio::AudioObjectType(mask), format))
+}
+
+// The types of the static objects in `mask`, in bed channel order.
+fn bed_object_types(mask: Audio::AudioObjectType) -> Vec<Audio::AudioObjectType> {
+    (0..i32::BITS)
+        .map(|bit| 1 << bit)
+        .filter(|bit| mask.0 & bit != 0)
+        .map(Audio::AudioObjectType)
+        .collect()
+}
+
+struct RenderObject {
+    object: Audio::ISpatialAudioObject,
+    samples: RingConsumer,
+    shared: Arc<ObjectShared>,
+}
+
+// Activate the spatial stream on the calling thread and render it until terminated.
+fn run<E>(
+    device: &Device,
+    max_objects: u32,
+    capacity: usize,
+    command_event: Foundation::HANDLE,
+    commands: Receiver<Command>,
+    ready: Sender<Result<(SpatialAudio, RingProducer), SpatialAudioError>>,
+    mut error_callback: E,
+) where
+    E: FnMut(StreamError),
+{
+    com::com_initialized();
+    let activated = unsafe { activate(device, max_objects, capacity) };
+    let (stream, render_event, bed_objects, mut bed) = match activated {
+        Ok((spatial_audio, stream, render_event, bed_objects, producer, consumer)) => {
+            if ready.send(Ok((spatial_audio, producer))).is_err() {
+                return;
+            }
+            (stream, render_event, bed_objects, consumer)
+        }
+        Err(err) => {
+            let _ = ready.send(Err(err));
+            return;
+        }
+    };
+
+    let mut objects: Vec<RenderObject> = Vec::new();
+    let mut pending = Vec::new();
+    let mut frame = Vec::new();
+    let handles = [command_event, render_event];
+    'run: loop {
+        let signalled = match wait_for_handle_signal(&handles) {
+            Ok(index) => index,
+            Err(err) => {
+                error_callback(err.into());
+                break;
+            }
+        };
+        for command in commands.try_iter() {
+            let result = unsafe {
+                match command {
+                    Command::Play => stream.Start(),
+                    Command::Pause => stream.Stop(),
+                    Command::AddObject(samples, shared) => {
+                        pending.push((samples, shared));
+                        Ok(())
+                    }
+                    Command::Terminate => break 'run,
+                }
+            };
+            if let Err(err) = result {
+                error_callback(windows_err_to_stream_err(err));
+            }
+        }
+        if signalled == 1 {
+            let result = unsafe {
+                render(
+                    &stream,
+                    &bed_objects,
+                    &mut bed,
+                    &mut frame,
+                    &mut objects,
+                    &mut pending,
+                )
+            };
+            if let Err(err) = result {
+                let err = windows_err_to_stream_err(err);
+                let gone = err == StreamError::DeviceNotAvailable;
+                error_callback(err);
+                if gone {
+                    break;
+                }
+            }
+        }
+    }
+    unsafe {
+        let _ = stream.Stop();
+        let _ = Foundation::CloseHandle(render_event);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+unsafe fn activate(
+    device: &Device,
+    max_objects: u32,
+    capacity: usize,
+) -> Result<
+    (
+        SpatialAudio,
+        Audio::ISpatialAudioObjectRenderStream,
+        Foundation::HANDLE,
+        Vec<Audio::ISpatialAudioObject>,
+        RingProducer,
+        RingConsumer,
+    ),
+    SpatialAudioError,
+> {
+    let client = device.spatial_audio_client()?;
+    let (spatial_audio, mask, format) = query_spatial_audio(&client)?;
+    if max_objects > spatial_audio.max_dynamic_objects {
+        return Err(SpatialAudioError::NoMoreObjects);
+    }
+    let render_event =
+        Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
+            .map_err(BackendSpecificError::from)?;
+    let params = Audio::SpatialAudioObjectRenderStreamActivationParams {
+        ObjectFormat: &format,
+        StaticObjectTypeMask: mask,
+        MinDynamicObjectCount: 0,
+        MaxDynamicObjectCount: max_objects,
+        Category: Audio::AudioCategory_GameEffects,
+        EventHandle: render_event,
+        NotifyObject: ManuallyDrop::new(None),
+    };
+    // The parameters are passed as a `VT_BLOB` property, which borrows them.
+    let mut raw: windows::core::imp::PROPVARIANT = mem::zeroed();
+    raw.Anonymous.Anonymous.vt = VT_BLOB.0;
+    raw.Anonymous.Anonymous.Anonymous.blob = windows::core::imp::BLOB {
+        cbSize: mem::size_of_val(&params) as u32,
+        pBlobData: &params as *const _ as *mut u8,
+    };
+    let stream = client
+        .ActivateSpatialAudioStream::<Audio::ISpatialAudioObjectRenderStream>(
+            &raw as *const _ as *const windows::core::PROPVARIANT,
+        )
+        .map_err(|err| {
+            let _ = Foundation::CloseHandle(render_event);
+            SpatialAudioError::from(err)
+        })?;
+    let bed_objects = bed_object_types(mask)
+        .into_iter()
+        .map(|kind| stream.ActivateSpatialAudioObject(kind))
+        .collect::<Result<Vec<_>, _>>()?;
+    let bed_capacity = capacity * (spatial_audio.bed_channels as usize).max(1);
+    let (producer, consumer) = ring_buffer(bed_capacity);
+    Ok((
+        spatial_audio,
+        stream,
+        render_event,
+        bed_objects,
+        producer,
+        consumer,
+    ))
+}
+
+// Fill the buffers of the bed and of every object for one processing pass.
+unsafe fn render(
+    stream: &Audio::ISpatialAudioObjectRenderStream,
+    bed_objects: &[Audio::ISpatialAudioObject],
+    bed: &mut RingConsumer,
+    frame: &mut Vec<f32>,
+    objects: &mut Vec<RenderObject>,
+    pending: &mut Vec<(RingConsumer, Arc<ObjectShared>)>,
+) -> windows::core::Result<()> {
+    let (mut available, mut frame_count) = (0, 0);
+    stream.BeginUpdatingAudioObjects(&mut available, &mut frame_count)?;
+
+    // Dynamic objects can only be activated while updating.
+    while available > 0 && !pending.is_empty() {
+        let (samples, shared) = pending.remove(0);
+        let object = stream.ActivateSpatialAudioObject(Audio::AudioObjectType_Dynamic)?;
+        objects.push(RenderObject {
+            object,
+            samples,
+            shared,
+        });
+        available -= 1;
+    }
+
+    // Deinterleave the bed, frame by frame.
+    let mut buffers = Vec::with_capacity(bed_objects.len());
+    for object in bed_objects {
+        buffers.push(object_buffer(object)?);
+    }
+    frame.resize(bed_objects.len(), 0.0);
+    for i in 0..frame_count as usize {
+        let n = bed.pop_slice(frame);
+        frame[n..].fill(0.0);
+        for (buffer, &sample) in buffers.iter_mut().zip(frame.iter()) {
+            if let Some(out) = buffer.get_mut(i) {
+                *out = sample;
+            }
+        }
+    }
+
+    let mut result = Ok(());
+    objects.retain_mut(|object| {
+        if object.shared.removed.load(Ordering::Acquire) && object.samples.is_empty() {
+            let _ = object.object.SetEndOfStream(0);
+            return false;
+        }
+        let [x, y, z] = &object.shared.position;
+        let [x, y, z] = [x, y, z].map(|c| f32::from_bits(c.load(Ordering::Relaxed)));
+        let filled = object_buffer(&object.object).and_then(|buffer| {
+            let n = object.samples.pop_slice(buffer);
+            buffer[n..].fill(0.0);
+            object.object.SetPosition(x, y, z)
+        });
+        if let Err(err) = filled {
+            result = Err(err);
+        }
+        true
+    });
+    stream.EndUpdatingAudioObjects()?;
+    result
+}
+
+// The `f32` buffer of an object for the current processing pass.
+unsafe fn object_buffer<'a>(
+    object: &Audio::ISpatialAudioObject,
+) -> windows::core::Result<&'a mut [f32]> {
+    let (mut data, mut len) = (ptr::null_mut(), 0);
+    object.GetBuffer(&mut data, &mut len)?;
+    if data.is_null() {
+        return Ok(&mut []);
+    }
+    Ok(slice::from_raw_parts_mut(
+        data as *mut f32,
+        len as usize / mem::size_of::<f32>(),
+    ))
+}
+
+fn windows_err_to_stream_err(err: windows::core::Error) -> StreamError {
+    match err.code() {
+        Audio::AUDCLNT_E_DEVICE_INVALIDATED | Audio::SPTLAUDCLNT_E_RESOURCES_INVALIDATED => {
+            StreamError::DeviceNotAvailable
+        }
+        _ => BackendSpecificError::from(err).into(),
+    }
+}
@@ -1,13 +1,20 @@
+use super::session::SessionWatch;
 use super::windows_err_to_cpal_err;
+use crate::diagnostics::{self, DiagnosticKind};
 use crate::traits::StreamTrait;
 use crate::{
-    BackendSpecificError, Data, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleFormat, StreamError,
+    BackendSpecificError, CloseStreamError, Data, DrainError, FrameCount, InputCallbackInfo,
+    OsProcessing, OutputCallbackInfo, PauseStreamError, PlayStreamError, PowerPreference,
+    SampleFormat, StreamError,
 };
 use std::mem;
 use std::ptr;
-use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, SendError, Sender};
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use windows::core::HSTRING;
 use windows::Win32::Foundation;
 use windows::Win32::Foundation::HANDLE;
 use windows::Win32::Foundation::WAIT_OBJECT_0;
@@ -30,6 +37,32 @@ pub struct Stream {
     // This event is signalled after a new entry is added to `commands`, so that the `run()`
     // method can be notified.
     pending_scheduled_event: Foundation::HANDLE,
+
+    // Whether the stream was handed to the endpoint's audio offload engine.
+    offloaded: bool,
+
+    // The size of every buffer handed to the data callback, for exclusive mode streams.
+    fixed_buffer_size: Option<FrameCount>,
+
+    // The size of the endpoint buffer, as reported by `IAudioClient::GetBufferSize`.
+    buffer_size: FrameCount,
+
+    os_processing: OsProcessing,
+
+    // Shared with the run loop, see `StreamInner::min_callback_frames`.
+    min_callback_frames: Arc<AtomicU32>,
+
+    // The `min_callback_frames` the stream was built with.
+    granularity: u32,
+
+    // See `StreamInner::period_frames`.
+    period_frames: u32,
+
+    sample_rate: crate::SampleRate,
+
+    // Whether the data callback can be held back for larger batches, which is only done for
+    // shared-mode output streams that aren't offloaded.
+    batchable: bool,
 }
 
 struct RunContext {
@@ -49,6 +82,11 @@ unsafe impl Send for RunContext {}
 pub enum Command {
     PlayStream,
     PauseStream,
+    // Stop calling the data callback and notify the sender once the queued audio has played.
+    Drain(Sender<()>),
+    SetDisplayName(String),
+    // Stop the audio client, send the result and exit the run loop.
+    Close(Sender<Result<(), CloseStreamError>>),
     Terminate,
 }
 
@@ -77,6 +115,43 @@ pub struct StreamInner {
     pub config: crate::StreamConfig,
     // The sample format with which the stream was created.
     pub sample_format: SampleFormat,
+    // The name of the device, used to identify the stream in diagnostics.
+    pub device_name: String,
+    // Set while the stream is draining, to be notified once the buffer is empty.
+    pub draining: Option<Sender<()>>,
+    // Whether the audio client was initialized in offload mode.
+    pub offloaded: bool,
+    // Whether the audio client was initialized in exclusive mode, where every event hands over
+    // the whole buffer.
+    pub exclusive: bool,
+    // What the audio engine does to the stream, compared with its mix format.
+    pub os_processing: OsProcessing,
+    // The data callback of an output stream is skipped until this many frames can be written.
+    pub min_callback_frames: Arc<AtomicU32>,
+    // The number of frames the audio engine plays or captures between two events, if known.
+    pub period_frames: u32,
+    // Watches the audio session for being disconnected, unless that couldn't be set up.
+    pub session: Option<SessionWatch>,
+}
+
+impl StreamInner {
+    fn report(&self, kind: DiagnosticKind) {
+        diagnostics::report("WASAPI", || self.device_name.clone(), kind);
+    }
+
+    fn fixed_buffer_size(&self) -> Option<FrameCount> {
+        self.exclusive.then_some(self.max_frames_in_buffer)
+    }
+
+    // Report a `CallbackOverrun` if the data callback, started at `start`, took longer than the
+    // `frames` it was handed last.
+    fn check_callback_duration(&self, start: Instant, frames: u32) {
+        let elapsed = start.elapsed();
+        let budget = frames_to_duration(frames, self.config.sample_rate);
+        if elapsed > budget {
+            self.report(DiagnosticKind::CallbackOverrun { elapsed, budget });
+        }
+    }
 }
 
 impl Stream {
@@ -94,9 +169,19 @@ impl Stream {
         }
         .expect("cpal: could not create input stream event");
         let (tx, rx) = channel();
-
+        let offloaded = stream_inner.offloaded;
+        let fixed_buffer_size = stream_inner.fixed_buffer_size();
+        let buffer_size = stream_inner.max_frames_in_buffer;
+        let os_processing = stream_inner.os_processing;
+        let min_callback_frames = stream_inner.min_callback_frames.clone();
+        let granularity = min_callback_frames.load(Ordering::Relaxed);
+        let period_frames = stream_inner.period_frames;
+        let sample_rate = stream_inner.config.sample_rate;
+
+        let mut handles = vec![pending_scheduled_event, stream_inner.event];
+        handles.extend(stream_inner.session.as_ref().map(|session| session.event));
         let run_context = RunContext {
-            handles: vec![pending_scheduled_event, stream_inner.event],
+            handles,
             stream: stream_inner,
             commands: rx,
         };
@@ -110,6 +195,15 @@ impl Stream {
             thread: Some(thread),
             commands: tx,
             pending_scheduled_event,
+            offloaded,
+            fixed_buffer_size,
+            buffer_size,
+            os_processing,
+            min_callback_frames,
+            granularity,
+            period_frames,
+            sample_rate,
+            batchable: false,
         }
     }
 
@@ -127,9 +221,19 @@ impl Stream {
         }
         .expect("cpal: could not create output stream event");
         let (tx, rx) = channel();
-
+        let offloaded = stream_inner.offloaded;
+        let fixed_buffer_size = stream_inner.fixed_buffer_size();
+        let buffer_size = stream_inner.max_frames_in_buffer;
+        let os_processing = stream_inner.os_processing;
+        let min_callback_frames = stream_inner.min_callback_frames.clone();
+        let granularity = min_callback_frames.load(Ordering::Relaxed);
+        let period_frames = stream_inner.period_frames;
+        let sample_rate = stream_inner.config.sample_rate;
+
+        let mut handles = vec![pending_scheduled_event, stream_inner.event];
+        handles.extend(stream_inner.session.as_ref().map(|session| session.event));
         let run_context = RunContext {
-            handles: vec![pending_scheduled_event, stream_inner.event],
+            handles,
             stream: stream_inner,
             commands: rx,
         };
@@ -143,6 +247,15 @@ impl Stream {
             thread: Some(thread),
             commands: tx,
             pending_scheduled_event,
+            offloaded,
+            fixed_buffer_size,
+            buffer_size,
+            os_processing,
+            min_callback_frames,
+            granularity,
+            period_frames,
+            sample_rate,
+            batchable: !offloaded && fixed_buffer_size.is_none(),
         }
     }
 
@@ -154,16 +267,38 @@ impl Stream {
         }
         Ok(())
     }
+
+    /// Set the name under which the stream's audio session is shown in the Windows volume mixer.
+    ///
+    /// By default all streams of a process share a single session, so this names the session of
+    /// the whole application rather than just this stream. The name is applied asynchronously on
+    /// the stream's thread. A failure to apply it is reported to the error callback.
+    pub fn set_display_name(&self, name: &str) -> Result<(), StreamError> {
+        self.push_command(Command::SetDisplayName(name.to_owned()))
+            .map_err(|_| StreamError::DeviceNotAvailable)
+    }
+
+    /// Whether the stream is played through the endpoint's audio offload engine, which happens
+    /// for output streams of devices preferring [`PowerPreference::LowPower`] if the endpoint
+    /// supports it.
+    ///
+    /// [`PowerPreference::LowPower`]: crate::PowerPreference::LowPower
+    pub fn is_offloaded(&self) -> bool {
+        self.offloaded
+    }
 }
 
 impl Drop for Stream {
     #[inline]
     fn drop(&mut self) {
-        if self.push_command(Command::Terminate).is_ok() {
-            self.thread.take().unwrap().join().unwrap();
-            unsafe {
-                let _ = Foundation::CloseHandle(self.pending_scheduled_event);
-            }
+        // The thread may already have exited after an error, in which case the command can't be
+        // delivered, but the thread still has to be joined and the event closed.
+        let _ = self.push_command(Command::Terminate);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe {
+            let _ = Foundation::CloseHandle(self.pending_scheduled_event);
         }
     }
 }
@@ -179,6 +314,68 @@ impl StreamTrait for Stream {
             .map_err(|_| crate::error::PauseStreamError::DeviceNotAvailable)?;
         Ok(())
     }
+    fn is_terminated(&self) -> bool {
+        // The run loop only exits early after reporting a fatal error, releasing the audio client.
+        self.thread
+            .as_ref()
+            .map_or(true, |thread| thread.is_finished())
+    }
+    fn close(mut self) -> Result<(), CloseStreamError> {
+        let (tx, rx) = channel();
+        let _ = self.push_command(Command::Close(tx));
+        if let Some(thread) = self.thread.take() {
+            thread.join().map_err(|_| BackendSpecificError {
+                description: "the stream's thread panicked".to_owned(),
+            })?;
+        }
+        // Nothing is received if the run loop had already exited after an error.
+        rx.recv()
+            .unwrap_or(Err(CloseStreamError::DeviceNotAvailable))
+    }
+    fn drain_timeout(&self, timeout: Duration) -> Result<(), DrainError> {
+        let (tx, rx) = channel();
+        self.push_command(Command::Drain(tx))
+            .map_err(|_| DrainError::DeviceNotAvailable)?;
+        match rx.recv_timeout(timeout) {
+            Ok(()) => Ok(()),
+            Err(RecvTimeoutError::Timeout) => Err(DrainError::TimedOut),
+            // Either the run loop exited or `play` cancelled the drain.
+            Err(RecvTimeoutError::Disconnected) if self.is_terminated() => {
+                Err(DrainError::DeviceNotAvailable)
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(DrainError::Cancelled),
+        }
+    }
+    fn fixed_buffer_size(&self) -> Option<FrameCount> {
+        self.fixed_buffer_size
+    }
+    fn actual_buffer_size(&self) -> Option<FrameCount> {
+        Some(self.buffer_size)
+    }
+    fn os_processing(&self) -> OsProcessing {
+        self.os_processing
+    }
+    fn set_power_preference(&self, preference: PowerPreference) -> Result<(), StreamError> {
+        if self.is_terminated() {
+            return Err(StreamError::DeviceNotAvailable);
+        }
+        // The buffer can't be resized without rebuilding the stream, so only the calls of the
+        // data callback are batched.
+        if self.batchable {
+            let frames = match preference {
+                PowerPreference::LowPower => self.granularity.max(self.buffer_size / 2),
+                PowerPreference::Default => self.granularity,
+            };
+            self.min_callback_frames.store(frames, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+    fn wakeup_interval(&self) -> Option<Duration> {
+        let frames = self
+            .period_frames
+            .max(self.min_callback_frames.load(Ordering::Relaxed));
+        (frames != 0).then(|| frames_to_duration(frames, self.sample_rate))
+    }
 }
 
 impl Drop for StreamInner {
@@ -192,11 +389,15 @@ impl Drop for StreamInner {
 
 // Process any pending commands that are queued within the `RunContext`.
 // Returns `true` if the loop should continue running, `false` if it should terminate.
-fn process_commands(run_context: &mut RunContext) -> Result<bool, StreamError> {
+fn process_commands(
+    run_context: &mut RunContext,
+    error_callback: &mut dyn FnMut(StreamError),
+) -> Result<bool, StreamError> {
     // Process the pending commands.
     for command in run_context.commands.try_iter() {
         match command {
             Command::PlayStream => unsafe {
+                run_context.stream.draining = None;
                 if !run_context.stream.playing {
                     run_context
                         .stream
@@ -216,6 +417,34 @@ fn process_commands(run_context: &mut RunContext) -> Result<bool, StreamError> {
                     run_context.stream.playing = false;
                 }
             },
+            Command::Drain(done) => match run_context.stream.client_flow {
+                AudioClientFlow::Render { .. } => run_context.stream.draining = Some(done),
+                // There is nothing to drain from a capture stream.
+                AudioClientFlow::Capture { .. } => {
+                    let _ = done.send(());
+                }
+            },
+            Command::SetDisplayName(name) => unsafe {
+                // Naming the session is cosmetic, so a failure shouldn't stop the stream.
+                let result = run_context
+                    .stream
+                    .audio_client
+                    .GetService::<Audio::IAudioSessionControl>()
+                    .and_then(|control| control.SetDisplayName(&HSTRING::from(name), ptr::null()));
+                if let Err(err) = result {
+                    error_callback(BackendSpecificError::from(err).into());
+                }
+            },
+            Command::Close(done) => {
+                let result = if run_context.stream.playing {
+                    unsafe { run_context.stream.audio_client.Stop() }
+                        .map_err(windows_err_to_cpal_err::<CloseStreamError>)
+                } else {
+                    Ok(())
+                };
+                let _ = done.send(result);
+                return Ok(false);
+            }
             Command::Terminate => {
                 return Ok(false);
             }
@@ -232,7 +461,9 @@ fn process_commands(run_context: &mut RunContext) -> Result<bool, StreamError> {
 // This is called when the `run` thread is ready to wait for the next event. The
 // next event might be some command submitted by the user (the first handle) or
 // might indicate that one of the streams is ready to deliver or receive audio.
-fn wait_for_handle_signal(handles: &[Foundation::HANDLE]) -> Result<usize, BackendSpecificError> {
+pub(super) fn wait_for_handle_signal(
+    handles: &[Foundation::HANDLE],
+) -> Result<usize, BackendSpecificError> {
     debug_assert!(handles.len() <= SystemServices::MAXIMUM_WAIT_OBJECTS as usize);
     let result = unsafe {
         Threading::WaitForMultipleObjectsEx(
@@ -271,6 +502,28 @@ fn run_input(
 ) {
     boost_current_thread_priority();
 
+    let device_name = run_ctxt.stream.device_name.clone();
+    let disconnect = run_ctxt
+        .stream
+        .session
+        .as_ref()
+        .map(|session| session.disconnect.clone());
+    let error_callback = &mut |err: StreamError| {
+        // The client fails as if the device was gone once the session is disconnected, whatever
+        // the reason.
+        let err = match err {
+            StreamError::DeviceNotAvailable => disconnect
+                .as_ref()
+                .and_then(|disconnect| disconnect.error())
+                .unwrap_or(err),
+            err => err,
+        };
+        if let StreamError::DeviceNotAvailable = err {
+            diagnostics::report("WASAPI", || device_name.clone(), DiagnosticKind::DeviceLost);
+        }
+        error_callback(err)
+    };
+
     loop {
         match process_commands_and_await_signal(&mut run_ctxt, error_callback) {
             Some(ControlFlow::Break) => break,
@@ -300,12 +553,41 @@ fn run_output(
 ) {
     boost_current_thread_priority();
 
+    let device_name = run_ctxt.stream.device_name.clone();
+    let disconnect = run_ctxt
+        .stream
+        .session
+        .as_ref()
+        .map(|session| session.disconnect.clone());
+    let error_callback = &mut |err: StreamError| {
+        // The client fails as if the device was gone once the session is disconnected, whatever
+        // the reason.
+        let err = match err {
+            StreamError::DeviceNotAvailable => disconnect
+                .as_ref()
+                .and_then(|disconnect| disconnect.error())
+                .unwrap_or(err),
+            err => err,
+        };
+        if let StreamError::DeviceNotAvailable = err {
+            diagnostics::report("WASAPI", || device_name.clone(), DiagnosticKind::DeviceLost);
+        }
+        error_callback(err)
+    };
+
     loop {
         match process_commands_and_await_signal(&mut run_ctxt, error_callback) {
             Some(ControlFlow::Break) => break,
             Some(ControlFlow::Continue) => continue,
             None => (),
         }
+        if run_ctxt.stream.draining.is_some() {
+            if let Err(err) = check_drained(&mut run_ctxt.stream) {
+                error_callback(err);
+                break;
+            }
+            continue;
+        }
         let render_client = match run_ctxt.stream.client_flow {
             AudioClientFlow::Render { ref render_client } => render_client.clone(),
             _ => unreachable!(),
@@ -322,6 +604,28 @@ fn run_output(
     }
 }
 
+// Called instead of `process_output` while draining. Once the engine has consumed everything
+// that was written, stop the stream and notify the waiting `drain_timeout`.
+fn check_drained(stream: &mut StreamInner) -> Result<(), StreamError> {
+    unsafe {
+        let padding = stream
+            .audio_client
+            .GetCurrentPadding()
+            .map_err(windows_err_to_cpal_err::<StreamError>)?;
+        if padding == 0 {
+            stream
+                .audio_client
+                .Stop()
+                .map_err(windows_err_to_cpal_err::<StreamError>)?;
+            stream.playing = false;
+            if let Some(done) = stream.draining.take() {
+                let _ = done.send(());
+            }
+        }
+    }
+    Ok(())
+}
+
 fn boost_current_thread_priority() {
     unsafe {
         let thread_id = Threading::GetCurrentThreadId();
@@ -343,7 +647,7 @@ fn process_commands_and_await_signal(
     error_callback: &mut dyn FnMut(StreamError),
 ) -> Option<ControlFlow> {
     // Process queued commands.
-    match process_commands(run_context) {
+    match process_commands(run_context, error_callback) {
         Ok(true) => (),
         Ok(false) => return Some(ControlFlow::Break),
         Err(err) => {
@@ -366,6 +670,12 @@ fn process_commands_and_await_signal(
     if handle_idx == 0 {
         return Some(ControlFlow::Continue);
     }
+    // The third handle is signalled once the audio session is disconnected, after which the
+    // client can't be used anymore. The error callback reports the reason.
+    if handle_idx == 2 {
+        error_callback(StreamError::DeviceNotAvailable);
+        return Some(ControlFlow::Break);
+    }
 
     None
 }
@@ -409,11 +719,20 @@ fn process_input(
                 Ok(_) => (),
             }
 
+            // The packet may have been consumed since `GetNextPacketSize`. Wait for the next one
+            // rather than delivering an empty buffer.
+            if frames_available == 0 {
+                return ControlFlow::Continue;
+            }
+
             debug_assert!(!buffer.is_null());
 
             let data = buffer as *mut ();
             let len = frames_available as usize * stream.bytes_per_frame as usize
                 / stream.sample_format.sample_size();
+            if stream.sample_format == SampleFormat::I24 {
+                i24_from_container(data, len);
+            }
             let data = Data::from_parts(data, len, stream.sample_format);
 
             // The `qpc_position` is in 100 nanosecond units. Convert it to nanoseconds.
@@ -424,8 +743,21 @@ fn process_input(
                     return ControlFlow::Break;
                 }
             };
-            let info = InputCallbackInfo { timestamp };
+            let discontinuity =
+                flags.assume_init() & Audio::AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY.0 as u32 != 0;
+            if discontinuity {
+                stream.report(DiagnosticKind::Overrun);
+            }
+
+            let info = InputCallbackInfo {
+                timestamp,
+                discontinuity,
+            };
+            let start = diagnostics::enabled().then(Instant::now);
             data_callback(&data, &info);
+            if let Some(start) = start {
+                stream.check_callback_duration(start, frames_available);
+            }
 
             // Release the buffer.
             let result = capture_client
@@ -449,6 +781,10 @@ fn process_output(
     // The number of frames available for writing.
     let frames_available = match get_available_frames(stream) {
         Ok(0) => return ControlFlow::Continue, // TODO: Can this happen?
+        // Wait for a larger batch, see `Device::with_wakeup_granularity`.
+        Ok(n) if n < stream.min_callback_frames.load(Ordering::Relaxed) => {
+            return ControlFlow::Continue
+        }
         Ok(n) => n,
         Err(err) => {
             error_callback(err);
@@ -467,6 +803,8 @@ fn process_output(
 
         debug_assert!(!buffer.is_null());
 
+        // The callback writes straight into the endpoint buffer, which is only valid until
+        // `ReleaseBuffer` below, so `data` must not outlive this block.
         let data = buffer as *mut ();
         let len = frames_available as usize * stream.bytes_per_frame as usize
             / stream.sample_format.sample_size();
@@ -475,12 +813,22 @@ fn process_output(
         let timestamp = match output_timestamp(stream, frames_available, sample_rate) {
             Ok(ts) => ts,
             Err(err) => {
+                // Every `GetBuffer` must be paired with a `ReleaseBuffer`, even if nothing was
+                // written.
+                let _ = render_client.ReleaseBuffer(0, 0);
                 error_callback(err);
                 return ControlFlow::Break;
             }
         };
         let info = OutputCallbackInfo { timestamp };
+        let start = diagnostics::enabled().then(Instant::now);
         data_callback(&mut data, &info);
+        if let Some(start) = start {
+            stream.check_callback_duration(start, frames_available);
+        }
+        if stream.sample_format == SampleFormat::I24 {
+            i24_to_container(buffer as *mut (), len);
+        }
 
         if let Err(err) = render_client.ReleaseBuffer(frames_available, 0) {
             error_callback(windows_err_to_cpal_err(err));
@@ -491,6 +839,20 @@ fn process_output(
     ControlFlow::Continue
 }
 
+// `I24` samples are exchanged with the engine as 24 valid bits in the upper three bytes of a
+// 32-bit container, but `Data` holds them in the lower three bytes, sign-extended.
+unsafe fn i24_from_container(data: *mut (), len: usize) {
+    for sample in std::slice::from_raw_parts_mut(data as *mut i32, len) {
+        *sample >>= 8;
+    }
+}
+
+unsafe fn i24_to_container(data: *mut (), len: usize) {
+    for sample in std::slice::from_raw_parts_mut(data as *mut i32, len) {
+        *sample <<= 8;
+    }
+}
+
 /// Convert the given duration in frames at the given sample rate to a `std::time::Duration`.
 fn frames_to_duration(frames: u32, rate: crate::SampleRate) -> std::time::Duration {
     let secsf = frames as f64 / rate.0 as f64;
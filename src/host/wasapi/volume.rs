@@ -0,0 +1,80 @@
+use super::com;
+use super::Device;
+use crate::BackendSpecificError;
+use std::sync::Mutex;
+use windows::core::implement;
+use windows::Win32::Media::Audio;
+use windows::Win32::Media::Audio::Endpoints;
+use windows::Win32::System::Com;
+
+/// Receives volume change notifications for a device. Created by
+/// [`Device::on_volume_changed`].
+///
+/// The callback stops being called when this is dropped.
+pub struct VolumeListener {
+    endpoint_volume: Endpoints::IAudioEndpointVolume,
+    callback: Endpoints::IAudioEndpointVolumeCallback,
+}
+
+#[implement(Endpoints::IAudioEndpointVolumeCallback)]
+struct VolumeCallback(Mutex<Box<dyn FnMut(f32) + Send>>);
+
+impl Endpoints::IAudioEndpointVolumeCallback_Impl for VolumeCallback {
+    fn OnNotify(
+        &self,
+        notification: *mut Audio::AUDIO_VOLUME_NOTIFICATION_DATA,
+    ) -> windows::core::Result<()> {
+        if let Some(notification) = unsafe { notification.as_ref() } {
+            if let Ok(mut callback) = self.0.lock() {
+                callback(notification.fMasterVolume);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Device {
+    // Whether the endpoint's volume is implemented in hardware. The endpoint volume interface
+    // exists on every endpoint, emulating the volume in software where there is none.
+    pub(crate) fn has_hardware_volume(&self) -> bool {
+        com::com_initialized();
+        unsafe {
+            self.immdevice()
+                .Activate::<Endpoints::IAudioEndpointVolume>(Com::CLSCTX_ALL, None)
+                .and_then(|endpoint_volume| endpoint_volume.QueryHardwareSupport())
+                .is_ok_and(|support| support & Audio::ENDPOINT_HARDWARE_SUPPORT_VOLUME != 0)
+        }
+    }
+
+    /// Call `callback` with the new master volume, between `0.0` and `1.0`, whenever the volume of
+    /// this endpoint is changed, e.g. from the system volume mixer.
+    ///
+    /// The callback is invoked on a thread owned by the system.
+    pub fn on_volume_changed<F>(&self, callback: F) -> Result<VolumeListener, BackendSpecificError>
+    where
+        F: FnMut(f32) + Send + 'static,
+    {
+        com::com_initialized();
+        unsafe {
+            let endpoint_volume: Endpoints::IAudioEndpointVolume =
+                self.immdevice().Activate(Com::CLSCTX_ALL, None)?;
+            let callback: Endpoints::IAudioEndpointVolumeCallback =
+                VolumeCallback(Mutex::new(Box::new(callback))).into();
+            endpoint_volume.RegisterControlChangeNotify(&callback)?;
+            Ok(VolumeListener {
+                endpoint_volume,
+                callback,
+            })
+        }
+    }
+}
+
+impl Drop for VolumeListener {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self
+                .endpoint_volume
+                .UnregisterControlChangeNotify(&self.callback);
+        }
+    }
+}
@@ -115,6 +115,10 @@
 //! }
 //! ```
 //!
+//! The [`Sample`] and [`FromSample`] traits used for converting between sample formats are
+//! re-exported from the `dasp_sample` crate, which supports `no_std`. It can be depended on
+//! directly where the conversions are needed without any of cpal's audio I/O.
+//!
 //! Not all platforms automatically run the stream upon creation. To ensure the stream has started,
 //! we can use [`Stream::play`](traits::StreamTrait::play).
 //!
@@ -147,6 +151,12 @@
 //! stream.pause().unwrap();
 //! ```
 //!
+//! There is no event loop to stop or restart: the host runs each stream on its own, and streams
+//! can be built, paused, resumed and dropped at any time, independently of each other. While a
+//! stream is paused its data callback isn't called, but it keeps its device open. To release the
+//! device entirely, e.g. while reconfiguring it, drop the stream and build a new one later; the
+//! [`recovery`] module helps with finding the device again by name.
+//!
 //! [`default_input_device()`]: traits::HostTrait::default_input_device
 //! [`default_output_device()`]: traits::HostTrait::default_output_device
 //! [`devices()`]: traits::HostTrait::devices
@@ -164,22 +174,54 @@ extern crate js_sys;
 #[cfg(target_os = "emscripten")]
 extern crate web_sys;
 
+pub use capabilities::{Capabilities, Capability};
+pub use channel_position::{sort_channels_canonical, ChannelPosition};
+pub use diagnostics::{set_diagnostics_handler, Diagnostic, DiagnosticKind};
 pub use error::*;
+pub use fill_check::set_output_fill_check;
+pub use log::{set_log_callback, LogLevel};
+pub use negotiate::{negotiate_config, negotiate_config_with_formats};
 pub use platform::{
-    available_hosts, default_host, host_from_id, Device, Devices, Host, HostId, Stream,
-    SupportedInputConfigs, SupportedOutputConfigs, ALL_HOSTS,
+    available_hosts, default_host, host_from_id, host_preference, set_host_preference, Device,
+    Devices, Host, HostId, LazySupportedConfigs, Stream, SupportedInputConfigs,
+    SupportedOutputConfigs, ALL_HOSTS,
+};
+pub use probe::{
+    probe_devices, probe_devices_in_background, probe_devices_timeout, DeviceCapabilities,
+};
+pub use samples_formats::{
+    f32_to_i16, i16_to_f32, FromSample, Sample, SampleFormat, SizedSample, I24, I48, U24, U48,
 };
-pub use samples_formats::{FromSample, Sample, SampleFormat, SizedSample, I24, I48, U24, U48};
 use std::convert::TryInto;
 use std::ops::{Div, Mul};
 use std::time::Duration;
+pub use timing_stats::TimingStats;
 #[cfg(target_os = "emscripten")]
 use wasm_bindgen::prelude::*;
 
+mod capabilities;
+mod channel_position;
+mod diagnostics;
+pub mod dsp;
 mod error;
+mod fill_check;
 mod host;
+mod log;
+pub mod multi_output;
+mod negotiate;
 pub mod platform;
+mod playback_clock;
+#[cfg(feature = "futures")]
+pub mod prebuffer;
+mod probe;
+mod rate_meter;
+pub mod recovery;
+pub mod ring_buffer;
 mod samples_formats;
+#[cfg(feature = "futures")]
+pub mod sink;
+pub mod testsignal;
+mod timing_stats;
 pub mod traits;
 
 /// A host's device iterator yielding only *input* devices.
@@ -188,12 +230,48 @@ pub type InputDevices<I> = std::iter::Filter<I, fn(&<I as Iterator>::Item) -> bo
 /// A host's device iterator yielding only *output* devices.
 pub type OutputDevices<I> = std::iter::Filter<I, fn(&<I as Iterator>::Item) -> bool>;
 
+/// A host's device iterator yielding each device with the directions it supports.
+pub type DevicesWithDirection<I> = std::iter::FilterMap<
+    I,
+    fn(<I as Iterator>::Item) -> Option<(<I as Iterator>::Item, DeviceDirection)>,
+>;
+
+/// The directions a device can stream audio in, see
+/// [`HostTrait::devices_with_direction`](traits::HostTrait::devices_with_direction).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum DeviceDirection {
+    /// The device only supports input streams.
+    Input,
+    /// The device only supports output streams.
+    Output,
+    /// The device supports both input and output streams.
+    Duplex,
+}
+
+impl DeviceDirection {
+    /// Whether input streams can be built on the device.
+    pub fn is_input(self) -> bool {
+        self != DeviceDirection::Output
+    }
+
+    /// Whether output streams can be built on the device.
+    pub fn is_output(self) -> bool {
+        self != DeviceDirection::Input
+    }
+}
+
 /// Number of channels.
 pub type ChannelCount = u16;
 
 /// The number of samples processed per second for a single channel of audio.
 #[cfg_attr(target_os = "emscripten", wasm_bindgen)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SampleRate(pub u32);
 
 impl<T> Mul<T> for SampleRate
@@ -229,8 +307,21 @@ pub type FrameCount = u32;
 ///
 /// [`Default`]: BufferSize::Default
 /// [`Fixed(FrameCount)`]: BufferSize::Fixed
+///
+/// The buffer size applies to input streams as well, where it sets e.g. the ALSA capture buffer
+/// or the WASAPI capture buffer duration. A smaller buffer lowers the capture latency at the cost
+/// of more wakeups; if the data callback can't keep up, captured audio is lost, which is flagged
+/// by [`InputCallbackInfo::discontinuity`] and counted by [`Stream::overruns`].
+///
+/// [`Default`] is ordered before any [`Fixed(FrameCount)`], which are ordered by size.
+///
 /// [`SupportedStreamConfig`]: SupportedStreamConfig::buffer_size
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum BufferSize {
     Default,
     Fixed(FrameCount),
@@ -253,19 +344,137 @@ impl wasm_bindgen::convert::IntoWasmAbi for BufferSize {
     }
 }
 
+/// What the operating system does to the audio of a stream between cpal and the device, see
+/// [`StreamTrait::os_processing`](traits::StreamTrait::os_processing).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OsProcessing {
+    /// The samples reach the device unchanged, e.g. in WASAPI exclusive mode or on an ALSA `hw`
+    /// device.
+    BitExact,
+    /// The stream is mixed with the streams of other applications at its own sample rate. It
+    /// isn't resampled, but the mixer may convert the sample format, apply volume or run system
+    /// effects.
+    Mixed,
+    /// The system resamples the stream to the rate the device runs at.
+    Resampled { device_rate: SampleRate },
+    /// The host can't tell.
+    Unknown,
+}
+
+/// How a stream should trade latency for power consumption.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PowerPreference {
+    /// The host's usual buffering.
+    #[default]
+    Default,
+    /// Large buffers and as few wakeups as possible, for long playback where latency doesn't
+    /// matter, e.g. music on a laptop.
+    ///
+    /// The preference can be given when a stream is built, or changed on a running stream with
+    /// [`StreamTrait::set_power_preference`](crate::traits::StreamTrait::set_power_preference).
+    ///
+    /// wasapi: the two mean different things. At build time, devices built with
+    /// `Device::with_power_preference` hand such output streams to the endpoint's audio offload
+    /// engine if it has one, and use a large shared-mode buffer otherwise. At runtime, the buffer
+    /// can't be resized, so shared-mode output streams only batch the calls of the data callback
+    /// until half of the buffer is free. Elsewhere, use [`buffer_size`](Self::buffer_size) to
+    /// pick a large buffer when building the stream.
+    LowPower,
+}
+
+impl PowerPreference {
+    /// The longest buffer, in seconds, picked for [`PowerPreference::LowPower`].
+    pub const LOW_POWER_BUFFER_SECS: f64 = 0.2;
+
+    /// The buffer size to open a stream with `config` at for this preference.
+    ///
+    /// For [`LowPower`](Self::LowPower), this is the largest size the device supports, up to
+    /// [`LOW_POWER_BUFFER_SECS`](Self::LOW_POWER_BUFFER_SECS) of audio. Otherwise, and when the
+    /// supported sizes are unknown, it is [`BufferSize::Default`].
+    pub fn buffer_size(self, config: &SupportedStreamConfig) -> BufferSize {
+        match (self, config.buffer_size) {
+            (PowerPreference::LowPower, SupportedBufferSize::Range { min, max }) => {
+                let frames =
+                    (config.sample_rate.0 as f64 * Self::LOW_POWER_BUFFER_SECS) as FrameCount;
+                BufferSize::Fixed(frames.min(max).max(min))
+            }
+            _ => BufferSize::Default,
+        }
+    }
+}
+
 /// The set of parameters used to describe how to open a stream.
 ///
 /// The sample format is omitted in favour of using a sample type.
+///
+/// Configurations are ordered by channel count, then sample rate, then buffer size. This order is
+/// stable across releases.
 #[cfg_attr(target_os = "emscripten", wasm_bindgen)]
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StreamConfig {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_channels"))]
     pub channels: ChannelCount,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_sample_rate"))]
     pub sample_rate: SampleRate,
     pub buffer_size: BufferSize,
 }
 
+impl StreamConfig {
+    /// Whether this configuration could describe a stream at all, i.e. it has at least one
+    /// channel and a non-zero sample rate.
+    ///
+    /// Building a stream from an invalid configuration, either through the typed
+    /// [`DeviceTrait`](traits::DeviceTrait) methods or through a [`Device`], fails with
+    /// [`BuildStreamError::StreamConfigNotSupported`] before the host is involved.
+    pub fn is_valid(&self) -> bool {
+        self.channels > 0 && self.sample_rate.0 > 0
+    }
+}
+
+// Deserialized configurations are checked like `StreamConfig::is_valid`, so that a corrupt
+// settings file fails to load rather than to open a stream later on.
+#[cfg(feature = "serde")]
+fn deserialize_channels<'de, D>(deserializer: D) -> Result<ChannelCount, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{Deserialize, Error, Unexpected};
+    match ChannelCount::deserialize(deserializer)? {
+        0 => Err(D::Error::invalid_value(
+            Unexpected::Unsigned(0),
+            &"at least one channel",
+        )),
+        channels => Ok(channels),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_sample_rate<'de, D>(deserializer: D) -> Result<SampleRate, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{Deserialize, Error, Unexpected};
+    match SampleRate::deserialize(deserializer)? {
+        SampleRate(0) => Err(D::Error::invalid_value(
+            Unexpected::Unsigned(0),
+            &"a non-zero sample rate",
+        )),
+        sample_rate => Ok(sample_rate),
+    }
+}
+
 /// Describes the minimum and maximum supported buffer size for the device
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+///
+/// Ranges are ordered by their minimum, then their maximum, and before [`Unknown`].
+///
+/// [`Unknown`]: SupportedBufferSize::Unknown
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum SupportedBufferSize {
     Range {
         min: FrameCount,
@@ -278,7 +487,12 @@ pub enum SupportedBufferSize {
 
 /// Describes a range of supported stream configurations, retrieved via the
 /// [`Device::supported_input/output_configs`](traits::DeviceTrait#required-methods) method.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Ranges are ordered by channel count, then minimum sample rate, then maximum sample rate, then
+/// sample format, then buffer size. This order is stable across releases, and the configurations
+/// of a [`Device`] are listed in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SupportedStreamConfigRange {
     pub(crate) channels: ChannelCount,
     /// Minimum value for the samples rate of the supported formats.
@@ -291,12 +505,72 @@ pub struct SupportedStreamConfigRange {
     pub(crate) sample_format: SampleFormat,
 }
 
+// Checked like `StreamConfig`, and for a sample rate range that isn't empty.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SupportedStreamConfigRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "SupportedStreamConfigRange")]
+        struct Fields {
+            #[serde(deserialize_with = "deserialize_channels")]
+            channels: ChannelCount,
+            #[serde(deserialize_with = "deserialize_sample_rate")]
+            min_sample_rate: SampleRate,
+            #[serde(deserialize_with = "deserialize_sample_rate")]
+            max_sample_rate: SampleRate,
+            buffer_size: SupportedBufferSize,
+            sample_format: SampleFormat,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        if fields.min_sample_rate > fields.max_sample_rate {
+            return Err(D::Error::custom(format!(
+                "min_sample_rate {} exceeds max_sample_rate {}",
+                fields.min_sample_rate.0, fields.max_sample_rate.0
+            )));
+        }
+        Ok(SupportedStreamConfigRange {
+            channels: fields.channels,
+            min_sample_rate: fields.min_sample_rate,
+            max_sample_rate: fields.max_sample_rate,
+            buffer_size: fields.buffer_size,
+            sample_format: fields.sample_format,
+        })
+    }
+}
+
+/// Minimal requirements for a stream configuration, used to narrow down the configurations
+/// supported by a device via
+/// [`Device::supported_input/output_configs_matching`](traits::DeviceTrait#provided-methods).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamConfigRequirements {
+    /// The minimum number of channels.
+    pub min_channels: ChannelCount,
+    /// The lowest acceptable sample rate.
+    pub min_sample_rate: SampleRate,
+    /// The highest acceptable sample rate.
+    pub max_sample_rate: SampleRate,
+    /// The acceptable sample formats. An empty list accepts any format.
+    pub sample_formats: Vec<SampleFormat>,
+}
+
 /// Describes a single supported stream configuration, retrieved via either a
 /// [`SupportedStreamConfigRange`] instance or one of the
 /// [`Device::default_input/output_config`](traits::DeviceTrait#required-methods) methods.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Configurations are ordered by channel count, then sample rate, then sample format, then
+/// buffer size. This order is stable across releases.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SupportedStreamConfig {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_channels"))]
     channels: ChannelCount,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_sample_rate"))]
     sample_rate: SampleRate,
     buffer_size: SupportedBufferSize,
     sample_format: SampleFormat,
@@ -306,6 +580,10 @@ pub struct SupportedStreamConfig {
 ///
 /// Raw input stream callbacks receive `&Data`, while raw output stream callbacks expect `&mut
 /// Data`.
+///
+/// Buffers handed to callbacks through [`platform::Device`] always hold whole frames: their
+/// length is a multiple of the stream's channel count, so interleaved samples can be processed
+/// frame by frame without carrying a partial frame over to the next callback.
 #[cfg_attr(target_os = "emscripten", wasm_bindgen)]
 #[derive(Debug)]
 pub struct Data {
@@ -364,6 +642,7 @@ pub struct OutputStreamTimestamp {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct InputCallbackInfo {
     timestamp: InputStreamTimestamp,
+    discontinuity: bool,
 }
 
 /// Information relevant to a single call to the user's output stream data callback.
@@ -486,13 +765,26 @@ impl StreamInstant {
 
 impl InputCallbackInfo {
     pub fn new(timestamp: InputStreamTimestamp) -> Self {
-        Self { timestamp }
+        Self {
+            timestamp,
+            discontinuity: false,
+        }
     }
 
     /// The timestamp associated with the call to an input stream's data callback.
     pub fn timestamp(&self) -> InputStreamTimestamp {
         self.timestamp
     }
+
+    /// Whether the device lost captured samples right before this buffer, because they weren't
+    /// read in time, so that it doesn't continue the previous one.
+    ///
+    /// Reported by ALSA after recovering from an overrun, by WASAPI for buffers flagged as a
+    /// data discontinuity and by CoreAudio on macOS when the device skipped ahead. Other hosts
+    /// always return `false`.
+    pub fn discontinuity(&self) -> bool {
+        self.discontinuity
+    }
 }
 
 impl OutputCallbackInfo {
@@ -514,7 +806,8 @@ impl Data {
     //
     // - The `data` pointer must point to the first sample in the slice containing all samples.
     // - The `len` must describe the length of the buffer as a number of samples in the expected
-    //   format specified via the `sample_format` argument.
+    //   format specified via the `sample_format` argument. Hosts never deliver empty buffers, so
+    //   `len` must hold at least one full frame.
     // - The `sample_format` must correctly represent the underlying sample data delivered/expected
     //   by the stream.
     pub(crate) unsafe fn from_parts(
@@ -522,6 +815,7 @@ impl Data {
         len: usize,
         sample_format: SampleFormat,
     ) -> Self {
+        debug_assert_ne!(len, 0, "hosts must not deliver empty buffers");
         Data {
             data,
             len,
@@ -542,11 +836,24 @@ impl Data {
         self.len
     }
 
+    /// The length of the buffer in frames of `channels` interleaved samples.
+    pub fn len_frames(&self, channels: ChannelCount) -> usize {
+        self.len / channels.max(1) as usize
+    }
+
+    /// The full length of the buffer in bytes.
+    ///
+    /// This is always a whole multiple of the sample size of [`Data::sample_format`].
+    pub fn len_bytes(&self) -> usize {
+        self.len * self.sample_format.sample_size()
+    }
+
     /// The raw slice of memory representing the underlying audio data as a slice of bytes.
     ///
     /// It is up to the user to interpret the slice of memory based on [`Data::sample_format`].
+    /// Samples are stored in native endianness.
     pub fn bytes(&self) -> &[u8] {
-        let len = self.len * self.sample_format.sample_size();
+        let len = self.len_bytes();
         // The safety of this block relies on correct construction of the `Data` instance.
         // See the unsafe `from_parts` constructor for these requirements.
         unsafe { std::slice::from_raw_parts(self.data as *const u8, len) }
@@ -555,8 +862,10 @@ impl Data {
     /// The raw slice of memory representing the underlying audio data as a slice of bytes.
     ///
     /// It is up to the user to interpret the slice of memory based on [`Data::sample_format`].
+    /// Samples are stored in native endianness. The slice always spans whole samples, so it can
+    /// be filled directly by decoders that produce raw PCM bytes in the stream's format.
     pub fn bytes_mut(&mut self) -> &mut [u8] {
-        let len = self.len * self.sample_format.sample_size();
+        let len = self.len_bytes();
         // The safety of this block relies on correct construction of the `Data` instance. See
         // the unsafe `from_parts` constructor for these requirements.
         unsafe { std::slice::from_raw_parts_mut(self.data as *mut u8, len) }
@@ -598,6 +907,79 @@ impl Data {
             None
         }
     }
+
+    // Call `f` with every sample converted to `f32`, in order.
+    pub(crate) fn for_each_f32(&self, mut f: impl FnMut(f32)) {
+        fn convert<T: SizedSample>(data: &Data, f: &mut impl FnMut(f32))
+        where
+            f32: FromSample<T>,
+        {
+            if let Some(samples) = data.as_slice::<T>() {
+                samples.iter().for_each(|&s| f(f32::from_sample(s)));
+            }
+        }
+        match self.sample_format {
+            SampleFormat::I8 => convert::<i8>(self, &mut f),
+            SampleFormat::I16 => convert::<i16>(self, &mut f),
+            SampleFormat::I24 => convert::<I24>(self, &mut f),
+            SampleFormat::I32 => convert::<i32>(self, &mut f),
+            SampleFormat::I64 => convert::<i64>(self, &mut f),
+            SampleFormat::U8 => convert::<u8>(self, &mut f),
+            SampleFormat::U16 => convert::<u16>(self, &mut f),
+            SampleFormat::U32 => convert::<u32>(self, &mut f),
+            SampleFormat::U64 => convert::<u64>(self, &mut f),
+            SampleFormat::F32 => convert::<f32>(self, &mut f),
+            SampleFormat::F64 => convert::<f64>(self, &mut f),
+        }
+    }
+
+    // Views of the whole frames of `channels` at the start of the buffer and of the samples of a
+    // partial frame after them, each `None` if empty.
+    pub(crate) fn split_whole_frames(
+        &self,
+        channels: ChannelCount,
+    ) -> (Option<Data>, Option<Data>) {
+        self.split_at(self.len - self.len % channels.max(1) as usize)
+    }
+
+    // Views of the samples before `mid` and of those from `mid` on, each `None` if empty.
+    pub(crate) fn split_at(&self, mid: usize) -> (Option<Data>, Option<Data>) {
+        let mid = mid.min(self.len);
+        let view = |start: usize, len: usize| {
+            (len > 0).then(|| Data {
+                // Stays within the buffer, as `start + len <= self.len`.
+                data: unsafe {
+                    (self.data as *mut u8).add(start * self.sample_format.sample_size())
+                } as *mut (),
+                len,
+                sample_format: self.sample_format,
+            })
+        };
+        (view(0, mid), view(mid, self.len - mid))
+    }
+
+    // Overwrite every sample, in order, with the `f32` returned by `f` converted to the sample
+    // format.
+    pub(crate) fn fill_from_f32(&mut self, mut f: impl FnMut() -> f32) {
+        fn convert<T: SizedSample + FromSample<f32>>(data: &mut Data, f: &mut impl FnMut() -> f32) {
+            if let Some(samples) = data.as_slice_mut::<T>() {
+                samples.iter_mut().for_each(|s| *s = T::from_sample(f()));
+            }
+        }
+        match self.sample_format {
+            SampleFormat::I8 => convert::<i8>(self, &mut f),
+            SampleFormat::I16 => convert::<i16>(self, &mut f),
+            SampleFormat::I24 => convert::<I24>(self, &mut f),
+            SampleFormat::I32 => convert::<i32>(self, &mut f),
+            SampleFormat::I64 => convert::<i64>(self, &mut f),
+            SampleFormat::U8 => convert::<u8>(self, &mut f),
+            SampleFormat::U16 => convert::<u16>(self, &mut f),
+            SampleFormat::U32 => convert::<u32>(self, &mut f),
+            SampleFormat::U64 => convert::<u64>(self, &mut f),
+            SampleFormat::F32 => convert::<f32>(self, &mut f),
+            SampleFormat::F64 => convert::<f64>(self, &mut f),
+        }
+    }
 }
 
 impl SupportedStreamConfigRange {
@@ -817,17 +1199,212 @@ fn test_cmp_default_heuristics() {
     assert_eq!(formats[4].channels(), 2);
 }
 
+impl Default for StreamConfigRequirements {
+    /// Requirements that accept any configuration.
+    fn default() -> Self {
+        StreamConfigRequirements {
+            min_channels: 1,
+            min_sample_rate: SampleRate(0),
+            max_sample_rate: SampleRate(u32::MAX),
+            sample_formats: Vec::new(),
+        }
+    }
+}
+
+impl StreamConfigRequirements {
+    /// Narrow `range` down to the part of it that meets these requirements.
+    ///
+    /// The sample rate range of the result is the intersection of both ranges. Returns `None` if
+    /// no configuration in `range` meets the requirements.
+    pub fn restrict(
+        &self,
+        range: &SupportedStreamConfigRange,
+    ) -> Option<SupportedStreamConfigRange> {
+        if range.channels < self.min_channels {
+            return None;
+        }
+        if !self.sample_formats.is_empty() && !self.sample_formats.contains(&range.sample_format) {
+            return None;
+        }
+        let min_sample_rate = range.min_sample_rate.max(self.min_sample_rate);
+        let max_sample_rate = range.max_sample_rate.min(self.max_sample_rate);
+        if min_sample_rate > max_sample_rate {
+            return None;
+        }
+        Some(SupportedStreamConfigRange {
+            min_sample_rate,
+            max_sample_rate,
+            ..*range
+        })
+    }
+}
+
+#[test]
+fn test_stream_config_requirements() {
+    let range = SupportedStreamConfigRange {
+        buffer_size: SupportedBufferSize::Unknown,
+        channels: 2,
+        min_sample_rate: SampleRate(8000),
+        max_sample_rate: SampleRate(48000),
+        sample_format: SampleFormat::I16,
+    };
+
+    let requirements = StreamConfigRequirements {
+        min_channels: 2,
+        min_sample_rate: SampleRate(44100),
+        max_sample_rate: SampleRate(96000),
+        sample_formats: vec![SampleFormat::I16, SampleFormat::F32],
+    };
+    let restricted = requirements.restrict(&range).unwrap();
+    assert_eq!(restricted.min_sample_rate(), SampleRate(44100));
+    assert_eq!(restricted.max_sample_rate(), SampleRate(48000));
+    assert_eq!(restricted.channels(), 2);
+
+    let too_many_channels = StreamConfigRequirements {
+        min_channels: 4,
+        ..requirements.clone()
+    };
+    assert_eq!(too_many_channels.restrict(&range), None);
+
+    let too_fast = StreamConfigRequirements {
+        min_sample_rate: SampleRate(88200),
+        ..requirements.clone()
+    };
+    assert_eq!(too_fast.restrict(&range), None);
+
+    let float_only = StreamConfigRequirements {
+        sample_formats: vec![SampleFormat::F32],
+        ..requirements
+    };
+    assert_eq!(float_only.restrict(&range), None);
+
+    assert_eq!(
+        StreamConfigRequirements::default().restrict(&range),
+        Some(range)
+    );
+}
+
 impl From<SupportedStreamConfig> for StreamConfig {
     fn from(conf: SupportedStreamConfig) -> Self {
         conf.config()
     }
 }
 
+impl Ord for SupportedStreamConfig {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.channels
+            .cmp(&other.channels)
+            .then(self.sample_rate.cmp(&other.sample_rate))
+            .then(self.sample_format.cmp(&other.sample_format))
+            .then(self.buffer_size.cmp(&other.buffer_size))
+    }
+}
+
+impl PartialOrd for SupportedStreamConfig {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SupportedStreamConfigRange {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.channels
+            .cmp(&other.channels)
+            .then(self.min_sample_rate.cmp(&other.min_sample_rate))
+            .then(self.max_sample_rate.cmp(&other.max_sample_rate))
+            .then(self.sample_format.cmp(&other.sample_format))
+            .then(self.buffer_size.cmp(&other.buffer_size))
+    }
+}
+
+impl PartialOrd for SupportedStreamConfigRange {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[test]
+fn test_power_preference_buffer_size() {
+    let config = |buffer_size| {
+        SupportedStreamConfig::new(2, SampleRate(48_000), buffer_size, SampleFormat::F32)
+    };
+    let range = SupportedBufferSize::Range { min: 64, max: 4096 };
+    assert_eq!(
+        PowerPreference::Default.buffer_size(&config(range)),
+        BufferSize::Default
+    );
+    assert_eq!(
+        PowerPreference::LowPower.buffer_size(&config(range)),
+        BufferSize::Fixed(4096)
+    );
+    let range = SupportedBufferSize::Range {
+        min: 64,
+        max: 1 << 20,
+    };
+    assert_eq!(
+        PowerPreference::LowPower.buffer_size(&config(range)),
+        BufferSize::Fixed(9600)
+    );
+    assert_eq!(
+        PowerPreference::LowPower.buffer_size(&config(SupportedBufferSize::Unknown)),
+        BufferSize::Default
+    );
+}
+
+#[test]
+fn test_config_ordering() {
+    let range = |channels, min, max, sample_format| {
+        SupportedStreamConfigRange::new(
+            channels,
+            SampleRate(min),
+            SampleRate(max),
+            SupportedBufferSize::Unknown,
+            sample_format,
+        )
+    };
+    let mut ranges = vec![
+        range(2, 44100, 48000, SampleFormat::F32),
+        range(1, 8000, 96000, SampleFormat::I16),
+        range(2, 44100, 48000, SampleFormat::I16),
+        range(2, 8000, 192000, SampleFormat::U8),
+        range(2, 44100, 44100, SampleFormat::F32),
+    ];
+    ranges.sort();
+    assert_eq!(
+        ranges,
+        [
+            range(1, 8000, 96000, SampleFormat::I16),
+            range(2, 8000, 192000, SampleFormat::U8),
+            range(2, 44100, 44100, SampleFormat::F32),
+            range(2, 44100, 48000, SampleFormat::I16),
+            range(2, 44100, 48000, SampleFormat::F32),
+        ]
+    );
+    assert_eq!(
+        ranges.binary_search(&range(2, 44100, 48000, SampleFormat::I16)),
+        Ok(3)
+    );
+
+    let config = |channels, rate, sample_format| {
+        SupportedStreamConfig::new(
+            channels,
+            SampleRate(rate),
+            SupportedBufferSize::Unknown,
+            sample_format,
+        )
+    };
+    assert!(config(1, 96000, SampleFormat::F32) < config(2, 8000, SampleFormat::I8));
+    assert!(config(2, 44100, SampleFormat::F32) < config(2, 48000, SampleFormat::I8));
+    assert!(config(2, 48000, SampleFormat::I16) < config(2, 48000, SampleFormat::F32));
+    assert!(SampleFormat::I64 < SampleFormat::U8 && SampleFormat::U64 < SampleFormat::F32);
+    assert!(BufferSize::Default < BufferSize::Fixed(0));
+}
+
 // If a backend does not provide an API for retrieving supported formats, we query it with a bunch
 // of commonly used rates. This is always the case for wasapi and is sometimes the case for alsa.
+// These are also the rates listed for continuous ranges by `distinct_sample_rates`.
 //
 // If a rate you desire is missing from this list, feel free to add it!
-#[cfg(target_os = "windows")]
 const COMMON_SAMPLE_RATES: &[SampleRate] = &[
     SampleRate(5512),
     SampleRate(8000),
@@ -845,6 +1422,52 @@ const COMMON_SAMPLE_RATES: &[SampleRate] = &[
     SampleRate(384000),
 ];
 
+// The sorted, deduplicated sample rates of `ranges`: the ends of each range and the common rates
+// within it.
+pub(crate) fn distinct_sample_rates(
+    ranges: impl IntoIterator<Item = SupportedStreamConfigRange>,
+) -> Vec<SampleRate> {
+    let mut rates = Vec::new();
+    for range in ranges {
+        let (min, max) = (range.min_sample_rate, range.max_sample_rate);
+        rates.push(min);
+        rates.extend(
+            COMMON_SAMPLE_RATES
+                .iter()
+                .copied()
+                .filter(|&rate| min < rate && rate < max),
+        );
+        rates.push(max);
+    }
+    rates.sort();
+    rates.dedup();
+    rates
+}
+
+#[test]
+fn test_distinct_sample_rates() {
+    let range = |min, max| {
+        SupportedStreamConfigRange::new(
+            2,
+            SampleRate(min),
+            SampleRate(max),
+            SupportedBufferSize::Unknown,
+            SampleFormat::F32,
+        )
+    };
+    let rates = distinct_sample_rates([
+        range(48_000, 48_000),
+        range(44_100, 44_100),
+        range(48_000, 48_000),
+        range(30_000, 50_000),
+    ]);
+    assert_eq!(
+        rates,
+        [30_000, 32_000, 44_100, 48_000, 50_000].map(SampleRate)
+    );
+    assert!(distinct_sample_rates([]).is_empty());
+}
+
 #[test]
 fn test_stream_instant() {
     let a = StreamInstant::new(2, 0);
@@ -878,3 +1501,112 @@ fn test_stream_instant() {
     );
     assert_eq!(max.add(Duration::from_secs(1)), None);
 }
+
+#[test]
+fn test_data_bytes() {
+    let mut samples = [0i16; 4];
+    let mut data =
+        unsafe { Data::from_parts(samples.as_mut_ptr() as *mut (), 4, SampleFormat::I16) };
+    assert_eq!(data.len(), 4);
+    assert_eq!(data.len_bytes(), 8);
+    data.bytes_mut()[2..4].copy_from_slice(&1234i16.to_ne_bytes());
+    assert_eq!(data.as_slice::<i16>(), Some(&[0, 1234, 0, 0][..]));
+    assert_eq!(data.as_slice::<f32>(), None);
+}
+
+#[test]
+fn test_data_whole_frames() {
+    // A buffer of two stereo frames and the first sample of a third.
+    let mut samples = [1i16, 2, 3, 4, 5];
+    let data = unsafe { Data::from_parts(samples.as_mut_ptr() as *mut (), 5, SampleFormat::I16) };
+    assert_eq!(data.len_frames(2), 2);
+    let (frames, partial) = data.split_whole_frames(2);
+    assert_eq!(frames.unwrap().as_slice::<i16>(), Some(&[1, 2, 3, 4][..]));
+    let mut partial = partial.unwrap();
+    partial.fill_from_f32(|| 0.0);
+    assert_eq!(samples, [1, 2, 3, 4, 0]);
+
+    let data = unsafe { Data::from_parts(samples.as_mut_ptr() as *mut (), 4, SampleFormat::I16) };
+    let (frames, partial) = data.split_whole_frames(2);
+    assert_eq!(frames.map(|frames| frames.len()), Some(4));
+    assert!(partial.is_none());
+    assert!(data.split_whole_frames(6).0.is_none());
+}
+
+#[test]
+fn test_invalid_stream_config() {
+    use crate::traits::DeviceTrait;
+
+    let config = StreamConfig {
+        channels: 2,
+        sample_rate: SampleRate(44100),
+        buffer_size: BufferSize::Default,
+    };
+    assert!(config.is_valid());
+    let no_channels = StreamConfig {
+        channels: 0,
+        ..config.clone()
+    };
+    assert!(!no_channels.is_valid());
+    let no_rate = StreamConfig {
+        sample_rate: SampleRate(0),
+        ..config
+    };
+    assert!(!no_rate.is_valid());
+
+    // Rejected before the host is asked to open anything.
+    let device = crate::host::null::Device;
+    for config in [no_channels, no_rate] {
+        let result = device.build_output_stream(&config, |_: &mut [f32], _: &_| (), |_| (), None);
+        assert!(matches!(
+            result,
+            Err(BuildStreamError::StreamConfigNotSupported)
+        ));
+        let supported = SupportedStreamConfig::new(
+            config.channels,
+            config.sample_rate,
+            SupportedBufferSize::Unknown,
+            SampleFormat::F32,
+        );
+        assert!(matches!(
+            device.play_buffer(&supported, &[]),
+            Err(PlayBufferError::Build(
+                BuildStreamError::StreamConfigNotSupported
+            ))
+        ));
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_supported_stream_config_range_serde() {
+    let range = SupportedStreamConfigRange::new(
+        2,
+        SampleRate(44100),
+        SampleRate(48000),
+        SupportedBufferSize::Range { min: 64, max: 4096 },
+        SampleFormat::F32,
+    );
+    let json = serde_json::to_value(range).unwrap();
+    assert_eq!(
+        serde_json::from_value::<SupportedStreamConfigRange>(json.clone()).unwrap(),
+        range
+    );
+
+    for (field, value) in [
+        ("channels", 0),
+        ("min_sample_rate", 0),
+        ("max_sample_rate", 0),
+        // An empty rate range.
+        ("min_sample_rate", 96000),
+    ] {
+        let mut json = json.clone();
+        json[field] = value.into();
+        assert!(
+            serde_json::from_value::<SupportedStreamConfigRange>(json).is_err(),
+            "{} = {}",
+            field,
+            value
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, RwLock};
+
+/// The severity of a message emitted by a backend.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error,
+    Warning,
+    Info,
+    Debug,
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            LogLevel::Error => write!(f, "error"),
+            LogLevel::Warning => write!(f, "warning"),
+            LogLevel::Info => write!(f, "info"),
+            LogLevel::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+type LogCallback = Arc<dyn Fn(LogLevel, &str) + Send + Sync + 'static>;
+
+static LOG_CALLBACK: RwLock<Option<LogCallback>> = RwLock::new(None);
+
+/// Route diagnostic messages printed by the underlying audio libraries through `callback`.
+///
+/// Some backends print warnings of their own (e.g. ALSA reports underruns on stderr, and JACK
+/// reports server errors). Once a callback has been set, these messages are passed to it instead,
+/// allowing them to be forwarded to the application's own logging. Setting a new callback
+/// replaces the previous one.
+///
+/// The callback may be invoked from any thread, including the high-priority audio threads, so it
+/// should return quickly.
+pub fn set_log_callback<F>(callback: F)
+where
+    F: Fn(LogLevel, &str) + Send + Sync + 'static,
+{
+    *LOG_CALLBACK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(callback));
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    ))]
+    crate::host::alsa::route_log_messages();
+
+    #[cfg(all(
+        any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd"
+        ),
+        feature = "jack"
+    ))]
+    crate::host::jack::route_log_messages();
+}
+
+/// Pass `message` to the user's log callback, if one has been set.
+///
+/// Returns `false` if no callback has been set.
+#[allow(dead_code)]
+pub(crate) fn log(level: LogLevel, message: &str) -> bool {
+    let callback = match LOG_CALLBACK.read() {
+        Ok(guard) => guard.clone(),
+        Err(_) => return false,
+    };
+    match callback {
+        Some(callback) => {
+            callback(level, message);
+            true
+        }
+        None => false,
+    }
+}
+
+#[test]
+fn test_log_callback() {
+    use std::sync::Mutex;
+
+    // Without a callback, the caller is told to fall back to its own output.
+    assert!(!log(LogLevel::Warning, "test: unheard"));
+
+    // Backends may log from other tests while this one runs, so only look at its own messages.
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    set_log_callback(move |level, message| {
+        if message.starts_with("test: ") {
+            sink.lock().unwrap().push((level, message.to_string()));
+        }
+    });
+    assert!(log(LogLevel::Error, "test: error"));
+    assert!(log(LogLevel::Debug, "test: debug"));
+    assert_eq!(
+        *received.lock().unwrap(),
+        [
+            (LogLevel::Error, "test: error".to_string()),
+            (LogLevel::Debug, "test: debug".to_string()),
+        ]
+    );
+
+    // ALSA's messages are expanded before they're passed on.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    ))]
+    {
+        unsafe {
+            crate::host::alsa::cpal_alsa_error_handler(
+                b"test: pcm.c\0".as_ptr() as *const libc::c_char,
+                42,
+                b"snd_pcm_open\0".as_ptr() as *const libc::c_char,
+                libc::ENOENT,
+                b"Unknown PCM %s\0".as_ptr() as *const libc::c_char,
+                b"x\0".as_ptr() as *const libc::c_char,
+            );
+        }
+        let expected = "test: pcm.c:42: snd_pcm_open: Unknown PCM x: No such file or directory";
+        assert_eq!(
+            received.lock().unwrap().pop(),
+            Some((LogLevel::Error, expected.to_string()))
+        );
+    }
+
+    // A new callback replaces the previous one.
+    set_log_callback(|_, _| {});
+    assert!(log(LogLevel::Info, "test: replaced"));
+    assert_eq!(received.lock().unwrap().len(), 2);
+
+    assert_eq!(LogLevel::Warning.to_string(), "warning");
+    assert!(LogLevel::Error < LogLevel::Debug);
+}
@@ -0,0 +1,544 @@
+//! Playing the same audio on several output devices at once.
+//!
+//! See [`MultiOutputStream`].
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::ring_buffer::{ring_buffer, RingConsumer, RingProducer};
+use crate::traits::{DeviceTrait, StreamTrait};
+use crate::{
+    BuildStreamError, Data, Device, PlayStreamError, SampleFormat, Stream, StreamConfig,
+    StreamError,
+};
+
+// The largest rate correction applied to an output, as a fraction of its sample rate.
+const MAX_CORRECTION: f64 = 0.002;
+
+// Gains of the controller keeping an output aligned with the master, acting on the misalignment
+// in seconds. They make it critically damped, settling in about a minute.
+const PROPORTIONAL_GAIN: f64 = 0.1;
+const INTEGRAL_GAIN: f64 = 0.0025;
+
+// The time constant, in seconds, of the filter smoothing out the misalignment before the
+// controller acts on it. Queue levels move in whole device buffers.
+const SMOOTHING_SECS: f64 = 1.0;
+
+// Longer gaps between appends than this are treated as this long, so that a stalled caller
+// doesn't make the controller overshoot.
+const MAX_STEP_SECS: f64 = 0.5;
+
+/// Why one of the outputs of a [`MultiOutputStream`] isn't playing, or the last error it reported.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OutputError {
+    /// The device's stream couldn't be built.
+    Build(BuildStreamError),
+    /// The device's stream couldn't be started.
+    Play(PlayStreamError),
+    /// The device's stream reported an error while running.
+    Stream(StreamError),
+}
+
+impl Display for OutputError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputError::Build(err) => err.fmt(f),
+            OutputError::Play(err) => err.fmt(f),
+            OutputError::Stream(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for OutputError {}
+
+// The state of an output shared with its stream's callbacks.
+struct Shared {
+    error: Mutex<Option<OutputError>>,
+    // Set once the output can't play anymore; it is no longer fed.
+    failed: AtomicBool,
+    underruns: AtomicUsize,
+    // Set once the stream has played appended samples.
+    primed: AtomicBool,
+    // The number of queued frames consumed per frame played, as `f64` bits.
+    ratio: AtomicU64,
+}
+
+impl Default for Shared {
+    fn default() -> Self {
+        Shared {
+            error: Mutex::new(None),
+            failed: AtomicBool::new(false),
+            underruns: AtomicUsize::new(0),
+            primed: AtomicBool::new(false),
+            ratio: AtomicU64::new(1.0f64.to_bits()),
+        }
+    }
+}
+
+impl Shared {
+    fn ratio(&self) -> f64 {
+        f64::from_bits(self.ratio.load(Ordering::Relaxed))
+    }
+
+    fn set_ratio(&self, ratio: f64) {
+        self.ratio.store(ratio.to_bits(), Ordering::Relaxed);
+    }
+
+    fn fail(&self, err: OutputError) {
+        self.failed.store(true, Ordering::Relaxed);
+        *self.error.lock().unwrap() = Some(err);
+    }
+
+    fn report(&self, err: StreamError) {
+        if err.is_fatal() {
+            self.failed.store(true, Ordering::Relaxed);
+        }
+        *self.error.lock().unwrap() = Some(OutputError::Stream(err));
+    }
+}
+
+struct Output {
+    // `None` if the stream couldn't be built.
+    stream: Option<Stream>,
+    producer: RingProducer,
+    shared: Arc<Shared>,
+    drift: DriftControl,
+}
+
+// Keeps an output's queue draining at the same pace as the master's by adjusting the rate at
+// which the output consumes queued frames.
+#[derive(Default)]
+struct DriftControl {
+    // The difference between the output's and the master's queue levels, in seconds, once both
+    // were playing. It reflects the devices' buffering and is kept constant.
+    reference: Option<f64>,
+    // The smoothed deviation from `reference`.
+    error: f64,
+    // The accumulated correction, which converges to the relative clock difference.
+    integral: f64,
+}
+
+impl DriftControl {
+    // Update the controller with the current level difference, returning the new consumption
+    // ratio.
+    fn update(&mut self, difference: f64, dt: f64) -> f64 {
+        let reference = *self.reference.get_or_insert(difference);
+        self.error += (difference - reference - self.error) * (dt / SMOOTHING_SECS).min(1.0);
+        self.integral = (self.integral + INTEGRAL_GAIN * self.error * dt)
+            .clamp(-MAX_CORRECTION, MAX_CORRECTION);
+        let correction = self.integral + PROPORTIONAL_GAIN * self.error;
+        1.0 + correction.clamp(-MAX_CORRECTION, MAX_CORRECTION)
+    }
+}
+
+// Reads whole frames from an output's queue at a variable rate, interpolating linearly between
+// them.
+struct FrameReader {
+    consumer: RingConsumer,
+    // The frames the current position lies between.
+    previous: Vec<f32>,
+    next: Vec<f32>,
+    // The position between `previous` and `next`, in `0.0..=1.0` once a frame has been read.
+    position: f64,
+    // Whether the queue ran dry while filling the current buffer.
+    ran_dry: bool,
+}
+
+impl FrameReader {
+    fn new(consumer: RingConsumer, channels: usize) -> Self {
+        FrameReader {
+            consumer,
+            previous: vec![0.0; channels],
+            next: vec![0.0; channels],
+            position: 1.0,
+            ran_dry: false,
+        }
+    }
+
+    // Fill an output buffer, advancing by `ratio` queued frames per frame played, with silence
+    // where the queue runs out.
+    fn fill(&mut self, data: &mut Data, shared: &Shared) {
+        let ratio = shared.ratio();
+        let channels = self.next.len();
+        let mut channel = 0;
+        self.ran_dry = false;
+        data.fill_from_f32(|| {
+            if channel == 0 {
+                self.advance(ratio, shared);
+            }
+            let (a, b) = (self.previous[channel], self.next[channel]);
+            channel = (channel + 1) % channels;
+            a + (b - a) * self.position as f32
+        });
+        if self.ran_dry && shared.primed.load(Ordering::Relaxed) {
+            shared.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn advance(&mut self, ratio: f64, shared: &Shared) {
+        self.position += ratio;
+        while self.position > 1.0 {
+            self.position -= 1.0;
+            std::mem::swap(&mut self.previous, &mut self.next);
+            let channels = self.next.len();
+            if self.consumer.len() >= channels
+                && self.consumer.pop_slice(&mut self.next) == channels
+            {
+                shared.primed.store(true, Ordering::Relaxed);
+            } else {
+                self.next.iter_mut().for_each(|sample| *sample = 0.0);
+                self.ran_dry = true;
+            }
+        }
+    }
+}
+
+/// Plays the same interleaved `f32` samples on several output devices, e.g. speakers and a
+/// headset.
+///
+/// Each device gets its own stream and its own queue of samples, so the devices run on their
+/// own clocks: every device takes samples from its queue as fast as it plays them, and plays
+/// silence when its queue runs dry. Samples are handed to every device with
+/// [`append`](Self::append).
+///
+/// Device clocks never run at exactly the same rate, so left alone the outputs would slowly
+/// drift apart. To keep them aligned, the first output that hasn't failed acts as the master,
+/// and every other output resamples its queue by up to 0.2% to keep draining it at the master's
+/// pace. The correction is updated on every [`append`](Self::append) and converges within about
+/// a minute; [`drift`](Self::drift) reports the clock difference it measured.
+///
+/// A device failing doesn't affect the others. Devices that can't be opened or started, or that
+/// are disconnected, stop being fed, and their errors are available through
+/// [`error`](Self::error). Outputs are identified by the index of their device in the slice
+/// passed to [`new`](Self::new).
+pub struct MultiOutputStream {
+    outputs: Vec<Output>,
+    channels: usize,
+    sample_rate: f64,
+    // The capacity of each output's queue, in samples.
+    capacity: usize,
+    // The output the others are aligned with.
+    master: Option<usize>,
+    last_update: Option<Instant>,
+}
+
+impl MultiOutputStream {
+    /// Open an output stream with `config` and `sample_format` on each of `devices`.
+    ///
+    /// Each device queues up to `capacity` samples. Devices that fail to open are kept as failed
+    /// outputs rather than failing the whole stream. The streams are paused until
+    /// [`play`](Self::play) is called, as far as their hosts allow.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(
+        devices: &[&Device],
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        capacity: usize,
+    ) -> Self {
+        let outputs = devices
+            .iter()
+            .map(|device| {
+                let (producer, consumer) = ring_buffer(capacity);
+                let shared = Arc::new(Shared::default());
+                let stream = {
+                    let (fill_shared, report_shared) = (shared.clone(), shared.clone());
+                    let mut reader = FrameReader::new(consumer, config.channels as usize);
+                    device.build_output_stream_raw(
+                        config,
+                        sample_format,
+                        move |data, _: &_| reader.fill(data, &fill_shared),
+                        move |err| report_shared.report(err),
+                        None,
+                    )
+                };
+                let stream = match stream {
+                    Ok(stream) => {
+                        // Some hosts start streams as soon as they are built, and not all of
+                        // them can pause.
+                        let _ = stream.pause();
+                        Some(stream)
+                    }
+                    Err(err) => {
+                        shared.fail(OutputError::Build(err));
+                        None
+                    }
+                };
+                Output {
+                    stream,
+                    producer,
+                    shared,
+                    drift: DriftControl::default(),
+                }
+            })
+            .collect();
+        MultiOutputStream {
+            outputs,
+            channels: config.channels as usize,
+            sample_rate: config.sample_rate.0 as f64,
+            capacity,
+            master: None,
+            last_update: None,
+        }
+    }
+
+    /// Start or resume playback on every output that hasn't failed.
+    ///
+    /// Outputs that fail to start are marked as failed, see [`error`](Self::error).
+    pub fn play(&self) {
+        for output in self.live_outputs() {
+            if let Some(stream) = &output.stream {
+                if let Err(err) = stream.play() {
+                    output.shared.fail(OutputError::Play(err));
+                }
+            }
+        }
+    }
+
+    /// Pause every output that hasn't failed, where the host supports it.
+    pub fn pause(&self) {
+        for output in self.live_outputs() {
+            if let Some(stream) = &output.stream {
+                let _ = stream.pause();
+            }
+        }
+    }
+
+    /// Queue interleaved samples on every output that hasn't failed.
+    ///
+    /// Only whole frames should be appended. An output whose queue can't take all of `samples`
+    /// drops its oldest queued samples to make room, see [`writable`](Self::writable).
+    pub fn append(&mut self, samples: &[f32]) {
+        debug_assert_eq!(samples.len() % self.channels, 0, "appended a partial frame");
+        self.compensate_drift();
+        for output in &mut self.outputs {
+            if !output.shared.failed.load(Ordering::Relaxed) {
+                output.producer.push_slice(samples);
+            }
+        }
+    }
+
+    /// The number of samples, in whole frames, that can be appended without dropping queued
+    /// samples on any output.
+    ///
+    /// This is limited by the output that is furthest behind. Returns `0` if every output has
+    /// failed.
+    pub fn writable(&self) -> usize {
+        let vacant = self
+            .live_outputs()
+            .map(|output| output.producer.vacant_len())
+            .min()
+            .unwrap_or(0);
+        vacant - vacant % self.channels
+    }
+
+    /// The number of frames queued on output `index` but not yet played. Queues hold up to the
+    /// capacity passed to [`new`](Self::new).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn queued_frames(&self, index: usize) -> usize {
+        (self.capacity - self.outputs[index].producer.vacant_len()) / self.channels
+    }
+
+    /// The number of outputs, including failed ones.
+    pub fn num_outputs(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Whether output `index` failed to open, start or stay connected, and is no longer fed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn has_failed(&self, index: usize) -> bool {
+        self.outputs[index].shared.failed.load(Ordering::Relaxed)
+    }
+
+    /// Why output `index` failed, or the last error its stream reported.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn error(&self, index: usize) -> Option<OutputError> {
+        self.outputs[index].shared.error.lock().unwrap().clone()
+    }
+
+    /// How much faster the clock of output `index` runs than the master's, in parts per million,
+    /// as measured so far. This is `0.0` for the master and for failed outputs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn drift(&self, index: usize) -> f64 {
+        let output = &self.outputs[index];
+        if output.shared.failed.load(Ordering::Relaxed) {
+            // The controller stops being updated once an output fails, so what it measured is
+            // stale.
+            return 0.0;
+        }
+        -output.drift.integral * 1e6
+    }
+
+    /// The number of buffers output `index` had to pad with silence because its queue ran dry,
+    /// not counting those before the first appended samples reached it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn underruns(&self, index: usize) -> usize {
+        self.outputs[index].shared.underruns.load(Ordering::Relaxed)
+    }
+
+    fn live_outputs(&self) -> impl Iterator<Item = &Output> {
+        self.outputs
+            .iter()
+            .filter(|output| !output.shared.failed.load(Ordering::Relaxed))
+    }
+
+    // Adjust the rate at which each output consumes its queue to keep its level in step with the
+    // master's.
+    fn compensate_drift(&mut self) {
+        let now = Instant::now();
+        let dt = match self.last_update.replace(now) {
+            Some(last) => (now - last).as_secs_f64().min(MAX_STEP_SECS),
+            None => return,
+        };
+        let master = self
+            .outputs
+            .iter()
+            .position(|output| !output.shared.failed.load(Ordering::Relaxed));
+        if master != self.master {
+            // Start over, aligning with the new master.
+            self.master = master;
+            for output in &mut self.outputs {
+                output.drift = DriftControl::default();
+                output.shared.set_ratio(1.0);
+            }
+            return;
+        }
+        let master = match master {
+            Some(master) => &self.outputs[master],
+            None => return,
+        };
+        if !master.shared.primed.load(Ordering::Relaxed) {
+            return;
+        }
+        // The queue level in seconds.
+        let capacity = self.capacity;
+        let samples_per_sec = self.sample_rate * self.channels as f64;
+        let level =
+            |output: &Output| (capacity - output.producer.vacant_len()) as f64 / samples_per_sec;
+        let master_level = level(master);
+        let master_index = self.master;
+        for (index, output) in self.outputs.iter_mut().enumerate() {
+            if Some(index) == master_index
+                || output.shared.failed.load(Ordering::Relaxed)
+                || !output.shared.primed.load(Ordering::Relaxed)
+            {
+                continue;
+            }
+            // An output whose queue fills up relative to the master's is playing slower and has
+            // to consume more frames per frame played.
+            let ratio = output.drift.update(level(output) - master_level, dt);
+            output.shared.set_ratio(ratio);
+        }
+    }
+}
+
+#[test]
+fn test_frame_reader() {
+    let (mut producer, consumer) = ring_buffer(16);
+    let shared = Shared::default();
+    let mut reader = FrameReader::new(consumer, 2);
+    let mut buffer = [1.0f32; 4];
+    let mut data =
+        unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), 4, SampleFormat::F32) };
+
+    // Silence before anything was appended isn't an underrun.
+    reader.fill(&mut data, &shared);
+    assert_eq!(buffer, [0.0; 4]);
+    assert_eq!(shared.underruns.load(Ordering::Relaxed), 0);
+
+    producer.push_slice(&[0.5, -0.5, 0.5, -0.5, 0.5, -0.5]);
+    reader.fill(&mut data, &shared);
+    assert_eq!(buffer, [0.5, -0.5, 0.5, -0.5]);
+    reader.fill(&mut data, &shared);
+    assert_eq!(buffer, [0.5, -0.5, 0.0, 0.0]);
+    assert_eq!(shared.underruns.load(Ordering::Relaxed), 1);
+
+    // Consuming queued frames faster interpolates between them.
+    shared.set_ratio(1.5);
+    producer.push_slice(&[1.0, 1.0, 0.0, 0.0, 1.0, 1.0]);
+    reader.fill(&mut data, &shared);
+    assert_eq!(buffer, [0.5, 0.5, 1.0, 1.0]);
+}
+
+#[test]
+fn test_drift_control() {
+    // An output running 100 ppm slower than the master falls behind by 100 µs of audio every
+    // second; the controller has to learn to consume its queue that much faster.
+    let mut control = DriftControl::default();
+    let (dt, drift) = (0.01, 100e-6);
+    let mut difference = 0.05;
+    let mut ratio = 1.0;
+    for _ in 0..60_000 {
+        ratio = control.update(difference, dt);
+        difference += (1.0 + drift - ratio) * dt;
+    }
+    assert!((ratio - (1.0 + drift)).abs() < 1e-6, "{}", ratio);
+    assert!((difference - 0.05).abs() < 1e-4, "{}", difference);
+}
+
+#[test]
+fn test_output_errors() {
+    for (err, fatal) in [
+        (StreamError::DeviceNotAvailable, true),
+        (StreamError::Interrupted { resumable: false }, true),
+        (StreamError::DeviceInvalidated, true),
+        (StreamError::Interrupted { resumable: true }, false),
+        (StreamError::Resumed, false),
+    ] {
+        let shared = Shared::default();
+        shared.report(err.clone());
+        assert_eq!(shared.failed.load(Ordering::Relaxed), fatal, "{:?}", err);
+        assert_eq!(
+            *shared.error.lock().unwrap(),
+            Some(OutputError::Stream(err))
+        );
+    }
+}
+
+#[test]
+fn test_drift_of_failed_output() {
+    let output = |integral| Output {
+        stream: None,
+        producer: ring_buffer(4).0,
+        shared: Arc::new(Shared::default()),
+        drift: DriftControl {
+            integral,
+            ..DriftControl::default()
+        },
+    };
+    let stream = MultiOutputStream {
+        outputs: vec![output(0.0), output(-50e-6)],
+        channels: 1,
+        sample_rate: 48000.0,
+        capacity: 4,
+        master: Some(0),
+        last_update: None,
+    };
+    assert!((stream.drift(1) - 50.0).abs() < 1e-9, "{}", stream.drift(1));
+
+    stream.outputs[1]
+        .shared
+        .report(StreamError::DeviceNotAvailable);
+    assert!(stream.has_failed(1));
+    assert_eq!(stream.drift(1), 0.0);
+}
@@ -0,0 +1,205 @@
+//! Choosing a supported configuration for a requested one.
+
+use std::cmp::{Ordering, Reverse};
+
+use crate::{
+    NegotiateConfigError, SampleFormat, StreamConfig, SupportedStreamConfig,
+    SupportedStreamConfigRange,
+};
+
+/// The supported configuration closest to `requested` in `sample_format`, or `None` if
+/// `supported` is empty.
+///
+/// If a range supports the request exactly, it is returned unchanged. Otherwise the fallback
+/// keeps, in order of priority:
+///
+/// 1. the sample rate, so that nothing has to be resampled,
+/// 2. the channel count,
+/// 3. the sample format,
+///
+/// and then prefers more channels over fewer, the channel count closest to the requested one and
+/// the sample rate closest to the requested one. Ties are broken by
+/// [`SupportedStreamConfigRange::cmp_default_heuristics`].
+///
+/// The requested buffer size isn't taken into account; pass it on when building the stream.
+///
+/// This is what [`DeviceTrait::negotiate_input_config`] and
+/// [`DeviceTrait::negotiate_output_config`] use with the configurations of a device. It is pure,
+/// so negotiation can be tested without a device.
+///
+/// [`DeviceTrait::negotiate_input_config`]: crate::traits::DeviceTrait::negotiate_input_config
+/// [`DeviceTrait::negotiate_output_config`]: crate::traits::DeviceTrait::negotiate_output_config
+pub fn negotiate_config(
+    requested: &StreamConfig,
+    sample_format: SampleFormat,
+    supported: &[SupportedStreamConfigRange],
+) -> Option<SupportedStreamConfig> {
+    let rank = |format| usize::from(format != sample_format);
+    let best = supported
+        .iter()
+        .max_by(|a, b| compare(requested, rank, a, b))?;
+    Some(with_requested_rate(requested, best))
+}
+
+/// Like [`negotiate_config`], but only accepting the sample formats in `formats`, in order of
+/// preference.
+///
+/// Configurations in other sample formats are left out. Among the rest, a format listed earlier
+/// takes the place of the requested format in the fallback order, e.g. `[F32, I32, I16]` never
+/// ends up with `U16`, and prefers `I32` over `I16` where the built-in heuristics would pick
+/// `I16`.
+///
+/// Fails with [`NegotiateConfigError::NoAcceptableFormat`] if none of the configurations is in an
+/// acceptable format, which includes an empty `formats`.
+pub fn negotiate_config_with_formats(
+    requested: &StreamConfig,
+    formats: &[SampleFormat],
+    supported: &[SupportedStreamConfigRange],
+) -> Result<SupportedStreamConfig, NegotiateConfigError> {
+    if supported.is_empty() {
+        return Err(NegotiateConfigError::NoConfigs);
+    }
+    let rank = |format| {
+        formats
+            .iter()
+            .position(|&f| f == format)
+            .unwrap_or(usize::MAX)
+    };
+    let best = supported
+        .iter()
+        .filter(|range| formats.contains(&range.sample_format))
+        .max_by(|a, b| compare(requested, rank, a, b))
+        .ok_or(NegotiateConfigError::NoAcceptableFormat)?;
+    Ok(with_requested_rate(requested, best))
+}
+
+// The configuration of `best` at the sample rate closest to the requested one.
+fn with_requested_rate(
+    requested: &StreamConfig,
+    best: &SupportedStreamConfigRange,
+) -> SupportedStreamConfig {
+    let sample_rate = requested
+        .sample_rate
+        .clamp(best.min_sample_rate, best.max_sample_rate);
+    SupportedStreamConfig::new(
+        best.channels,
+        sample_rate,
+        best.buffer_size,
+        best.sample_format,
+    )
+}
+
+// Whether `a` is a worse (`Less`) or better (`Greater`) match for the request than `b`. `rank`
+// orders sample formats by preference, lower being better.
+fn compare(
+    requested: &StreamConfig,
+    rank: impl Fn(SampleFormat) -> usize,
+    a: &SupportedStreamConfigRange,
+    b: &SupportedStreamConfigRange,
+) -> Ordering {
+    let key = |range: &SupportedStreamConfigRange| {
+        let rate = requested
+            .sample_rate
+            .clamp(range.min_sample_rate, range.max_sample_rate);
+        (
+            rate == requested.sample_rate,
+            range.channels == requested.channels,
+            Reverse(rank(range.sample_format)),
+            range.channels >= requested.channels,
+            Reverse(range.channels.abs_diff(requested.channels)),
+            Reverse(rate.0.abs_diff(requested.sample_rate.0)),
+        )
+    };
+    key(a)
+        .cmp(&key(b))
+        .then_with(|| a.cmp_default_heuristics(b))
+}
+
+#[cfg(test)]
+fn range(
+    channels: crate::ChannelCount,
+    min_rate: u32,
+    max_rate: u32,
+    sample_format: SampleFormat,
+) -> SupportedStreamConfigRange {
+    SupportedStreamConfigRange::new(
+        channels,
+        crate::SampleRate(min_rate),
+        crate::SampleRate(max_rate),
+        crate::SupportedBufferSize::Unknown,
+        sample_format,
+    )
+}
+
+#[cfg(test)]
+fn request(channels: crate::ChannelCount, rate: u32) -> StreamConfig {
+    StreamConfig {
+        channels,
+        sample_rate: crate::SampleRate(rate),
+        buffer_size: crate::BufferSize::Default,
+    }
+}
+
+#[test]
+fn test_negotiate_config_exact() {
+    use SampleFormat::{F32, I16};
+    let supported = [range(2, 44_100, 48_000, I16), range(2, 8_000, 96_000, F32)];
+    let config = negotiate_config(&request(2, 48_000), I16, &supported).unwrap();
+    assert_eq!(config.config(), request(2, 48_000));
+    assert_eq!(config.sample_format(), I16);
+    assert_eq!(negotiate_config(&request(2, 48_000), F32, &[]), None);
+}
+
+#[test]
+fn test_negotiate_config_fallback() {
+    use SampleFormat::{F32, I16, I32};
+
+    // The sample rate is kept over the sample format.
+    let supported = [range(2, 44_100, 44_100, F32), range(2, 48_000, 48_000, I16)];
+    let config = negotiate_config(&request(2, 48_000), F32, &supported).unwrap();
+    assert_eq!(config.sample_format(), I16);
+
+    // More channels are preferred over fewer, then the closest count.
+    let supported = [
+        range(1, 48_000, 48_000, F32),
+        range(8, 48_000, 48_000, F32),
+        range(4, 48_000, 48_000, F32),
+    ];
+    let config = negotiate_config(&request(2, 48_000), F32, &supported).unwrap();
+    assert_eq!(config.channels(), 4);
+
+    // Without the rate, the closest one is used.
+    let supported = [range(2, 8_000, 22_050, I32), range(2, 88_200, 96_000, I32)];
+    let config = negotiate_config(&request(2, 48_000), I32, &supported).unwrap();
+    assert_eq!(config.sample_rate(), crate::SampleRate(22_050));
+}
+
+#[test]
+fn test_negotiate_config_with_formats() {
+    use SampleFormat::{F32, I16, I32, U16};
+
+    // The device prefers `U16`, which isn't acceptable.
+    let supported = [range(2, 48_000, 48_000, U16), range(2, 48_000, 48_000, I16)];
+    let config = negotiate_config_with_formats(&request(2, 48_000), &[F32, I16], &supported);
+    assert_eq!(config.unwrap().sample_format(), I16);
+    assert_eq!(
+        negotiate_config_with_formats(&request(2, 48_000), &[F32], &supported),
+        Err(NegotiateConfigError::NoAcceptableFormat)
+    );
+    assert_eq!(
+        negotiate_config_with_formats(&request(2, 48_000), &[F32], &[]),
+        Err(NegotiateConfigError::NoConfigs)
+    );
+
+    // The default heuristics prefer `I16` over `I32`; the list says otherwise.
+    let supported = [range(2, 48_000, 48_000, I16), range(2, 48_000, 48_000, I32)];
+    let config = negotiate_config(&request(2, 48_000), F32, &supported).unwrap();
+    assert_eq!(config.sample_format(), I16);
+    let config = negotiate_config_with_formats(&request(2, 48_000), &[F32, I32, I16], &supported);
+    assert_eq!(config.unwrap().sample_format(), I32);
+
+    // The sample rate still comes before the format.
+    let supported = [range(2, 44_100, 44_100, F32), range(2, 48_000, 48_000, I16)];
+    let config = negotiate_config_with_formats(&request(2, 48_000), &[F32, I16], &supported);
+    assert_eq!(config.unwrap().sample_format(), I16);
+}
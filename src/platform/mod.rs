@@ -7,6 +7,10 @@
 #[doc(inline)]
 pub use self::platform_impl::*;
 
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Mutex, TryLockError};
+use std::time::Duration;
+
 /// A macro to assist with implementing a platform's dynamically dispatched [`Host`] type.
 ///
 /// These dynamically dispatched types are necessary to allow for users to switch between hosts at
@@ -56,7 +60,7 @@ macro_rules! impl_platform_host {
         /// The `Device` implementation associated with the platform's dynamically dispatched
         /// [`Host`] type.
         #[derive(Clone)]
-        pub struct Device(DeviceInner);
+        pub struct Device(DeviceInner, Option<bool>);
 
         /// The `Devices` iterator associated with the platform's dynamically dispatched [`Host`]
         /// type.
@@ -65,15 +69,30 @@ macro_rules! impl_platform_host {
         /// The `Stream` implementation associated with the platform's dynamically dispatched
         /// [`Host`] type.
         #[must_use = "If the stream is not stored it will not play."]
-        pub struct Stream(StreamInner);
+        pub struct Stream(StreamInner, std::sync::Arc<crate::platform::StreamShared>);
 
         /// The `SupportedInputConfigs` iterator associated with the platform's dynamically
         /// dispatched [`Host`] type.
-        pub struct SupportedInputConfigs(SupportedInputConfigsInner);
+        ///
+        /// Configurations are yielded in ascending order (see [`crate::SupportedStreamConfigRange`]),
+        /// so the collected configurations can be binary-searched.
+        pub struct SupportedInputConfigs(std::vec::IntoIter<crate::SupportedStreamConfigRange>);
 
         /// The `SupportedOutputConfigs` iterator associated with the platform's dynamically
         /// dispatched [`Host`] type.
-        pub struct SupportedOutputConfigs(SupportedOutputConfigsInner);
+        ///
+        /// Configurations are yielded in ascending order (see [`crate::SupportedStreamConfigRange`]),
+        /// so the collected configurations can be binary-searched.
+        pub struct SupportedOutputConfigs(std::vec::IntoIter<crate::SupportedStreamConfigRange>);
+
+        /// The configurations supported by a [`Device`], in the order the host probes them. See
+        /// [`Device::supported_output_configs_lazy`].
+        ///
+        /// If the device fails while it is probed, e.g. because it was unplugged, the error is
+        /// yielded last.
+        pub struct LazySupportedConfigs(
+            pub(crate) Option<Box<dyn crate::host::ProbedConfigs + Send>>,
+        );
 
         /// Unique identifier for available hosts on the platform.
         #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
@@ -117,20 +136,6 @@ macro_rules! impl_platform_host {
             )*
         }
 
-        enum SupportedInputConfigsInner {
-            $(
-                $(#[cfg($feat)])?
-                $HostVariant(crate::host::$host_mod::SupportedInputConfigs),
-            )*
-        }
-
-        enum SupportedOutputConfigsInner {
-            $(
-                $(#[cfg($feat)])?
-                $HostVariant(crate::host::$host_mod::SupportedOutputConfigs),
-            )*
-        }
-
         impl HostId {
             pub fn name(&self) -> &'static str {
                 match self {
@@ -178,6 +183,87 @@ macro_rules! impl_platform_host {
             pub fn into_inner(self) -> DeviceInner {
                 self.0
             }
+
+            /// Build the streams of this device paused or playing, rather than in whatever state
+            /// the host creates them in.
+            ///
+            /// Without this, some hosts (e.g. ALSA) start calling the data callback as soon as
+            /// the stream is built, while others (e.g. WASAPI) wait for `play`. A stream built
+            /// paused doesn't call the data callback until `play` is first called, even on hosts
+            /// that can't pause the device, which play silence until then. This lets the
+            /// application e.g. prebuffer a [`SampleSink`](crate::sink::SampleSink) before any
+            /// audio is played. A stream built playing is started before it is returned.
+            pub fn with_start_paused(mut self, paused: bool) -> Self {
+                self.1 = Some(paused);
+                self
+            }
+
+            /// The initial state set with [`with_start_paused`](Self::with_start_paused), if any.
+            pub fn start_paused(&self) -> Option<bool> {
+                self.1
+            }
+
+            /// The supported input configurations, probed as the iterator is advanced where the
+            /// host supports it. See
+            /// [`supported_output_configs_lazy`](Self::supported_output_configs_lazy).
+            pub fn supported_input_configs_lazy(
+                &self,
+            ) -> Result<LazySupportedConfigs, crate::SupportedStreamConfigsError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => {
+                            let configs = crate::traits::DeviceTrait::supported_input_configs(d)?;
+                            Ok(LazySupportedConfigs(Some(Box::new(configs))))
+                        }
+                    )*
+                }
+            }
+
+            /// The supported output configurations, probed as the iterator is advanced where the
+            /// host supports it.
+            ///
+            /// Unlike [`supported_output_configs`](crate::traits::DeviceTrait::supported_output_configs),
+            /// the configurations aren't collected and sorted first, so e.g. taking the first
+            /// suitable one and dropping the iterator skips probing the rest. On ALSA, which
+            /// tests each combination of sample format, channel count and rate with the device,
+            /// this saves most of the probing. The configurations are yielded in the order the
+            /// host finds them, and a device that fails while it is probed yields its error last.
+            pub fn supported_output_configs_lazy(
+                &self,
+            ) -> Result<LazySupportedConfigs, crate::SupportedStreamConfigsError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => {
+                            let configs = crate::traits::DeviceTrait::supported_output_configs(d)?;
+                            Ok(LazySupportedConfigs(Some(Box::new(configs))))
+                        }
+                    )*
+                }
+            }
+
+            // Put a newly built stream into the initial state requested for this device.
+            fn apply_start_state(&self, stream: Stream) -> Result<Stream, crate::BuildStreamError> {
+                use crate::traits::StreamTrait;
+
+                match self.1 {
+                    // The stream is held, so a host that can't pause only plays silence.
+                    Some(true) => {
+                        let _ = stream.pause();
+                    }
+                    Some(false) => stream.play().map_err(|err| match err {
+                        crate::PlayStreamError::DeviceNotAvailable => {
+                            crate::BuildStreamError::DeviceNotAvailable
+                        }
+                        crate::PlayStreamError::BackendSpecific { err } => {
+                            crate::BuildStreamError::BackendSpecific { err }
+                        }
+                    })?,
+                    None => (),
+                }
+                Ok(stream)
+            }
         }
 
         impl Host {
@@ -226,6 +312,205 @@ macro_rules! impl_platform_host {
             pub fn into_inner(self) -> StreamInner {
                 self.0
             }
+
+            /// Call `tap` with each buffer of an output stream once the data callback has filled
+            /// it, as the raw bytes handed to the device along with their sample format. This
+            /// replaces any previous tap.
+            ///
+            /// The tap runs on the audio thread right after the data callback, so it should only
+            /// copy the bytes somewhere, e.g. into a ring buffer for an oscilloscope. A buffer is
+            /// skipped rather than waited for while the tap is being replaced.
+            ///
+            /// Only streams built through [`Device`] are tapped; input streams and streams
+            /// converted from a host's own stream type never call the tap.
+            pub fn set_output_tap<F>(&self, tap: F)
+            where
+                F: FnMut(&[u8], crate::SampleFormat) + Send + 'static,
+            {
+                self.1.tap.set(Some(Box::new(tap)));
+            }
+
+            /// Remove the tap set by [`set_output_tap`](Self::set_output_tap).
+            pub fn clear_output_tap(&self) {
+                self.1.tap.set(None);
+            }
+
+            /// End the output of the stream exactly at `position`, in the timeline of
+            /// [`frames_committed`](Self::frames_committed), and call `on_stopped` once it has
+            /// been reached. This replaces any stop set before.
+            ///
+            /// The data callback fills the buffer that spans `position` as usual, after which the
+            /// frames from `position` on are replaced with silence. The stream then holds as if
+            /// paused, without calling the data callback or advancing
+            /// [`frames_committed`](Self::frames_committed), until [`play`] is called. On hosts
+            /// that can't pause, the device plays silence meanwhile; have `on_stopped` signal a
+            /// thread that calls [`pause`] to stop it as well. If the stream has already passed
+            /// `position`, it stops at the start of the next buffer.
+            ///
+            /// Positions count the frames committed to the device, so a region looped by a
+            /// [`SampleSink`](crate::sink::SampleSink) counts every time it plays, and the
+            /// final buffer counts in full, silence included. `on_stopped` runs on the audio
+            /// thread, so it should only signal another thread, e.g. through a channel.
+            ///
+            /// Input streams and streams that weren't built through [`Device`] never stop.
+            ///
+            /// [`play`]: crate::traits::StreamTrait::play
+            /// [`pause`]: crate::traits::StreamTrait::pause
+            pub fn stop_at<F>(&self, position: u64, on_stopped: F)
+            where
+                F: FnOnce() + Send + 'static,
+            {
+                self.1.stop.set(Some((position, Box::new(on_stopped))));
+            }
+
+            /// Cancel the stop set by [`stop_at`](Self::stop_at), unless it has been reached.
+            pub fn cancel_stop(&self) {
+                self.1.stop.set(None);
+            }
+
+            /// The number of channels the stream was built with.
+            ///
+            /// Returns `None` for streams that weren't built through [`Device`].
+            pub fn channels(&self) -> Option<crate::ChannelCount> {
+                (self.1.channels != 0).then_some(self.1.channels)
+            }
+
+            /// The nominal sample rate the stream was built with. See
+            /// [`measured_sample_rate`](Self::measured_sample_rate) for the rate the device
+            /// actually runs at.
+            ///
+            /// Returns `None` for streams that weren't built through [`Device`].
+            pub fn sample_rate(&self) -> Option<crate::SampleRate> {
+                (self.1.sample_rate.0 != 0).then_some(self.1.sample_rate)
+            }
+
+            /// The rate at which the device actually plays or captures frames, in frames per
+            /// second, as measured against the stream's timestamps over the last few seconds.
+            ///
+            /// A device's clock rarely runs at exactly its nominal rate, which matters for long
+            /// recordings or when streaming to another clock. The measurement leaves out the
+            /// time the stream is paused and gaps due to underruns, and is `None` until it covers
+            /// a couple of seconds. Its precision depends on the host's timestamps.
+            ///
+            /// Only streams built through [`Device`] are measured.
+            pub fn measured_sample_rate(&self) -> Option<f64> {
+                self.1.measured_sample_rate()
+            }
+
+            /// Enable or disable measuring the peak level of each channel, see
+            /// [`last_channel_levels`](Self::last_channel_levels). Metering is disabled by
+            /// default.
+            pub fn set_metering(&self, enabled: bool) {
+                self.1.meter.set_enabled(enabled);
+            }
+
+            /// The peak level of each channel in the last buffer of the stream while metering is
+            /// enabled, as absolute sample values where `1.0` is full scale, e.g. for a level
+            /// meter per channel. The levels are zero while metering is disabled.
+            ///
+            /// Metering costs a pass over each buffer on the audio thread, and nothing while it's
+            /// disabled.
+            ///
+            /// Output streams are measured after the data callback has filled the buffer. Only
+            /// streams built through [`Device`] are measured.
+            pub fn last_channel_levels(&self) -> Vec<f32> {
+                self.1.meter.levels()
+            }
+
+            /// The peak level of the mono mix of the last buffer of the stream while metering is
+            /// enabled, where each frame is mixed by averaging its channels, e.g. for a single
+            /// level meter on stereo content. Zero while metering is disabled.
+            ///
+            /// Content that is out of phase between channels cancels out in the mix. See
+            /// [`last_channel_levels`](Self::last_channel_levels).
+            pub fn last_mono_level(&self) -> f32 {
+                self.1.meter.mono_level()
+            }
+
+            /// Statistics on the calls of the data callback since the stream was built or the
+            /// statistics were last reset, e.g. to put numbers on occasional clicks.
+            ///
+            /// Only streams built through [`Device`] are measured.
+            pub fn timing_stats(&self) -> crate::TimingStats {
+                self.1.timing.stats()
+            }
+
+            /// Start the [`timing_stats`](Self::timing_stats) over.
+            pub fn reset_timing_stats(&self) {
+                self.1.timing.reset();
+            }
+
+            /// Count output buffers handed to the data callback less than `threshold` before
+            /// they are due to be played as near underruns in the
+            /// [`timing_stats`](Self::timing_stats). Defaults to 2 ms.
+            pub fn set_near_underrun_threshold(&self, threshold: std::time::Duration) {
+                self.1.timing.set_near_underrun_threshold(threshold);
+            }
+
+            /// How long `frames` frames take to play or capture at the device's actual rate: the
+            /// [`measured_sample_rate`](Self::measured_sample_rate) once there is one, and the
+            /// rate the stream was built with until then.
+            ///
+            /// Returns `None` for streams that weren't built through [`Device`].
+            pub fn frames_to_duration(&self, frames: u64) -> Option<std::time::Duration> {
+                self.1.frames_to_duration(frames)
+            }
+
+            /// The number of frames played or captured in `duration` at the device's actual
+            /// rate, rounded to the nearest frame. See
+            /// [`frames_to_duration`](Self::frames_to_duration).
+            pub fn duration_to_frames(&self, duration: std::time::Duration) -> Option<u64> {
+                self.1.duration_to_frames(duration)
+            }
+
+            /// How much audio the stream has played or captured so far, e.g. for the position of
+            /// a seek bar.
+            ///
+            /// This is the duration of the frames handed to or from the data callback, less the
+            /// audio of an output stream still queued on the device as far as the host's
+            /// timestamps tell. Time spent paused isn't counted. With `include_underruns`, the
+            /// silence the device played or the audio it dropped during underruns is counted as
+            /// well, which keeps the position in step with the wall clock rather than with the
+            /// audio.
+            ///
+            /// Returns `None` for streams that weren't built through [`Device`].
+            pub fn elapsed(&self, include_underruns: bool) -> Option<std::time::Duration> {
+                self.1.elapsed(include_underruns)
+            }
+
+            /// The total number of frames handed to or from the data callback since the stream
+            /// was built, i.e. the offset in the stream's timeline of the first frame of the next
+            /// buffer. Silence the data callback writes itself, such as that of a
+            /// [`SampleSink`](crate::sink::SampleSink) running dry, is counted like any other
+            /// frame; see [`SampleSink::total_silence`](crate::sink::SampleSink::total_silence).
+            ///
+            /// For an output stream, `frames_committed() - frames_queued()` is the position of
+            /// [`elapsed(false)`](Self::elapsed) in frames.
+            ///
+            /// Returns `None` for streams that weren't built through [`Device`].
+            pub fn frames_committed(&self) -> Option<u64> {
+                self.1.frames_committed()
+            }
+
+            /// The number of frames of an output stream committed but not yet played, as far as
+            /// the host's timestamps tell. Always zero for input streams.
+            ///
+            /// Returns `None` for streams that weren't built through [`Device`].
+            pub fn frames_queued(&self) -> Option<u64> {
+                self.1.frames_queued()
+            }
+
+            /// The number of times an input stream lost captured audio because the device's
+            /// buffer filled up before the data callback got to it, i.e. the buffers handed to
+            /// the callback with [`InputCallbackInfo::discontinuity`](crate::InputCallbackInfo::discontinuity)
+            /// set. Always zero for output streams.
+            ///
+            /// Overruns mean the callback can't keep up with the chosen
+            /// [`BufferSize`](crate::BufferSize). Only streams built through [`Device`] are
+            /// counted.
+            pub fn overruns(&self) -> u64 {
+                self.1.overruns()
+            }
         }
 
         impl Iterator for Devices {
@@ -256,21 +541,11 @@ macro_rules! impl_platform_host {
             type Item = crate::SupportedStreamConfigRange;
 
             fn next(&mut self) -> Option<Self::Item> {
-                match self.0 {
-                    $(
-                        $(#[cfg($feat)])?
-                        SupportedInputConfigsInner::$HostVariant(ref mut s) => s.next(),
-                    )*
-                }
+                self.0.next()
             }
 
             fn size_hint(&self) -> (usize, Option<usize>) {
-                match self.0 {
-                    $(
-                        $(#[cfg($feat)])?
-                        SupportedInputConfigsInner::$HostVariant(ref d) => d.size_hint(),
-                    )*
-                }
+                self.0.size_hint()
             }
         }
 
@@ -278,20 +553,26 @@ macro_rules! impl_platform_host {
             type Item = crate::SupportedStreamConfigRange;
 
             fn next(&mut self) -> Option<Self::Item> {
-                match self.0 {
-                    $(
-                        $(#[cfg($feat)])?
-                        SupportedOutputConfigsInner::$HostVariant(ref mut s) => s.next(),
-                    )*
-                }
+                self.0.next()
             }
 
             fn size_hint(&self) -> (usize, Option<usize>) {
-                match self.0 {
-                    $(
-                        $(#[cfg($feat)])?
-                        SupportedOutputConfigsInner::$HostVariant(ref d) => d.size_hint(),
-                    )*
+                self.0.size_hint()
+            }
+        }
+
+        impl Iterator for LazySupportedConfigs {
+            type Item = Result<crate::SupportedStreamConfigRange, crate::SupportedStreamConfigsError>;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let configs = self.0.as_mut()?;
+                match configs.next() {
+                    Some(config) => Some(Ok(config)),
+                    None => {
+                        let err = configs.take_error();
+                        self.0 = None;
+                        err.map(Err)
+                    }
                 }
             }
         }
@@ -328,14 +609,25 @@ macro_rules! impl_platform_host {
                 }
             }
 
+            fn adapter_id(&self) -> Option<String> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d.adapter_id(),
+                    )*
+                }
+            }
+
             fn supported_input_configs(&self) -> Result<Self::SupportedInputConfigs, crate::SupportedStreamConfigsError> {
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
                         DeviceInner::$HostVariant(ref d) => {
-                            d.supported_input_configs()
-                                .map(SupportedInputConfigsInner::$HostVariant)
-                                .map(SupportedInputConfigs)
+                            d.supported_input_configs().map(|configs| {
+                                let mut configs: Vec<_> = configs.collect();
+                                configs.sort();
+                                SupportedInputConfigs(configs.into_iter())
+                            })
                         }
                     )*
                 }
@@ -346,9 +638,11 @@ macro_rules! impl_platform_host {
                     $(
                         $(#[cfg($feat)])?
                         DeviceInner::$HostVariant(ref d) => {
-                            d.supported_output_configs()
-                                .map(SupportedOutputConfigsInner::$HostVariant)
-                                .map(SupportedOutputConfigs)
+                            d.supported_output_configs().map(|configs| {
+                                let mut configs: Vec<_> = configs.collect();
+                                configs.sort();
+                                SupportedOutputConfigs(configs.into_iter())
+                            })
                         }
                     )*
                 }
@@ -372,11 +666,76 @@ macro_rules! impl_platform_host {
                 }
             }
 
+            fn current_input_config(&self) -> Option<crate::SupportedStreamConfig> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d.current_input_config(),
+                    )*
+                }
+            }
+
+            fn current_output_config(&self) -> Option<crate::SupportedStreamConfig> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d.current_output_config(),
+                    )*
+                }
+            }
+
+            fn backend_info(&self) -> Option<String> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d.backend_info(),
+                    )*
+                }
+            }
+
+            fn physical_location(&self) -> Option<String> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d.physical_location(),
+                    )*
+                }
+            }
+
+            fn has_hardware_volume(&self) -> bool {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d.has_hardware_volume(),
+                    )*
+                }
+            }
+
+            fn refresh(&mut self) -> Result<(), crate::RefreshDeviceError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref mut d) => d.refresh(),
+                    )*
+                }
+            }
+
+            fn capabilities(
+                &self,
+            ) -> Result<crate::Capabilities, crate::SupportedStreamConfigsError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d.capabilities(),
+                    )*
+                }
+            }
+
             fn build_input_stream_raw<D, E>(
                 &self,
                 config: &crate::StreamConfig,
                 sample_format: crate::SampleFormat,
-                data_callback: D,
+                mut data_callback: D,
                 error_callback: E,
                 timeout: Option<std::time::Duration>,
             ) -> Result<Self::Stream, crate::BuildStreamError>
@@ -384,6 +743,52 @@ macro_rules! impl_platform_host {
                 D: FnMut(&crate::Data, &crate::InputCallbackInfo) + Send + 'static,
                 E: FnMut(crate::StreamError) + Send + 'static,
             {
+                if !config.is_valid() {
+                    return Err(crate::BuildStreamError::StreamConfigNotSupported);
+                }
+                // Hosts deliver whole frames, but a partial one is dropped rather than handed on
+                // should a driver ever report one.
+                let channels = config.channels;
+                let shared = std::sync::Arc::new(crate::platform::StreamShared::new(
+                    channels,
+                    config.sample_rate,
+                ));
+                if self.1 == Some(true) {
+                    shared.hold();
+                }
+                let mut rate_meter = crate::rate_meter::RateMeter::new(config.sample_rate.0);
+                let mut peaks = vec![0.0; channels as usize];
+                let mut last_callback = None;
+                let mut last_buffer = None;
+                let data_callback = {
+                    let shared = shared.clone();
+                    move |data: &crate::Data, info: &crate::InputCallbackInfo| {
+                        if shared.is_held() {
+                            return;
+                        }
+                        let timestamp = info.timestamp();
+                        shared.timing.update(&mut last_callback, timestamp.callback, None);
+                        if info.discontinuity() {
+                            shared.count_overrun();
+                        }
+                        if data.len() % channels as usize == 0 {
+                            data_callback(data, info);
+                        } else if let (Some(frames), _) = data.split_whole_frames(channels) {
+                            data_callback(&frames, info);
+                        }
+                        shared.meter.process(data, &mut peaks);
+                        let frames = data.len_frames(channels);
+                        let rate = rate_meter.update(timestamp.capture, frames);
+                        shared.set_measured_sample_rate(rate);
+                        shared.clock.update(
+                            &mut last_buffer,
+                            timestamp.callback,
+                            frames,
+                            None,
+                            shared.sample_rate.0,
+                        );
+                    }
+                };
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -395,8 +800,8 @@ macro_rules! impl_platform_host {
                                 error_callback,
                                 timeout,
                             )
-                            .map(StreamInner::$HostVariant)
-                            .map(Stream::from),
+                            .map(|s| Stream(StreamInner::$HostVariant(s), shared.clone()))
+                            .and_then(|s| self.apply_start_state(s)),
                     )*
                 }
             }
@@ -405,7 +810,7 @@ macro_rules! impl_platform_host {
                 &self,
                 config: &crate::StreamConfig,
                 sample_format: crate::SampleFormat,
-                data_callback: D,
+                mut data_callback: D,
                 error_callback: E,
                 timeout: Option<std::time::Duration>,
             ) -> Result<Self::Stream, crate::BuildStreamError>
@@ -413,6 +818,78 @@ macro_rules! impl_platform_host {
                 D: FnMut(&mut crate::Data, &crate::OutputCallbackInfo) + Send + 'static,
                 E: FnMut(crate::StreamError) + Send + 'static,
             {
+                if !config.is_valid() {
+                    return Err(crate::BuildStreamError::StreamConfigNotSupported);
+                }
+                let channels = config.channels;
+                let shared = std::sync::Arc::new(crate::platform::StreamShared::new(
+                    channels,
+                    config.sample_rate,
+                ));
+                if self.1 == Some(true) {
+                    shared.hold();
+                }
+                let mut rate_meter = crate::rate_meter::RateMeter::new(config.sample_rate.0);
+                let mut peaks = vec![0.0; channels as usize];
+                let mut last_callback = None;
+                let mut last_buffer = None;
+                let data_callback = {
+                    let shared = shared.clone();
+                    move |data: &mut crate::Data, info: &crate::OutputCallbackInfo| {
+                        if shared.is_held() {
+                            data.fill_from_f32(|| 0.0);
+                            return;
+                        }
+                        let timestamp = info.timestamp();
+                        let lead = timestamp
+                            .playback
+                            .duration_since(&timestamp.callback)
+                            .unwrap_or_default();
+                        shared.timing.update(&mut last_callback, timestamp.callback, Some(lead));
+                        let check_fill = crate::fill_check::enabled();
+                        if check_fill {
+                            crate::fill_check::mark(data);
+                        }
+                        let position = shared.clock.frames();
+                        // As for input, only whole frames are handed on; a partial frame is
+                        // played as silence.
+                        if shared.stop.is_due(position) {
+                            data.fill_from_f32(|| 0.0);
+                        } else if data.len() % channels as usize == 0 {
+                            data_callback(data, info);
+                        } else {
+                            let (frames, partial) = data.split_whole_frames(channels);
+                            if let Some(mut frames) = frames {
+                                data_callback(&mut frames, info);
+                            }
+                            if let Some(mut partial) = partial {
+                                partial.fill_from_f32(|| 0.0);
+                            }
+                        }
+                        if check_fill {
+                            crate::fill_check::assert_filled(data);
+                        }
+                        let stopped = shared.stop.process(data, position, channels);
+                        shared.tap.process(data);
+                        shared.meter.process(data, &mut peaks);
+                        let frames = data.len_frames(channels);
+                        let rate = rate_meter.update(timestamp.playback, frames);
+                        shared.set_measured_sample_rate(rate);
+                        shared.clock.update(
+                            &mut last_buffer,
+                            timestamp.callback,
+                            frames,
+                            Some(lead),
+                            shared.sample_rate.0,
+                        );
+                        if let Some(on_stopped) = stopped {
+                            // The time spent held isn't an underrun.
+                            shared.clock.restart();
+                            shared.hold();
+                            on_stopped();
+                        }
+                    }
+                };
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -424,8 +901,8 @@ macro_rules! impl_platform_host {
                                 error_callback,
                                 timeout,
                             )
-                            .map(StreamInner::$HostVariant)
-                            .map(Stream::from),
+                            .map(|s| Stream(StreamInner::$HostVariant(s), shared.clone()))
+                            .and_then(|s| self.apply_start_state(s)),
                     )*
                 }
             }
@@ -475,10 +952,33 @@ macro_rules! impl_platform_host {
                     )*
                 }
             }
+
+            fn try_default_input_device(&self) -> Result<Option<Self::Device>, crate::DefaultDeviceError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        HostInner::$HostVariant(ref h) => {
+                            Ok(h.try_default_input_device()?.map(DeviceInner::$HostVariant).map(Device::from))
+                        }
+                    )*
+                }
+            }
+
+            fn try_default_output_device(&self) -> Result<Option<Self::Device>, crate::DefaultDeviceError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        HostInner::$HostVariant(ref h) => {
+                            Ok(h.try_default_output_device()?.map(DeviceInner::$HostVariant).map(Device::from))
+                        }
+                    )*
+                }
+            }
         }
 
         impl crate::traits::StreamTrait for Stream {
             fn play(&self) -> Result<(), crate::PlayStreamError> {
+                self.1.release();
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -490,6 +990,8 @@ macro_rules! impl_platform_host {
             }
 
             fn pause(&self) -> Result<(), crate::PauseStreamError> {
+                self.1.timing.restart();
+                self.1.clock.restart();
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -499,11 +1001,122 @@ macro_rules! impl_platform_host {
                     )*
                 }
             }
+
+            fn is_terminated(&self) -> bool {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.is_terminated()
+                        }
+                    )*
+                }
+            }
+
+            fn drain_timeout(&self, timeout: std::time::Duration) -> Result<(), crate::DrainError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.drain_timeout(timeout)
+                        }
+                    )*
+                }
+            }
+
+            fn fixed_buffer_size(&self) -> Option<crate::FrameCount> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.fixed_buffer_size()
+                        }
+                    )*
+                }
+            }
+
+            fn actual_buffer_size(&self) -> Option<crate::FrameCount> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.actual_buffer_size()
+                        }
+                    )*
+                }
+            }
+
+            fn set_power_preference(
+                &self,
+                preference: crate::PowerPreference,
+            ) -> Result<(), crate::StreamError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.set_power_preference(preference)
+                        }
+                    )*
+                }
+            }
+
+            fn wakeup_interval(&self) -> Option<std::time::Duration> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.wakeup_interval()
+                        }
+                    )*
+                }
+            }
+
+            fn os_processing(&self) -> crate::OsProcessing {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.os_processing()
+                        }
+                    )*
+                }
+            }
+
+            fn close(self) -> Result<(), crate::CloseStreamError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(s) => {
+                            s.close()
+                        }
+                    )*
+                }
+            }
+        }
+
+        /// Devices are equal if they are the same device of the same host, compared by the
+        /// host's stable identifier for the device, e.g. the endpoint ID on WASAPI or the PCM
+        /// name on ALSA. A device compares equal to the same device from a later enumeration, even
+        /// if its name or configurations have changed in between. Options set on the device, such
+        /// as [`with_start_paused`](Device::with_start_paused), aren't compared.
+        impl PartialEq for Device {
+            fn eq(&self, other: &Self) -> bool {
+                match (&self.0, &other.0) {
+                    $(
+                        $(#[cfg($feat)])?
+                        (DeviceInner::$HostVariant(a), DeviceInner::$HostVariant(b)) => a == b,
+                    )*
+                    #[allow(unreachable_patterns)]
+                    _ => false,
+                }
+            }
         }
 
+        impl Eq for Device {}
+
         impl From<DeviceInner> for Device {
             fn from(d: DeviceInner) -> Self {
-                Device(d)
+                Device(d, None)
             }
         }
 
@@ -521,7 +1134,10 @@ macro_rules! impl_platform_host {
 
         impl From<StreamInner> for Stream {
             fn from(s: StreamInner) -> Self {
-                Stream(s)
+                // The channel count and sample rate are unknown, and the stream isn't metered
+                // anyway.
+                let shared = crate::platform::StreamShared::new(0, crate::SampleRate(0));
+                Stream(s, std::sync::Arc::new(shared))
             }
         }
 
@@ -567,6 +1183,61 @@ macro_rules! impl_platform_host {
             host_ids
         }
 
+        // The hosts set with `set_host_preference`, and the one `default_host` picked from them,
+        // if it has picked one since they were set.
+        struct HostPreference {
+            hosts: Vec<HostId>,
+            chosen: Option<Option<HostId>>,
+        }
+
+        static HOST_PREFERENCE: std::sync::Mutex<HostPreference> =
+            std::sync::Mutex::new(HostPreference {
+                hosts: Vec::new(),
+                chosen: None,
+            });
+
+        /// Set the hosts [`default_host`] tries first, most preferred first.
+        ///
+        /// Each host is tried in order, and the first one that is available and has at least one
+        /// device is used. If none do, `default_host` falls back to the platform's default. An
+        /// empty list, the initial setting, goes straight to the default. This is meant for
+        /// applications that want to pick a host from their own configuration without managing
+        /// hosts themselves.
+        ///
+        /// The hosts are only enumerated the first time `default_host` is called afterwards, and
+        /// the choice is reused until the preference is set again.
+        pub fn set_host_preference(hosts: &[HostId]) {
+            let mut preference = HOST_PREFERENCE.lock().unwrap_or_else(|e| e.into_inner());
+            preference.hosts = hosts.to_vec();
+            preference.chosen = None;
+        }
+
+        /// The hosts set with [`set_host_preference`].
+        pub fn host_preference() -> Vec<HostId> {
+            HOST_PREFERENCE
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .hosts
+                .clone()
+        }
+
+        // The first host of the preference that is available and has a device, chosen once per
+        // preference.
+        fn preferred_host() -> Option<Host> {
+            use crate::traits::HostTrait;
+            let mut preference = HOST_PREFERENCE.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(chosen) = preference.chosen {
+                return host_from_id(chosen?).ok();
+            }
+            let host = preference.hosts.iter().find_map(|&id| {
+                let host = host_from_id(id).ok()?;
+                let mut devices = host.devices().ok()?;
+                devices.next().is_some().then_some(host)
+            });
+            preference.chosen = Some(host.as_ref().map(Host::id));
+            host
+        }
+
         /// Given a unique host identifier, initialise and produce the host if it is available.
         pub fn host_from_id(id: HostId) -> Result<Host, crate::HostUnavailable> {
             match id {
@@ -583,7 +1254,7 @@ macro_rules! impl_platform_host {
     };
 }
 
-// TODO: Add pulseaudio and jack here eventually.
+// TODO: Add pulseaudio here eventually.
 #[cfg(any(
     target_os = "linux",
     target_os = "dragonfly",
@@ -592,9 +1263,10 @@ macro_rules! impl_platform_host {
 ))]
 mod platform_impl {
     pub use crate::host::alsa::{
-        Device as AlsaDevice, Devices as AlsaDevices, Host as AlsaHost, Stream as AlsaStream,
+        Device as AlsaDevice, Devices as AlsaDevices, Host as AlsaHost,
+        MixerVolume as AlsaMixerVolume, Stream as AlsaStream,
         SupportedInputConfigs as AlsaSupportedInputConfigs,
-        SupportedOutputConfigs as AlsaSupportedOutputConfigs,
+        SupportedOutputConfigs as AlsaSupportedOutputConfigs, VolumeListener as AlsaVolumeListener,
     };
     #[cfg(feature = "jack")]
     pub use crate::host::jack::{
@@ -606,25 +1278,68 @@ mod platform_impl {
     impl_platform_host!(#[cfg(feature = "jack")] Jack jack "JACK", Alsa alsa "ALSA");
 
     /// The default host for the current compilation target platform.
+    ///
+    /// The hosts are tried in the following order, and the first one that is running is used:
+    ///
+    /// 1. JACK, if the `jack` feature is enabled and a JACK server is running.
+    /// 2. ALSA.
+    ///
+    /// Setting the `CPAL_HOST` environment variable to the name of a host (e.g. `CPAL_HOST=alsa`)
+    /// selects that host instead. The choice is made on the first call and reused afterwards.
+    /// Otherwise, the hosts set with [`set_host_preference`] are tried first. To pick a host
+    /// programmatically, use [`host_from_id`].
     pub fn default_host() -> Host {
-        AlsaHost::new()
+        if std::env::var_os("CPAL_HOST").is_none() {
+            if let Some(host) = preferred_host() {
+                return host;
+            }
+        }
+        static DEFAULT_HOST_ID: std::sync::OnceLock<HostId> = std::sync::OnceLock::new();
+        let id = *DEFAULT_HOST_ID.get_or_init(default_host_id);
+        host_from_id(id)
+            .or_else(|_| AlsaHost::new().map(Host::from))
             .expect("the default host should always be available")
-            .into()
+    }
+
+    fn default_host_id() -> HostId {
+        if let Ok(name) = std::env::var("CPAL_HOST") {
+            let requested = ALL_HOSTS
+                .iter()
+                .find(|id| id.name().eq_ignore_ascii_case(name.trim()));
+            if let Some(&id) = requested {
+                return id;
+            }
+        }
+        #[cfg(feature = "jack")]
+        if crate::host::jack::server_running() {
+            return HostId::Jack;
+        }
+        HostId::Alsa
     }
 }
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 mod platform_impl {
+    #[cfg(target_os = "macos")]
+    pub use crate::host::coreaudio::{
+        AggregateDevice as CoreAudioAggregateDevice, OutputBuffer as CoreAudioOutputBuffer,
+        OutputBuffers as CoreAudioOutputBuffers,
+    };
     pub use crate::host::coreaudio::{
         Device as CoreAudioDevice, Devices as CoreAudioDevices, Host as CoreAudioHost,
         Stream as CoreAudioStream, SupportedInputConfigs as CoreAudioSupportedInputConfigs,
         SupportedOutputConfigs as CoreAudioSupportedOutputConfigs,
+        VoiceProcessing as CoreAudioVoiceProcessing,
     };
 
     impl_platform_host!(CoreAudio coreaudio "CoreAudio");
 
-    /// The default host for the current compilation target platform.
+    /// The default host for the current compilation target platform, unless
+    /// [`set_host_preference`] picks another one.
     pub fn default_host() -> Host {
+        if let Some(host) = preferred_host() {
+            return host;
+        }
         CoreAudioHost::new()
             .expect("the default host should always be available")
             .into()
@@ -641,8 +1356,12 @@ mod platform_impl {
 
     impl_platform_host!(Emscripten emscripten "Emscripten");
 
-    /// The default host for the current compilation target platform.
+    /// The default host for the current compilation target platform, unless
+    /// [`set_host_preference`] picks another one.
     pub fn default_host() -> Host {
+        if let Some(host) = preferred_host() {
+            return host;
+        }
         EmscriptenHost::new()
             .expect("the default host should always be available")
             .into()
@@ -659,8 +1378,12 @@ mod platform_impl {
 
     impl_platform_host!(WebAudio webaudio "WebAudio");
 
-    /// The default host for the current compilation target platform.
+    /// The default host for the current compilation target platform, unless
+    /// [`set_host_preference`] picks another one.
     pub fn default_host() -> Host {
+        if let Some(host) = preferred_host() {
+            return host;
+        }
         WebAudioHost::new()
             .expect("the default host should always be available")
             .into()
@@ -676,15 +1399,24 @@ mod platform_impl {
         SupportedOutputConfigs as AsioSupportedOutputConfigs,
     };
     pub use crate::host::wasapi::{
-        Device as WasapiDevice, Devices as WasapiDevices, Host as WasapiHost,
+        DefaultDeviceChange as WasapiDefaultDeviceChange,
+        DefaultDeviceListener as WasapiDefaultDeviceListener, Device as WasapiDevice,
+        Devices as WasapiDevices, Host as WasapiHost, ObjectHandle as WasapiObjectHandle,
+        ShareMode as WasapiShareMode, SpatialAudio as WasapiSpatialAudio,
+        SpatialAudioError as WasapiSpatialAudioError, SpatialStream as WasapiSpatialStream,
         Stream as WasapiStream, SupportedInputConfigs as WasapiSupportedInputConfigs,
         SupportedOutputConfigs as WasapiSupportedOutputConfigs,
+        VolumeListener as WasapiVolumeListener,
     };
 
     impl_platform_host!(#[cfg(feature = "asio")] Asio asio "ASIO", Wasapi wasapi "WASAPI");
 
-    /// The default host for the current compilation target platform.
+    /// The default host for the current compilation target platform, unless
+    /// [`set_host_preference`] picks another one.
     pub fn default_host() -> Host {
+        if let Some(host) = preferred_host() {
+            return host;
+        }
         WasapiHost::new()
             .expect("the default host should always be available")
             .into()
@@ -701,16 +1433,55 @@ mod platform_impl {
 
     impl_platform_host!(AAudio aaudio "AAudio");
 
-    /// The default host for the current compilation target platform.
+    /// The default host for the current compilation target platform, unless
+    /// [`set_host_preference`] picks another one.
     pub fn default_host() -> Host {
+        if let Some(host) = preferred_host() {
+            return host;
+        }
         AAudioHost::new()
             .expect("the default host should always be available")
             .into()
     }
 }
 
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "android",
+        target_os = "emscripten"
+    ))
+))]
+mod platform_impl {
+    pub use crate::host::oss::{
+        Device as OssDevice, Devices as OssDevices, Host as OssHost, Stream as OssStream,
+        SupportedInputConfigs as OssSupportedInputConfigs,
+        SupportedOutputConfigs as OssSupportedOutputConfigs,
+    };
+
+    impl_platform_host!(Oss oss "OSS");
+
+    /// The default host for the current compilation target platform, unless
+    /// [`set_host_preference`] picks another one.
+    pub fn default_host() -> Host {
+        if let Some(host) = preferred_host() {
+            return host;
+        }
+        OssHost::new()
+            .expect("the default host should always be available")
+            .into()
+    }
+}
+
 #[cfg(not(any(
     windows,
+    unix,
     target_os = "linux",
     target_os = "dragonfly",
     target_os = "freebsd",
@@ -730,8 +1501,12 @@ mod platform_impl {
 
     impl_platform_host!(Null null "Null");
 
-    /// The default host for the current compilation target platform.
+    /// The default host for the current compilation target platform, unless
+    /// [`set_host_preference`] picks another one.
     pub fn default_host() -> Host {
+        if let Some(host) = preferred_host() {
+            return host;
+        }
         NullHost::new()
             .expect("the default host should always be available")
             .into()
@@ -753,3 +1528,405 @@ impl Default for NotSendSyncAcrossAllPlatforms {
         NotSendSyncAcrossAllPlatforms(std::marker::PhantomData)
     }
 }
+
+// The state shared between a stream built through `Device` and the wrappers of its callbacks.
+pub(crate) struct StreamShared {
+    pub(crate) tap: OutputTap,
+    pub(crate) meter: ChannelMeter,
+    pub(crate) timing: crate::timing_stats::TimingMeter,
+    pub(crate) clock: crate::playback_clock::PlaybackClock,
+    pub(crate) stop: StopPoint,
+    // The channel count and nominal sample rate of the stream.
+    pub(crate) channels: crate::ChannelCount,
+    pub(crate) sample_rate: crate::SampleRate,
+    // The bits of the `f64` measured sample rate, or zero while there is none.
+    measured_sample_rate: AtomicU64,
+    // The number of input buffers that followed lost audio.
+    overruns: AtomicU64,
+    // Set for a stream built paused until `play` is first called, keeping the data callback from
+    // being called.
+    held: AtomicBool,
+}
+
+impl StreamShared {
+    pub(crate) fn new(channels: crate::ChannelCount, sample_rate: crate::SampleRate) -> Self {
+        StreamShared {
+            tap: OutputTap::default(),
+            meter: ChannelMeter::new(channels),
+            timing: crate::timing_stats::TimingMeter::new(),
+            clock: crate::playback_clock::PlaybackClock::new(),
+            stop: StopPoint::default(),
+            channels,
+            sample_rate,
+            measured_sample_rate: AtomicU64::new(0),
+            overruns: AtomicU64::new(0),
+            held: AtomicBool::new(false),
+        }
+    }
+
+    fn hold(&self) {
+        self.held.store(true, Ordering::Release);
+    }
+
+    fn release(&self) {
+        self.held.store(false, Ordering::Release);
+    }
+
+    fn is_held(&self) -> bool {
+        self.held.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_measured_sample_rate(&self, rate: Option<f64>) {
+        let bits = rate.map_or(0, f64::to_bits);
+        self.measured_sample_rate.store(bits, Ordering::Relaxed);
+    }
+
+    pub(crate) fn measured_sample_rate(&self) -> Option<f64> {
+        match self.measured_sample_rate.load(Ordering::Relaxed) {
+            0 => None,
+            bits => Some(f64::from_bits(bits)),
+        }
+    }
+
+    // The measured sample rate, or the nominal one until there is a measurement. `None` if the
+    // stream wasn't built through `Device`.
+    fn effective_sample_rate(&self) -> Option<f64> {
+        match self.sample_rate.0 {
+            0 => None,
+            nominal => Some(self.measured_sample_rate().unwrap_or(nominal as f64)),
+        }
+    }
+
+    fn frames_to_duration(&self, frames: u64) -> Option<Duration> {
+        let rate = self.effective_sample_rate()?;
+        Some(Duration::from_secs_f64(frames as f64 / rate))
+    }
+
+    fn duration_to_frames(&self, duration: Duration) -> Option<u64> {
+        let rate = self.effective_sample_rate()?;
+        Some((duration.as_secs_f64() * rate).round() as u64)
+    }
+
+    pub(crate) fn count_overrun(&self) {
+        self.overruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    fn frames_committed(&self) -> Option<u64> {
+        (self.sample_rate.0 != 0).then(|| self.clock.frames())
+    }
+
+    fn frames_queued(&self) -> Option<u64> {
+        self.queued_of(self.clock.frames())
+    }
+
+    // The frames still queued on the device out of `committed`, a single reading of the clock so
+    // that the callback running in between can't make them exceed it.
+    fn queued_of(&self, committed: u64) -> Option<u64> {
+        let queued = self.duration_to_frames(self.clock.lead())?;
+        Some(queued.min(committed))
+    }
+
+    fn elapsed(&self, include_underruns: bool) -> Option<Duration> {
+        let committed = self.frames_committed()?;
+        let played = committed - self.queued_of(committed)?;
+        let underruns = if include_underruns {
+            self.clock.underruns()
+        } else {
+            Duration::ZERO
+        };
+        Some(self.frames_to_duration(played)? + underruns)
+    }
+}
+
+// The tap of an output stream, see `Stream::set_output_tap`.
+#[derive(Default)]
+pub(crate) struct OutputTap {
+    // Whether a tap is set, so that untapped streams don't touch the lock.
+    active: AtomicBool,
+    callback: Mutex<Option<OutputTapCallback>>,
+}
+
+type OutputTapCallback = Box<dyn FnMut(&[u8], crate::SampleFormat) + Send + 'static>;
+
+impl OutputTap {
+    pub(crate) fn set(&self, callback: Option<OutputTapCallback>) {
+        let mut slot = self.callback.lock().unwrap();
+        self.active.store(callback.is_some(), Ordering::Release);
+        *slot = callback;
+    }
+
+    // Hand a buffer filled by the data callback to the tap. Called on the audio thread, so it
+    // skips the buffer instead of waiting for `set`.
+    pub(crate) fn process(&self, data: &crate::Data) {
+        if !self.active.load(Ordering::Acquire) {
+            return;
+        }
+        if let Ok(mut callback) = self.callback.try_lock() {
+            if let Some(callback) = callback.as_mut() {
+                callback(data.bytes(), data.sample_format());
+            }
+        }
+    }
+}
+
+// The position at which an output stream stops, see `Stream::stop_at`.
+pub(crate) struct StopPoint {
+    // The frame to stop at, or `u64::MAX` while there is none, so that streams without a stop
+    // don't touch the lock.
+    position: AtomicU64,
+    on_stopped: Mutex<Option<StopCallback>>,
+}
+
+type StopCallback = Box<dyn FnOnce() + Send + 'static>;
+
+impl Default for StopPoint {
+    fn default() -> Self {
+        StopPoint {
+            position: AtomicU64::new(u64::MAX),
+            on_stopped: Mutex::new(None),
+        }
+    }
+}
+
+impl StopPoint {
+    pub(crate) fn set(&self, stop: Option<(u64, StopCallback)>) {
+        let mut slot = self.on_stopped.lock().unwrap();
+        let (position, on_stopped) = stop.unzip();
+        self.position
+            .store(position.unwrap_or(u64::MAX), Ordering::Release);
+        *slot = on_stopped;
+    }
+
+    // Whether a buffer starting at frame `position` lies entirely past the stop.
+    pub(crate) fn is_due(&self, position: u64) -> bool {
+        self.position.load(Ordering::Acquire) <= position
+    }
+
+    // Silence the frames of a buffer filled by the data callback from the stop on, `position`
+    // being the frame the buffer starts at. Returns the callback to call once the stream is held
+    // if the buffer reaches the stop. Called on the audio thread, so it doesn't wait for `set`:
+    // while `set` holds the lock it is replacing the stop, and the next buffer is checked against
+    // the new one.
+    pub(crate) fn process(
+        &self,
+        data: &mut crate::Data,
+        position: u64,
+        channels: crate::ChannelCount,
+    ) -> Option<StopCallback> {
+        let stop = self.position.load(Ordering::Acquire);
+        let frames = data.len_frames(channels) as u64;
+        if stop >= position.saturating_add(frames) {
+            return None;
+        }
+        let mut slot = match self.on_stopped.try_lock() {
+            Ok(slot) => slot,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        // Leave a stop set in the meantime for the next buffer.
+        if self.position.load(Ordering::Acquire) != stop {
+            return None;
+        }
+        let mid = stop.saturating_sub(position) as usize * channels as usize;
+        if let (_, Some(mut rest)) = data.split_at(mid) {
+            rest.fill_from_f32(|| 0.0);
+        }
+        self.position.store(u64::MAX, Ordering::Release);
+        slot.take()
+    }
+}
+
+// The peak level of each channel of a stream, see `Stream::last_channel_levels`.
+pub(crate) struct ChannelMeter {
+    enabled: AtomicBool,
+    // The bits of the `f32` peak of each channel in the last buffer.
+    levels: Box<[AtomicU32]>,
+    // The bits of the `f32` peak of the average of the channels in the last buffer.
+    mono: AtomicU32,
+}
+
+impl ChannelMeter {
+    fn new(channels: crate::ChannelCount) -> Self {
+        ChannelMeter {
+            enabled: AtomicBool::new(false),
+            levels: (0..channels).map(|_| AtomicU32::new(0)).collect(),
+            mono: AtomicU32::new(0),
+        }
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            for level in self.levels.iter() {
+                level.store(0, Ordering::Relaxed);
+            }
+            self.mono.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn mono_level(&self) -> f32 {
+        f32::from_bits(self.mono.load(Ordering::Relaxed))
+    }
+
+    fn levels(&self) -> Vec<f32> {
+        self.levels
+            .iter()
+            .map(|level| f32::from_bits(level.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    // Measure a buffer of whole frames, using `peaks`, with one element per channel, to
+    // accumulate the levels so the audio thread doesn't allocate.
+    pub(crate) fn process(&self, data: &crate::Data, peaks: &mut [f32]) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        peaks.fill(0.0);
+        let mut channel = 0;
+        let mut sum = 0.0;
+        let mut mono = 0.0f32;
+        data.for_each_f32(|sample| {
+            peaks[channel] = peaks[channel].max(sample.abs());
+            sum += sample;
+            channel += 1;
+            if channel == peaks.len() {
+                mono = mono.max((sum / channel as f32).abs());
+                sum = 0.0;
+                channel = 0;
+            }
+        });
+        for (level, peak) in self.levels.iter().zip(peaks.iter()) {
+            level.store(peak.to_bits(), Ordering::Relaxed);
+        }
+        self.mono.store(mono.to_bits(), Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn test_channel_meter() {
+    let mut samples = [0.5f32, -0.25, -1.0, 0.125, 0.75, 0.0];
+    let data = unsafe {
+        crate::Data::from_parts(samples.as_mut_ptr() as *mut (), 6, crate::SampleFormat::F32)
+    };
+    let meter = ChannelMeter::new(2);
+    let mut peaks = [0.0; 2];
+    meter.process(&data, &mut peaks);
+    assert_eq!(meter.levels(), [0.0, 0.0]);
+    meter.set_enabled(true);
+    meter.process(&data, &mut peaks);
+    assert_eq!(meter.levels(), [1.0, 0.25]);
+    assert_eq!(meter.mono_level(), 0.4375);
+    meter.set_enabled(false);
+    assert_eq!(meter.levels(), [0.0, 0.0]);
+    assert_eq!(meter.mono_level(), 0.0);
+}
+
+#[test]
+fn test_stop_point() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    let mut samples = [0.5f32; 6];
+    let mut data = unsafe {
+        crate::Data::from_parts(samples.as_mut_ptr() as *mut (), 6, crate::SampleFormat::F32)
+    };
+    let stop = StopPoint::default();
+    assert!(stop.process(&mut data, 0, 2).is_none());
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let on_stopped = Box::new(move || {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+    // Frame 5 lies in the second buffer of three frames, at its second frame.
+    stop.set(Some((5, on_stopped)));
+    assert!(!stop.is_due(3));
+    assert!(stop.process(&mut data, 0, 2).is_none());
+    assert_eq!(samples, [0.5; 6]);
+    let mut data = unsafe {
+        crate::Data::from_parts(samples.as_mut_ptr() as *mut (), 6, crate::SampleFormat::F32)
+    };
+    stop.process(&mut data, 3, 2).unwrap()();
+    assert_eq!(samples, [0.5, 0.5, 0.5, 0.5, 0.0, 0.0]);
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+    // The stop is only reached once.
+    assert!(stop.process(&mut data, 6, 2).is_none());
+    assert!(!stop.is_due(6));
+
+    // A stop being replaced is left alone rather than waited for.
+    stop.set(Some((7, Box::new(|| ()))));
+    let guard = stop.on_stopped.lock().unwrap();
+    samples = [0.5; 6];
+    assert!(stop.process(&mut data, 6, 2).is_none());
+    assert_eq!(samples, [0.5; 6]);
+    drop(guard);
+    assert!(stop.process(&mut data, 6, 2).is_some());
+
+    stop.set(Some((2, Box::new(|| ()))));
+    assert!(stop.is_due(3));
+    stop.set(None);
+    assert!(!stop.is_due(3));
+}
+
+#[test]
+fn test_elapsed_with_long_lead() {
+    let shared = StreamShared::new(2, crate::SampleRate(1000));
+    let mut last = None;
+    // A lead of a second, longer than the 100 frames committed so far.
+    shared.clock.update(
+        &mut last,
+        crate::StreamInstant::new(0, 0),
+        100,
+        Some(Duration::from_secs(1)),
+        1000,
+    );
+    assert_eq!(shared.frames_committed(), Some(100));
+    assert_eq!(shared.frames_queued(), Some(100));
+    assert_eq!(shared.elapsed(false), Some(Duration::ZERO));
+}
+
+#[test]
+fn test_lazy_supported_configs() {
+    use crate::{SampleFormat, SupportedBufferSize, SupportedStreamConfigsError};
+
+    // Yields one configuration, then fails like a device unplugged while it is probed.
+    struct Failing(usize);
+
+    impl Iterator for Failing {
+        type Item = crate::SupportedStreamConfigRange;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0 += 1;
+            (self.0 == 1).then_some(crate::SupportedStreamConfigRange {
+                channels: 2,
+                min_sample_rate: crate::SampleRate(48000),
+                max_sample_rate: crate::SampleRate(48000),
+                buffer_size: SupportedBufferSize::Unknown,
+                sample_format: SampleFormat::F32,
+            })
+        }
+    }
+
+    impl crate::host::ProbedConfigs for Failing {
+        fn take_error(&mut self) -> Option<SupportedStreamConfigsError> {
+            Some(SupportedStreamConfigsError::DeviceNotAvailable)
+        }
+    }
+
+    let mut configs = LazySupportedConfigs(Some(Box::new(Failing(0))));
+    assert!(matches!(configs.next(), Some(Ok(_))));
+    assert_eq!(
+        configs.next(),
+        Some(Err(SupportedStreamConfigsError::DeviceNotAvailable))
+    );
+    assert_eq!(configs.next(), None);
+    // The iterator it wraps isn't advanced anymore.
+    assert!(configs.0.is_none());
+
+    let mut configs = LazySupportedConfigs(Some(Box::new(Vec::new().into_iter())));
+    assert_eq!(configs.next(), None);
+}
@@ -0,0 +1,105 @@
+//! Tracking how much audio a stream has played or captured.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::StreamInstant;
+
+// Intervals between buffers this many times longer than the previous buffer takes to play at the
+// nominal rate are underruns, as in `RateMeter`.
+const MAX_STRETCH: u32 = 2;
+
+// Counts the frames of a stream on the audio thread, readable from any thread.
+pub(crate) struct PlaybackClock {
+    frames: AtomicU64,
+    // The time lost to underruns, in nanoseconds.
+    underruns: AtomicU64,
+    // How long before it plays the last output buffer was handed to the data callback, in
+    // nanoseconds.
+    lead: AtomicU64,
+    // Set when the stream is paused, so that the pause isn't taken for an underrun.
+    restart: AtomicBool,
+}
+
+impl PlaybackClock {
+    pub(crate) fn new() -> Self {
+        PlaybackClock {
+            frames: AtomicU64::new(0),
+            underruns: AtomicU64::new(0),
+            lead: AtomicU64::new(0),
+            restart: AtomicBool::new(false),
+        }
+    }
+
+    // Account for a buffer of `frames` frames handed to the data callback at `callback`, `last`
+    // holding the timestamp and frame count of the previous one. `lead` is how long before it
+    // plays an output buffer was handed to the callback.
+    pub(crate) fn update(
+        &self,
+        last: &mut Option<(StreamInstant, usize)>,
+        callback: StreamInstant,
+        frames: usize,
+        lead: Option<Duration>,
+        sample_rate: u32,
+    ) {
+        self.frames.fetch_add(frames as u64, Ordering::Relaxed);
+        let lead = lead.map_or(0, |lead| lead.as_nanos() as u64);
+        self.lead.store(lead, Ordering::Relaxed);
+        let previous = last.replace((callback, frames));
+        if self.restart.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        if let Some((previous, previous_frames)) = previous {
+            let expected = Duration::from_secs(previous_frames as u64) / sample_rate.max(1);
+            match callback.duration_since(&previous) {
+                Some(elapsed) if elapsed > expected * MAX_STRETCH => {
+                    let lost = (elapsed - expected).as_nanos() as u64;
+                    self.underruns.fetch_add(lost, Ordering::Relaxed);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    pub(crate) fn restart(&self) {
+        self.restart.store(true, Ordering::Relaxed);
+    }
+
+    // The frames handed to or from the data callback so far.
+    pub(crate) fn frames(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    // The lead of the last output buffer.
+    pub(crate) fn lead(&self) -> Duration {
+        Duration::from_nanos(self.lead.load(Ordering::Relaxed))
+    }
+
+    // The time lost to underruns so far.
+    pub(crate) fn underruns(&self) -> Duration {
+        Duration::from_nanos(self.underruns.load(Ordering::Relaxed))
+    }
+}
+
+#[test]
+fn test_playback_clock() {
+    let clock = PlaybackClock::new();
+    let at = |millis: u32| StreamInstant::new(0, millis * 1_000_000);
+    let mut last = None;
+    // 10 ms buffers at 1 kHz.
+    clock.update(&mut last, at(0), 10, Some(Duration::from_millis(5)), 1000);
+    clock.update(&mut last, at(10), 10, Some(Duration::from_millis(5)), 1000);
+    assert_eq!(clock.frames(), 20);
+    assert_eq!(clock.lead(), Duration::from_millis(5));
+    assert_eq!(clock.underruns(), Duration::ZERO);
+
+    // A late buffer.
+    clock.update(&mut last, at(60), 10, None, 1000);
+    assert_eq!(clock.underruns(), Duration::from_millis(40));
+
+    // A pause isn't an underrun.
+    clock.restart();
+    clock.update(&mut last, at(1000), 10, None, 1000);
+    assert_eq!(clock.frames(), 40);
+    assert_eq!(clock.underruns(), Duration::from_millis(40));
+}
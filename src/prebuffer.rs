@@ -0,0 +1,222 @@
+//! Keeping a [`SampleSink`] filled a given duration ahead of the device.
+//!
+//! See [`Prebuffer`].
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::sink::SampleSink;
+use crate::StreamError;
+
+// The longest the helper thread sleeps between checks of the queue.
+const MAX_TICK: Duration = Duration::from_millis(10);
+
+struct Shared {
+    sink: SampleSink,
+    // In nanoseconds.
+    target: AtomicU64,
+    stop: AtomicBool,
+    // The error that stopped the prebuffering, if any.
+    error: Mutex<Option<StreamError>>,
+}
+
+/// Keeps a [`SampleSink`] filled `target` ahead of the device from a pull callback, on a helper
+/// thread.
+///
+/// Whenever less than the target is queued, the callback is asked for more interleaved `f32`
+/// samples at the stream's channel count, which it appends to the `Vec` it is handed. It may
+/// append any number of samples, including none if it has nothing right now; a trailing partial
+/// frame is kept until the next call completes it. The callback runs on the helper thread, so it
+/// may block, e.g. to decode.
+///
+/// While the stream is paused, nothing is taken from the queue, so the callback isn't called
+/// either. Once the stream reports an error it can't recover from, such as
+/// [`StreamError::DeviceNotAvailable`], the prebuffering stops and the error is returned by
+/// [`error`](Self::error). Dropping the `Prebuffer` stops the helper thread; the stream plays
+/// what is still queued.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use cpal::prebuffer::Prebuffer;
+/// use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+///
+/// let device = cpal::default_host().default_output_device().unwrap();
+/// let supported = device.default_output_config().unwrap();
+/// let (stream, sink) = device
+///     .build_output_sink(
+///         &supported.config(),
+///         supported.sample_format(),
+///         4096,
+///         None,
+///     )
+///     .unwrap();
+/// let mut phase = 0.0f32;
+/// let prebuffer = Prebuffer::new(sink, Duration::from_millis(100), move |samples| {
+///     for _ in 0..480 {
+///         phase = (phase + 440.0 / 48_000.0) % 1.0;
+///         samples.push(0.1 * (phase * std::f32::consts::TAU).sin());
+///     }
+/// })
+/// .unwrap();
+/// stream.play().unwrap();
+/// ```
+pub struct Prebuffer {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Prebuffer {
+    /// Start keeping `sink` filled `target` ahead with the samples appended by `pull`.
+    ///
+    /// Fails if the helper thread can't be spawned.
+    pub fn new<F>(sink: SampleSink, target: Duration, pull: F) -> std::io::Result<Self>
+    where
+        F: FnMut(&mut Vec<f32>) + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            sink,
+            target: AtomicU64::new(nanos(target)),
+            stop: AtomicBool::new(false),
+            error: Mutex::new(None),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("cpal_prebuffer".to_owned())
+                .spawn(move || run(&shared, pull))?
+        };
+        Ok(Prebuffer {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// How long the queued samples take to play.
+    pub fn queued(&self) -> Duration {
+        self.shared.sink.buffered_duration()
+    }
+
+    /// The duration kept queued.
+    pub fn target(&self) -> Duration {
+        Duration::from_nanos(self.shared.target.load(Ordering::Relaxed))
+    }
+
+    /// Change the duration kept queued. A shorter target takes effect once the queue has played
+    /// down to it.
+    pub fn set_target(&self, target: Duration) {
+        self.shared.target.store(nanos(target), Ordering::Relaxed);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+
+    /// The total number of frames of silence the stream played because the queue ran out. See
+    /// [`SampleSink::total_silence`].
+    pub fn underrun_frames(&self) -> u64 {
+        self.shared.sink.total_silence()
+    }
+
+    /// The error that stopped the prebuffering, if the stream reported one it can't recover
+    /// from.
+    pub fn error(&self) -> Option<StreamError> {
+        self.shared.error.lock().unwrap().clone()
+    }
+
+    /// Whether the prebuffering has stopped because of an [`error`](Self::error).
+    pub fn is_stopped(&self) -> bool {
+        self.thread.as_ref().map_or(true, JoinHandle::is_finished)
+    }
+}
+
+impl Drop for Prebuffer {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run<F>(shared: &Shared, mut pull: F)
+where
+    F: FnMut(&mut Vec<f32>),
+{
+    let channels = shared.sink.channels();
+    let mut samples = Vec::new();
+    while !shared.stop.load(Ordering::Relaxed) {
+        // Other errors leave the stream running.
+        if let Some(err) = shared.sink.take_error().filter(StreamError::is_fatal) {
+            *shared.error.lock().unwrap() = Some(err);
+            return;
+        }
+        let target = Duration::from_nanos(shared.target.load(Ordering::Relaxed));
+        while shared.sink.buffered_duration() < target && !shared.stop.load(Ordering::Relaxed) {
+            let before = samples.len();
+            pull(&mut samples);
+            if samples.len() == before {
+                break;
+            }
+            let whole = samples.len() - samples.len() % channels;
+            shared.sink.append(samples.drain(..whole));
+        }
+        // Check again well before the queue has played down by a quarter of the target.
+        thread::park_timeout((target / 4).clamp(Duration::from_millis(1), MAX_TICK));
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().min(u64::MAX as u128) as u64
+}
+
+#[test]
+fn test_prebuffer() {
+    use crate::sink;
+    use crate::{Data, SampleFormat, StreamConfig};
+    use std::time::Instant;
+
+    let wait_for = |condition: &dyn Fn() -> bool| {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    };
+
+    let config = StreamConfig {
+        channels: 2,
+        sample_rate: crate::SampleRate(1000),
+        buffer_size: crate::BufferSize::Default,
+    };
+    let shared = sink::Shared::new(64, &config, SampleFormat::F32);
+    // Half frames, completed by the next call.
+    let prebuffer = Prebuffer::new(
+        SampleSink::new(shared.clone()),
+        Duration::from_millis(100),
+        |samples| samples.extend_from_slice(&[0.5; 15]),
+    )
+    .unwrap();
+    wait_for(&|| prebuffer.queued() >= Duration::from_millis(100));
+    // Nothing is played, so nothing more is pulled.
+    thread::sleep(Duration::from_millis(30));
+    assert!(prebuffer.queued() < Duration::from_millis(110));
+
+    // The device takes 50 ms worth, which is made up for.
+    let mut buffer = [0.0f32; 100];
+    let mut data =
+        unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), 100, SampleFormat::F32) };
+    shared.fill(&mut data);
+    assert_eq!(buffer, [0.5; 100]);
+    wait_for(&|| prebuffer.queued() >= Duration::from_millis(100));
+    assert_eq!(prebuffer.underrun_frames(), 0);
+
+    // A recoverable error doesn't stop the prebuffering, a fatal one does.
+    shared.report(StreamError::Resumed);
+    thread::sleep(Duration::from_millis(30));
+    assert!(!prebuffer.is_stopped());
+    shared.report(StreamError::DeviceNotAvailable);
+    wait_for(&|| prebuffer.is_stopped());
+    assert_eq!(prebuffer.error(), Some(StreamError::DeviceNotAvailable));
+}
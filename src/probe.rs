@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::traits::{DeviceTrait, HostTrait};
+use crate::{
+    BackendSpecificError, DefaultStreamConfigError, Device, DeviceNameError, DevicesError, Host,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+
+// The most threads used to probe devices at once.
+const MAX_PROBE_THREADS: usize = 4;
+
+/// Everything [`probe_devices`] found out about a device.
+pub struct DeviceCapabilities {
+    /// The device that was probed.
+    pub device: Device,
+    /// See [`DeviceTrait::name`].
+    pub name: Result<String, DeviceNameError>,
+    /// See [`DeviceTrait::supported_input_configs`].
+    pub input_configs: Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError>,
+    /// See [`DeviceTrait::supported_output_configs`].
+    pub output_configs: Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError>,
+    /// See [`DeviceTrait::default_input_config`].
+    pub default_input_config: Result<SupportedStreamConfig, DefaultStreamConfigError>,
+    /// See [`DeviceTrait::default_output_config`].
+    pub default_output_config: Result<SupportedStreamConfig, DefaultStreamConfigError>,
+    /// Whether the device didn't answer within the timeout of [`probe_devices_timeout`], in which
+    /// case all of the above are errors.
+    pub timed_out: bool,
+}
+
+/// Query the name and supported configurations of all devices of `host`.
+///
+/// Probing a device can mean opening it, which takes a noticeable time with some hosts (e.g.
+/// ALSA), so devices are probed on a few threads at once. Devices that can't safely be opened at
+/// the same time, such as the PCMs of one ALSA sound card, are probed one after another. The
+/// results are in the order the host enumerates the devices.
+///
+/// Some devices take seconds to answer or never do, see [`probe_devices_timeout`] and
+/// [`probe_devices_in_background`] to avoid waiting for them.
+pub fn probe_devices(host: &Host) -> Result<Vec<DeviceCapabilities>, DevicesError> {
+    let devices: Vec<_> = host.devices()?.collect();
+    let results = Mutex::new((0..devices.len()).map(|_| None).collect::<Vec<_>>());
+    probe_all(devices, |index, capabilities| {
+        results.lock().unwrap()[index] = Some(capabilities);
+    });
+    Ok(results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|capabilities| capabilities.expect("every device is probed"))
+        .collect())
+}
+
+/// Like [`probe_devices`], but gives up on devices that haven't been probed within `timeout`.
+///
+/// Devices that weren't probed in time are returned with [`timed_out`] set and every query
+/// failing with a backend-specific error. Their probes are left to finish in the background, so
+/// using such a device may block until its probe completes.
+///
+/// Probing is known to block for a long time with:
+///
+/// - ALSA, which opens every PCM and waits while a device is busy or a plugin (e.g. a Bluetooth
+///   or network sink) is unresponsive.
+/// - WASAPI, which activates an audio client on every endpoint; Bluetooth endpoints can take
+///   seconds to respond.
+/// - CoreAudio, when a device's driver is slow to answer property queries.
+///
+/// Enumerating the devices themselves is not covered by the timeout. Fails with a
+/// backend-specific error if the probing thread can't be spawned.
+///
+/// [`timed_out`]: DeviceCapabilities::timed_out
+pub fn probe_devices_timeout(
+    host: &Host,
+    timeout: Duration,
+) -> Result<Vec<DeviceCapabilities>, DevicesError> {
+    let deadline = Instant::now() + timeout;
+    let devices: Vec<_> = host.devices()?.collect();
+    let mut results: Vec<_> = devices.iter().map(|_| None).collect();
+    let receiver = spawn_probe(devices.clone())?;
+    let mut remaining = devices.len();
+    while remaining > 0 {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match receiver.recv_timeout(timeout) {
+            Ok((index, capabilities)) => {
+                results[index] = Some(capabilities);
+                remaining -= 1;
+            }
+            Err(_) => break,
+        }
+    }
+    Ok(results
+        .into_iter()
+        .zip(devices)
+        .map(|(capabilities, device)| capabilities.unwrap_or_else(|| timed_out(device)))
+        .collect())
+}
+
+/// Probe the devices of `host` on a background thread, receiving each device's capabilities as
+/// soon as it has been probed.
+///
+/// This returns once the devices have been enumerated. The results arrive in the order the
+/// probes finish, and the channel is closed once every device has been probed. Fails with a
+/// backend-specific error if the probing threads can't be spawned.
+pub fn probe_devices_in_background(
+    host: &Host,
+) -> Result<mpsc::Receiver<DeviceCapabilities>, DevicesError> {
+    let devices: Vec<_> = host.devices()?.collect();
+    let (sender, receiver) = mpsc::channel();
+    let indexed = spawn_probe(devices)?;
+    thread::Builder::new()
+        .name("cpal_probe_forward".to_owned())
+        .spawn(move || {
+            for (_, capabilities) in indexed {
+                if sender.send(capabilities).is_err() {
+                    return;
+                }
+            }
+        })
+        .map_err(spawn_error)?;
+    Ok(receiver)
+}
+
+// Probe `devices` on a detached thread, sending each result with the index of its device.
+fn spawn_probe(
+    devices: Vec<Device>,
+) -> Result<mpsc::Receiver<(usize, DeviceCapabilities)>, DevicesError> {
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("cpal_probe".to_owned())
+        .spawn(move || {
+            let sender = Mutex::new(sender);
+            probe_all(devices, |index, capabilities| {
+                let _ = sender.lock().unwrap().send((index, capabilities));
+            });
+        })
+        .map_err(spawn_error)?;
+    Ok(receiver)
+}
+
+fn spawn_error(err: std::io::Error) -> DevicesError {
+    BackendSpecificError {
+        description: format!("failed to spawn the device probing thread: {}", err),
+    }
+    .into()
+}
+
+// Probe `devices` on a few threads, passing each result to `report` with the index of its device
+// as soon as it is available.
+fn probe_all(devices: Vec<Device>, report: impl Fn(usize, DeviceCapabilities) + Sync) {
+    // Probe the devices that can't share the host with any other first, then group the devices
+    // that have to be probed one after another.
+    let mut groups: Vec<Vec<(usize, Device)>> = Vec::new();
+    let mut group_of_key = HashMap::new();
+    for (index, device) in devices.into_iter().enumerate() {
+        match concurrency(&device) {
+            Concurrency::Exclusive => report(index, probe(device)),
+            Concurrency::Group(key) => {
+                let group = *group_of_key.entry(key).or_insert_with(|| {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                });
+                groups[group].push((index, device));
+            }
+            Concurrency::Any => groups.push(vec![(index, device)]),
+        }
+    }
+
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_PROBE_THREADS)
+        .min(groups.len());
+    if threads <= 1 {
+        for (index, device) in groups.into_iter().flatten() {
+            report(index, probe(device));
+        }
+        return;
+    }
+    let groups = Mutex::new(groups);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let group = match groups.lock().unwrap().pop() {
+                    Some(group) => group,
+                    None => return,
+                };
+                for (index, device) in group {
+                    report(index, probe(device));
+                }
+            });
+        }
+    });
+}
+
+fn probe(device: Device) -> DeviceCapabilities {
+    DeviceCapabilities {
+        name: device.name(),
+        input_configs: device.supported_input_configs().map(Iterator::collect),
+        output_configs: device.supported_output_configs().map(Iterator::collect),
+        default_input_config: device.default_input_config(),
+        default_output_config: device.default_output_config(),
+        timed_out: false,
+        device,
+    }
+}
+
+fn timed_out(device: Device) -> DeviceCapabilities {
+    let err = || BackendSpecificError {
+        description: "probing the device timed out".to_string(),
+    };
+    DeviceCapabilities {
+        device,
+        name: Err(err().into()),
+        input_configs: Err(err().into()),
+        output_configs: Err(err().into()),
+        default_input_config: Err(err().into()),
+        default_output_config: Err(err().into()),
+        timed_out: true,
+    }
+}
+
+// Which devices a device can be probed alongside.
+#[allow(dead_code)] // Not every host restricts probing.
+enum Concurrency {
+    // Any other device.
+    Any,
+    // Any device outside of the group with this key.
+    Group(String),
+    // None; it is probed on its own.
+    Exclusive,
+}
+
+fn concurrency(device: &Device) -> Concurrency {
+    #[allow(unreachable_patterns)]
+    match device.as_inner() {
+        // The PCMs of a card share its hardware. Plugin PCMs such as `default` can open any card.
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd"
+        ))]
+        crate::platform::DeviceInner::Alsa(device) => match device.card() {
+            Some(card) => Concurrency::Group(card.to_owned()),
+            None => Concurrency::Exclusive,
+        },
+        _ => Concurrency::Any,
+    }
+}
@@ -0,0 +1,101 @@
+//! Measuring the rate at which a device actually consumes or produces frames.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::StreamInstant;
+
+// Frames and time are summed into buckets of about this length, the last `BUCKETS` of which make
+// up the window the rate is measured over.
+const BUCKET: Duration = Duration::from_secs(1);
+const BUCKETS: usize = 8;
+
+// The least time the window must cover before the rate is reported.
+const MIN_WINDOW: Duration = Duration::from_secs(2);
+
+// Intervals between buffers this many times longer than the previous buffer takes to play at the
+// nominal rate are pauses or underruns, and left out of the measurement.
+const MAX_STRETCH: u32 = 2;
+
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    frames: u64,
+    time: Duration,
+}
+
+// Measures the rate of a stream from the number of frames in each buffer and the device
+// timestamp of the buffer.
+pub(crate) struct RateMeter {
+    nominal_rate: u32,
+    buckets: VecDeque<Bucket>,
+    current: Bucket,
+    // The timestamp and frame count of the previous buffer.
+    last: Option<(StreamInstant, usize)>,
+}
+
+impl RateMeter {
+    pub(crate) fn new(nominal_rate: u32) -> Self {
+        RateMeter {
+            nominal_rate,
+            // Allocated up front, as `update` runs on the audio thread.
+            buckets: VecDeque::with_capacity(BUCKETS),
+            current: Bucket::default(),
+            last: None,
+        }
+    }
+
+    // Account for a buffer of `frames` frames at `instant`, returning the measured rate in
+    // frames per second once the window is long enough.
+    pub(crate) fn update(&mut self, instant: StreamInstant, frames: usize) -> Option<f64> {
+        if let Some((last_instant, last_frames)) = self.last.replace((instant, frames)) {
+            let expected = Duration::from_secs(last_frames as u64) / self.nominal_rate.max(1);
+            match instant.duration_since(&last_instant) {
+                Some(elapsed) if elapsed <= expected * MAX_STRETCH => {
+                    self.current.frames += last_frames as u64;
+                    self.current.time += elapsed;
+                }
+                // A gap, or time going backwards after the stream was restarted.
+                _ => (),
+            }
+        }
+        if self.current.time >= BUCKET {
+            if self.buckets.len() == BUCKETS {
+                self.buckets.pop_front();
+            }
+            self.buckets.push_back(std::mem::take(&mut self.current));
+        }
+        self.rate()
+    }
+
+    fn rate(&self) -> Option<f64> {
+        let total = self.buckets.iter().chain(Some(&self.current)).fold(
+            Bucket::default(),
+            |total, bucket| Bucket {
+                frames: total.frames + bucket.frames,
+                time: total.time + bucket.time,
+            },
+        );
+        (total.time >= MIN_WINDOW).then(|| total.frames as f64 / total.time.as_secs_f64())
+    }
+}
+
+#[test]
+fn test_rate_meter() {
+    // A device consuming 480 frames every 10.0005 ms, i.e. at about 47,997.6 Hz.
+    let mut meter = RateMeter::new(48_000);
+    let mut nanos = 0i64;
+    let mut rate = None;
+    for i in 0..1000 {
+        rate = meter.update(StreamInstant::from_nanos(nanos), 480);
+        if i < 150 {
+            assert_eq!(rate, None);
+        }
+        nanos += 10_000_500;
+        // A pause of a second, which is left out.
+        if i == 500 {
+            nanos += 1_000_000_000;
+        }
+    }
+    let rate = rate.unwrap();
+    assert!((rate - 47_997.6).abs() < 0.01, "measured {}", rate);
+}
@@ -0,0 +1,160 @@
+//! Rebuilding a stream after its device has been removed or its format changed.
+//!
+//! Once a device is unplugged, its streams report [`StreamError::DeviceNotAvailable`] to their
+//! error callback and stop, and every call on the [`Device`] fails with a `DeviceNotAvailable`
+//! error. Neither the device nor its streams recover when the device is plugged back in, so the
+//! stream has to be rebuilt:
+//!
+//! 1. In the error callback, only signal another thread, as the callback runs on the audio
+//!    thread and the stream can't be dropped from within its own callback.
+//! 2. On that thread, drop the old stream first. Some hosts, e.g. ALSA hardware devices, only
+//!    allow one stream per device.
+//! 3. Look the device up again by name with [`rebuild_output_stream`] or
+//!    [`rebuild_input_stream`], which fall back to the default device while it is missing, and
+//!    start the new stream.
+//!
+//! The same applies when the system changes the format of the device under a running stream,
+//! e.g. because the user picked another sample rate in the sound settings. The stream then
+//! reports [`StreamError::DeviceInvalidated`] and stops. As the rebuild functions query the
+//! device's configurations again, the new stream is built with the closest configuration the
+//! device supports in its new format, which is returned alongside it.
+//!
+//! ```no_run
+//! use std::sync::mpsc;
+//! use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+//! use cpal::StreamError;
+//!
+//! let host = cpal::default_host();
+//! let device = host.default_output_device().expect("no output device available");
+//! let name = device.name().unwrap();
+//! let supported = device.default_output_config().unwrap();
+//! let (config, sample_format) = (supported.config(), supported.sample_format());
+//!
+//! let (lost_tx, lost_rx) = mpsc::channel();
+//! let error_callback = move |err| {
+//!     if matches!(err, StreamError::DeviceNotAvailable | StreamError::DeviceInvalidated) {
+//!         let _ = lost_tx.send(());
+//!     }
+//! };
+//! let data_callback = |data: &mut cpal::Data, _: &cpal::OutputCallbackInfo| {
+//!     data.bytes_mut().fill(0);
+//! };
+//! let mut stream = device
+//!     .build_output_stream_raw(&config, sample_format, data_callback, error_callback.clone(), None)
+//!     .unwrap();
+//! stream.play().unwrap();
+//!
+//! while lost_rx.recv().is_ok() {
+//!     drop(stream);
+//!     let (_device, new_stream, _config) = cpal::recovery::rebuild_output_stream(
+//!         &host,
+//!         &name,
+//!         &config,
+//!         sample_format,
+//!         data_callback,
+//!         error_callback.clone(),
+//!     )
+//!     .unwrap();
+//!     new_stream.play().unwrap();
+//!     stream = new_stream;
+//! }
+//! ```
+
+use crate::traits::{DeviceTrait, HostTrait};
+use crate::{
+    BuildStreamError, Data, Device, Host, InputCallbackInfo, OutputCallbackInfo, SampleFormat,
+    Stream, StreamConfig, StreamError, SupportedStreamConfig, SupportedStreamConfigsError,
+};
+
+/// The input device called `name`, or the default input device if there is none by that name.
+pub fn reopen_input_device(host: &Host, name: &str) -> Option<Device> {
+    let devices = host.input_devices().ok();
+    find_by_name(devices.into_iter().flatten(), name).or_else(|| host.default_input_device())
+}
+
+/// The output device called `name`, or the default output device if there is none by that name.
+pub fn reopen_output_device(host: &Host, name: &str) -> Option<Device> {
+    let devices = host.output_devices().ok();
+    find_by_name(devices.into_iter().flatten(), name).or_else(|| host.default_output_device())
+}
+
+// The error to report for a failure to look up the configurations of the device.
+fn build_error(err: SupportedStreamConfigsError) -> BuildStreamError {
+    match err {
+        SupportedStreamConfigsError::DeviceNotAvailable => BuildStreamError::DeviceNotAvailable,
+        SupportedStreamConfigsError::InvalidArgument => BuildStreamError::InvalidArgument,
+        SupportedStreamConfigsError::BackendSpecific { err } => {
+            BuildStreamError::BackendSpecific { err }
+        }
+    }
+}
+
+fn find_by_name<D: DeviceTrait>(devices: impl IntoIterator<Item = D>, name: &str) -> Option<D> {
+    devices
+        .into_iter()
+        .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+}
+
+/// Build an input stream on the device called `name`, or on the default input device while
+/// there is none by that name, with the configuration closest to `config` and `sample_format`
+/// that the device supports.
+///
+/// Returns the device and the configuration the stream was built with, which differs from the
+/// requested one if the device doesn't support it (see
+/// [`negotiate_config`](crate::negotiate_config)). Fails with
+/// [`BuildStreamError::DeviceNotAvailable`] if there is no input device at all.
+pub fn rebuild_input_stream<D, E>(
+    host: &Host,
+    name: &str,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    data_callback: D,
+    error_callback: E,
+) -> Result<(Device, Stream, SupportedStreamConfig), BuildStreamError>
+where
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let device = reopen_input_device(host, name).ok_or(BuildStreamError::DeviceNotAvailable)?;
+    let supported = device
+        .negotiate_input_config(config, sample_format)
+        .map_err(build_error)?
+        .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+    let stream = device.build_input_stream_raw(
+        &supported.config(),
+        supported.sample_format(),
+        data_callback,
+        error_callback,
+        None,
+    )?;
+    Ok((device, stream, supported))
+}
+
+/// Build an output stream on the device called `name`, or on the default output device while
+/// there is none by that name. See [`rebuild_input_stream`].
+pub fn rebuild_output_stream<D, E>(
+    host: &Host,
+    name: &str,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    data_callback: D,
+    error_callback: E,
+) -> Result<(Device, Stream, SupportedStreamConfig), BuildStreamError>
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let device = reopen_output_device(host, name).ok_or(BuildStreamError::DeviceNotAvailable)?;
+    let supported = device
+        .negotiate_output_config(config, sample_format)
+        .map_err(build_error)?
+        .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+    let stream = device.build_output_stream_raw(
+        &supported.config(),
+        supported.sample_format(),
+        data_callback,
+        error_callback,
+        None,
+    )?;
+    Ok((device, stream, supported))
+}
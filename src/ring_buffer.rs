@@ -0,0 +1,280 @@
+//! A lock-free ring buffer for moving captured samples out of the audio thread.
+//!
+//! See [`DeviceTrait::build_input_stream_into_ring_buffer`](crate::traits::DeviceTrait::build_input_stream_into_ring_buffer).
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::ChannelCount;
+
+struct Shared {
+    // The `f32` samples, stored as their bit patterns.
+    slots: Box<[AtomicU32]>,
+    // Total number of samples ever pushed. Only written by the producer.
+    head: AtomicUsize,
+    // Total number of samples ever popped or dropped. Advanced by the consumer when reading and by
+    // the producer when it has to make room.
+    tail: AtomicUsize,
+    // Number of samples the producer has dropped because the buffer was full.
+    overruns: AtomicUsize,
+    // Whether a full buffer drops the samples being pushed rather than the oldest ones.
+    drop_newest: AtomicBool,
+    // Number of gaps in the stream of samples, from overflows or marked by the producer.
+    discontinuities: AtomicUsize,
+    // The number of interleaved channels, for counting overruns in frames.
+    channels: usize,
+}
+
+/// What a [`RingProducer`] does with samples pushed while the buffer is full. Set with
+/// [`RingConsumer::set_overflow_policy`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Drop the oldest samples to make room, so that the consumer always gets the most recent
+    /// audio.
+    #[default]
+    DropOldest,
+    /// Drop the samples being pushed, so that the consumer gets what was already buffered. Keep
+    /// the capacity a multiple of the channel count so that only whole frames are dropped.
+    DropNewest,
+}
+
+/// The writing half of a ring buffer created with [`ring_buffer`].
+pub struct RingProducer {
+    shared: Arc<Shared>,
+}
+
+/// The reading half of a ring buffer created with [`ring_buffer`].
+pub struct RingConsumer {
+    shared: Arc<Shared>,
+}
+
+/// Create a single-producer, single-consumer ring buffer holding up to `capacity` samples.
+///
+/// Neither side ever blocks. When the buffer is full, pushing a sample drops either the oldest one
+/// or the new one, depending on the [`OverflowPolicy`], and counts it as an overrun.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn ring_buffer(capacity: usize) -> (RingProducer, RingConsumer) {
+    interleaved_ring_buffer(capacity, 1)
+}
+
+// A ring buffer of samples interleaved over `channels`, which only affects
+// `RingConsumer::overrun_frames`.
+pub(crate) fn interleaved_ring_buffer(
+    capacity: usize,
+    channels: ChannelCount,
+) -> (RingProducer, RingConsumer) {
+    assert!(capacity > 0, "ring buffer capacity must be non-zero");
+    let shared = Arc::new(Shared {
+        slots: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        overruns: AtomicUsize::new(0),
+        drop_newest: AtomicBool::new(false),
+        discontinuities: AtomicUsize::new(0),
+        channels: channels.max(1) as usize,
+    });
+    let producer = RingProducer {
+        shared: shared.clone(),
+    };
+    (producer, RingConsumer { shared })
+}
+
+impl RingProducer {
+    /// Push a sample, dropping a sample according to the [`OverflowPolicy`] if the buffer is
+    /// full.
+    pub fn push(&mut self, sample: f32) {
+        if !self.push_sample(sample) {
+            self.mark_discontinuity();
+        }
+    }
+
+    /// Push all of `samples`, dropping samples according to the [`OverflowPolicy`] as necessary.
+    /// Dropping any counts as a single discontinuity.
+    pub fn push_slice(&mut self, samples: &[f32]) {
+        let mut complete = true;
+        for &sample in samples {
+            complete &= self.push_sample(sample);
+        }
+        if !complete {
+            self.mark_discontinuity();
+        }
+    }
+
+    /// Record a gap in the samples, e.g. because the device lost some before they were pushed.
+    /// See [`RingConsumer::discontinuities`].
+    pub fn mark_discontinuity(&mut self) {
+        self.shared.discontinuities.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Push a sample, returning whether it was stored without dropping any.
+    fn push_sample(&mut self, sample: f32) -> bool {
+        let shared = &*self.shared;
+        let capacity = shared.slots.len();
+        let head = shared.head.load(Ordering::Relaxed);
+        let mut complete = true;
+        loop {
+            let tail = shared.tail.load(Ordering::Acquire);
+            if head - tail < capacity {
+                break;
+            }
+            if shared.drop_newest.load(Ordering::Relaxed) {
+                shared.overruns.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+            // Make room by dropping the oldest sample, unless the consumer got to it first.
+            if shared
+                .tail
+                .compare_exchange(tail, tail + 1, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                shared.overruns.fetch_add(1, Ordering::Relaxed);
+                complete = false;
+                break;
+            }
+        }
+        shared.slots[head % capacity].store(sample.to_bits(), Ordering::Relaxed);
+        shared.head.store(head + 1, Ordering::Release);
+        complete
+    }
+
+    /// The number of samples that can be pushed before the oldest ones start being dropped.
+    pub fn vacant_len(&self) -> usize {
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        let head = self.shared.head.load(Ordering::Relaxed);
+        self.shared.slots.len() - (head - tail)
+    }
+}
+
+impl RingConsumer {
+    /// Pop as many samples as are available into `out`, oldest first.
+    ///
+    /// Returns the number of samples written to `out`.
+    pub fn pop_slice(&mut self, out: &mut [f32]) -> usize {
+        let shared = &*self.shared;
+        let capacity = shared.slots.len();
+        loop {
+            let tail = shared.tail.load(Ordering::Acquire);
+            let head = shared.head.load(Ordering::Acquire);
+            let n = (head - tail).min(out.len());
+            for (i, sample) in out[..n].iter_mut().enumerate() {
+                *sample =
+                    f32::from_bits(shared.slots[(tail + i) % capacity].load(Ordering::Relaxed));
+            }
+            // If the producer dropped samples while we were reading, what we read may have been
+            // overwritten, so start over.
+            if shared
+                .tail
+                .compare_exchange(tail, tail + n, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return n;
+            }
+        }
+    }
+
+    /// Pop the oldest sample, if any.
+    pub fn pop(&mut self) -> Option<f32> {
+        let mut sample = [0.0];
+        match self.pop_slice(&mut sample) {
+            0 => None,
+            _ => Some(sample[0]),
+        }
+    }
+
+    /// The number of samples currently available to pop.
+    pub fn len(&self) -> usize {
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        let head = self.shared.head.load(Ordering::Acquire);
+        head.saturating_sub(tail)
+    }
+
+    /// Whether there are no samples available to pop.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of samples the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len()
+    }
+
+    /// The total number of samples that were dropped because the buffer was full.
+    pub fn overruns(&self) -> usize {
+        self.shared.overruns.load(Ordering::Relaxed)
+    }
+
+    /// [`overruns`](Self::overruns) in frames of the stream feeding the buffer, for keeping
+    /// timestamps of the consumed audio correct. Buffers created with [`ring_buffer`] count
+    /// single samples.
+    pub fn overrun_frames(&self) -> usize {
+        self.overruns() / self.shared.channels
+    }
+
+    /// The total number of gaps in the samples: pushes that had to drop samples, and gaps marked
+    /// with [`RingProducer::mark_discontinuity`]. A change since the last pop means that the
+    /// samples popped next don't continue the previous ones.
+    pub fn discontinuities(&self) -> usize {
+        self.shared.discontinuities.load(Ordering::Relaxed)
+    }
+
+    /// Choose what happens to samples pushed while the buffer is full.
+    pub fn set_overflow_policy(&self, policy: OverflowPolicy) {
+        let drop_newest = policy == OverflowPolicy::DropNewest;
+        self.shared
+            .drop_newest
+            .store(drop_newest, Ordering::Relaxed);
+    }
+
+    /// What happens to samples pushed while the buffer is full.
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        if self.shared.drop_newest.load(Ordering::Relaxed) {
+            OverflowPolicy::DropNewest
+        } else {
+            OverflowPolicy::DropOldest
+        }
+    }
+}
+
+#[test]
+fn test_ring_buffer() {
+    let (mut producer, mut consumer) = ring_buffer(4);
+    assert!(consumer.is_empty());
+    assert_eq!(consumer.pop(), None);
+
+    producer.push_slice(&[1.0, 2.0, 3.0]);
+    assert_eq!(consumer.len(), 3);
+    assert_eq!(producer.vacant_len(), 1);
+    assert_eq!(consumer.pop(), Some(1.0));
+
+    // Overfill: 2.0, 3.0 and 4.0 are dropped to make room.
+    producer.push_slice(&[4.0, 5.0, 6.0, 7.0, 8.0]);
+    assert_eq!(consumer.overruns(), 3);
+    let mut out = [0.0; 8];
+    assert_eq!(consumer.pop_slice(&mut out), 4);
+    assert_eq!(out[..4], [5.0, 6.0, 7.0, 8.0]);
+    assert!(consumer.is_empty());
+}
+
+#[test]
+fn test_ring_buffer_overflow() {
+    let (mut producer, mut consumer) = interleaved_ring_buffer(4, 2);
+    producer.push_slice(&[1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(consumer.discontinuities(), 0);
+
+    // A slice dropping samples is a single discontinuity.
+    producer.push_slice(&[5.0, 6.0, 7.0, 8.0]);
+    assert_eq!(consumer.overrun_frames(), 2);
+    assert_eq!(consumer.discontinuities(), 1);
+
+    consumer.set_overflow_policy(OverflowPolicy::DropNewest);
+    producer.push_slice(&[9.0, 10.0]);
+    producer.mark_discontinuity();
+    assert_eq!(consumer.overruns(), 6);
+    assert_eq!(consumer.discontinuities(), 3);
+    let mut out = [0.0; 4];
+    assert_eq!(consumer.pop_slice(&mut out), 4);
+    assert_eq!(out, [5.0, 6.0, 7.0, 8.0]);
+}
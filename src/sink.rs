@@ -0,0 +1,778 @@
+//! Feeding an output stream through a [`futures_sink::Sink`].
+//!
+//! See [`DeviceTrait::build_output_sink`](crate::traits::DeviceTrait::build_output_sink).
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use futures_sink::Sink;
+
+use crate::{Data, FromSample, Sample, SampleFormat, StreamConfig, StreamError, I24};
+
+struct State {
+    // Interleaved samples waiting to be played.
+    queue: VecDeque<f32>,
+    // An error reported by the stream that hasn't been returned from the sink yet.
+    error: Option<StreamError>,
+    // The task waiting for the stream to consume samples.
+    waker: Option<Waker>,
+    closed: bool,
+    // The total number of samples sent to the sink, and handed from the queue to the device.
+    appended: u64,
+    consumed: u64,
+    // The total number of samples of silence played because the queue ran out.
+    silence: u64,
+    // The region played once the queue runs out, until new samples are sent.
+    looping: Option<Loop>,
+    // The bytes of a partial frame passed to `append_raw`, completed by the next call.
+    partial_frame: Vec<u8>,
+    // The samples of a partial frame sent to the sink, completed by the next item.
+    partial_samples: Vec<f32>,
+    // Whether `append_raw` rejects formats other than the stream's.
+    strict_format: bool,
+}
+
+impl State {
+    fn append(&mut self, samples: impl IntoIterator<Item = f32>) {
+        let before = self.queue.len();
+        self.queue.extend(samples);
+        self.appended += (self.queue.len() - before) as u64;
+        self.looping = None;
+    }
+}
+
+struct Loop {
+    buffer: Arc<[f32]>,
+    range: Range<usize>,
+    // The index of the next sample to play.
+    position: usize,
+}
+
+impl Loop {
+    fn next_sample(&mut self) -> f32 {
+        let sample = self.buffer[self.position];
+        self.position += 1;
+        if self.position == self.range.end {
+            self.position = self.range.start;
+        }
+        sample
+    }
+}
+
+pub(crate) struct Shared {
+    state: Mutex<State>,
+    // The number of queued samples above which the sink stops accepting items.
+    capacity: usize,
+    // The number of samples the stream plays per second, over all channels.
+    samples_per_sec: u64,
+    channels: usize,
+    // The sample format of the stream.
+    sample_format: SampleFormat,
+}
+
+impl Shared {
+    pub(crate) fn new(
+        capacity: usize,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Arc<Self> {
+        Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::with_capacity(capacity),
+                error: None,
+                waker: None,
+                closed: false,
+                appended: 0,
+                consumed: 0,
+                silence: 0,
+                looping: None,
+                partial_frame: Vec::new(),
+                partial_samples: Vec::new(),
+                strict_format: false,
+            }),
+            capacity,
+            samples_per_sec: config.channels as u64 * config.sample_rate.0 as u64,
+            channels: config.channels as usize,
+            sample_format,
+        })
+    }
+
+    // Fill an output buffer from the queue, with silence where the queue runs out.
+    pub(crate) fn fill(&self, data: &mut Data) {
+        let mut state = self.state.lock().unwrap();
+        let before = state.queue.len();
+        let State {
+            queue,
+            looping,
+            silence,
+            ..
+        } = &mut *state;
+        data.fill_from_f32(|| {
+            queue
+                .pop_front()
+                .or_else(|| looping.as_mut().map(Loop::next_sample))
+                .unwrap_or_else(|| {
+                    *silence += 1;
+                    0.0
+                })
+        });
+        state.consumed += (before - state.queue.len()) as u64;
+        if state.queue.len() < before {
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    // Keep an error that stops the stream for the sink to return. The stream goes on after
+    // others, e.g. an interruption that resumes, so the sink does too.
+    pub(crate) fn report(&self, err: StreamError) {
+        if !err.is_fatal() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.error.get_or_insert(err);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Accepts interleaved `f32` samples for an output stream, created by
+/// [`DeviceTrait::build_output_sink`](crate::traits::DeviceTrait::build_output_sink).
+///
+/// Each item is a `Vec<f32>` of interleaved samples at the stream's channel count. Items are
+/// queued and converted to the stream's sample format as the device asks for them; the stream
+/// plays silence, or the region set with [`set_loop`](Self::set_loop), while the queue is empty.
+/// Once the queue holds the sink's capacity, `poll_ready` waits for the stream to make room, so the
+/// queue never holds more than the capacity plus one item. Flushing waits until every queued
+/// sample has been handed to the device.
+///
+/// Items don't have to end on a frame boundary. The samples of a trailing partial frame are held
+/// back until the next item completes it, so the channels never shift, and aren't played if no
+/// item follows.
+///
+/// Errors after which the stream can't play anymore, e.g. [`StreamError::DeviceNotAvailable`], are
+/// returned by the next call on the sink. The sink ignores the others, which the stream goes on
+/// after, e.g. [`StreamError::Interrupted`] with `resumable` set.
+pub struct SampleSink {
+    shared: Arc<Shared>,
+}
+
+impl SampleSink {
+    pub(crate) fn new(shared: Arc<Shared>) -> Self {
+        SampleSink { shared }
+    }
+
+    /// The number of samples that can be queued before the sink reaches its capacity.
+    pub fn writable_len(&self) -> usize {
+        let state = self.shared.state.lock().unwrap();
+        self.shared.capacity.saturating_sub(state.queue.len())
+    }
+
+    /// The number of whole frames queued but not yet handed to the device. Together with the
+    /// device's own buffering this is the latency of the sink.
+    pub fn queued_frames(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len() / self.shared.channels.max(1)
+    }
+
+    /// The total number of frames sent to the sink.
+    ///
+    /// Together with [`total_consumed`](Self::total_consumed) this allows tests to check that
+    /// feeding code produces exactly the audio the device asked for, e.g. that
+    /// `total_appended() - total_consumed()` stays at the intended queue level.
+    pub fn total_appended(&self) -> u64 {
+        self.shared.state.lock().unwrap().appended / self.shared.channels.max(1) as u64
+    }
+
+    /// The total number of frames the device has taken from the queue, not counting the silence
+    /// played while the queue was empty.
+    pub fn total_consumed(&self) -> u64 {
+        self.shared.state.lock().unwrap().consumed / self.shared.channels.max(1) as u64
+    }
+
+    /// The total number of frames of silence played because the queue was empty and no loop was
+    /// set, i.e. the underruns of the sink.
+    ///
+    /// These frames are counted by the stream's
+    /// [`frames_committed`](crate::platform::Stream::frames_committed) like any other, so that
+    /// the frames committed from the queue are `frames_committed() - total_silence()` less any
+    /// looped frames.
+    pub fn total_silence(&self) -> u64 {
+        self.shared.state.lock().unwrap().silence / self.shared.channels.max(1) as u64
+    }
+
+    /// Once the queued samples have played, play `buffer[range]` over and over instead of
+    /// silence, until [`clear_loop`](Self::clear_loop) is called or more samples are sent.
+    ///
+    /// The region is repeated within the stream's callback, so the seam is sample-accurate no
+    /// matter how the device's buffers fall. Sending samples ends the loop right away, at the
+    /// boundary of a device buffer, and the samples play next. Looped samples don't count towards
+    /// [`total_consumed`](Self::total_consumed).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is empty, doesn't lie within `buffer` or doesn't start and end on a frame
+    /// boundary.
+    pub fn set_loop(&self, buffer: Arc<[f32]>, range: Range<usize>) {
+        let channels = self.shared.channels.max(1);
+        assert!(
+            !range.is_empty() && range.end <= buffer.len(),
+            "loop range {:?} is empty or out of bounds for a buffer of {} samples",
+            range,
+            buffer.len()
+        );
+        assert!(
+            range.start % channels == 0 && range.end % channels == 0,
+            "loop range {:?} doesn't span whole frames of {} channels",
+            range,
+            channels
+        );
+        let position = range.start;
+        self.shared.state.lock().unwrap().looping = Some(Loop {
+            buffer,
+            range,
+            position,
+        });
+    }
+
+    /// Stop playing the region set with [`set_loop`](Self::set_loop), at the boundary of the
+    /// next device buffer.
+    pub fn clear_loop(&self) {
+        self.shared.state.lock().unwrap().looping = None;
+    }
+
+    /// Whether a region set with [`set_loop`](Self::set_loop) is to play once the queue runs out.
+    pub fn is_looping(&self) -> bool {
+        self.shared.state.lock().unwrap().looping.is_some()
+    }
+
+    /// Queue a complete clip of interleaved samples at the stream's channel count, to play right
+    /// after the samples already queued.
+    ///
+    /// Clips queued back to back play without a gap between them, however the device's buffers
+    /// fall, as long as the queue doesn't run out. Unlike sending, this doesn't wait for room in
+    /// the queue, so several clips can be queued ahead of time; the sink's capacity only limits
+    /// how far ahead the [`Sink`] is fed. An error reported by the stream is returned instead of
+    /// queueing the clip.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `clip` doesn't hold whole frames, as that would shift the channels of everything
+    /// queued after it.
+    pub fn enqueue(&self, clip: Vec<f32>) -> Result<(), StreamError> {
+        let channels = self.shared.channels.max(1);
+        assert!(
+            clip.len() % channels == 0,
+            "a clip of {} samples doesn't hold whole frames of {} channels",
+            clip.len(),
+            channels
+        );
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(err) = state.error.take() {
+            return Err(err);
+        }
+        state.append(clip);
+        Ok(())
+    }
+
+    // The channel count of the stream.
+    pub(crate) fn channels(&self) -> usize {
+        self.shared.channels.max(1)
+    }
+
+    // Take the error reported by the stream, if any.
+    pub(crate) fn take_error(&self) -> Option<StreamError> {
+        self.shared.state.lock().unwrap().error.take()
+    }
+
+    // Queue interleaved samples of whole frames without checking for an error.
+    pub(crate) fn append(&self, samples: impl IntoIterator<Item = f32>) {
+        self.shared.state.lock().unwrap().append(samples);
+    }
+
+    /// Queue raw PCM bytes, e.g. as received from the network, holding interleaved samples at
+    /// the stream's channel count in `sample_format` and `endianness`.
+    ///
+    /// The samples are converted to `f32` and queued like the items sent to the sink. If `bytes`
+    /// ends within a frame, the bytes of the partial frame are kept and completed by the next
+    /// call, so packets don't have to be split on frame boundaries; every call must use the same
+    /// format. Samples of [`SampleFormat::I24`] take four bytes, as in [`Data`].
+    ///
+    /// Unlike sending, this doesn't wait for room in the queue; use
+    /// [`would_block`](Self::would_block) to keep to the sink's capacity. An error reported by the
+    /// stream is returned instead of queueing the bytes, as is
+    /// [`AppendError::FormatMismatch`] in [strict mode](Self::set_strict_format).
+    pub fn append_raw(
+        &self,
+        bytes: &[u8],
+        sample_format: SampleFormat,
+        endianness: Endianness,
+    ) -> Result<(), AppendError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(err) = state.error.take() {
+            return Err(err.into());
+        }
+        if state.strict_format && sample_format != self.shared.sample_format {
+            return Err(AppendError::FormatMismatch {
+                expected: self.shared.sample_format,
+                actual: sample_format,
+            });
+        }
+        let mut pending = std::mem::take(&mut state.partial_frame);
+        pending.extend_from_slice(bytes);
+        let frame_size = sample_format.sample_size() * self.shared.channels.max(1);
+        let whole = pending.len() - pending.len() % frame_size;
+        state.append(decode(&pending[..whole], sample_format, endianness));
+        pending.drain(..whole);
+        state.partial_frame = pending;
+        Ok(())
+    }
+
+    /// Make [`append_raw`](Self::append_raw) reject bytes in any format but the stream's with
+    /// [`AppendError::FormatMismatch`], rather than converting them. Disabled by default.
+    ///
+    /// This guarantees that the samples reach the device unchanged, e.g. for measurements. The
+    /// sink never resamples. Items sent through the [`Sink`] are `f32` and converted regardless.
+    ///
+    /// The sink queues `f32` samples, which only hold [`SampleFormat::F32`] and the integer
+    /// formats of up to 24 bits exactly. Enabling strict mode on a stream of any other format
+    /// fails with [`AppendError::InexactFormat`] and leaves the mode unchanged.
+    pub fn set_strict_format(&self, strict: bool) -> Result<(), AppendError> {
+        let sample_format = self.shared.sample_format;
+        if strict && !is_exact_in_f32(sample_format) {
+            return Err(AppendError::InexactFormat(sample_format));
+        }
+        self.shared.state.lock().unwrap().strict_format = strict;
+        Ok(())
+    }
+
+    /// How long the queued samples take to play.
+    ///
+    /// To show progress while prebuffering, queue samples while the stream is paused and poll
+    /// this, or [`buffered_fraction`](Self::buffered_fraction), until enough is buffered.
+    pub fn buffered_duration(&self) -> Duration {
+        let queued = self.shared.state.lock().unwrap().queue.len() as u64;
+        match self.shared.samples_per_sec {
+            0 => Duration::ZERO,
+            samples_per_sec => Duration::from_nanos(queued * 1_000_000_000 / samples_per_sec),
+        }
+    }
+
+    /// The queued samples as a fraction of the sink's capacity, between `0.0` and `1.0`.
+    pub fn buffered_fraction(&self) -> f32 {
+        let queued = self.shared.state.lock().unwrap().queue.len();
+        (queued as f32 / self.shared.capacity as f32).min(1.0)
+    }
+
+    /// Whether queueing `sample_count` samples right now would fill the sink to its capacity,
+    /// so that the next `poll_ready` would wait for the stream.
+    ///
+    /// This allows real-time code to decide between producing samples now and deferring, without
+    /// risking a wait.
+    pub fn would_block(&self, sample_count: usize) -> bool {
+        sample_count >= self.writable_len()
+    }
+
+    // Return `Ready` once `ready` holds for the queue, registering the task to be woken otherwise.
+    fn poll_until(
+        &self,
+        cx: &mut Context<'_>,
+        ready: impl Fn(&VecDeque<f32>) -> bool,
+    ) -> Poll<Result<(), StreamError>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(err) = state.error.take() {
+            return Poll::Ready(Err(err));
+        }
+        if ready(&state.queue) {
+            return Poll::Ready(Ok(()));
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Sink<Vec<f32>> for SampleSink {
+    type Error = StreamError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), StreamError>> {
+        let capacity = self.shared.capacity;
+        self.poll_until(cx, |queue| queue.len() < capacity)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Vec<f32>) -> Result<(), StreamError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(err) = state.error.take() {
+            return Err(err);
+        }
+        debug_assert!(!state.closed, "`start_send` called on a closed sink");
+        let mut samples = match state.partial_samples.is_empty() {
+            true => item,
+            false => {
+                let mut samples = std::mem::take(&mut state.partial_samples);
+                samples.extend(item);
+                samples
+            }
+        };
+        let whole = samples.len() - samples.len() % self.shared.channels.max(1);
+        state.partial_samples = samples.split_off(whole);
+        state.append(samples);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), StreamError>> {
+        self.poll_until(cx, VecDeque::is_empty)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), StreamError>> {
+        let flushed = self.poll_until(cx, VecDeque::is_empty);
+        if let Poll::Ready(Ok(())) = flushed {
+            self.shared.state.lock().unwrap().closed = true;
+        }
+        flushed
+    }
+}
+
+/// An error returned by [`SampleSink::append_raw`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AppendError {
+    /// The bytes aren't in the sample format of the stream, and the sink is in
+    /// [strict mode](SampleSink::set_strict_format).
+    FormatMismatch {
+        expected: SampleFormat,
+        actual: SampleFormat,
+    },
+    /// Strict mode was requested for a stream whose sample format the sink's `f32` queue can't
+    /// hold exactly, see [`SampleSink::set_strict_format`].
+    InexactFormat(SampleFormat),
+    /// The stream reported an error.
+    Stream(StreamError),
+}
+
+impl fmt::Display for AppendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FormatMismatch { expected, actual } => write!(
+                f,
+                "The samples are in format {}, but the stream plays {}.",
+                actual, expected
+            ),
+            Self::InexactFormat(format) => write!(
+                f,
+                "Samples of format {} can't be passed through the sink unchanged.",
+                format
+            ),
+            Self::Stream(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for AppendError {}
+
+impl From<StreamError> for AppendError {
+    fn from(err: StreamError) -> Self {
+        Self::Stream(err)
+    }
+}
+
+/// The byte order of the raw samples passed to [`SampleSink::append_raw`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    /// The byte order of the target, in which [`Data`] holds samples.
+    pub const NATIVE: Self = if cfg!(target_endian = "big") {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    };
+}
+
+// Whether every sample of `sample_format` survives the conversion to `f32` and back.
+fn is_exact_in_f32(sample_format: SampleFormat) -> bool {
+    matches!(
+        sample_format,
+        SampleFormat::I8
+            | SampleFormat::U8
+            | SampleFormat::I16
+            | SampleFormat::U16
+            | SampleFormat::I24
+            | SampleFormat::F32
+    )
+}
+
+// Convert raw samples, of which `bytes` holds a whole number, to `f32`.
+fn decode(bytes: &[u8], sample_format: SampleFormat, endianness: Endianness) -> Vec<f32> {
+    fn samples<const N: usize, T>(
+        bytes: &[u8],
+        endianness: Endianness,
+        from_le: fn([u8; N]) -> T,
+        from_be: fn([u8; N]) -> T,
+    ) -> Vec<f32>
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        let from_bytes = match endianness {
+            Endianness::Little => from_le,
+            Endianness::Big => from_be,
+        };
+        bytes
+            .chunks_exact(N)
+            .map(|chunk| f32::from_sample(from_bytes(chunk.try_into().unwrap())))
+            .collect()
+    }
+    let e = endianness;
+    match sample_format {
+        SampleFormat::I8 => samples(bytes, e, i8::from_le_bytes, i8::from_be_bytes),
+        SampleFormat::I16 => samples(bytes, e, i16::from_le_bytes, i16::from_be_bytes),
+        SampleFormat::I24 => samples(
+            bytes,
+            e,
+            |b| I24::new_unchecked(i32::from_le_bytes(b)),
+            |b| I24::new_unchecked(i32::from_be_bytes(b)),
+        ),
+        SampleFormat::I32 => samples(bytes, e, i32::from_le_bytes, i32::from_be_bytes),
+        SampleFormat::I64 => samples(bytes, e, i64::from_le_bytes, i64::from_be_bytes),
+        SampleFormat::U8 => samples(bytes, e, u8::from_le_bytes, u8::from_be_bytes),
+        SampleFormat::U16 => samples(bytes, e, u16::from_le_bytes, u16::from_be_bytes),
+        SampleFormat::U32 => samples(bytes, e, u32::from_le_bytes, u32::from_be_bytes),
+        SampleFormat::U64 => samples(bytes, e, u64::from_le_bytes, u64::from_be_bytes),
+        SampleFormat::F32 => samples(bytes, e, f32::from_le_bytes, f32::from_be_bytes),
+        SampleFormat::F64 => samples(bytes, e, f64::from_le_bytes, f64::from_be_bytes),
+    }
+}
+
+#[test]
+fn test_sample_sink() {
+    use crate::SampleFormat;
+    use std::task::Wake;
+
+    struct NoopWaker;
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+
+    let config = StreamConfig {
+        channels: 2,
+        sample_rate: crate::SampleRate(4),
+        buffer_size: crate::BufferSize::Default,
+    };
+    let shared = Shared::new(4, &config, SampleFormat::F32);
+    let mut sink = SampleSink::new(shared.clone());
+    let mut sink = Pin::new(&mut sink);
+    assert!(sink.as_mut().poll_ready(&mut cx).is_ready());
+    assert_eq!(sink.writable_len(), 4);
+    assert!(!sink.would_block(3));
+    assert!(sink.would_block(4));
+    sink.as_mut().start_send(vec![0.5; 6]).unwrap();
+    assert_eq!(sink.queued_frames(), 3);
+    assert_eq!(sink.buffered_duration(), Duration::from_millis(750));
+    assert_eq!(sink.buffered_fraction(), 1.0);
+    assert_eq!(sink.writable_len(), 0);
+    assert!(sink.would_block(0));
+    assert!(sink.as_mut().poll_ready(&mut cx).is_pending());
+    assert!(sink.as_mut().poll_flush(&mut cx).is_pending());
+
+    // The device takes four samples, converted to `i16`, which makes room for more.
+    let mut buffer = [0i16; 4];
+    let mut data =
+        unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), 4, SampleFormat::I16) };
+    shared.fill(&mut data);
+    assert_eq!(buffer, [16384; 4]);
+    assert!(sink.as_mut().poll_ready(&mut cx).is_ready());
+
+    // The rest is played, followed by silence.
+    shared.fill(&mut data);
+    assert_eq!(buffer, [16384, 16384, 0, 0]);
+    assert_eq!((sink.total_appended(), sink.total_consumed()), (3, 3));
+    assert_eq!(sink.total_silence(), 1);
+    assert!(sink.as_mut().poll_flush(&mut cx).is_ready());
+
+    // A partial frame waits for the rest of its samples.
+    sink.as_mut().start_send(vec![0.25; 3]).unwrap();
+    assert_eq!(sink.queued_frames(), 1);
+    sink.as_mut().start_send(vec![0.25]).unwrap();
+    assert_eq!(sink.queued_frames(), 2);
+    shared.fill(&mut data);
+    assert_eq!(buffer, [8192; 4]);
+
+    shared.report(StreamError::DeviceNotAvailable);
+    assert_eq!(
+        sink.as_mut().poll_ready(&mut cx),
+        Poll::Ready(Err(StreamError::DeviceNotAvailable))
+    );
+}
+
+#[test]
+fn test_sample_sink_loop() {
+    use crate::SampleFormat;
+
+    let config = StreamConfig {
+        channels: 1,
+        sample_rate: crate::SampleRate(4),
+        buffer_size: crate::BufferSize::Default,
+    };
+    let shared = Shared::new(8, &config, SampleFormat::F32);
+    let mut sink = SampleSink::new(shared.clone());
+    Pin::new(&mut sink).start_send(vec![0.5, 0.5]).unwrap();
+    let buffer: Arc<[f32]> = vec![0.0, 0.25, 0.5, -0.25, -0.5].into();
+    sink.set_loop(buffer, 1..4);
+
+    // Buffers of three samples, so that the seam falls in different places.
+    let mut played = Vec::new();
+    let mut buffer = [0.0f32; 3];
+    for _ in 0..4 {
+        let mut data =
+            unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), 3, SampleFormat::F32) };
+        shared.fill(&mut data);
+        played.extend_from_slice(&buffer);
+    }
+    assert_eq!(
+        played,
+        [0.5, 0.5, 0.25, 0.5, -0.25, 0.25, 0.5, -0.25, 0.25, 0.5, -0.25, 0.25]
+    );
+    assert_eq!(sink.total_consumed(), 2);
+    // The loop isn't silence.
+    assert_eq!(sink.total_silence(), 0);
+
+    // Sending samples ends the loop.
+    Pin::new(&mut sink).start_send(vec![1.0 / 8.0]).unwrap();
+    assert!(!sink.is_looping());
+    let mut data =
+        unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), 3, SampleFormat::F32) };
+    shared.fill(&mut data);
+    assert_eq!(buffer, [0.125, 0.0, 0.0]);
+}
+
+#[test]
+fn test_sample_sink_append_raw() {
+    let config = StreamConfig {
+        channels: 2,
+        sample_rate: crate::SampleRate(4),
+        buffer_size: crate::BufferSize::Default,
+    };
+    let sink = SampleSink::new(Shared::new(8, &config, SampleFormat::F32));
+    let bytes = [0x40, 0x00, 0xc0, 0x00, 0x20, 0x00, 0xe0, 0x00];
+    // One and a half frames, the rest of which arrives with the next call.
+    sink.append_raw(&bytes[..6], SampleFormat::I16, Endianness::Big)
+        .unwrap();
+    assert_eq!(sink.queued_frames(), 1);
+    sink.append_raw(&bytes[6..], SampleFormat::I16, Endianness::Big)
+        .unwrap();
+    assert_eq!(sink.total_appended(), 2);
+    let queue: Vec<f32> = sink
+        .shared
+        .state
+        .lock()
+        .unwrap()
+        .queue
+        .iter()
+        .copied()
+        .collect();
+    assert_eq!(queue, [0.5, -0.5, 0.25, -0.25]);
+
+    sink.append_raw(
+        &0.75f32.to_le_bytes(),
+        SampleFormat::F32,
+        Endianness::Little,
+    )
+    .unwrap();
+    sink.append_raw(
+        &(-0.75f32).to_le_bytes(),
+        SampleFormat::F32,
+        Endianness::Little,
+    )
+    .unwrap();
+    assert_eq!(sink.shared.state.lock().unwrap().queue.back(), Some(&-0.75));
+
+    // The stream plays `f32`, so strict mode rejects the `i16` bytes.
+    sink.set_strict_format(true).unwrap();
+    assert_eq!(
+        sink.append_raw(&bytes, SampleFormat::I16, Endianness::Big),
+        Err(AppendError::FormatMismatch {
+            expected: SampleFormat::F32,
+            actual: SampleFormat::I16,
+        })
+    );
+    assert_eq!(sink.total_appended(), 3);
+
+    // An `f32` queue can't hold every `i32` sample, so strict mode can't be promised.
+    let sink = SampleSink::new(Shared::new(8, &config, SampleFormat::I32));
+    assert_eq!(
+        sink.set_strict_format(true),
+        Err(AppendError::InexactFormat(SampleFormat::I32))
+    );
+    assert!(!sink.shared.state.lock().unwrap().strict_format);
+    assert_eq!(sink.set_strict_format(false), Ok(()));
+}
+
+#[test]
+fn test_sample_sink_enqueue() {
+    let config = StreamConfig {
+        channels: 1,
+        sample_rate: crate::SampleRate(4),
+        buffer_size: crate::BufferSize::Default,
+    };
+    let shared = Shared::new(2, &config, SampleFormat::F32);
+    let sink = SampleSink::new(shared.clone());
+    // Beyond the capacity, and played back to back across a device buffer.
+    sink.enqueue(vec![0.25; 3]).unwrap();
+    sink.enqueue(vec![0.5; 2]).unwrap();
+    assert_eq!(sink.queued_frames(), 5);
+    let mut buffer = [0.0f32; 4];
+    let mut data =
+        unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), 4, SampleFormat::F32) };
+    shared.fill(&mut data);
+    assert_eq!(buffer, [0.25, 0.25, 0.25, 0.5]);
+    shared.fill(&mut data);
+    assert_eq!(buffer, [0.5, 0.0, 0.0, 0.0]);
+
+    shared.report(StreamError::DeviceNotAvailable);
+    assert_eq!(
+        sink.enqueue(vec![0.5]),
+        Err(StreamError::DeviceNotAvailable)
+    );
+}
+
+#[test]
+fn test_sample_sink_errors() {
+    let config = StreamConfig {
+        channels: 1,
+        sample_rate: crate::SampleRate(4),
+        buffer_size: crate::BufferSize::Default,
+    };
+    let shared = Shared::new(2, &config, SampleFormat::F32);
+    let sink = SampleSink::new(shared.clone());
+    // The stream goes on after these, and so does the sink.
+    for err in [
+        StreamError::Interrupted { resumable: true },
+        StreamError::Resumed,
+        StreamError::BackendSpecific {
+            err: crate::BackendSpecificError {
+                description: "glitch".to_owned(),
+            },
+        },
+    ] {
+        shared.report(err);
+        assert_eq!(sink.enqueue(vec![0.5]), Ok(()));
+    }
+    for err in [
+        StreamError::DeviceNotAvailable,
+        StreamError::Interrupted { resumable: false },
+        StreamError::DeviceInvalidated,
+    ] {
+        shared.report(err.clone());
+        assert_eq!(sink.enqueue(vec![0.5]), Err(err));
+    }
+}
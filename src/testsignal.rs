@@ -0,0 +1,379 @@
+//! Deterministic test signals, and their analysis once played or captured.
+//!
+//! [`SignalGenerator`] writes a signal in which every channel has its own frequency, so that a
+//! swapped or missing channel shows up when [`SignalVerifier`] analyzes the audio that came out
+//! of the device, e.g. through a loopback capture.
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use crate::{ChannelCount, Data, SampleRate};
+
+// The frequency of the first channel and the step between channels, in Hz. The frequencies
+// aren't multiples of each other, so that no channel is a harmonic of another.
+const BASE_FREQUENCY: f32 = 300.0;
+const FREQUENCY_STEP: f32 = 200.0;
+
+// The default peak level of the signal, leaving headroom for conversions.
+const DEFAULT_AMPLITUDE: f32 = 0.5;
+
+// The peak level of a beep, and how long it fades in and out so that it doesn't click.
+const BEEP_AMPLITUDE: f32 = 0.25;
+const BEEP_FADE: Duration = Duration::from_millis(10);
+
+/// The shape of the signal written by a [`SignalGenerator`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    /// A sine wave.
+    Sine,
+    /// A sawtooth rising from minus to plus the amplitude once per period.
+    Ramp,
+    /// A single sample at the amplitude once per period, silence otherwise.
+    Impulses,
+}
+
+/// The frequency of `channel` in the signals of [`SignalGenerator`], in Hz.
+pub fn channel_frequency(channel: usize) -> f32 {
+    BASE_FREQUENCY + FREQUENCY_STEP * channel as f32
+}
+
+/// Writes a [`Signal`] with a distinct [`channel_frequency`] on every channel.
+///
+/// The signal continues across calls, so the buffers of a stream's data callback join up
+/// without discontinuities. Every sample is a function of the frame position only, so the
+/// signal is the same on every run.
+#[derive(Clone, Debug)]
+pub struct SignalGenerator {
+    signal: Signal,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    amplitude: f32,
+    // The interleaved sample the next call starts at.
+    position: u64,
+}
+
+impl SignalGenerator {
+    /// Create a generator for interleaved audio with `channels` channels at `sample_rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` or `sample_rate` is zero.
+    pub fn new(signal: Signal, channels: ChannelCount, sample_rate: SampleRate) -> Self {
+        assert!(channels > 0, "the signal needs at least one channel");
+        assert!(sample_rate.0 > 0, "the sample rate must not be zero");
+        SignalGenerator {
+            signal,
+            channels,
+            sample_rate,
+            amplitude: DEFAULT_AMPLITUDE,
+            position: 0,
+        }
+    }
+
+    /// The peak level of the signal. Defaults to `0.5`.
+    pub fn amplitude(&self) -> f32 {
+        self.amplitude
+    }
+
+    /// Change the peak level of the signal.
+    pub fn set_amplitude(&mut self, amplitude: f32) {
+        self.amplitude = amplitude;
+    }
+
+    /// The number of whole frames written so far.
+    pub fn position(&self) -> u64 {
+        self.position / self.channels as u64
+    }
+
+    /// Fill `data` with the next samples of the signal, converted to its sample format.
+    ///
+    /// A buffer may end within a frame, in which case the next call continues with the rest of
+    /// the frame.
+    pub fn fill(&mut self, data: &mut Data) {
+        data.fill_from_f32(|| self.next_sample());
+    }
+
+    /// Fill `output` with the next samples of the signal. See [`fill`](Self::fill).
+    pub fn fill_f32(&mut self, output: &mut [f32]) {
+        output.iter_mut().for_each(|s| *s = self.next_sample());
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let channels = self.channels as u64;
+        let sample = self.sample(
+            self.position / channels,
+            (self.position % channels) as usize,
+        );
+        self.position += 1;
+        sample
+    }
+
+    fn sample(&self, frame: u64, channel: usize) -> f32 {
+        let rate = self.sample_rate.0 as f64;
+        let period = rate / channel_frequency(channel) as f64;
+        // The position within the period, in `[0, 1)`, computed in `f64` so that it stays exact
+        // over long runs.
+        let phase = (frame as f64 / period).fract() as f32;
+        let value = match self.signal {
+            Signal::Sine => (2.0 * PI * phase).sin(),
+            Signal::Ramp => 2.0 * phase - 1.0,
+            Signal::Impulses => {
+                // The first frame of each period.
+                let previous = ((frame as f64 - 1.0) / period).floor();
+                if frame == 0 || (frame as f64 / period).floor() > previous {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+        self.amplitude * value
+    }
+}
+
+/// The analysis of one channel by [`SignalVerifier::report`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelReport {
+    /// The estimated frequency in Hz, or `None` if the channel doesn't complete two periods.
+    pub frequency: Option<f32>,
+    /// The highest absolute sample value.
+    pub peak: f32,
+    /// The root mean square of the samples.
+    pub rms: f32,
+    /// The number of places at which the signal breaks off, e.g. because samples were dropped
+    /// or repeated.
+    pub discontinuities: usize,
+}
+
+impl ChannelReport {
+    /// Whether the estimated frequency is within `tolerance` Hz of `frequency`.
+    pub fn has_frequency(&self, frequency: f32, tolerance: f32) -> bool {
+        self.frequency
+            .is_some_and(|estimate| (estimate - frequency).abs() <= tolerance)
+    }
+}
+
+/// Analyzes audio that was produced by a [`SignalGenerator`] with the same [`Signal`] and
+/// channel count, after it went through a device.
+///
+/// Feed it the played or captured audio with [`push`](Self::push) and check each channel's
+/// [`ChannelReport`] against the [`channel_frequency`] it should carry. Leading and trailing
+/// silence, such as the latency of a loopback, is skipped.
+#[derive(Clone, Debug)]
+pub struct SignalVerifier {
+    signal: Signal,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    // The samples pushed so far, per channel.
+    samples: Vec<Vec<f32>>,
+    // The channel of the next sample pushed.
+    next_channel: usize,
+}
+
+impl SignalVerifier {
+    /// Create a verifier for interleaved audio with `channels` channels at `sample_rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` or `sample_rate` is zero.
+    pub fn new(signal: Signal, channels: ChannelCount, sample_rate: SampleRate) -> Self {
+        assert!(channels > 0, "the signal needs at least one channel");
+        assert!(sample_rate.0 > 0, "the sample rate must not be zero");
+        SignalVerifier {
+            signal,
+            channels,
+            sample_rate,
+            samples: vec![Vec::new(); channels as usize],
+            next_channel: 0,
+        }
+    }
+
+    /// Add the samples of `data`, converted to `f32`.
+    pub fn push(&mut self, data: &Data) {
+        data.for_each_f32(|sample| self.push_sample(sample));
+    }
+
+    /// Add interleaved `f32` samples.
+    pub fn push_f32(&mut self, samples: &[f32]) {
+        samples.iter().for_each(|&sample| self.push_sample(sample));
+    }
+
+    fn push_sample(&mut self, sample: f32) {
+        self.samples[self.next_channel].push(sample);
+        self.next_channel = (self.next_channel + 1) % self.channels as usize;
+    }
+
+    /// The analysis of every channel of the audio pushed so far.
+    pub fn report(&self) -> Vec<ChannelReport> {
+        self.samples
+            .iter()
+            .enumerate()
+            .map(|(channel, samples)| self.analyze(channel, trim_silence(samples)))
+            .collect()
+    }
+
+    fn analyze(&self, channel: usize, samples: &[f32]) -> ChannelReport {
+        let rate = self.sample_rate.0 as f32;
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let rms = if samples.is_empty() {
+            0.0
+        } else {
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+
+        // Rising crossings of the middle between the extremes, interpolated between samples.
+        let min = samples.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = samples.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let middle = (min + max) / 2.0;
+        let crossings: Vec<f32> = samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < middle && w[1] >= middle)
+            .map(|(i, w)| i as f32 + (middle - w[0]) / (w[1] - w[0]))
+            .collect();
+        let frequency = match (crossings.first(), crossings.last()) {
+            (Some(first), Some(last)) if crossings.len() > 2 => {
+                Some((crossings.len() - 1) as f32 * rate / (last - first))
+            }
+            _ => None,
+        };
+
+        let expected = channel_frequency(channel);
+        let discontinuities = match self.signal {
+            // No step between samples can be steeper than the sine itself, with some margin.
+            Signal::Sine => {
+                let limit = 1.5 * peak * (2.0 * PI * expected / rate).min(2.0) + 1e-3;
+                samples
+                    .windows(2)
+                    .filter(|w| (w[1] - w[0]).abs() > limit)
+                    .count()
+            }
+            // Every step is the slope of the ramp, except for the fall once per period.
+            Signal::Ramp => {
+                let slope = 2.0 * peak * expected / rate;
+                samples
+                    .windows(2)
+                    .filter(|w| {
+                        let step = w[1] - w[0];
+                        step > -peak && (step - slope).abs() > slope / 2.0 + 1e-3
+                    })
+                    .count()
+            }
+            // Impulses are a period apart, give or take the rounding to whole frames.
+            Signal::Impulses => {
+                let period = rate / expected;
+                crossings
+                    .windows(2)
+                    .filter(|c| (c[1] - c[0] - period).abs() > 1.5)
+                    .count()
+            }
+        };
+
+        ChannelReport {
+            frequency,
+            peak,
+            rms,
+            discontinuities,
+        }
+    }
+}
+
+// `samples` without the silence at either end.
+fn trim_silence(samples: &[f32]) -> &[f32] {
+    const SILENCE: f32 = 1e-4;
+    let start = samples.iter().position(|s| s.abs() > SILENCE);
+    let end = samples.iter().rposition(|s| s.abs() > SILENCE);
+    match (start, end) {
+        (Some(start), Some(end)) => &samples[start..=end],
+        _ => &[],
+    }
+}
+
+// A sine beep of `frequency` Hz lasting `duration`, as interleaved samples with the same signal
+// on every channel, fading in and out.
+pub(crate) fn beep(
+    frequency: f32,
+    duration: Duration,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+) -> Vec<f32> {
+    let rate = sample_rate.0 as f64;
+    let frames = (duration.as_secs_f64() * rate).round() as usize;
+    let fade = ((BEEP_FADE.as_secs_f64() * rate) as usize).clamp(1, frames.max(2) / 2);
+    (0..frames)
+        .flat_map(|frame| {
+            let phase = (frame as f64 * frequency as f64 / rate).fract() as f32;
+            let envelope = (frame.min(frames - 1 - frame) as f32 / fade as f32).min(1.0);
+            let sample = BEEP_AMPLITUDE * envelope * (2.0 * PI * phase).sin();
+            std::iter::repeat(sample).take(channels as usize)
+        })
+        .collect()
+}
+
+#[test]
+fn test_signal_round_trip() {
+    use crate::SampleFormat;
+
+    for signal in [Signal::Sine, Signal::Ramp, Signal::Impulses] {
+        let mut generator = SignalGenerator::new(signal, 2, SampleRate(48_000));
+        let mut verifier = SignalVerifier::new(signal, 2, SampleRate(48_000));
+        // Through `i16` and in buffers that split frames, which the signal continues across.
+        let mut buffer = [0i16; 301];
+        for _ in 0..320 {
+            let mut data =
+                unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), 301, SampleFormat::I16) };
+            generator.fill(&mut data);
+            verifier.push(&data);
+        }
+        let report = verifier.report();
+        for (channel, report) in report.iter().enumerate() {
+            assert!(
+                report.has_frequency(channel_frequency(channel), 1.0),
+                "{:?} {}: {:?}",
+                signal,
+                channel,
+                report
+            );
+            assert!((report.peak - 0.5).abs() < 1e-3, "{:?}", report);
+            assert_eq!(report.discontinuities, 0, "{:?} {}", signal, channel);
+        }
+    }
+}
+
+#[test]
+fn test_signal_discontinuity() {
+    let mut generator = SignalGenerator::new(Signal::Sine, 1, SampleRate(48_000));
+    let mut verifier = SignalVerifier::new(Signal::Sine, 1, SampleRate(48_000));
+    let mut buffer = [0.0; 480];
+    generator.fill_f32(&mut buffer);
+    verifier.push_f32(&buffer);
+    // Drop a few frames.
+    generator.fill_f32(&mut buffer[..25]);
+    generator.fill_f32(&mut buffer);
+    verifier.push_f32(&buffer);
+    let report = verifier.report()[0];
+    assert_eq!(report.discontinuities, 1);
+    assert!((report.rms - 0.5 * std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-2);
+}
+
+#[test]
+fn test_beep() {
+    let samples = beep(
+        channel_frequency(0),
+        Duration::from_millis(100),
+        2,
+        SampleRate(48_000),
+    );
+    assert_eq!(samples.len(), 2 * 4_800);
+    assert_eq!(samples[..2], [0.0, 0.0]);
+    assert_eq!(samples[samples.len() - 2..], [0.0, 0.0]);
+    let mut verifier = SignalVerifier::new(Signal::Sine, 1, SampleRate(48_000));
+    verifier.push_f32(&samples.iter().step_by(2).copied().collect::<Vec<_>>());
+    let report = verifier.report()[0];
+    assert!(
+        report.has_frequency(channel_frequency(0), 1.0),
+        "{:?}",
+        report
+    );
+    assert!((report.peak - BEEP_AMPLITUDE).abs() < 1e-3, "{:?}", report);
+}
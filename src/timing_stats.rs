@@ -0,0 +1,145 @@
+//! Statistics on when a stream's data callback is called, for diagnosing glitches.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::StreamInstant;
+
+// The default playback lead below which an output buffer counts as a near underrun.
+const DEFAULT_NEAR_UNDERRUN_THRESHOLD: Duration = Duration::from_millis(2);
+
+/// Statistics on the calls of a stream's data callback, see
+/// [`Stream::timing_stats`](crate::platform::Stream::timing_stats).
+///
+/// Gaps are the time between the callback timestamps of consecutive buffers. The gap across a
+/// pause isn't counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimingStats {
+    /// The number of buffers handed to the data callback.
+    pub buffers: u64,
+    /// The shortest gap, or `None` until there has been one.
+    pub min_gap: Option<Duration>,
+    /// The longest gap, or `None` until there has been one.
+    pub max_gap: Option<Duration>,
+    /// The average gap, or `None` until there has been one.
+    pub avg_gap: Option<Duration>,
+    /// The number of output buffers that were handed to the data callback less than the near
+    /// underrun threshold before they were due to be played. Always zero for input streams.
+    pub near_underruns: u64,
+}
+
+// Accumulates the `TimingStats` of a stream on the audio thread, readable from any thread.
+pub(crate) struct TimingMeter {
+    buffers: AtomicU64,
+    gaps: AtomicU64,
+    // In nanoseconds.
+    min_gap: AtomicU64,
+    max_gap: AtomicU64,
+    total_gap: AtomicU64,
+    near_underruns: AtomicU64,
+    near_underrun_threshold: AtomicU64,
+    // Set when the stream is paused, so that the next buffer doesn't count a gap.
+    restart: AtomicBool,
+}
+
+impl TimingMeter {
+    pub(crate) fn new() -> Self {
+        TimingMeter {
+            buffers: AtomicU64::new(0),
+            gaps: AtomicU64::new(0),
+            min_gap: AtomicU64::new(u64::MAX),
+            max_gap: AtomicU64::new(0),
+            total_gap: AtomicU64::new(0),
+            near_underruns: AtomicU64::new(0),
+            near_underrun_threshold: AtomicU64::new(
+                DEFAULT_NEAR_UNDERRUN_THRESHOLD.as_nanos() as u64
+            ),
+            restart: AtomicBool::new(false),
+        }
+    }
+
+    // Account for a buffer handed to the data callback at `callback`, `last` holding the
+    // timestamp of the previous one. `lead` is how long before it plays an output buffer was
+    // handed to the callback.
+    pub(crate) fn update(
+        &self,
+        last: &mut Option<StreamInstant>,
+        callback: StreamInstant,
+        lead: Option<Duration>,
+    ) {
+        self.buffers.fetch_add(1, Ordering::Relaxed);
+        let previous = last.replace(callback);
+        if self.restart.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(gap) = previous.and_then(|previous| callback.duration_since(&previous)) {
+            let gap = gap.as_nanos().min(u64::MAX as u128) as u64;
+            self.gaps.fetch_add(1, Ordering::Relaxed);
+            self.min_gap.fetch_min(gap, Ordering::Relaxed);
+            self.max_gap.fetch_max(gap, Ordering::Relaxed);
+            self.total_gap.fetch_add(gap, Ordering::Relaxed);
+        }
+        let threshold = self.near_underrun_threshold.load(Ordering::Relaxed);
+        if lead.is_some_and(|lead| lead.as_nanos() < threshold as u128) {
+            self.near_underruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn restart(&self) {
+        self.restart.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_near_underrun_threshold(&self, threshold: Duration) {
+        let threshold = threshold.as_nanos().min(u64::MAX as u128) as u64;
+        self.near_underrun_threshold
+            .store(threshold, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> TimingStats {
+        let gaps = self.gaps.load(Ordering::Relaxed);
+        let gap = |nanos: u64| (gaps > 0).then(|| Duration::from_nanos(nanos));
+        TimingStats {
+            buffers: self.buffers.load(Ordering::Relaxed),
+            min_gap: gap(self.min_gap.load(Ordering::Relaxed)),
+            max_gap: gap(self.max_gap.load(Ordering::Relaxed)),
+            avg_gap: gap(self.total_gap.load(Ordering::Relaxed) / gaps.max(1)),
+            near_underruns: self.near_underruns.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.buffers.store(0, Ordering::Relaxed);
+        self.gaps.store(0, Ordering::Relaxed);
+        self.min_gap.store(u64::MAX, Ordering::Relaxed);
+        self.max_gap.store(0, Ordering::Relaxed);
+        self.total_gap.store(0, Ordering::Relaxed);
+        self.near_underruns.store(0, Ordering::Relaxed);
+        self.restart();
+    }
+}
+
+#[test]
+fn test_timing_meter() {
+    let meter = TimingMeter::new();
+    assert_eq!(meter.stats(), TimingStats::default());
+
+    let at = |millis: u32| StreamInstant::new(0, millis * 1_000_000);
+    let mut last = None;
+    meter.update(&mut last, at(0), Some(Duration::from_millis(10)));
+    meter.update(&mut last, at(10), Some(Duration::from_millis(1)));
+    meter.update(&mut last, at(30), Some(Duration::from_millis(10)));
+    let stats = meter.stats();
+    assert_eq!(stats.buffers, 3);
+    assert_eq!(stats.min_gap, Some(Duration::from_millis(10)));
+    assert_eq!(stats.max_gap, Some(Duration::from_millis(20)));
+    assert_eq!(stats.avg_gap, Some(Duration::from_millis(15)));
+    assert_eq!(stats.near_underruns, 1);
+
+    // The gap across a pause isn't counted.
+    meter.restart();
+    meter.update(&mut last, at(1000), None);
+    assert_eq!(meter.stats().max_gap, Some(Duration::from_millis(20)));
+
+    meter.reset();
+    assert_eq!(meter.stats(), TimingStats::default());
+}
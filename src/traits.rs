@@ -1,11 +1,21 @@
 //! The suite of traits allowing CPAL to abstract over hosts, devices, event loops and stream IDs.
 
+use std::sync::mpsc;
+use std::thread;
 use std::time::Duration;
 
+use crate::dsp::{self, Downmix, ResampleQuality, Resampler};
+use crate::ring_buffer::{interleaved_ring_buffer, RingConsumer};
+#[cfg(feature = "futures")]
+use crate::sink::{self, SampleSink};
+use crate::testsignal;
 use crate::{
-    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
-    InputCallbackInfo, InputDevices, OutputCallbackInfo, OutputDevices, PauseStreamError,
-    PlayStreamError, SampleFormat, SizedSample, StreamConfig, StreamError, SupportedStreamConfig,
+    BackendSpecificError, BuildStreamError, Capabilities, CloseStreamError, Data,
+    DefaultDeviceError, DefaultStreamConfigError, DeviceDirection, DeviceNameError, DevicesError,
+    DevicesWithDirection, DrainError, FrameCount, InputCallbackInfo, InputDevices, OsProcessing,
+    OutputCallbackInfo, OutputDevices, OutputStreamTimestamp, PauseStreamError, PlayBufferError,
+    PlayStreamError, PowerPreference, RefreshDeviceError, SampleFormat, SampleRate, SizedSample,
+    StreamConfig, StreamConfigRequirements, StreamError, SupportedStreamConfig,
     SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 
@@ -29,6 +39,10 @@ use crate::{
 /// less readily available by default in many Linux distributions and is known to be tricky to
 /// set up.
 ///
+/// Devices and streams don't borrow from their host. Each keeps alive whatever it needs from the
+/// host, so a host can be dropped at any time without affecting the devices and streams created
+/// through it, and those can be dropped in any order.
+///
 /// [`Host`]: crate::Host
 pub trait HostTrait {
     /// The type used for enumerating available devices by the host.
@@ -54,6 +68,22 @@ pub trait HostTrait {
     /// Returns `None` if no output device is available.
     fn default_output_device(&self) -> Option<Self::Device>;
 
+    /// The default input audio device on the system, distinguishing a system without an input
+    /// device (`Ok(None)`) from a failure to look it up.
+    ///
+    /// [`default_input_device`](Self::default_input_device) returns `None` in both cases.
+    fn try_default_input_device(&self) -> Result<Option<Self::Device>, DefaultDeviceError> {
+        Ok(self.default_input_device())
+    }
+
+    /// The default output audio device on the system, distinguishing a system without an output
+    /// device (`Ok(None)`) from a failure to look it up.
+    ///
+    /// [`default_output_device`](Self::default_output_device) returns `None` in both cases.
+    fn try_default_output_device(&self) -> Result<Option<Self::Device>, DefaultDeviceError> {
+        Ok(self.default_output_device())
+    }
+
     /// An iterator yielding all `Device`s currently available to the system that support one or more
     /// input stream formats.
     ///
@@ -69,6 +99,25 @@ pub trait HostTrait {
     fn output_devices(&self) -> Result<OutputDevices<Self::Devices>, DevicesError> {
         Ok(self.devices()?.filter(DeviceTrait::supports_output))
     }
+
+    /// An iterator yielding every `Device` that supports input, output or both, tagged with its
+    /// directions, from a single enumeration.
+    ///
+    /// This suits a unified device list better than calling both
+    /// [`input_devices`](Self::input_devices) and [`output_devices`](Self::output_devices), which
+    /// enumerate the devices twice. Devices supporting neither direction are skipped.
+    fn devices_with_direction(&self) -> Result<DevicesWithDirection<Self::Devices>, DevicesError> {
+        fn with_direction<D: DeviceTrait>(device: D) -> Option<(D, DeviceDirection)> {
+            let direction = match (device.supports_input(), device.supports_output()) {
+                (true, true) => DeviceDirection::Duplex,
+                (true, false) => DeviceDirection::Input,
+                (false, true) => DeviceDirection::Output,
+                (false, false) => return None,
+            };
+            Some((device, direction))
+        }
+        Ok(self.devices()?.filter_map(with_direction))
+    }
 }
 
 /// A device that is capable of audio input and/or output.
@@ -103,9 +152,29 @@ pub trait DeviceTrait {
             .unwrap_or(false)
     }
 
+    /// An identifier of the piece of hardware the device belongs to, shared by all devices of
+    /// that hardware, e.g. to group the many devices of a multi-channel interface in a UI.
+    ///
+    /// On ALSA this is the index or name of the sound card, and on WASAPI the device instance
+    /// path of the audio adapter. The identifier is only meant to be compared with those of other
+    /// devices of the same host. Returns `None` for devices that don't belong to a single piece
+    /// of hardware, such as ALSA's `default`, and on hosts that don't tell.
+    fn adapter_id(&self) -> Option<String> {
+        None
+    }
+
     /// An iterator yielding formats that are supported by the backend.
     ///
     /// Can return an error if the device is no longer valid (e.g. it has been disconnected).
+    /// A host may probe the device lazily while the iterator is advanced, in which case a device
+    /// that goes away during iteration ends it early. The iterators of the platform's
+    /// [`Device`](crate::Device) are collected when this is called, so iterating them never
+    /// touches the device; see
+    /// [`Device::supported_input_configs_lazy`](crate::Device::supported_input_configs_lazy) for
+    /// probing only as far as needed.
+    ///
+    /// The configurations of a [`Device`](crate::Device) are yielded in ascending order, see
+    /// [`SupportedStreamConfigRange`].
     fn supported_input_configs(
         &self,
     ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError>;
@@ -113,17 +182,219 @@ pub trait DeviceTrait {
     /// An iterator yielding output stream formats that are supported by the device.
     ///
     /// Can return an error if the device is no longer valid (e.g. it has been disconnected).
+    /// A host may probe the device lazily while the iterator is advanced, in which case a device
+    /// that goes away during iteration ends it early. The iterators of the platform's
+    /// [`Device`](crate::Device) are collected when this is called, so iterating them never
+    /// touches the device; see
+    /// [`Device::supported_output_configs_lazy`](crate::Device::supported_output_configs_lazy) for
+    /// probing only as far as needed.
+    ///
+    /// The configurations of a [`Device`](crate::Device) are yielded in ascending order, see
+    /// [`SupportedStreamConfigRange`].
     fn supported_output_configs(
         &self,
     ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError>;
 
+    /// The supported input configurations that meet `requirements`, narrowed down to the parts
+    /// that meet them.
+    ///
+    /// The configurations are sorted by preference according to
+    /// [`SupportedStreamConfigRange::cmp_default_heuristics`], best first.
+    fn supported_input_configs_matching(
+        &self,
+        requirements: &StreamConfigRequirements,
+    ) -> Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError> {
+        let mut configs: Vec<_> = self
+            .supported_input_configs()?
+            .filter_map(|range| requirements.restrict(&range))
+            .collect();
+        configs.sort_by(|a, b| b.cmp_default_heuristics(a));
+        Ok(configs)
+    }
+
+    /// The supported output configurations that meet `requirements`, narrowed down to the parts
+    /// that meet them.
+    ///
+    /// The configurations are sorted by preference according to
+    /// [`SupportedStreamConfigRange::cmp_default_heuristics`], best first.
+    fn supported_output_configs_matching(
+        &self,
+        requirements: &StreamConfigRequirements,
+    ) -> Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError> {
+        let mut configs: Vec<_> = self
+            .supported_output_configs()?
+            .filter_map(|range| requirements.restrict(&range))
+            .collect();
+        configs.sort_by(|a, b| b.cmp_default_heuristics(a));
+        Ok(configs)
+    }
+
+    /// The distinct sample rates of the supported input configurations, in ascending order.
+    ///
+    /// Configurations that support a continuous range of rates contribute both ends of the range
+    /// and the common rates (44.1 kHz, 48 kHz, ...) within it.
+    fn supported_input_sample_rates(&self) -> Result<Vec<SampleRate>, SupportedStreamConfigsError> {
+        Ok(crate::distinct_sample_rates(
+            self.supported_input_configs()?,
+        ))
+    }
+
+    /// The distinct sample rates of the supported output configurations, in ascending order. See
+    /// [`supported_input_sample_rates`](Self::supported_input_sample_rates).
+    fn supported_output_sample_rates(
+        &self,
+    ) -> Result<Vec<SampleRate>, SupportedStreamConfigsError> {
+        Ok(crate::distinct_sample_rates(
+            self.supported_output_configs()?,
+        ))
+    }
+
+    /// The supported input configuration closest to `config` in `sample_format`, or `None` if the
+    /// device has no input configurations. See [`negotiate_config`](crate::negotiate_config) for
+    /// how the fallback is chosen.
+    fn negotiate_input_config(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<Option<SupportedStreamConfig>, SupportedStreamConfigsError> {
+        let supported: Vec<_> = self.supported_input_configs()?.collect();
+        Ok(crate::negotiate_config(config, sample_format, &supported))
+    }
+
+    /// The supported output configuration closest to `config` in `sample_format`, or `None` if
+    /// the device has no output configurations. See [`negotiate_config`](crate::negotiate_config)
+    /// for how the fallback is chosen.
+    fn negotiate_output_config(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<Option<SupportedStreamConfig>, SupportedStreamConfigsError> {
+        let supported: Vec<_> = self.supported_output_configs()?.collect();
+        Ok(crate::negotiate_config(config, sample_format, &supported))
+    }
+
     /// The default input stream format for the device.
     fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError>;
 
     /// The default output stream format for the device.
     fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError>;
 
+    /// The input configuration the device is currently set up with, if the host can report it
+    /// without enumerating the supported configurations.
+    ///
+    /// Where [`default_input_config`](Self::default_input_config) is a suggestion, this is the
+    /// format the device itself runs at, e.g. the shared-mode mix format on WASAPI. Streams
+    /// opened with a different configuration are converted by the host or the OS. Returns `None`
+    /// if the host can't tell.
+    fn current_input_config(&self) -> Option<SupportedStreamConfig> {
+        None
+    }
+
+    /// The output configuration the device is currently set up with. See
+    /// [`current_input_config`](Self::current_input_config).
+    fn current_output_config(&self) -> Option<SupportedStreamConfig> {
+        None
+    }
+
+    /// A description of the driver behind the device for logs and bug reports, e.g. the sound
+    /// card and its driver on ALSA, the audio adapter on WASAPI or the manufacturer on CoreAudio.
+    /// Together with [`name`](Self::name) and [`HostId::name`](crate::HostId::name) this tells
+    /// which audio path is in use.
+    ///
+    /// This doesn't open a stream or wait on the device. The text is meant for humans and may
+    /// change between releases. Returns `None` if the host has nothing to add.
+    fn backend_info(&self) -> Option<String> {
+        None
+    }
+
+    /// Where the hardware behind the device is attached, e.g. the USB port or PCI slot, for
+    /// telling apart identical devices and recognizing a device across reboots.
+    ///
+    /// Unlike the device name and the host's indices, the location stays the same as long as the
+    /// device stays in the same port.
+    ///
+    /// - alsa: the sound card's link in `/dev/snd/by-path`, e.g.
+    ///   `pci-0000:00:14.0-usb-0:2:1.0`, or its path in sysfs where udev doesn't create the links.
+    ///   It changes when the device is moved to another port.
+    /// - wasapi: the container ID Windows groups the functions of a physical device under, e.g.
+    ///   `{8C7ED206-3F8A-4827-B3AB-AE9E1FAEFC6C}`. Windows derives it from the port for devices
+    ///   without a serial number, and from the serial number otherwise, in which case it stays the
+    ///   same when the device is moved. All devices built into the computer share one container.
+    ///
+    /// Returns `None` for devices that don't correspond to hardware, such as ALSA's `default`,
+    /// and on other hosts.
+    fn physical_location(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the device has a volume control of its own, so that a volume slider can be shown
+    /// only where there is something for it to change.
+    ///
+    /// - alsa: whether the device's sound card has a mixer element with a playback volume, i.e.
+    ///   whether the ALSA `Device::mixer_elements` lists any.
+    /// - wasapi: whether the endpoint's volume is implemented in hardware rather than emulated by
+    ///   the system.
+    ///
+    /// Many digital outputs, such as HDMI, have no volume control. Returns `false` on other
+    /// hosts.
+    fn has_hardware_volume(&self) -> bool {
+        false
+    }
+
+    /// Check that the device is still available and drop anything cached about it, so that its
+    /// name and supported configurations are looked up afresh, e.g. after a driver update.
+    ///
+    /// Devices held on to for a long time can be refreshed instead of being looked up again by
+    /// enumerating the host. Streams already built on the device aren't affected.
+    ///
+    /// - alsa: reopens the device and looks up the name of its sound card again.
+    ///
+    /// Other hosts don't cache anything and only check that the device's configurations can still
+    /// be queried.
+    fn refresh(&mut self) -> Result<(), RefreshDeviceError> {
+        let input = self.supported_input_configs().map(drop);
+        let output = self.supported_output_configs().map(drop);
+        match input.or(output) {
+            Ok(()) => Ok(()),
+            Err(SupportedStreamConfigsError::DeviceNotAvailable) => {
+                Err(RefreshDeviceError::DeviceNotAvailable)
+            }
+            Err(err) => Err(BackendSpecificError {
+                description: err.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// A summary of what the device supports, for choosing a device or reporting on it.
+    ///
+    /// The channel counts, sample rates, sample formats and buffer sizes are gathered from the
+    /// supported configurations of both directions. Anything the host can't determine is left
+    /// `None` or [`Capability::Unknown`](crate::Capability::Unknown).
+    ///
+    /// Fails if the device is no longer available.
+    fn capabilities(&self) -> Result<Capabilities, SupportedStreamConfigsError> {
+        // A direction the device doesn't support may fail instead of yielding no configurations.
+        fn collect<I: Iterator<Item = SupportedStreamConfigRange>>(
+            configs: Result<I, SupportedStreamConfigsError>,
+        ) -> Result<Vec<SupportedStreamConfigRange>, SupportedStreamConfigsError> {
+            match configs {
+                Ok(configs) => Ok(configs.collect()),
+                Err(SupportedStreamConfigsError::DeviceNotAvailable) => {
+                    Err(SupportedStreamConfigsError::DeviceNotAvailable)
+                }
+                Err(_) => Ok(Vec::new()),
+            }
+        }
+        let input = collect(self.supported_input_configs())?;
+        let output = collect(self.supported_output_configs())?;
+        Ok(Capabilities::from_configs(&input, &output))
+    }
+
     /// Create an input stream.
+    ///
+    /// Fails with [`BuildStreamError::StreamConfigNotSupported`] if the configuration has zero
+    /// channels or a zero sample rate (see [`StreamConfig::is_valid`]).
     fn build_input_stream<T, D, E>(
         &self,
         config: &StreamConfig,
@@ -136,6 +407,9 @@ pub trait DeviceTrait {
         D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        if !config.is_valid() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
         self.build_input_stream_raw(
             config,
             T::FORMAT,
@@ -152,6 +426,28 @@ pub trait DeviceTrait {
     }
 
     /// Create an output stream.
+    ///
+    /// The configuration of a stream is fixed once it has been built. To switch to a different
+    /// configuration (e.g. when moving from a 44.1 kHz track to a 48 kHz one), build a new stream
+    /// and start it before dropping the old one. Whether the switch is gapless depends on the host:
+    /// WASAPI (shared mode), CoreAudio, JACK and ALSA devices routed through a sound server (such
+    /// as `default` with PulseAudio or PipeWire) allow both streams to exist at once. ALSA hardware
+    /// devices can only be opened once, so the old stream must be dropped first and building the
+    /// new one returns [`BuildStreamError::DeviceNotAvailable`] until it has been.
+    ///
+    /// Building a stream opens the device and, on most hosts, starts a thread, which typically
+    /// takes a few milliseconds. Dropping the stream releases everything it holds, so streams can
+    /// be built and dropped any number of times, but for many short sounds (such as the effects
+    /// of a game) mixing them into a single long-lived stream is much cheaper.
+    ///
+    /// If the device only plays mono at the configuration's sample rate, e.g. a Bluetooth
+    /// hands-free headset, the stream is opened with a single channel and the frames the callback
+    /// writes are mixed down with [`Downmix::for_channels`], so that audio panned to one side isn't
+    /// lost. Use [`build_output_stream_downmixed`](Self::build_output_stream_downmixed) to choose
+    /// the weights.
+    ///
+    /// Fails with [`BuildStreamError::StreamConfigNotSupported`] if the configuration has zero
+    /// channels or a zero sample rate (see [`StreamConfig::is_valid`]).
     fn build_output_stream<T, D, E>(
         &self,
         config: &StreamConfig,
@@ -164,15 +460,354 @@ pub trait DeviceTrait {
         D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        if !config.is_valid() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let data_callback = move |data: &mut Data, info: &OutputCallbackInfo| {
+            data_callback(
+                data.as_slice_mut()
+                    .expect("host supplied incorrect sample type"),
+                info,
+            )
+        };
+        let mono = self
+            .supported_output_configs()
+            .is_ok_and(|configs| dsp::needs_downmix(configs, config));
+        if mono {
+            self.build_output_stream_downmixed(
+                config,
+                T::FORMAT,
+                Downmix::for_channels(config.channels),
+                data_callback,
+                error_callback,
+                timeout,
+            )
+        } else {
+            self.build_output_stream_raw(config, T::FORMAT, data_callback, error_callback, timeout)
+        }
+    }
+
+    /// Create an output stream on a mono device whose data callback writes frames with
+    /// `config.channels` channels, mixed down to one with the weights of `downmix`.
+    ///
+    /// The device is opened with `config` but a single channel.
+    /// [`build_output_stream`](Self::build_output_stream) does this on its own with
+    /// [`Downmix::for_channels`] when the device only plays mono; build the stream with this
+    /// instead to choose the weights, e.g. to keep only the left channel.
+    ///
+    /// Fails with [`BuildStreamError::StreamConfigNotSupported`] if the configuration is invalid
+    /// (see [`StreamConfig::is_valid`]) or its channel count differs from `downmix`'s.
+    fn build_output_stream_downmixed<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        downmix: Downmix,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !config.is_valid() || config.channels != downmix.channels() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let device_config = StreamConfig {
+            channels: 1,
+            ..config.clone()
+        };
         self.build_output_stream_raw(
+            &device_config,
+            sample_format,
+            dsp::downmixing_callback(sample_format, downmix, data_callback),
+            error_callback,
+            timeout,
+        )
+    }
+
+    /// Create an input stream that pushes the captured samples, converted to `f32`, into a ring
+    /// buffer holding up to `capacity` samples.
+    ///
+    /// The returned [`RingConsumer`] can be drained from any thread. If it isn't drained quickly
+    /// enough, samples are dropped according to its
+    /// [`OverflowPolicy`](crate::ring_buffer::OverflowPolicy) and counted in
+    /// [`RingConsumer::overrun_frames`]. Samples lost by the device are marked as a gap in
+    /// [`RingConsumer::discontinuities`] as well.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    fn build_input_stream_into_ring_buffer<E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        capacity: usize,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<(Self::Stream, RingConsumer), BuildStreamError>
+    where
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (mut producer, consumer) = interleaved_ring_buffer(capacity, config.channels);
+        let stream = self.build_input_stream_raw(
             config,
-            T::FORMAT,
+            sample_format,
+            move |data, info: &InputCallbackInfo| {
+                if info.discontinuity() {
+                    producer.mark_discontinuity();
+                }
+                data.for_each_f32(|sample| producer.push(sample))
+            },
+            error_callback,
+            timeout,
+        )?;
+        Ok((stream, consumer))
+    }
+
+    /// Create an output stream that plays the samples sent to the returned [`SampleSink`].
+    ///
+    /// The sink accepts `Vec<f32>`s of interleaved samples with `config.channels` channels and
+    /// converts them to `sample_format`. It queues up to `capacity` samples before making the
+    /// sender wait for the stream to catch up, which bounds both the memory used and the latency
+    /// added by the sink; a few buffers' worth of samples is usually enough. Errors from the
+    /// stream are returned by the sink.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    #[cfg(feature = "futures")]
+    fn build_output_sink(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        capacity: usize,
+        timeout: Option<Duration>,
+    ) -> Result<(Self::Stream, SampleSink), BuildStreamError> {
+        assert!(capacity > 0, "sink capacity must be non-zero");
+        let shared = sink::Shared::new(capacity, config, sample_format);
+        let stream = {
+            let (fill, report) = (shared.clone(), shared.clone());
+            self.build_output_stream_raw(
+                config,
+                sample_format,
+                move |data, _: &_| fill.fill(data),
+                move |err| report.report(err),
+                timeout,
+            )?
+        };
+        Ok((stream, SampleSink::new(shared)))
+    }
+
+    /// Play `samples`, interleaved `f32` samples with `config.channels()` channels, once and
+    /// return when they have been played.
+    ///
+    /// This builds an output stream with `config`, e.g. the device's
+    /// [`default_output_config`](Self::default_output_config), feeds it the samples converted to
+    /// the config's sample format, waits for the device to play them and drops the stream. The
+    /// calling thread is blocked meanwhile. For anything beyond playing a single sound, build a
+    /// stream instead.
+    ///
+    /// Fails if the stream can't be built or started, or stops before the samples have been
+    /// played, e.g. because the device was disconnected. Fails with
+    /// [`BuildStreamError::StreamConfigNotSupported`] if the configuration is invalid (see
+    /// [`StreamConfig::is_valid`]).
+    fn play_buffer(
+        &self,
+        config: &SupportedStreamConfig,
+        samples: &[f32],
+    ) -> Result<(), PlayBufferError> {
+        if !config.config().is_valid() {
+            return Err(BuildStreamError::StreamConfigNotSupported.into());
+        }
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let channels = config.channels() as usize;
+        let sample_rate = config.sample_rate().0 as u64;
+        let samples = samples.to_vec();
+        let mut position = 0;
+        // Receives how long after the last callback its samples finish playing, or the error
+        // that stopped the stream.
+        let (done_tx, done_rx) = mpsc::channel();
+        let error_tx = done_tx.clone();
+        let stream = self.build_output_stream_raw(
+            &config.config(),
+            config.sample_format(),
+            move |data, info: &OutputCallbackInfo| {
+                if position == samples.len() {
+                    data.fill_from_f32(|| 0.0);
+                    return;
+                }
+                let start = position;
+                data.fill_from_f32(|| match samples.get(position) {
+                    Some(&sample) => {
+                        position += 1;
+                        sample
+                    }
+                    None => 0.0,
+                });
+                if position == samples.len() {
+                    let timestamp = info.timestamp();
+                    let latency = timestamp.playback.duration_since(&timestamp.callback);
+                    let frames = ((position - start) / channels) as u64;
+                    let played = Duration::from_nanos(frames * 1_000_000_000 / sample_rate);
+                    let _ = done_tx.send(Ok(latency.unwrap_or_default() + played));
+                }
+            },
+            move |err| {
+                // The stream goes on after other errors.
+                if err.is_fatal() {
+                    let _ = error_tx.send(Err(err));
+                }
+            },
+            None,
+        )?;
+        stream.play()?;
+        // Both senders are gone if the host dropped the callbacks without an error.
+        let remaining = done_rx
+            .recv()
+            .unwrap_or(Err(StreamError::DeviceNotAvailable))?;
+        match stream.drain_timeout(remaining + Duration::from_secs(1)) {
+            // The stream is private to this call, so nothing else resumes it and cancels the drain.
+            Ok(()) | Err(DrainError::TimedOut) | Err(DrainError::Cancelled) => Ok(()),
+            Err(DrainError::DeviceNotAvailable) => Err(StreamError::DeviceNotAvailable.into()),
+            // The host can't drain, so wait for the samples to play out instead.
+            Err(DrainError::BackendSpecific { .. }) => {
+                thread::sleep(remaining);
+                Ok(())
+            }
+        }
+    }
+
+    /// Play a short sine beep of `frequency_hz` on every channel and return once it has been
+    /// played, e.g. for a button that lets the user check they picked the right speakers.
+    ///
+    /// The beep is played with the device's
+    /// [`default_output_config`](Self::default_output_config) through
+    /// [`play_buffer`](Self::play_buffer), at a moderate level and fading in and out over a few
+    /// milliseconds so that it doesn't click. The calling thread is blocked meanwhile, and the
+    /// stream is dropped before returning.
+    ///
+    /// Fails like [`play_buffer`](Self::play_buffer), or with a [`BuildStreamError`] if the
+    /// device has no default output configuration.
+    fn test_tone(&self, frequency_hz: f32, duration: Duration) -> Result<(), PlayBufferError> {
+        let config = self.default_output_config().map_err(|err| match err {
+            DefaultStreamConfigError::DeviceNotAvailable => BuildStreamError::DeviceNotAvailable,
+            DefaultStreamConfigError::StreamTypeNotSupported => {
+                BuildStreamError::StreamConfigNotSupported
+            }
+            DefaultStreamConfigError::BackendSpecific { err } => {
+                BuildStreamError::BackendSpecific { err }
+            }
+        })?;
+        let samples = testsignal::beep(
+            frequency_hz,
+            duration,
+            config.channels(),
+            config.sample_rate(),
+        );
+        self.play_buffer(&config, &samples)
+    }
+
+    /// Create an output stream whose data callback fills blocks of exactly `block_frames` frames
+    /// of interleaved `f32` samples, whatever the size of the device's buffers.
+    ///
+    /// This suits processing that works in fixed blocks, e.g. a DSP graph. The blocks are
+    /// converted to `sample_format` and split across or gathered from the device's buffers as
+    /// needed, which adds up to one block of latency. The playback timestamp handed to the
+    /// callback is the instant the first frame of the block is played, so it accounts for this.
+    ///
+    /// Fails with [`BuildStreamError::StreamConfigNotSupported`] if `block_frames` is zero or the
+    /// configuration is invalid (see [`StreamConfig::is_valid`]).
+    fn build_output_stream_in_blocks<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        block_frames: FrameCount,
+        mut data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut [f32], &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !config.is_valid() || block_frames == 0 {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let channels = config.channels as usize;
+        let sample_rate = config.sample_rate.0 as u64;
+        let mut block = vec![0.0; block_frames as usize * channels];
+        // The index of the next sample of `block` to play, at its end once it has been played.
+        let mut position = block.len();
+        self.build_output_stream_raw(
+            config,
+            sample_format,
             move |data, info| {
-                data_callback(
-                    data.as_slice_mut()
-                        .expect("host supplied incorrect sample type"),
-                    info,
-                )
+                let mut written = 0;
+                data.fill_from_f32(|| {
+                    if position == block.len() {
+                        let ahead = (written / channels) as u64 * 1_000_000_000 / sample_rate;
+                        let timestamp = info.timestamp();
+                        let playback = timestamp.playback.add(Duration::from_nanos(ahead));
+                        let timestamp = OutputStreamTimestamp {
+                            playback: playback.unwrap_or(timestamp.playback),
+                            ..timestamp
+                        };
+                        data_callback(&mut block, &OutputCallbackInfo::new(timestamp));
+                        position = 0;
+                    }
+                    written += 1;
+                    position += 1;
+                    block[position - 1]
+                });
+            },
+            error_callback,
+            timeout,
+        )
+    }
+
+    /// Create an input stream that delivers `f32` samples at `sample_rate`, resampling from the
+    /// rate of `config` if the two differ.
+    ///
+    /// This allows capturing at a rate the device doesn't support, e.g. 16 kHz for speech
+    /// recognition from a device that only runs at 44.1 kHz. The data callback receives whole,
+    /// interleaved frames with `config.channels` channels. See [`Resampler`] for the latency this
+    /// adds.
+    #[allow(clippy::too_many_arguments)]
+    fn build_input_stream_resampled<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        sample_rate: SampleRate,
+        quality: ResampleQuality,
+        mut data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&[f32], &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !config.is_valid() || sample_rate.0 == 0 {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let mut resampler =
+            Resampler::new(config.channels, config.sample_rate, sample_rate, quality);
+        let mut input = Vec::new();
+        let mut output = Vec::new();
+        self.build_input_stream_raw(
+            config,
+            sample_format,
+            move |data, info| {
+                input.clear();
+                data.for_each_f32(|sample| input.push(sample));
+                output.clear();
+                resampler.process(&input, &mut output);
+                if !output.is_empty() {
+                    data_callback(&output, info);
+                }
             },
             error_callback,
             timeout,
@@ -207,6 +842,9 @@ pub trait DeviceTrait {
 }
 
 /// A stream created from [`Device`](DeviceTrait), with methods to control playback.
+///
+/// Dropping a stream stops it: once the drop returns, neither of its callbacks is running or will
+/// be called again, and the thread the stream ran on, if any, has exited.
 pub trait StreamTrait {
     /// Run the stream.
     ///
@@ -217,7 +855,123 @@ pub trait StreamTrait {
     /// Some devices support pausing the audio stream. This can be useful for saving energy in
     /// moments of silence.
     ///
+    /// The data callback isn't called while the stream is paused. For output streams, audio
+    /// already queued on the device isn't lost: [`play`](Self::play) continues with it where it
+    /// stopped, without repeating or skipping any of it. Input streams may drop audio that was
+    /// captured but not yet delivered, see below.
+    ///
+    /// - alsa: devices that can't pause in hardware, such as many plugins, are stopped right away
+    ///   and the audio they hadn't played yet is kept and written again by `play`. Resuming then
+    ///   takes up to one extra device buffer (see [`actual_buffer_size`](Self::actual_buffer_size))
+    ///   before new audio is heard. Input streams drop what was captured but not yet delivered.
+    ///
     /// Note: Not all devices support suspending the stream at the hardware level. This method may
     /// fail in these cases.
     fn pause(&self) -> Result<(), PauseStreamError>;
+
+    /// Whether the stream has stopped for good, e.g. because its device was disconnected.
+    ///
+    /// The error that ended the stream has already been passed to the error callback, and neither
+    /// callback will be called again. `play` and `pause` return `DeviceNotAvailable` from then on.
+    /// The stream still has to be dropped, but there is no need to keep it around.
+    ///
+    /// Hosts that can't detect this always return `false`.
+    fn is_terminated(&self) -> bool {
+        false
+    }
+
+    /// Stop calling the data callback and wait up to `timeout` for the audio already queued on the
+    /// device to finish playing.
+    ///
+    /// On success the stream is paused and `play` resumes it. If the queue hasn't emptied in time,
+    /// [`DrainError::TimedOut`] is returned and the stream keeps draining in the background:
+    /// `play` resumes it straight away and dropping it discards whatever is left. Calling `play`
+    /// while another thread waits here cancels the drain, which returns
+    /// [`DrainError::Cancelled`]. A paused stream doesn't drain. Input streams have nothing to
+    /// drain and return immediately.
+    ///
+    /// Hosts that can't tell when the queued audio has been played return a
+    /// [`DrainError::BackendSpecific`] error.
+    fn drain_timeout(&self, _timeout: Duration) -> Result<(), DrainError> {
+        Err(BackendSpecificError {
+            description: "draining is not supported by this host".to_string(),
+        }
+        .into())
+    }
+
+    /// The number of frames the data callback is handed on every call, if the host always hands
+    /// it buffers of the same size.
+    ///
+    /// With a fixed size, the callback can allocate its working buffers once up front. Returns
+    /// `None` if the size varies from call to call, e.g. on ALSA, CoreAudio or WASAPI in shared
+    /// mode, or if the host doesn't guarantee it.
+    fn fixed_buffer_size(&self) -> Option<FrameCount> {
+        None
+    }
+
+    /// The size of the buffer the host allocated for the stream on the device, in frames.
+    ///
+    /// Hosts round the [`BufferSize::Fixed`](crate::BufferSize::Fixed) requested in the
+    /// [`StreamConfig`](crate::StreamConfig) to what the device supports, and pick a size of
+    /// their own for `BufferSize::Default`, so this is the size to base latency calculations on.
+    /// The data callback may be handed only part of it at a time, e.g. one ALSA period.
+    ///
+    /// Returns `None` if the host doesn't tell.
+    fn actual_buffer_size(&self) -> Option<FrameCount> {
+        None
+    }
+
+    /// What the operating system does to the stream's audio between cpal and the device, e.g. to
+    /// tell whether playback is bit-exact or whether the stream is resampled a second time.
+    ///
+    /// On WASAPI this compares the stream's format with the endpoint's mix format, and shared
+    /// mode streams are never bit-exact. On ALSA only `hw` devices are known to be bit-exact. On
+    /// macOS this compares the stream's rate with the device's nominal rate. Other hosts return
+    /// [`OsProcessing::Unknown`].
+    fn os_processing(&self) -> OsProcessing {
+        OsProcessing::Unknown
+    }
+
+    /// Change how the stream trades latency for power consumption while it runs, without
+    /// rebuilding it.
+    ///
+    /// With [`PowerPreference::LowPower`], the stream is woken up less often, letting more of
+    /// the device's buffer drain, or fill for input, between calls of the data callback. This
+    /// leaves less headroom against underruns. [`PowerPreference::Default`] goes back to the
+    /// wakeups the stream was built with. Check the result with
+    /// [`wakeup_interval`](Self::wakeup_interval).
+    ///
+    /// On ALSA this raises the frames the device must have ready before waking the stream, to
+    /// all of its buffer but one period. WASAPI can't resize a running stream, so shared-mode
+    /// output streams call the data callback only once half of the buffer is free, though the
+    /// audio engine still wakes the stream's thread every period. Other hosts ignore the
+    /// preference.
+    fn set_power_preference(&self, _preference: PowerPreference) -> Result<(), StreamError> {
+        Ok(())
+    }
+
+    /// How long the data callback is left waiting between calls while the stream runs, given
+    /// the device's buffering and the [power preference](Self::set_power_preference).
+    ///
+    /// Returns `None` if the host doesn't tell.
+    fn wakeup_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Stop the stream and release the device, returning any error that occurs on the way.
+    ///
+    /// Dropping a stream does the same but has to ignore errors. The stream is released even if
+    /// an error is returned. Audio still queued on the device is discarded; call
+    /// [`drain_timeout`](Self::drain_timeout) first to let it play out.
+    ///
+    /// Returns [`CloseStreamError::DeviceNotAvailable`] if the stream had already stopped because
+    /// of an error, see [`is_terminated`](Self::is_terminated). Hosts that can't detect errors
+    /// while closing just drop the stream.
+    fn close(self) -> Result<(), CloseStreamError>
+    where
+        Self: Sized,
+    {
+        drop(self);
+        Ok(())
+    }
 }
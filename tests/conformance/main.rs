@@ -0,0 +1,385 @@
+//! A scripted scenario checking the documented behaviour of whichever host is active.
+//!
+//! The scenario opens real devices and plays sound, so it only runs when `CPAL_CONFORMANCE` is
+//! set, e.g. `CPAL_CONFORMANCE=1 cargo test --test conformance -- --nocapture`. `CPAL_HOST`
+//! selects the host on Linux. A capability report for each device is printed as it is checked.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cpal::testsignal::{Signal, SignalGenerator};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{
+    BuildStreamError, Data, PauseStreamError, PowerPreference, SampleFormat, StreamConfig,
+    SupportedStreamConfigRange,
+};
+
+// How long to wait for a stream to start or resume calling back.
+const START_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How long a stream has to settle after a command before its callbacks are counted.
+const SETTLE: Duration = Duration::from_millis(200);
+
+#[derive(Default)]
+struct Stats {
+    callbacks: AtomicUsize,
+    frames: AtomicUsize,
+    // Set if a buffer didn't contain whole frames.
+    partial_frames: AtomicBool,
+    // Set if a timestamp put playback before the callback, or capture after it.
+    bad_timestamp: AtomicBool,
+    // Set to make the next output callback stall for this many milliseconds.
+    stall_ms: AtomicUsize,
+}
+
+impl Stats {
+    fn callbacks(&self) -> usize {
+        self.callbacks.load(Ordering::SeqCst)
+    }
+
+    fn check(&self, what: &str) {
+        assert!(
+            !self.partial_frames.load(Ordering::SeqCst),
+            "{}: a buffer didn't contain whole frames",
+            what
+        );
+        assert!(
+            !self.bad_timestamp.load(Ordering::SeqCst),
+            "{}: inconsistent callback timestamps",
+            what
+        );
+    }
+}
+
+#[test]
+fn conformance() {
+    if std::env::var_os("CPAL_CONFORMANCE").is_none() {
+        println!("CPAL_CONFORMANCE is not set, skipping");
+        return;
+    }
+
+    let host = cpal::default_host();
+    println!("host: {}", host.id().name());
+    let devices: Vec<_> = host
+        .devices()
+        .expect("failed to enumerate devices")
+        .collect();
+    println!("{} device(s)", devices.len());
+
+    // Devices are identified by the same device in a later enumeration, and by themselves after
+    // a refresh.
+    let enumerated_again: Vec<_> = host
+        .devices()
+        .expect("failed to enumerate devices")
+        .collect();
+    for device in &devices {
+        let name = device.name().unwrap_or_default();
+        assert!(
+            enumerated_again.contains(device),
+            "{}: not equal to itself in a later enumeration",
+            name
+        );
+        let mut refreshed = device.clone();
+        refreshed.refresh().expect("failed to refresh the device");
+        assert!(refreshed == *device, "{}: not equal after a refresh", name);
+    }
+
+    for device in &devices {
+        let name = device.name().expect("failed to get the device name");
+        assert!(!name.is_empty(), "device names must not be empty");
+        println!("\n{}", name);
+
+        if let Ok(configs) = device.supported_output_configs() {
+            let configs: Vec<_> = configs.collect();
+            if let Ok(default) = device.default_output_config() {
+                println!("  default output: {:?}", default);
+                assert!(
+                    configs
+                        .iter()
+                        .any(|range| contains(range, &default.config())),
+                    "{}: the default output config isn't among the supported ones",
+                    name
+                );
+            }
+            for range in representative(&configs) {
+                check_output(device, &name, range);
+            }
+            if let Ok(default) = device.default_output_config() {
+                check_output_lifecycle(device, &name, default.sample_format(), &default.config());
+            }
+        }
+
+        if let Ok(configs) = device.supported_input_configs() {
+            let configs: Vec<_> = configs.collect();
+            if let Ok(default) = device.default_input_config() {
+                println!("  default input: {:?}", default);
+                assert!(
+                    configs
+                        .iter()
+                        .any(|range| contains(range, &default.config())),
+                    "{}: the default input config isn't among the supported ones",
+                    name
+                );
+                check_input(device, &name, default.sample_format(), &default.config());
+            }
+        }
+    }
+}
+
+fn contains(range: &SupportedStreamConfigRange, config: &StreamConfig) -> bool {
+    range.channels() == config.channels
+        && range.min_sample_rate() <= config.sample_rate
+        && config.sample_rate <= range.max_sample_rate()
+}
+
+// One config per channel count and sample format, to keep the scenario short on devices that
+// support many rates.
+fn representative(configs: &[SupportedStreamConfigRange]) -> Vec<SupportedStreamConfigRange> {
+    let mut picked: Vec<SupportedStreamConfigRange> = Vec::new();
+    for range in configs {
+        let seen = picked.iter().any(|p| {
+            p.channels() == range.channels() && p.sample_format() == range.sample_format()
+        });
+        if !seen {
+            picked.push(*range);
+        }
+    }
+    picked
+}
+
+// Open a config the device claims to support and check that it runs.
+fn check_output(device: &cpal::Device, name: &str, range: SupportedStreamConfigRange) {
+    let config = range.with_max_sample_rate();
+    let sample_format = config.sample_format();
+    let config = config.config();
+    let what = format!("{} {:?} {:?}", name, sample_format, config);
+
+    let stats = Arc::new(Stats::default());
+    let stream = match build_output(device, sample_format, &config, &stats) {
+        Ok(stream) => stream,
+        // Claimed configs can still fail to open, e.g. if the device is busy.
+        Err(err) => {
+            println!(
+                "  output {:?} {:?}: failed to open: {}",
+                sample_format, config, err
+            );
+            return;
+        }
+    };
+    stream.play().expect("failed to play");
+    let running = wait_for(|| stats.callbacks() > 0, START_TIMEOUT);
+    drop(stream);
+    stats.check(&what);
+    println!(
+        "  output {:?} {:?}: {}",
+        sample_format,
+        config,
+        if running { "ok" } else { "no callbacks" }
+    );
+    assert!(running, "{}: the stream never called back", what);
+}
+
+// Play, pause, resume, force an underrun, drop and reopen a stream.
+fn check_output_lifecycle(
+    device: &cpal::Device,
+    name: &str,
+    sample_format: SampleFormat,
+    config: &StreamConfig,
+) {
+    let stats = Arc::new(Stats::default());
+    let stream = build_output(device, sample_format, config, &stats)
+        .unwrap_or_else(|err| panic!("{}: failed to open the default config: {}", name, err));
+    stream.play().expect("failed to play");
+    assert!(
+        wait_for(|| stats.callbacks() > 0, START_TIMEOUT),
+        "{}: the stream never called back",
+        name
+    );
+    let actual = stream.actual_buffer_size();
+    println!("  buffer size: {:?}", actual);
+    println!("  os processing: {:?}", stream.os_processing());
+    if let (Some(fixed), Some(actual)) = (stream.fixed_buffer_size(), actual) {
+        assert!(
+            fixed <= actual,
+            "{}: the callback buffers are larger than the device buffer",
+            name
+        );
+    }
+
+    match stream.pause() {
+        Ok(()) => {
+            thread::sleep(SETTLE);
+            let paused = stats.callbacks();
+            thread::sleep(SETTLE);
+            assert_eq!(
+                stats.callbacks(),
+                paused,
+                "{}: the stream called back while paused",
+                name
+            );
+            stream.play().expect("failed to resume");
+            assert!(
+                wait_for(|| stats.callbacks() > paused, START_TIMEOUT),
+                "{}: the stream didn't resume",
+                name
+            );
+            println!("  pause: ok");
+        }
+        Err(PauseStreamError::DeviceNotAvailable) => panic!("{}: the device went away", name),
+        Err(err) => println!("  pause: unsupported ({})", err),
+    }
+
+    let interval = stream.wakeup_interval();
+    stream
+        .set_power_preference(PowerPreference::LowPower)
+        .unwrap_or_else(|err| panic!("{}: failed to switch to low power: {}", name, err));
+    let low_power = stream.wakeup_interval();
+    assert!(
+        low_power >= interval,
+        "{}: low power woke up more often ({:?} < {:?})",
+        name,
+        low_power,
+        interval
+    );
+    let called = stats.callbacks();
+    assert!(
+        wait_for(|| stats.callbacks() > called, START_TIMEOUT),
+        "{}: the stream stopped calling back in low power",
+        name
+    );
+    stream
+        .set_power_preference(PowerPreference::Default)
+        .unwrap_or_else(|err| panic!("{}: failed to switch back from low power: {}", name, err));
+    println!(
+        "  wakeup interval: {:?}, low power: {:?}",
+        interval, low_power
+    );
+
+    // Stall one callback for a whole second, which any buffer size underruns on.
+    stats.stall_ms.store(1000, Ordering::SeqCst);
+    assert!(
+        wait_for(|| stats.stall_ms.load(Ordering::SeqCst) == 0, START_TIMEOUT),
+        "{}: the stream stopped calling back",
+        name
+    );
+    let after_stall = stats.callbacks();
+    assert!(
+        wait_for(|| stats.callbacks() > after_stall, START_TIMEOUT),
+        "{}: the stream didn't recover from an underrun",
+        name
+    );
+    assert!(
+        !stream.is_terminated(),
+        "{}: an underrun terminated the stream",
+        name
+    );
+    println!("  underrun recovery: ok");
+
+    drop(stream);
+    stats.check(name);
+
+    let stats = Arc::new(Stats::default());
+    let stream = build_output(device, sample_format, config, &stats)
+        .unwrap_or_else(|err| panic!("{}: failed to reopen the default config: {}", name, err));
+    stream.play().expect("failed to play");
+    assert!(
+        wait_for(|| stats.callbacks() > 0, START_TIMEOUT),
+        "{}: the reopened stream never called back",
+        name
+    );
+    stream
+        .close()
+        .unwrap_or_else(|err| panic!("{}: failed to close the stream: {}", name, err));
+    stats.check(name);
+    println!("  reopen and close: ok");
+}
+
+fn check_input(
+    device: &cpal::Device,
+    name: &str,
+    sample_format: SampleFormat,
+    config: &StreamConfig,
+) {
+    let stats = Arc::new(Stats::default());
+    let channels = config.channels as usize;
+    let stream = {
+        let stats = stats.clone();
+        device.build_input_stream_raw(
+            config,
+            sample_format,
+            move |data: &Data, info: &cpal::InputCallbackInfo| {
+                let timestamp = info.timestamp();
+                if timestamp.capture > timestamp.callback {
+                    stats.bad_timestamp.store(true, Ordering::SeqCst);
+                }
+                record(&stats, data.len(), channels);
+            },
+            |err| eprintln!("input stream error: {}", err),
+            None,
+        )
+    };
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(err) => {
+            println!("  input: failed to open: {}", err);
+            return;
+        }
+    };
+    stream.play().expect("failed to play");
+    let running = wait_for(|| stats.callbacks() > 0, START_TIMEOUT);
+    drop(stream);
+    stats.check(name);
+    assert!(running, "{}: the input stream never called back", name);
+    println!("  input: ok");
+}
+
+fn build_output(
+    device: &cpal::Device,
+    sample_format: SampleFormat,
+    config: &StreamConfig,
+    stats: &Arc<Stats>,
+) -> Result<cpal::Stream, BuildStreamError> {
+    let stats = stats.clone();
+    let channels = config.channels as usize;
+    let mut generator = SignalGenerator::new(Signal::Sine, config.channels, config.sample_rate);
+    generator.set_amplitude(0.1);
+    device.build_output_stream_raw(
+        config,
+        sample_format,
+        move |data: &mut Data, info: &cpal::OutputCallbackInfo| {
+            let stall_ms = stats.stall_ms.swap(0, Ordering::SeqCst);
+            if stall_ms > 0 {
+                thread::sleep(Duration::from_millis(stall_ms as u64));
+            }
+            let timestamp = info.timestamp();
+            if timestamp.playback < timestamp.callback {
+                stats.bad_timestamp.store(true, Ordering::SeqCst);
+            }
+            generator.fill(data);
+            record(&stats, data.len(), channels);
+        },
+        |err| eprintln!("output stream error: {}", err),
+        None,
+    )
+}
+
+fn record(stats: &Stats, samples: usize, channels: usize) {
+    if samples % channels != 0 {
+        stats.partial_frames.store(true, Ordering::SeqCst);
+    }
+    stats.frames.fetch_add(samples / channels, Ordering::SeqCst);
+    stats.callbacks.fetch_add(1, Ordering::SeqCst);
+}
+
+fn wait_for(condition: impl Fn() -> bool, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    condition()
+}
@@ -0,0 +1,102 @@
+//! Builds and drops many short-lived streams on the default output device, checking that the
+//! number of handles (file descriptors on Unix) held by the process stays flat.
+//!
+//! The soak opens a real device, so it only runs when `CPAL_SOAK` is set, e.g.
+//! `CPAL_SOAK=1 cargo test --release --test soak -- --nocapture`. A number given as the value of
+//! `CPAL_SOAK` sets the count of streams, which defaults to 10,000. `CPAL_HOST` selects the host
+//! on Linux. Streams play zeroed buffers, which is silence unless the default format is unsigned.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+const DEFAULT_STREAMS: usize = 10_000;
+
+// Streams built before the handle count is taken, letting hosts set up whatever they keep for the
+// life of the process (COM, sound server connections, ...).
+const WARM_UP: usize = 16;
+
+// Handles the process may gain during the soak for reasons other than a leak, such as a thread
+// pool growing.
+const SLACK: usize = 8;
+
+#[test]
+fn soak() {
+    let Some(value) = std::env::var_os("CPAL_SOAK") else {
+        println!("CPAL_SOAK is not set, skipping");
+        return;
+    };
+    let streams = value
+        .to_str()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_STREAMS);
+
+    let host = cpal::default_host();
+    let device = host
+        .default_output_device()
+        .expect("no default output device");
+    let config = device
+        .default_output_config()
+        .expect("failed to get the default output config");
+    println!(
+        "host: {}, device: {}, config: {:?}",
+        host.id().name(),
+        device.name().unwrap_or_default(),
+        config
+    );
+    let sample_format = config.sample_format();
+    let config = config.config();
+
+    let build_and_drop = || {
+        let stream = device
+            .build_output_stream_raw(
+                &config,
+                sample_format,
+                |data: &mut cpal::Data, _: &cpal::OutputCallbackInfo| data.bytes_mut().fill(0),
+                |err| panic!("stream error: {}", err),
+                None,
+            )
+            .expect("failed to build the stream");
+        stream.play().expect("failed to play the stream");
+    };
+
+    for _ in 0..WARM_UP {
+        build_and_drop();
+    }
+    let before = open_handles();
+    for i in 0..streams {
+        build_and_drop();
+        if (i + 1) % 1000 == 0 {
+            println!("{} streams, {:?} handles", i + 1, open_handles());
+        }
+    }
+    let after = open_handles();
+
+    match (before, after) {
+        (Some(before), Some(after)) => assert!(
+            after <= before + SLACK,
+            "{} handles before building {} streams, {} after",
+            before,
+            streams,
+            after
+        ),
+        _ => println!("can't count the handles of the process on this platform"),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn open_handles() -> Option<usize> {
+    use windows::Win32::System::Threading::{GetCurrentProcess, GetProcessHandleCount};
+    let mut count = 0;
+    unsafe { GetProcessHandleCount(GetCurrentProcess(), &mut count) }.ok()?;
+    Some(count as usize)
+}
+
+#[cfg(unix)]
+fn open_handles() -> Option<usize> {
+    // Lists the open file descriptors on Linux, the BSDs and macOS.
+    Some(std::fs::read_dir("/dev/fd").ok()?.count())
+}
+
+#[cfg(not(any(unix, target_os = "windows")))]
+fn open_handles() -> Option<usize> {
+    None
+}